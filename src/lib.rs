@@ -0,0 +1,2 @@
+mod modules;
+pub use modules::*;
@@ -1,12 +1,64 @@
-mod modules;
-use modules::*;
-
-use win_fact::{WindowBuilder, WindowType};
-use window_controller::CONTROLLER;
+use ocr::cli::{self, Cli};
+#[cfg(windows)]
+use ocr::{
+    config::Config,
+    ipc,
+    win_fact::{enable_dpi_awareness, WindowBuilder, WindowType},
+    window_controller::CONTROLLER,
+    winproc::{self, opaque_handler, transparent_handler, ControlHandler},
+};
+#[cfg(windows)]
+use std::sync::Arc;
+#[cfg(windows)]
 use windows::Win32::UI::WindowsAndMessaging::*;
-use winproc::{opaque_handler, transparent_handler};
 
 fn main() {
+    let cli = match Cli::parse(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(e.exit.code());
+        }
+    };
+
+    #[cfg(windows)]
+    enable_dpi_awareness();
+
+    if cli.command.is_some() {
+        let mut out = std::io::stdout().lock();
+        if let Err(e) = cli::run(&cli, &mut out) {
+            eprintln!("{}", e);
+            std::process::exit(e.exit.code());
+        }
+        return;
+    }
+
+    let config = match cli.load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Fehler in der Konfiguration: {}", e);
+            std::process::exit(e.exit.code());
+        }
+    };
+    run_overlay(config);
+}
+
+#[cfg(not(windows))]
+fn run_overlay(_config: ocr::config::Config) {
+    eprintln!("Das Overlay wird nur unter Windows unterstützt.");
+    std::process::exit(1);
+}
+
+#[cfg(windows)]
+fn run_overlay(config: Config) {
+    if let Err(e) = winproc::configure(&config) {
+        eprintln!("Fehler in der Konfiguration: {}", e);
+        std::process::exit(cli::ExitCode::Config.code());
+    }
+    let opacity = config.overlay.opacity;
+    let control = config.ipc.enabled.then(|| config.ipc_address());
+    let _ = CONTROLLER.configure(config);
+
     let opaque_window = WindowBuilder::new()
         .set_window_type(WindowType::Opaque)
         .set_window_proc(opaque_handler)
@@ -23,6 +75,7 @@ fn main() {
     let trans_window = WindowBuilder::new()
         .set_window_type(WindowType::Transparent)
         .set_window_proc(transparent_handler)
+        .set_opacity(opacity)
         .build();
 
     let t_win = match trans_window {
@@ -34,6 +87,13 @@ fn main() {
     };
     o_win.show();
 
+    if let Some(address) = control {
+        let handler = Arc::new(ControlHandler::new(t_win.get_hwnd()));
+        if let Err(e) = ipc::listen(&address, handler) {
+            eprintln!("Steuerkanal nicht verfügbar: {}", e);
+        }
+    }
+
     let _ = CONTROLLER.add_window(t_win);
     let _ = CONTROLLER.add_window(o_win);
 
@@ -46,4 +106,5 @@ fn main() {
             let _ = GetMessageW(&mut msg, None, 0, 0);
         }
     }
+    CONTROLLER.close_history();
 }
@@ -0,0 +1,973 @@
+use crate::geometry::{distance_to_segment, Point, Rect};
+use crate::redact::{Redaction, RedactionStyle};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Pointer distance in pixels that still counts as hitting a thin stroke.
+pub const HIT_TOLERANCE: f32 = 4.0;
+
+/// Length of an arrow head relative to the stroke width.
+const ARROW_HEAD_SCALE: f32 = 4.0;
+const ARROW_HEAD_MIN: f32 = 10.0;
+const ARROW_HEAD_ANGLE: f32 = 28.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::rgb(0, 0, 0);
+    pub const WHITE: Color = Color::rgb(255, 255, 255);
+    pub const RED: Color = Color::rgb(230, 40, 40);
+    pub const YELLOW: Color = Color::rgb(255, 230, 0);
+
+    pub const fn rgb(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+/// How an annotation's outline is painted.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stroke {
+    pub color: Color,
+    pub width: f32,
+    /// 0.0 (invisible) - 1.0 (opaque).
+    pub opacity: f32,
+}
+
+impl Default for Stroke {
+    fn default() -> Self {
+        Stroke {
+            color: Color::RED,
+            width: 3.0,
+            opacity: 1.0,
+        }
+    }
+}
+
+impl Stroke {
+    pub fn new(color: Color, width: f32, opacity: f32) -> Self {
+        Stroke {
+            color,
+            width,
+            opacity,
+        }
+    }
+
+    /// Wide translucent yellow, as used by [`Highlighter`].
+    pub fn highlighter() -> Self {
+        Stroke::new(Color::YELLOW, 16.0, 0.4)
+    }
+}
+
+/// Drawing surface annotations render onto.
+///
+/// Implemented by the Direct2D overlay and by renderers that work on plain
+/// frames, so the annotation model itself never touches a graphics API.
+pub trait Canvas {
+    fn stroke_line(&mut self, from: Point, to: Point, stroke: &Stroke);
+    fn stroke_rect(&mut self, rect: &Rect, stroke: &Stroke);
+    /// Ellipse inscribed in `rect`.
+    fn stroke_ellipse(&mut self, rect: &Rect, stroke: &Stroke);
+    fn fill_rect(&mut self, rect: &Rect, color: Color, opacity: f32);
+    fn fill_ellipse(&mut self, rect: &Rect, color: Color, opacity: f32);
+    fn fill_polygon(&mut self, points: &[Point], color: Color, opacity: f32);
+
+    /// Connected segments; canvases that can draw real paths should override
+    /// this so translucent strokes don't darken at the joints.
+    fn stroke_polyline(&mut self, points: &[Point], stroke: &Stroke) {
+        for segment in points.windows(2) {
+            self.stroke_line(segment[0], segment[1], stroke);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnnotationKind {
+    Line,
+    Arrow,
+    Rectangle,
+    Ellipse,
+    Freehand,
+    Highlighter,
+    Redaction,
+}
+
+/// A shape drawn on top of a capture.
+pub trait Annotation: Debug + Send {
+    fn kind(&self) -> AnnotationKind;
+    fn stroke(&self) -> &Stroke;
+    fn set_stroke(&mut self, stroke: Stroke);
+    /// Area covered including the stroke width.
+    fn bounds(&self) -> Rect;
+    /// True if `p` lies within `tolerance` pixels of the painted stroke.
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool;
+    fn translate(&mut self, dx: f32, dy: f32);
+    fn draw(&self, canvas: &mut dyn Canvas);
+    /// Serializable copy of the annotation.
+    fn to_shape(&self) -> Shape;
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Line {
+    pub from: Point,
+    pub to: Point,
+    pub stroke: Stroke,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Arrow {
+    pub from: Point,
+    /// Tip of the arrow head.
+    pub to: Point,
+    pub stroke: Stroke,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rectangle {
+    pub rect: Rect,
+    pub stroke: Stroke,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Ellipse {
+    /// Bounding box of the ellipse.
+    pub rect: Rect,
+    pub stroke: Stroke,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Freehand {
+    pub points: Vec<Point>,
+    pub stroke: Stroke,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Highlighter {
+    pub points: Vec<Point>,
+    pub stroke: Stroke,
+}
+
+fn hits_polyline(points: &[Point], p: Point, reach: f32) -> bool {
+    match points {
+        [] => false,
+        [single] => (p.x - single.x).hypot(p.y - single.y) <= reach,
+        _ => points
+            .windows(2)
+            .any(|s| distance_to_segment(p, s[0], s[1]) <= reach),
+    }
+}
+
+fn points_bounds(points: &[Point], stroke: &Stroke) -> Rect {
+    points
+        .iter()
+        .map(|p| Rect::new(p.x, p.y, p.x, p.y))
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_default()
+        .inflate(stroke.width / 2.0)
+}
+
+fn rect_corners(rect: &Rect) -> [Point; 5] {
+    [
+        Point::new(rect.left, rect.top),
+        Point::new(rect.right, rect.top),
+        Point::new(rect.right, rect.bottom),
+        Point::new(rect.left, rect.bottom),
+        Point::new(rect.left, rect.top),
+    ]
+}
+
+impl Arrow {
+    fn head_length(&self) -> f32 {
+        (self.stroke.width * ARROW_HEAD_SCALE).max(ARROW_HEAD_MIN)
+    }
+
+    /// Triangle of the arrow head: tip, then the two barbs.
+    pub fn head(&self) -> [Point; 3] {
+        let angle = (self.to.y - self.from.y).atan2(self.to.x - self.from.x);
+        let spread = ARROW_HEAD_ANGLE.to_radians();
+        let length = self.head_length();
+        let barb = |a: f32| Point::new(self.to.x - length * a.cos(), self.to.y - length * a.sin());
+        [self.to, barb(angle - spread), barb(angle + spread)]
+    }
+
+    /// End of the shaft, pulled back so it does not poke through the tip.
+    fn shaft_end(&self) -> Point {
+        let [_, a, b] = self.head();
+        Point::new((a.x + b.x) / 2.0, (a.y + b.y) / 2.0)
+    }
+}
+
+impl Annotation for Line {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Line
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        points_bounds(&[self.from, self.to], &self.stroke)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        distance_to_segment(p, self.from, self.to) <= self.stroke.width / 2.0 + tolerance
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.from = Point::new(self.from.x + dx, self.from.y + dy);
+        self.to = Point::new(self.to.x + dx, self.to.y + dy);
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_line(self.from, self.to, &self.stroke);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Line(self.clone())
+    }
+}
+
+impl Annotation for Arrow {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Arrow
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        let [tip, a, b] = self.head();
+        points_bounds(&[self.from, tip, a, b], &self.stroke)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        let [tip, a, b] = self.head();
+        let reach = self.stroke.width / 2.0 + tolerance;
+        hits_polyline(&[self.from, tip], p, reach) || hits_polyline(&[a, tip, b, a], p, reach)
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.from = Point::new(self.from.x + dx, self.from.y + dy);
+        self.to = Point::new(self.to.x + dx, self.to.y + dy);
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_line(self.from, self.shaft_end(), &self.stroke);
+        canvas.fill_polygon(&self.head(), self.stroke.color, self.stroke.opacity);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Arrow(self.clone())
+    }
+}
+
+impl Annotation for Rectangle {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Rectangle
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        self.rect.inflate(self.stroke.width / 2.0)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        hits_polyline(
+            &rect_corners(&self.rect),
+            p,
+            self.stroke.width / 2.0 + tolerance,
+        )
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.rect = self.rect.translate(dx, dy);
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_rect(&self.rect, &self.stroke);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Rectangle(self.clone())
+    }
+}
+
+impl Annotation for Ellipse {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Ellipse
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        self.rect.inflate(self.stroke.width / 2.0)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        let rx = self.rect.width() / 2.0;
+        let ry = self.rect.height() / 2.0;
+        let reach = self.stroke.width / 2.0 + tolerance;
+        if rx <= 0.0 || ry <= 0.0 {
+            return hits_polyline(&rect_corners(&self.rect), p, reach);
+        }
+
+        // Radial distance to the outline, exact for circles and close enough
+        // for the flat ellipses people actually draw
+        let (dx, dy) = (p.x - (self.rect.left + rx), p.y - (self.rect.top + ry));
+        let distance = dx.hypot(dy);
+        let normalized = ((dx / rx).powi(2) + (dy / ry).powi(2)).sqrt();
+        let radius = if normalized > 0.0 {
+            distance / normalized
+        } else {
+            rx.min(ry)
+        };
+        (distance - radius).abs() <= reach
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.rect = self.rect.translate(dx, dy);
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_ellipse(&self.rect, &self.stroke);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Ellipse(self.clone())
+    }
+}
+
+impl Annotation for Freehand {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Freehand
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        points_bounds(&self.points, &self.stroke)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        hits_polyline(&self.points, p, self.stroke.width / 2.0 + tolerance)
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        for point in &mut self.points {
+            *point = Point::new(point.x + dx, point.y + dy);
+        }
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_polyline(&self.points, &self.stroke);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Freehand(self.clone())
+    }
+}
+
+impl Annotation for Highlighter {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Highlighter
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        points_bounds(&self.points, &self.stroke)
+    }
+
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        hits_polyline(&self.points, p, self.stroke.width / 2.0 + tolerance)
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        for point in &mut self.points {
+            *point = Point::new(point.x + dx, point.y + dy);
+        }
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        canvas.stroke_polyline(&self.points, &self.stroke);
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Highlighter(self.clone())
+    }
+}
+
+/// Serialized form of an annotation, tagged with its kind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Shape {
+    Line(Line),
+    Arrow(Arrow),
+    Rectangle(Rectangle),
+    Ellipse(Ellipse),
+    Freehand(Freehand),
+    Highlighter(Highlighter),
+    Redaction(Redaction),
+}
+
+impl Shape {
+    pub fn into_annotation(self) -> Box<dyn Annotation> {
+        match self {
+            Shape::Line(shape) => Box::new(shape),
+            Shape::Arrow(shape) => Box::new(shape),
+            Shape::Rectangle(shape) => Box::new(shape),
+            Shape::Ellipse(shape) => Box::new(shape),
+            Shape::Freehand(shape) => Box::new(shape),
+            Shape::Highlighter(shape) => Box::new(shape),
+            Shape::Redaction(shape) => Box::new(shape),
+        }
+    }
+}
+
+/// Drawing tool that turns the points of a pointer drag into an annotation.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Tool {
+    Line,
+    Arrow,
+    Rectangle,
+    Ellipse,
+    Pen,
+    Highlighter,
+    /// Redaction of the dragged rectangle; the stroke is ignored.
+    Redact(RedactionStyle),
+}
+
+impl Tool {
+    /// Two-point tools use the first and last point, pens keep all of them.
+    /// Returns `None` if `points` is empty.
+    pub fn build(&self, points: &[Point], stroke: Stroke) -> Option<Box<dyn Annotation>> {
+        let (&from, &to) = (points.first()?, points.last()?);
+        Some(match self {
+            Tool::Line => Box::new(Line { from, to, stroke }),
+            Tool::Arrow => Box::new(Arrow { from, to, stroke }),
+            Tool::Rectangle => Box::new(Rectangle {
+                rect: Rect::from_points(from, to),
+                stroke,
+            }),
+            Tool::Ellipse => Box::new(Ellipse {
+                rect: Rect::from_points(from, to),
+                stroke,
+            }),
+            Tool::Pen => Box::new(Freehand {
+                points: points.to_vec(),
+                stroke,
+            }),
+            Tool::Highlighter => Box::new(Highlighter {
+                points: points.to_vec(),
+                stroke,
+            }),
+            Tool::Redact(style) => Box::new(Redaction::new(Rect::from_points(from, to), *style)),
+        })
+    }
+}
+
+/// Ordered annotations of one capture, bottom to top, plus the one picked
+/// for editing.
+#[derive(Debug, Default)]
+pub struct AnnotationLayer {
+    annotations: Vec<Box<dyn Annotation>>,
+    selected: Option<usize>,
+}
+
+impl Clone for AnnotationLayer {
+    fn clone(&self) -> Self {
+        AnnotationLayer {
+            annotations: self
+                .annotations
+                .iter()
+                .map(|a| a.to_shape().into_annotation())
+                .collect(),
+            selected: self.selected,
+        }
+    }
+}
+
+impl AnnotationLayer {
+    pub fn new() -> Self {
+        AnnotationLayer {
+            annotations: Vec::new(),
+            selected: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.annotations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.annotations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Annotation> {
+        self.annotations.iter().map(|a| a.as_ref())
+    }
+
+    pub fn get(&self, index: usize) -> Option<&dyn Annotation> {
+        self.annotations.get(index).map(|a| a.as_ref())
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut (dyn Annotation + 'static)> {
+        self.annotations.get_mut(index).map(|a| a.as_mut())
+    }
+
+    /// Adds an annotation on top and returns its index.
+    pub fn push(&mut self, annotation: Box<dyn Annotation>) -> usize {
+        self.annotations.push(annotation);
+        self.annotations.len() - 1
+    }
+
+    pub fn insert(&mut self, index: usize, annotation: Box<dyn Annotation>) {
+        let index = index.min(self.annotations.len());
+        self.annotations.insert(index, annotation);
+        if let Some(selected) = self.selected.as_mut() {
+            if *selected >= index {
+                *selected += 1;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Box<dyn Annotation>> {
+        if index >= self.annotations.len() {
+            return None;
+        }
+        self.selected = match self.selected {
+            Some(selected) if selected == index => None,
+            Some(selected) if selected > index => Some(selected - 1),
+            selected => selected,
+        };
+        Some(self.annotations.remove(index))
+    }
+
+    /// Swaps the annotation at `index`, returning the previous one.
+    pub fn replace(
+        &mut self,
+        index: usize,
+        annotation: Box<dyn Annotation>,
+    ) -> Option<Box<dyn Annotation>> {
+        let slot = self.annotations.get_mut(index)?;
+        Some(std::mem::replace(slot, annotation))
+    }
+
+    pub fn clear(&mut self) {
+        self.annotations.clear();
+        self.selected = None;
+    }
+
+    /// Index of the topmost annotation under `p`.
+    pub fn hit_test(&self, p: Point, tolerance: f32) -> Option<usize> {
+        self.annotations
+            .iter()
+            .rposition(|a| a.hit_test(p, tolerance))
+    }
+
+    /// Selects the topmost annotation under `p`, or clears the selection.
+    pub fn select_at(&mut self, p: Point) -> Option<usize> {
+        self.selected = self.hit_test(p, HIT_TOLERANCE);
+        self.selected
+    }
+
+    pub fn select(&mut self, index: Option<usize>) {
+        self.selected = index.filter(|&i| i < self.annotations.len());
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    pub fn selected_mut(&mut self) -> Option<&mut (dyn Annotation + 'static)> {
+        let index = self.selected?;
+        self.get_mut(index)
+    }
+
+    /// Union of all annotation bounds.
+    pub fn bounds(&self) -> Option<Rect> {
+        self.annotations
+            .iter()
+            .map(|a| a.bounds())
+            .reduce(|a, b| a.union(&b))
+    }
+
+    /// Paints all annotations bottom to top.
+    pub fn draw(&self, canvas: &mut dyn Canvas) {
+        for annotation in &self.annotations {
+            annotation.draw(canvas);
+        }
+    }
+
+    pub fn shapes(&self) -> Vec<Shape> {
+        self.annotations.iter().map(|a| a.to_shape()).collect()
+    }
+
+    pub fn from_shapes(shapes: Vec<Shape>) -> Self {
+        AnnotationLayer {
+            annotations: shapes.into_iter().map(Shape::into_annotation).collect(),
+            selected: None,
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.shapes())?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(Self::from_shapes(serde_json::from_str(json)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thin() -> Stroke {
+        Stroke::new(Color::RED, 2.0, 1.0)
+    }
+
+    fn hits(annotation: &dyn Annotation, x: f32, y: f32) -> bool {
+        annotation.hit_test(Point::new(x, y), 0.0)
+    }
+
+    /// One annotation of every kind, spread out so none overlap.
+    fn shapes() -> Vec<Shape> {
+        vec![
+            Shape::Line(Line {
+                from: Point::new(0.0, 0.0),
+                to: Point::new(100.0, 0.0),
+                stroke: thin(),
+            }),
+            Shape::Arrow(Arrow {
+                from: Point::new(0.0, 50.0),
+                to: Point::new(100.0, 50.0),
+                stroke: thin(),
+            }),
+            Shape::Rectangle(Rectangle {
+                rect: Rect::new(10.0, 100.0, 50.0, 140.0),
+                stroke: thin(),
+            }),
+            Shape::Ellipse(Ellipse {
+                rect: Rect::new(0.0, 200.0, 40.0, 240.0),
+                stroke: thin(),
+            }),
+            Shape::Freehand(Freehand {
+                points: vec![
+                    Point::new(0.0, 300.0),
+                    Point::new(10.0, 300.0),
+                    Point::new(10.0, 310.0),
+                ],
+                stroke: thin(),
+            }),
+            Shape::Highlighter(Highlighter {
+                points: vec![Point::new(0.0, 400.0), Point::new(50.0, 400.0)],
+                stroke: Stroke::highlighter(),
+            }),
+            Shape::Redaction(Redaction::new(
+                Rect::new(0.0, 500.0, 30.0, 520.0),
+                RedactionStyle::pixelate(),
+            )),
+        ]
+    }
+
+    #[test]
+    fn lines_are_hit_within_half_the_width_plus_tolerance() {
+        let line = Line {
+            from: Point::new(0.0, 0.0),
+            to: Point::new(100.0, 0.0),
+            stroke: Stroke::new(Color::RED, 4.0, 1.0),
+        };
+        assert!(line.hit_test(Point::new(50.0, 6.0), 4.0));
+        assert!(!line.hit_test(Point::new(50.0, 6.1), 4.0));
+        // Past the ends the distance is measured to the end point
+        assert!(line.hit_test(Point::new(104.0, 0.0), 2.0));
+        assert!(!line.hit_test(Point::new(103.0, 3.0), 2.0));
+        assert!(!line.hit_test(Point::new(-5.0, 0.0), 2.0));
+    }
+
+    #[test]
+    fn arrows_are_hit_on_the_shaft_and_the_head_outline() {
+        let arrow = Arrow {
+            from: Point::new(0.0, 0.0),
+            to: Point::new(100.0, 0.0),
+            stroke: thin(),
+        };
+        // Thin strokes get the minimum head length of 10 at 28 degrees
+        let [tip, a, b] = arrow.head();
+        assert_eq!(tip, Point::new(100.0, 0.0));
+        let (cos, sin) = (28f32.to_radians().cos(), 28f32.to_radians().sin());
+        for (barb, y) in [(a, 10.0 * sin), (b, -10.0 * sin)] {
+            assert!((barb.x - (100.0 - 10.0 * cos)).abs() < 1e-4, "{:?}", barb);
+            assert!((barb.y - y).abs() < 1e-4, "{:?}", barb);
+        }
+
+        assert!(hits(&arrow, 50.0, 1.0));
+        assert!(!hits(&arrow, 50.0, 1.5));
+        assert!(hits(&arrow, a.x, a.y));
+        assert!(hits(&arrow, b.x, b.y - 1.0));
+        // The back of the head joins the barbs
+        assert!(hits(&arrow, a.x, 2.0));
+        assert!(!hits(&arrow, a.x - 3.0, 4.0));
+        let bounds = arrow.bounds();
+        assert!(bounds.top <= b.y - 1.0 && bounds.bottom >= a.y + 1.0);
+    }
+
+    #[test]
+    fn rectangles_are_hit_on_the_outline_only() {
+        let rectangle = Rectangle {
+            rect: Rect::new(10.0, 10.0, 50.0, 40.0),
+            stroke: thin(),
+        };
+        assert!(hits(&rectangle, 30.0, 10.0));
+        assert!(hits(&rectangle, 51.0, 25.0));
+        assert!(hits(&rectangle, 9.0, 10.0));
+        assert!(!hits(&rectangle, 9.0, 9.0));
+        assert!(!hits(&rectangle, 30.0, 25.0));
+        assert!(!hits(&rectangle, 52.0, 25.0));
+        assert_eq!(rectangle.bounds(), Rect::new(9.0, 9.0, 51.0, 41.0));
+    }
+
+    #[test]
+    fn ellipses_are_hit_on_the_outline_only() {
+        let circle = Ellipse {
+            rect: Rect::new(0.0, 0.0, 40.0, 40.0),
+            stroke: thin(),
+        };
+        let diagonal = 20.0 * std::f32::consts::FRAC_1_SQRT_2;
+        assert!(hits(&circle, 20.0, 0.0));
+        assert!(hits(&circle, 20.0 + diagonal, 20.0 + diagonal));
+        assert!(hits(&circle, 41.0, 20.0));
+        assert!(!hits(&circle, 20.0, 20.0));
+        assert!(!hits(&circle, 20.0, 3.0));
+        // The corner of the box is off the outline
+        assert!(!hits(&circle, 1.0, 1.0));
+
+        let flat = Ellipse {
+            rect: Rect::new(0.0, 0.0, 100.0, 20.0),
+            stroke: thin(),
+        };
+        assert!(hits(&flat, 50.0, 0.0));
+        assert!(hits(&flat, 0.0, 10.0));
+        assert!(!hits(&flat, 50.0, 10.0));
+
+        // Without height it is hit like a line
+        let collapsed = Ellipse {
+            rect: Rect::new(0.0, 10.0, 100.0, 10.0),
+            stroke: thin(),
+        };
+        assert!(hits(&collapsed, 50.0, 11.0));
+        assert!(!hits(&collapsed, 50.0, 12.0));
+    }
+
+    #[test]
+    fn freehand_is_hit_along_every_segment() {
+        let pen = Freehand {
+            points: vec![
+                Point::new(0.0, 0.0),
+                Point::new(10.0, 0.0),
+                Point::new(10.0, 10.0),
+            ],
+            stroke: thin(),
+        };
+        assert!(hits(&pen, 5.0, 1.0));
+        assert!(hits(&pen, 11.0, 5.0));
+        assert!(!hits(&pen, 5.0, 5.0));
+        assert_eq!(pen.bounds(), Rect::new(-1.0, -1.0, 11.0, 11.0));
+
+        let dot = Freehand {
+            points: vec![Point::new(5.0, 5.0)],
+            stroke: thin(),
+        };
+        assert!(hits(&dot, 5.0, 6.0));
+        assert!(!hits(&dot, 5.0, 6.5));
+        let empty = Freehand {
+            points: Vec::new(),
+            stroke: thin(),
+        };
+        assert!(!empty.hit_test(Point::new(0.0, 0.0), 100.0));
+    }
+
+    #[test]
+    fn shapes_round_trip_through_json() {
+        let shapes = shapes();
+        for shape in &shapes {
+            let json = serde_json::to_string(shape).unwrap();
+            assert_eq!(&serde_json::from_str::<Shape>(&json).unwrap(), shape);
+        }
+
+        let json = serde_json::to_value(&shapes[1]).unwrap();
+        assert_eq!(json["type"], "arrow");
+        assert_eq!(json["stroke"]["color"]["r"], 230);
+        let json = serde_json::to_value(&shapes[6]).unwrap();
+        assert_eq!(json["type"], "redaction");
+        assert_eq!(json["style"]["style"], "pixelate");
+
+        let layer = AnnotationLayer::from_shapes(shapes.clone());
+        let restored = AnnotationLayer::from_json(&layer.to_json().unwrap()).unwrap();
+        assert_eq!(restored.shapes(), shapes);
+        assert!(AnnotationLayer::from_json("[{\"type\": \"circle\"}]").is_err());
+    }
+
+    #[test]
+    fn annotations_keep_their_kind_and_move_together() {
+        let kinds: Vec<AnnotationKind> = shapes()
+            .into_iter()
+            .map(|s| s.into_annotation().kind())
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                AnnotationKind::Line,
+                AnnotationKind::Arrow,
+                AnnotationKind::Rectangle,
+                AnnotationKind::Ellipse,
+                AnnotationKind::Freehand,
+                AnnotationKind::Highlighter,
+                AnnotationKind::Redaction,
+            ]
+        );
+
+        for shape in shapes() {
+            let mut annotation = shape.into_annotation();
+            let before = annotation.bounds();
+            annotation.translate(5.0, -3.0);
+            assert_eq!(annotation.bounds(), before.translate(5.0, -3.0));
+        }
+    }
+
+    #[test]
+    fn tools_build_from_drag_points() {
+        let points = [
+            Point::new(40.0, 30.0),
+            Point::new(25.0, 5.0),
+            Point::new(10.0, 20.0),
+        ];
+        let rectangle = Tool::Rectangle.build(&points, thin()).unwrap();
+        assert_eq!(
+            rectangle.to_shape(),
+            Shape::Rectangle(Rectangle {
+                rect: Rect::new(10.0, 20.0, 40.0, 30.0),
+                stroke: thin(),
+            })
+        );
+        let Shape::Freehand(pen) = Tool::Pen.build(&points, thin()).unwrap().to_shape() else {
+            panic!("not a freehand shape");
+        };
+        assert_eq!(pen.points, points);
+        let Shape::Redaction(redaction) = Tool::Redact(RedactionStyle::blur())
+            .build(&points, thin())
+            .unwrap()
+            .to_shape()
+        else {
+            panic!("not a redaction");
+        };
+        assert_eq!(redaction.style, RedactionStyle::blur());
+        assert!(Tool::Line.build(&[], thin()).is_none());
+    }
+
+    #[test]
+    fn insert_and_remove_keep_the_selection_on_its_annotation() {
+        let mut layer = AnnotationLayer::from_shapes(shapes());
+        layer.select(Some(2));
+        let selected = layer.get(2).unwrap().to_shape();
+
+        // Below the selection shifts it up, above leaves it
+        layer.insert(0, shapes()[0].clone().into_annotation());
+        assert_eq!(layer.selected(), Some(3));
+        layer.insert(3, shapes()[1].clone().into_annotation());
+        assert_eq!(layer.selected(), Some(4));
+        layer.insert(5, shapes()[1].clone().into_annotation());
+        assert_eq!(layer.selected(), Some(4));
+        layer.insert(99, shapes()[1].clone().into_annotation());
+        assert_eq!(layer.len(), 11);
+        assert_eq!(layer.get(10).unwrap().kind(), AnnotationKind::Arrow);
+        assert_eq!(layer.get(4).unwrap().to_shape(), selected);
+
+        assert!(layer.remove(5).is_some());
+        assert_eq!(layer.selected(), Some(4));
+        assert!(layer.remove(0).is_some());
+        assert_eq!(layer.selected(), Some(3));
+        assert_eq!(layer.get(3).unwrap().to_shape(), selected);
+        assert!(layer.remove(99).is_none());
+        assert_eq!(layer.selected(), Some(3));
+
+        // Removing the selected annotation drops the selection
+        assert!(layer.remove(3).is_some());
+        assert_eq!(layer.selected(), None);
+        assert_eq!(layer.len(), 8);
+    }
+
+    #[test]
+    fn selection_follows_hits_and_stays_in_range() {
+        let mut layer = AnnotationLayer::new();
+        let stroke = Stroke::new(Color::RED, 2.0, 1.0);
+        layer.push(
+            Tool::Rectangle
+                .build(&[Point::new(0.0, 0.0), Point::new(50.0, 50.0)], stroke)
+                .unwrap(),
+        );
+        layer.push(
+            Tool::Line
+                .build(&[Point::new(0.0, 0.0), Point::new(50.0, 50.0)], stroke)
+                .unwrap(),
+        );
+
+        // The topmost annotation at a shared point wins
+        assert_eq!(layer.select_at(Point::new(0.0, 0.0)), Some(1));
+        assert_eq!(layer.select_at(Point::new(50.0, 25.0)), Some(0));
+        assert_eq!(layer.select_at(Point::new(10.0, 30.0)), None);
+
+        layer.select(Some(1));
+        assert_eq!(layer.clone().selected(), Some(1));
+        layer.replace(1, shapes()[0].clone().into_annotation());
+        assert_eq!(layer.selected(), Some(1));
+        layer.select(Some(2));
+        assert_eq!(layer.selected(), None);
+        layer.select(Some(0));
+        layer.clear();
+        assert_eq!(layer.selected(), None);
+        assert!(layer.is_empty());
+        assert_eq!(layer.bounds(), None);
+    }
+}
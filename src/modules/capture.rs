@@ -0,0 +1,514 @@
+use crate::geometry::Rect;
+use anyhow::{anyhow, Result};
+use image::RgbaImage;
+use std::path::{Path, PathBuf};
+
+/// Owned RGBA8 copy of a captured screen area.
+///
+/// `origin` is the position of the top-left pixel on the virtual screen and
+/// `scale` the DPI scale factor the pixels were captured at (1.0 = 96 DPI).
+/// Rows are `stride` bytes apart, which may be more than `width * 4`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub stride: usize,
+    pub origin: (i32, i32),
+    pub scale: f32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a fully transparent frame.
+    pub fn new(width: u32, height: u32) -> Self {
+        let stride = width as usize * 4;
+        Frame {
+            width,
+            height,
+            stride,
+            origin: (0, 0),
+            scale: 1.0,
+            pixels: vec![0; stride * height as usize],
+        }
+    }
+
+    pub fn from_rgba(width: u32, height: u32, stride: usize, pixels: Vec<u8>) -> Result<Self> {
+        if stride < width as usize * 4 {
+            return Err(anyhow!("Stride {} too small for width {}", stride, width));
+        }
+        if pixels.len() < stride * height as usize {
+            return Err(anyhow!(
+                "Pixel buffer holds {} bytes, expected {}",
+                pixels.len(),
+                stride * height as usize
+            ));
+        }
+
+        Ok(Frame {
+            width,
+            height,
+            stride,
+            origin: (0, 0),
+            scale: 1.0,
+            pixels,
+        })
+    }
+
+    pub fn from_image(image: RgbaImage) -> Self {
+        let (width, height) = image.dimensions();
+        Frame {
+            width,
+            height,
+            stride: width as usize * 4,
+            origin: (0, 0),
+            scale: 1.0,
+            pixels: image.into_raw(),
+        }
+    }
+
+    pub fn to_image(&self) -> RgbaImage {
+        let mut raw = Vec::with_capacity(self.width as usize * self.height as usize * 4);
+        for y in 0..self.height {
+            raw.extend_from_slice(self.row(y));
+        }
+        RgbaImage::from_raw(self.width, self.height, raw).expect("buffer matches dimensions")
+    }
+
+    pub fn with_origin(mut self, x: i32, y: i32) -> Self {
+        self.origin = (x, y);
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Returns the `width * 4` pixel bytes of row `y` without stride padding.
+    pub fn row(&self, y: u32) -> &[u8] {
+        let start = y as usize * self.stride;
+        &self.pixels[start..start + self.width as usize * 4]
+    }
+
+    pub fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let start = y as usize * self.stride;
+        let len = self.width as usize * 4;
+        &mut self.pixels[start..start + len]
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = y as usize * self.stride + x as usize * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    pub fn put_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = y as usize * self.stride + x as usize * 4;
+        self.pixels[i..i + 4].copy_from_slice(&rgba);
+    }
+
+    /// Copies a pixel rectangle into a new tightly packed frame.
+    ///
+    /// The origin of the result is moved along so it still describes where
+    /// the pixels were on the virtual screen.
+    pub fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Result<Frame> {
+        if x.checked_add(width).is_none_or(|r| r > self.width)
+            || y.checked_add(height).is_none_or(|b| b > self.height)
+        {
+            return Err(anyhow!(
+                "Crop {}x{}+{}+{} exceeds frame of {}x{}",
+                width,
+                height,
+                x,
+                y,
+                self.width,
+                self.height
+            ));
+        }
+
+        let mut pixels = Vec::with_capacity(width as usize * height as usize * 4);
+        for row in y..y + height {
+            let start = x as usize * 4;
+            pixels.extend_from_slice(&self.row(row)[start..start + width as usize * 4]);
+        }
+
+        Ok(Frame {
+            width,
+            height,
+            stride: width as usize * 4,
+            origin: (self.origin.0 + x as i32, self.origin.1 + y as i32),
+            scale: self.scale,
+            pixels,
+        })
+    }
+
+    /// Crops every pixel `rect` touches, clipped to the frame.
+    pub fn crop_rect(&self, rect: &Rect) -> Result<Frame> {
+        let left = rect.left.floor().clamp(0.0, self.width as f32) as u32;
+        let top = rect.top.floor().clamp(0.0, self.height as f32) as u32;
+        let right = rect.right.ceil().clamp(0.0, self.width as f32) as u32;
+        let bottom = rect.bottom.ceil().clamp(0.0, self.height as f32) as u32;
+
+        if right <= left || bottom <= top {
+            return Err(anyhow!("Selection {:?} lies outside the frame", rect));
+        }
+        self.crop(left, top, right - left, bottom - top)
+    }
+
+    /// Crops a selection made in logical units, relative to the top-left
+    /// corner of the frame, on a monitor with `scale` pixels per unit.
+    ///
+    /// Each edge is scaled and rounded to the nearest pixel boundary (see
+    /// [`Rect::round`]), then clipped to the frame. The result is what was
+    /// shown inside the selection, without an extra row or column from
+    /// rounding outwards.
+    pub fn crop_logical(&self, rect: &Rect, scale: f32) -> Result<Frame> {
+        if scale.is_nan() || scale <= 0.0 {
+            return Err(anyhow!("Invalid scale factor {}", scale));
+        }
+        let pixels = rect.scale(scale).round();
+        let clip = |v: f32, max: u32| v.clamp(0.0, max as f32) as u32;
+        let (left, right) = (
+            clip(pixels.left, self.width),
+            clip(pixels.right, self.width),
+        );
+        let (top, bottom) = (
+            clip(pixels.top, self.height),
+            clip(pixels.bottom, self.height),
+        );
+
+        if right <= left || bottom <= top {
+            return Err(anyhow!(
+                "Selection {:?} at scale {} covers no pixels of the frame",
+                rect,
+                scale
+            ));
+        }
+        self.crop(left, top, right - left, bottom - top)
+    }
+}
+
+/// Source of screen pixels.
+///
+/// The GDI implementation lives next to the Direct2D code; everything else
+/// only sees the returned [`Frame`] and therefore runs on any platform.
+pub trait CaptureBackend {
+    fn capture(&self) -> Result<Frame>;
+}
+
+/// Serves a PNG (or any format `image` can decode) as a fake desktop.
+pub struct FixtureBackend {
+    path: PathBuf,
+    origin: (i32, i32),
+    scale: f32,
+}
+
+impl FixtureBackend {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FixtureBackend {
+            path: path.as_ref().to_path_buf(),
+            origin: (0, 0),
+            scale: 1.0,
+        }
+    }
+
+    pub fn set_origin(&mut self, x: i32, y: i32) -> &mut Self {
+        self.origin = (x, y);
+        self
+    }
+
+    pub fn set_scale(&mut self, scale: f32) -> &mut Self {
+        self.scale = scale;
+        self
+    }
+}
+
+impl CaptureBackend for FixtureBackend {
+    fn capture(&self) -> Result<Frame> {
+        let image = image::open(&self.path)
+            .map_err(|e| anyhow!("Failed to load fixture {}: {}", self.path.display(), e))?
+            .to_rgba8();
+
+        Ok(Frame::from_image(image)
+            .with_origin(self.origin.0, self.origin.1)
+            .with_scale(self.scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame whose pixels hold their own position.
+    fn numbered(width: u32, height: u32) -> Frame {
+        let mut frame = Frame::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                frame.put_pixel(x, y, [x as u8, y as u8, 0, 255]);
+            }
+        }
+        frame
+    }
+
+    /// Logical edges that fall between pixels at 125% and 150%.
+    const COLUMNS: [f32; 6] = [3.3, 7.0, 10.5, 13.1, 21.7, 40.2];
+    const ROWS: [f32; 3] = [5.5, 9.9, 17.3];
+
+    /// The same edges in pixels, worked out by hand: `edge * scale`
+    /// rounded to the nearest whole pixel, halves up.
+    const PIXEL_COLUMNS: [(f32, [u32; 8]); 2] = [
+        (1.25, [0, 4, 9, 13, 16, 27, 50, 100]),
+        (1.5, [0, 5, 11, 16, 20, 33, 60, 100]),
+    ];
+    const PIXEL_ROWS: [(f32, [u32; 5]); 2] =
+        [(1.25, [0, 7, 12, 22, 60]), (1.5, [0, 8, 15, 26, 60])];
+
+    fn edges(cuts: &[f32], end: f32) -> Vec<f32> {
+        let mut edges = vec![0.0];
+        edges.extend_from_slice(cuts);
+        edges.push(end);
+        edges
+    }
+
+    #[test]
+    fn adjacent_logical_selections_tile_the_frame() {
+        for ((scale, pixel_columns), (_, pixel_rows)) in PIXEL_COLUMNS.into_iter().zip(PIXEL_ROWS) {
+            let frame = numbered(100, 60);
+            let columns = edges(&COLUMNS, 100.0 / scale);
+            let rows = edges(&ROWS, 60.0 / scale);
+
+            let mut covered = vec![0u32; 100 * 60];
+            for (j, row) in rows.windows(2).enumerate() {
+                for (i, column) in columns.windows(2).enumerate() {
+                    let rect = Rect::new(column[0], row[0], column[1], row[1]);
+                    let tile = frame.crop_logical(&rect, scale).unwrap();
+                    assert_eq!(
+                        tile.origin,
+                        (pixel_columns[i] as i32, pixel_rows[j] as i32),
+                        "tile {},{} at scale {}",
+                        i,
+                        j,
+                        scale
+                    );
+                    assert_eq!(
+                        (tile.width, tile.height),
+                        (
+                            pixel_columns[i + 1] - pixel_columns[i],
+                            pixel_rows[j + 1] - pixel_rows[j]
+                        ),
+                        "tile {},{} at scale {}",
+                        i,
+                        j,
+                        scale
+                    );
+
+                    let (left, top) = (tile.origin.0 as u32, tile.origin.1 as u32);
+                    for y in 0..tile.height {
+                        for x in 0..tile.width {
+                            assert_eq!(tile.pixel(x, y), frame.pixel(left + x, top + y));
+                            covered[((top + y) * 100 + left + x) as usize] += 1;
+                        }
+                    }
+                }
+            }
+            assert!(
+                covered.iter().all(|&count| count == 1),
+                "gap or overlap at scale {}",
+                scale
+            );
+        }
+    }
+
+    #[test]
+    fn crop_rect_takes_every_touched_pixel() {
+        let frame = numbered(20, 10);
+        let rect = Rect::new(3.3, 2.5, 6.6, 4.0).scale(1.5);
+        let touched = frame.crop_rect(&rect).unwrap();
+        assert_eq!(touched.origin, (4, 3));
+        assert_eq!((touched.width, touched.height), (6, 3));
+        // Rounding keeps only what mostly lies inside
+        let rounded = frame
+            .crop_logical(&Rect::new(3.3, 2.5, 6.6, 4.0), 1.5)
+            .unwrap();
+        assert_eq!(rounded.origin, (5, 4));
+        assert_eq!((rounded.width, rounded.height), (5, 2));
+    }
+
+    #[test]
+    fn logical_crops_clip_to_the_frame() {
+        let frame = numbered(30, 20);
+        let clipped = frame
+            .crop_logical(&Rect::new(-4.0, 10.0, 10.0, 40.0), 1.5)
+            .unwrap();
+        assert_eq!(clipped.origin, (0, 15));
+        assert_eq!((clipped.width, clipped.height), (15, 5));
+
+        let error = frame
+            .crop_logical(&Rect::new(30.0, 0.0, 40.0, 10.0), 1.25)
+            .unwrap_err();
+        assert!(error.to_string().contains("covers no pixels"));
+        let error = frame
+            .crop_logical(&Rect::new(0.0, 0.0, 10.0, 10.0), 0.0)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid scale factor 0");
+    }
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("capture_{}_{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&path);
+            std::fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn from_rgba_checks_stride_and_buffer_length() {
+        let error = Frame::from_rgba(4, 2, 12, vec![0; 32]).unwrap_err();
+        assert_eq!(error.to_string(), "Stride 12 too small for width 4");
+        let error = Frame::from_rgba(4, 2, 20, vec![0; 39]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Pixel buffer holds 39 bytes, expected 40"
+        );
+
+        // Padding bytes at the end of each row are skipped
+        let mut pixels: Vec<u8> = (0..40).collect();
+        pixels[16..20].copy_from_slice(&[0xee; 4]);
+        let frame = Frame::from_rgba(4, 2, 20, pixels).unwrap();
+        assert_eq!(frame.row(1), &(20..36).collect::<Vec<u8>>()[..]);
+        assert_eq!(frame.pixel(0, 1), [20, 21, 22, 23]);
+        assert!(!frame.to_image().into_raw().contains(&0xee));
+    }
+
+    #[test]
+    fn crop_rejects_out_of_bounds_and_overflowing_rects() {
+        let frame = numbered(10, 8);
+        let error = frame.crop(6, 2, 5, 3).unwrap_err();
+        assert_eq!(error.to_string(), "Crop 5x3+6+2 exceeds frame of 10x8");
+        assert!(frame.crop(0, 6, 1, 3).is_err());
+        // x + width wraps around u32 and must not pass the bounds check
+        assert!(frame.crop(u32::MAX, 0, 2, 1).is_err());
+        assert!(frame.crop(0, 1, 1, u32::MAX).is_err());
+
+        assert_eq!(frame.crop(0, 0, 10, 8).unwrap(), frame);
+        let empty = frame.crop(10, 8, 0, 0).unwrap();
+        assert_eq!((empty.width, empty.height), (0, 0));
+    }
+
+    #[test]
+    fn crop_moves_the_origin_along_and_keeps_the_scale() {
+        let frame = numbered(10, 8).with_origin(-1920, 40).with_scale(1.5);
+        let crop = frame.crop(3, 2, 4, 5).unwrap();
+        assert_eq!(crop.origin, (-1917, 42));
+        assert_eq!(crop.scale, 1.5);
+        assert_eq!(crop.stride, 16);
+        assert_eq!(crop.pixel(0, 0), [3, 2, 0, 255]);
+        assert_eq!(crop.pixel(3, 4), [6, 6, 0, 255]);
+
+        let nested = crop.crop(1, 1, 2, 2).unwrap();
+        assert_eq!(nested.origin, (-1916, 43));
+        assert_eq!(nested.pixel(0, 0), [4, 3, 0, 255]);
+    }
+
+    #[test]
+    fn fixture_backend_serves_a_png_as_the_desktop() {
+        let dir = TempDir::new("fixture");
+        let path = dir.0.join("desktop.png");
+        let mut image = numbered(6, 4).to_image();
+        image.put_pixel(5, 3, image::Rgba([1, 2, 3, 128]));
+        image.save(&path).unwrap();
+
+        let mut backend = FixtureBackend::new(&path);
+        backend.set_origin(-6, 100).set_scale(2.0);
+        let frame = backend.capture().unwrap();
+
+        assert_eq!((frame.width, frame.height), (6, 4));
+        assert_eq!(frame.origin, (-6, 100));
+        assert_eq!(frame.scale, 2.0);
+        assert_eq!(frame.pixel(2, 1), [2, 1, 0, 255]);
+        assert_eq!(frame.pixel(5, 3), [1, 2, 3, 128]);
+
+        let missing = FixtureBackend::new(dir.0.join("missing.png")).capture();
+        assert!(missing
+            .unwrap_err()
+            .to_string()
+            .starts_with("Failed to load fixture"));
+    }
+
+    /// xorshift32, so the property test is reproducible without extra crates.
+    struct Rng(u32);
+
+    impl Rng {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        /// Uniform value in `low..high`.
+        fn range(&mut self, low: f32, high: f32) -> f32 {
+            low + (self.next() as f32 / u32::MAX as f32) * (high - low)
+        }
+    }
+
+    #[test]
+    fn random_logical_crops_stay_inside_and_match_the_rounded_edges() {
+        let mut rng = Rng(0x9e37_79b9);
+        let frame = numbered(97, 61).with_origin(-40, 25);
+
+        for _ in 0..2000 {
+            let scale = rng.range(1.0, 3.0);
+            // Selections may start or end outside the frame on either side
+            let (x0, x1) = (
+                rng.range(-10.0, 110.0) / scale,
+                rng.range(-10.0, 110.0) / scale,
+            );
+            let (y0, y1) = (
+                rng.range(-10.0, 70.0) / scale,
+                rng.range(-10.0, 70.0) / scale,
+            );
+            let rect = Rect::new(x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1));
+
+            let pixel = |edge: f32, max: u32| ((edge * scale).round().max(0.0) as u32).min(max);
+            let (left, right) = (pixel(rect.left, 97), pixel(rect.right, 97));
+            let (top, bottom) = (pixel(rect.top, 61), pixel(rect.bottom, 61));
+
+            let result = frame.crop_logical(&rect, scale);
+            if right <= left || bottom <= top {
+                assert!(result.is_err(), "{:?} at scale {}", rect, scale);
+                continue;
+            }
+            let crop = result.unwrap_or_else(|e| panic!("{:?} at scale {}: {}", rect, scale, e));
+
+            assert_eq!(
+                (crop.width, crop.height),
+                (right - left, bottom - top),
+                "{:?} at scale {}",
+                rect,
+                scale
+            );
+            assert_eq!(crop.origin, (-40 + left as i32, 25 + top as i32));
+            assert!(left + crop.width <= frame.width && top + crop.height <= frame.height);
+            assert_eq!(crop.pixel(0, 0), [left as u8, top as u8, 0, 255]);
+            assert_eq!(
+                crop.pixel(crop.width - 1, crop.height - 1),
+                [(right - 1) as u8, (bottom - 1) as u8, 0, 255]
+            );
+        }
+    }
+}
@@ -0,0 +1,800 @@
+use crate::annotation::AnnotationLayer;
+use crate::capture::{CaptureBackend, FixtureBackend, Frame};
+use crate::config::{Config, ConfigLoader};
+use crate::export::{ExportFormat, ExportOptions, Exporter};
+use crate::geometry::Rect;
+use crate::ipc::{Client, IpcCommand, Request};
+use crate::monitor::{CaptureRegion, MonitorLayout, Viewport};
+use crate::ocr::{default_engine, recognize_region, FixtureEngine, OcrDocument, OcrEngine};
+use crate::preprocess::{Pipeline, PreprocessedEngine};
+use crate::raster::flatten;
+use crate::recording::{load_session, Pace, Replayer};
+use anyhow::anyhow;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const USAGE: &str = "\
+Usage: ocr [OPTIONS] [COMMAND]
+
+Without a command the overlay opens for an interactive capture.
+
+Commands:
+  capture [--region X,Y,W,H] [--monitor N] [--logical] [--delay SECS]
+          [-o PATH] [--format FORMAT] [--quality 1-100]
+      Saves part of the screen. The region is in virtual-screen pixels,
+      relative to the monitor if one is given, or in logical units with
+      --logical. PATH `-` writes the image to standard output.
+  ocr [IMAGE] [--region X,Y,W,H] [--format text|json|hocr|alto] [-o PATH] [--raw]
+      Recognizes the text of an image file, or of the screen without IMAGE.
+      --raw skips the preprocessing for screen text.
+  export IMAGE [--region X,Y,W,H] [--annotations FILE] [-o PATH]
+         [--format FORMAT] [--quality 1-100]
+      Crops an image, burns in annotations and converts it.
+  monitors [--json]
+      Lists the monitors with their index for --monitor.
+  config [--path]
+      Prints the effective configuration, or where it is read from.
+  replay FILE [--output-dir DIR] [--realtime] [--json]
+      Replays a session recorded in the overlay against the screen or
+      --fixture and prints what it produced. --output-dir saves files there
+      instead of their recorded paths, --realtime keeps the recorded pace.
+  send COMMAND [ARGS]
+      Sends a command with its arguments (JSON) to the running overlay and
+      prints the result, e.g. send capture_region
+      '{\"rect\":{\"left\":0,\"top\":0,\"right\":800,\"bottom\":600}}'.
+  help
+      Prints this text.
+
+Options:
+  --config PATH        Configuration file to read
+  --set KEY=VALUE      Overrides a setting, e.g. --set overlay.opacity=0.7
+  --fixture IMAGE      Captures this image instead of the screen
+  --layout FILE        Monitor layout (JSON) for --fixture, one monitor
+                       covering the image by default
+  --ocr-fixture FILE   Answers OCR with this document (JSON)
+
+Formats: png, jpg, webp, bmp, tiff; by default from the file extension.
+
+Exit codes: 0 success, 1 failure, 2 usage, 3 configuration, 4 capture or
+input image, 5 OCR, 6 no text recognized, 7 writing the output.
+";
+
+/// Process exit status of a command-line run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitCode {
+    Success = 0,
+    Failure = 1,
+    /// Unknown command or option, or an invalid value.
+    Usage = 2,
+    Config = 3,
+    /// The screen or an input image could not be read, or the region lies
+    /// outside of it.
+    Capture = 4,
+    Ocr = 5,
+    /// OCR ran but found nothing.
+    NoText = 6,
+    Output = 7,
+}
+
+impl ExitCode {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+}
+
+/// An error together with the exit status it ends the run with.
+#[derive(Debug)]
+pub struct CliError {
+    pub exit: ExitCode,
+    pub error: anyhow::Error,
+}
+
+impl CliError {
+    pub fn new(exit: ExitCode, error: anyhow::Error) -> Self {
+        CliError { exit, error }
+    }
+
+    fn usage(message: String) -> Self {
+        CliError::new(ExitCode::Usage, anyhow!(message))
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+/// Attaches the exit status to the errors of a step.
+trait OrExit<T> {
+    fn or_exit(self, exit: ExitCode) -> Result<T, CliError>;
+}
+
+impl<T, E: Into<anyhow::Error>> OrExit<T> for Result<T, E> {
+    fn or_exit(self, exit: ExitCode) -> Result<T, CliError> {
+        self.map_err(|e| CliError::new(exit, e.into()))
+    }
+}
+
+/// How `ocr` writes what it recognized.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextFormat {
+    #[default]
+    Text,
+    Json,
+    Hocr,
+    Alto,
+}
+
+impl TextFormat {
+    fn render(self, document: &OcrDocument) -> anyhow::Result<String> {
+        match self {
+            TextFormat::Text => Ok(document.text() + "\n"),
+            TextFormat::Json => document.to_json(),
+            TextFormat::Hocr => Ok(document.to_hocr()),
+            TextFormat::Alto => Ok(document.to_alto()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CaptureArgs {
+    pub region: Option<Rect>,
+    pub monitor: Option<usize>,
+    pub logical: bool,
+    pub delay: Option<Duration>,
+    pub output: Option<PathBuf>,
+    pub format: Option<ExportFormat>,
+    pub quality: Option<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OcrArgs {
+    /// Recognizes the screen if unset.
+    pub image: Option<PathBuf>,
+    pub region: Option<Rect>,
+    pub format: TextFormat,
+    pub output: Option<PathBuf>,
+    pub raw: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReplayArgs {
+    pub session: PathBuf,
+    pub output_dir: Option<PathBuf>,
+    pub realtime: bool,
+    pub json: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExportArgs {
+    pub image: PathBuf,
+    pub region: Option<Rect>,
+    pub annotations: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub format: Option<ExportFormat>,
+    pub quality: Option<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subcommand {
+    Capture(CaptureArgs),
+    Ocr(OcrArgs),
+    Export(ExportArgs),
+    Monitors {
+        json: bool,
+    },
+    Config {
+        path: bool,
+    },
+    Replay(ReplayArgs),
+    /// A command for the running overlay, see [`crate::ipc::IpcCommand`].
+    Send {
+        command: IpcCommand,
+    },
+    Help,
+}
+
+/// Parsed command line.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cli {
+    pub config: Option<PathBuf>,
+    /// `section.key=value` overrides, in order.
+    pub settings: Vec<String>,
+    pub fixture: Option<PathBuf>,
+    pub layout: Option<PathBuf>,
+    pub ocr_fixture: Option<PathBuf>,
+    /// `None` opens the interactive overlay.
+    pub command: Option<Subcommand>,
+}
+
+/// Pulls the value of option `name` off `args`.
+fn value(args: &mut impl Iterator<Item = String>, name: &str) -> Result<String, CliError> {
+    args.next()
+        .ok_or_else(|| CliError::usage(format!("{} needs a value", name)))
+}
+
+fn parse_number<T: std::str::FromStr>(text: &str, name: &str) -> Result<T, CliError> {
+    text.trim()
+        .parse()
+        .map_err(|_| CliError::usage(format!("{}: `{}` is not a valid number", name, text)))
+}
+
+/// Parses `X,Y,W,H` into a rectangle.
+pub fn parse_region(text: &str) -> Result<Rect, CliError> {
+    let values = text
+        .split(',')
+        .map(|v| parse_number::<f32>(v, "--region"))
+        .collect::<Result<Vec<_>, _>>()?;
+    match values[..] {
+        [x, y, w, h] if w > 0.0 && h > 0.0 => Ok(Rect::new(x, y, x + w, y + h)),
+        [_, _, _, _] => Err(CliError::usage(format!(
+            "--region {}: width and height must be positive",
+            text
+        ))),
+        _ => Err(CliError::usage(format!(
+            "--region {}: expected X,Y,W,H",
+            text
+        ))),
+    }
+}
+
+fn parse_format(text: &str) -> Result<ExportFormat, CliError> {
+    Exporter::new()
+        .format_for_extension(text.trim_start_matches('.'))
+        .map_err(|_| CliError::usage(format!("Unknown image format `{}`", text)))
+}
+
+fn parse_text_format(text: &str) -> Result<TextFormat, CliError> {
+    match text.to_ascii_lowercase().as_str() {
+        "text" | "txt" => Ok(TextFormat::Text),
+        "json" => Ok(TextFormat::Json),
+        "hocr" => Ok(TextFormat::Hocr),
+        "alto" => Ok(TextFormat::Alto),
+        _ => Err(CliError::usage(format!("Unknown text format `{}`", text))),
+    }
+}
+
+fn parse_quality(text: &str) -> Result<u8, CliError> {
+    match parse_number::<u8>(text, "--quality")? {
+        quality @ 1..=100 => Ok(quality),
+        _ => Err(CliError::usage(format!(
+            "--quality {}: expected 1 to 100",
+            text
+        ))),
+    }
+}
+
+fn unexpected(command: &str, arg: &str) -> CliError {
+    CliError::usage(format!("{}: unexpected argument `{}`", command, arg))
+}
+
+impl Cli {
+    /// Parses the arguments after the program name. Global options may
+    /// stand before or after the command.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Cli, CliError> {
+        let mut cli = Cli::default();
+        let mut command = None;
+        let mut rest = Vec::new();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => cli.config = Some(value(&mut args, &arg)?.into()),
+                "--set" => cli.settings.push(value(&mut args, &arg)?),
+                "--fixture" => cli.fixture = Some(value(&mut args, &arg)?.into()),
+                "--layout" => cli.layout = Some(value(&mut args, &arg)?.into()),
+                "--ocr-fixture" => cli.ocr_fixture = Some(value(&mut args, &arg)?.into()),
+                "-h" | "--help" => command = Some("help".to_string()),
+                _ if command.is_none() => {
+                    if arg.starts_with('-') {
+                        return Err(CliError::usage(format!("Unknown option `{}`", arg)));
+                    }
+                    command = Some(arg);
+                }
+                _ => rest.push(arg),
+            }
+        }
+
+        cli.command = match command.as_deref() {
+            None => None,
+            Some("capture") => Some(Subcommand::Capture(parse_capture(rest)?)),
+            Some("ocr") => Some(Subcommand::Ocr(parse_ocr(rest)?)),
+            Some("export") => Some(Subcommand::Export(parse_export(rest)?)),
+            Some("monitors") => Some(Subcommand::Monitors {
+                json: parse_flags(rest, "monitors", "--json")?,
+            }),
+            Some("config") => Some(Subcommand::Config {
+                path: parse_flags(rest, "config", "--path")?,
+            }),
+            Some("replay") => Some(Subcommand::Replay(parse_replay(rest)?)),
+            Some("send") => Some(Subcommand::Send {
+                command: parse_send(rest)?,
+            }),
+            Some("help") => Some(Subcommand::Help),
+            Some(other) => {
+                return Err(CliError::usage(format!(
+                    "Unknown command `{}`, see `help`",
+                    other
+                )))
+            }
+        };
+        Ok(cli)
+    }
+
+    /// Reads the configuration file and the environment with `--config`
+    /// and `--set` applied.
+    pub fn config_loader(&self) -> Result<ConfigLoader, CliError> {
+        let mut loader = ConfigLoader::new();
+        if let Some(path) = &self.config {
+            loader.set_path(path);
+        }
+        for setting in &self.settings {
+            loader.set_arg(setting).or_exit(ExitCode::Usage)?;
+        }
+        Ok(loader)
+    }
+
+    pub fn load_config(&self) -> Result<Config, CliError> {
+        self.config_loader()?.load().or_exit(ExitCode::Config)
+    }
+}
+
+/// Whether the only allowed `flag` was given.
+fn parse_flags(args: Vec<String>, command: &str, flag: &str) -> Result<bool, CliError> {
+    let mut set = false;
+    for arg in args {
+        if arg != flag {
+            return Err(unexpected(command, &arg));
+        }
+        set = true;
+    }
+    Ok(set)
+}
+
+fn parse_send(args: Vec<String>) -> Result<IpcCommand, CliError> {
+    let (command, args) = match &args[..] {
+        [command] => (command, None),
+        [command, args] => (
+            command,
+            Some(
+                serde_json::from_str::<serde_json::Value>(args).map_err(|e| {
+                    CliError::usage(format!("send {}: invalid arguments: {}", command, e))
+                })?,
+            ),
+        ),
+        [] => return Err(CliError::usage("send needs a command".to_string())),
+        [_, _, extra, ..] => return Err(unexpected("send", extra)),
+    };
+    let line = serde_json::json!({ "command": command, "args": args });
+    Request::parse(&line.to_string())
+        .map(|request| request.command)
+        .map_err(|e| CliError::usage(format!("send: {}", e)))
+}
+
+fn parse_capture(args: Vec<String>) -> Result<CaptureArgs, CliError> {
+    let mut parsed = CaptureArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--region" => parsed.region = Some(parse_region(&value(&mut args, &arg)?)?),
+            "--monitor" => parsed.monitor = Some(parse_number(&value(&mut args, &arg)?, &arg)?),
+            "--logical" => parsed.logical = true,
+            "--delay" => {
+                let seconds: f32 = parse_number(&value(&mut args, &arg)?, &arg)?;
+                parsed.delay = Some(
+                    Duration::try_from_secs_f32(seconds)
+                        .map_err(|_| CliError::usage(format!("--delay {}: invalid", seconds)))?,
+                );
+            }
+            "-o" | "--output" => parsed.output = Some(value(&mut args, &arg)?.into()),
+            "--format" => parsed.format = Some(parse_format(&value(&mut args, &arg)?)?),
+            "--quality" => parsed.quality = Some(parse_quality(&value(&mut args, &arg)?)?),
+            _ => return Err(unexpected("capture", &arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_ocr(args: Vec<String>) -> Result<OcrArgs, CliError> {
+    let mut parsed = OcrArgs::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--region" => parsed.region = Some(parse_region(&value(&mut args, &arg)?)?),
+            "--format" => parsed.format = parse_text_format(&value(&mut args, &arg)?)?,
+            "-o" | "--output" => parsed.output = Some(value(&mut args, &arg)?.into()),
+            "--raw" => parsed.raw = true,
+            _ if !arg.starts_with('-') && parsed.image.is_none() => parsed.image = Some(arg.into()),
+            _ => return Err(unexpected("ocr", &arg)),
+        }
+    }
+    Ok(parsed)
+}
+
+fn parse_export(args: Vec<String>) -> Result<ExportArgs, CliError> {
+    let mut parsed = ExportArgs::default();
+    let mut image = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--region" => parsed.region = Some(parse_region(&value(&mut args, &arg)?)?),
+            "--annotations" => parsed.annotations = Some(value(&mut args, &arg)?.into()),
+            "-o" | "--output" => parsed.output = Some(value(&mut args, &arg)?.into()),
+            "--format" => parsed.format = Some(parse_format(&value(&mut args, &arg)?)?),
+            "--quality" => parsed.quality = Some(parse_quality(&value(&mut args, &arg)?)?),
+            _ if !arg.starts_with('-') && image.is_none() => image = Some(PathBuf::from(arg)),
+            _ => return Err(unexpected("export", &arg)),
+        }
+    }
+    parsed.image = image.ok_or_else(|| CliError::usage("export needs an image".to_string()))?;
+    Ok(parsed)
+}
+
+fn parse_replay(args: Vec<String>) -> Result<ReplayArgs, CliError> {
+    let mut parsed = ReplayArgs::default();
+    let mut session = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output-dir" => parsed.output_dir = Some(value(&mut args, &arg)?.into()),
+            "--realtime" => parsed.realtime = true,
+            "--json" => parsed.json = true,
+            _ if !arg.starts_with('-') && session.is_none() => session = Some(PathBuf::from(arg)),
+            _ => return Err(unexpected("replay", &arg)),
+        }
+    }
+    parsed.session =
+        session.ok_or_else(|| CliError::usage("replay needs a recorded session".to_string()))?;
+    Ok(parsed)
+}
+
+/// Stands in for the screen where there is none to capture.
+#[cfg(not(windows))]
+struct NoScreen;
+
+#[cfg(not(windows))]
+impl CaptureBackend for NoScreen {
+    fn capture(&self) -> anyhow::Result<Frame> {
+        Err(anyhow!(
+            "Capturing the screen is only supported on Windows, use --fixture"
+        ))
+    }
+}
+
+/// Monitors of a captured frame.
+pub type LayoutSource = Box<dyn Fn(&Frame) -> anyhow::Result<MonitorLayout>>;
+
+/// Where a run gets its pixels, monitors and text recognition from.
+pub struct Platform {
+    pub backend: Box<dyn CaptureBackend>,
+    /// Monitors of a frame captured by `backend`.
+    pub layout: LayoutSource,
+    pub engine: Box<dyn Fn() -> Box<dyn OcrEngine>>,
+}
+
+impl Platform {
+    /// The real screen, its monitors and the default OCR engine.
+    pub fn screen() -> Self {
+        #[cfg(windows)]
+        let (backend, layout): (Box<dyn CaptureBackend>, _) = (
+            Box::new(crate::direct2d::GdiBackend),
+            Box::new(|_: &Frame| crate::win_fact::monitor_layout()) as LayoutSource,
+        );
+        #[cfg(not(windows))]
+        let (backend, layout): (Box<dyn CaptureBackend>, _) = (
+            Box::new(NoScreen),
+            Box::new(|_: &Frame| Err(anyhow!("No monitors without a screen"))) as LayoutSource,
+        );
+        Platform {
+            backend,
+            layout,
+            engine: Box::new(default_engine),
+        }
+    }
+
+    /// The screen with the fixtures of `cli` swapped in.
+    pub fn for_cli(cli: &Cli) -> Result<Self, CliError> {
+        let mut platform = Platform::screen();
+        if let Some(path) = &cli.fixture {
+            platform.backend = Box::new(FixtureBackend::new(path));
+            platform.layout = match &cli.layout {
+                Some(path) => {
+                    let json = fs::read_to_string(path).or_exit(ExitCode::Usage)?;
+                    let layout: MonitorLayout = serde_json::from_str(&json)
+                        .map_err(|e| anyhow!("Invalid layout {}: {}", path.display(), e))
+                        .or_exit(ExitCode::Usage)?;
+                    Box::new(move |_: &Frame| Ok(layout.clone()))
+                }
+                None => Box::new(|frame: &Frame| {
+                    Ok(MonitorLayout::single(
+                        frame.width,
+                        frame.height,
+                        frame.scale,
+                    ))
+                }),
+            };
+        }
+        if let Some(path) = &cli.ocr_fixture {
+            let engine = FixtureEngine::load(path).or_exit(ExitCode::Usage)?;
+            platform.engine = Box::new(move || Box::new(engine.clone()));
+        }
+        Ok(platform)
+    }
+}
+
+/// Runs the command of `cli`, which must have one, writing results to
+/// `out`.
+pub fn run(cli: &Cli, out: &mut dyn Write) -> Result<(), CliError> {
+    let platform = Platform::for_cli(cli)?;
+    run_with(cli, &platform, out)
+}
+
+pub fn run_with(cli: &Cli, platform: &Platform, out: &mut dyn Write) -> Result<(), CliError> {
+    let Some(command) = &cli.command else {
+        return Err(CliError::usage("No command given".to_string()));
+    };
+    match command {
+        Subcommand::Replay(args) => replay(args, &cli.load_config()?, platform, out),
+        Subcommand::Send { command } => {
+            let address = cli.load_config()?.ipc_address();
+            let result = Client::connect(&address)
+                .and_then(|mut client| client.request(command.clone()))
+                .or_exit(ExitCode::Failure)?;
+            writeln!(out, "{}", result).or_exit(ExitCode::Output)
+        }
+        Subcommand::Help => out.write_all(USAGE.as_bytes()).or_exit(ExitCode::Output),
+        Subcommand::Config { path: true } => {
+            let path = cli
+                .config_loader()?
+                .path()
+                .ok_or_else(|| anyhow!("No configuration directory"))
+                .or_exit(ExitCode::Config)?;
+            writeln!(out, "{}", path.display()).or_exit(ExitCode::Output)
+        }
+        Subcommand::Config { path: false } => {
+            let toml = cli.load_config()?.to_toml().or_exit(ExitCode::Failure)?;
+            out.write_all(toml.as_bytes()).or_exit(ExitCode::Output)
+        }
+        Subcommand::Monitors { json } => {
+            let frame = platform.backend.capture().or_exit(ExitCode::Capture)?;
+            let layout = (platform.layout)(&frame).or_exit(ExitCode::Capture)?;
+            list_monitors(&layout, *json, out)
+        }
+        Subcommand::Capture(args) => capture(args, &cli.load_config()?, platform, out),
+        Subcommand::Ocr(args) => recognize(args, platform, out),
+        Subcommand::Export(args) => export(args, &cli.load_config()?, out),
+    }
+}
+
+fn list_monitors(layout: &MonitorLayout, json: bool, out: &mut dyn Write) -> Result<(), CliError> {
+    if json {
+        let json = serde_json::to_string_pretty(layout).or_exit(ExitCode::Failure)?;
+        return writeln!(out, "{}", json).or_exit(ExitCode::Output);
+    }
+    for (index, monitor) in layout.monitors().iter().enumerate() {
+        writeln!(
+            out,
+            "{}\t{}\t{}x{}+{}+{}\t{}{}",
+            index,
+            monitor.name,
+            monitor.rect.width(),
+            monitor.rect.height(),
+            monitor.rect.left,
+            monitor.rect.top,
+            monitor.scale,
+            if monitor.primary { "\tprimary" } else { "" }
+        )
+        .or_exit(ExitCode::Output)?;
+    }
+    Ok(())
+}
+
+fn capture(
+    args: &CaptureArgs,
+    config: &Config,
+    platform: &Platform,
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    if let Some(delay) = args.delay {
+        std::thread::sleep(delay);
+    }
+    let screen = platform.backend.capture().or_exit(ExitCode::Capture)?;
+    let layout = (platform.layout)(&screen).or_exit(ExitCode::Capture)?;
+
+    let monitor = match args.monitor {
+        Some(index) => Some(
+            layout
+                .monitors()
+                .get(index)
+                .ok_or_else(|| {
+                    CliError::usage(format!(
+                        "--monitor {}: there are {} monitors",
+                        index,
+                        layout.monitors().len()
+                    ))
+                })?
+                .clone(),
+        ),
+        None => None,
+    };
+    let frame = match (args.region, args.monitor) {
+        (None, None) => layout.extract(&screen, CaptureRegion::AllMonitors),
+        (None, Some(index)) => layout.extract(&screen, CaptureRegion::Monitor { index }),
+        (Some(region), _) => {
+            // Logical rectangles of monitors keep their physical top-left
+            let region = match &monitor {
+                Some(monitor) => region.translate(monitor.rect.left, monitor.rect.top),
+                None => region,
+            };
+            let region = if args.logical {
+                layout.rect_to_physical(&region)
+            } else {
+                region
+            };
+            layout.extract(&screen, CaptureRegion::Rect { rect: region })
+        }
+    }
+    .or_exit(ExitCode::Capture)?;
+
+    let target = args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(config, args.format));
+    save(&frame, &target, args.format, args.quality, out)
+}
+
+fn replay(
+    args: &ReplayArgs,
+    config: &Config,
+    platform: &Platform,
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    let events = load_session(&args.session).or_exit(ExitCode::Usage)?;
+    let mut replayer = Replayer::new(platform, config);
+    if let Some(directory) = &args.output_dir {
+        replayer.set_output_dir(directory);
+    }
+    if args.realtime {
+        replayer.set_pace(Pace::Recorded);
+    }
+    let outputs = replayer.replay(&events).or_exit(ExitCode::Failure)?;
+    for output in outputs {
+        if args.json {
+            let json = serde_json::to_string(&output).or_exit(ExitCode::Failure)?;
+            writeln!(out, "{}", json)
+        } else {
+            writeln!(out, "{}", output)
+        }
+        .or_exit(ExitCode::Output)?;
+    }
+    Ok(())
+}
+
+/// A time-stamped file in the configured capture directory.
+fn default_output(config: &Config, format: Option<ExportFormat>) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let extension = Exporter::new()
+        .extension_for(format.unwrap_or(ExportFormat::Png))
+        .unwrap_or("png");
+    config.output.directory.join(format!(
+        "{}-{}.{}",
+        config.output.prefix, seconds, extension
+    ))
+}
+
+fn export_options(format: ExportFormat, quality: Option<u8>) -> Result<ExportOptions, CliError> {
+    match (format, quality) {
+        (_, None) => Ok(ExportOptions::default_for(format)),
+        (ExportFormat::Jpeg, Some(quality)) => Ok(ExportOptions::Jpeg { quality }),
+        (ExportFormat::WebP, Some(quality)) => Ok(ExportOptions::WebP {
+            lossless: false,
+            quality: quality as f32,
+        }),
+        _ => Err(CliError::usage(
+            "--quality only applies to jpg and webp".to_string(),
+        )),
+    }
+}
+
+/// Writes `frame` to `target`, or to `out` if it is `-`, and prints where
+/// it went.
+fn save(
+    frame: &Frame,
+    target: &Path,
+    format: Option<ExportFormat>,
+    quality: Option<u8>,
+    out: &mut dyn Write,
+) -> Result<(), CliError> {
+    let exporter = Exporter::new();
+    let to_stdout = target == Path::new("-");
+    let format = match format {
+        Some(format) => format,
+        None if to_stdout => ExportFormat::Png,
+        None => exporter.format_for_path(target).or_exit(ExitCode::Usage)?,
+    };
+    let bytes = exporter
+        .encode(frame, &export_options(format, quality)?)
+        .or_exit(ExitCode::Output)?;
+
+    if to_stdout {
+        return out.write_all(&bytes).or_exit(ExitCode::Output);
+    }
+    if let Some(directory) = target.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(directory).or_exit(ExitCode::Output)?;
+    }
+    fs::write(target, bytes)
+        .map_err(|e| anyhow!("Failed to write {}: {}", target.display(), e))
+        .or_exit(ExitCode::Output)?;
+    writeln!(out, "{}", target.display()).or_exit(ExitCode::Output)
+}
+
+fn load_image(path: &Path) -> Result<Frame, CliError> {
+    image::open(path)
+        .map(|image| Frame::from_image(image.to_rgba8()))
+        .map_err(|e| anyhow!("Failed to load {}: {}", path.display(), e))
+        .or_exit(ExitCode::Capture)
+}
+
+fn recognize(args: &OcrArgs, platform: &Platform, out: &mut dyn Write) -> Result<(), CliError> {
+    let frame = match &args.image {
+        Some(path) => load_image(path)?,
+        None => platform.backend.capture().or_exit(ExitCode::Capture)?,
+    };
+    let region = args
+        .region
+        .map(|rect| Viewport::for_frame(&frame).rect_to_client(&rect));
+    if let Some(rect) = &region {
+        // Reported as a capture problem rather than an OCR failure
+        frame.crop_rect(rect).or_exit(ExitCode::Capture)?;
+    }
+
+    let engine = (platform.engine)();
+    let engine: Box<dyn OcrEngine> = if args.raw {
+        engine
+    } else {
+        Box::new(PreprocessedEngine::new(Pipeline::for_screen_text(), engine))
+    };
+    let document = recognize_region(engine.as_ref(), &frame, region).or_exit(ExitCode::Ocr)?;
+    let text = args.format.render(&document).or_exit(ExitCode::Failure)?;
+
+    match &args.output {
+        Some(path) if path != Path::new("-") => fs::write(path, text)
+            .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+            .or_exit(ExitCode::Output)?,
+        _ => out.write_all(text.as_bytes()).or_exit(ExitCode::Output)?,
+    }
+    if document.text().trim().is_empty() {
+        return Err(CliError::new(
+            ExitCode::NoText,
+            anyhow!("No text recognized"),
+        ));
+    }
+    Ok(())
+}
+
+fn export(args: &ExportArgs, config: &Config, out: &mut dyn Write) -> Result<(), CliError> {
+    let mut frame = load_image(&args.image)?;
+    if let Some(path) = &args.annotations {
+        let annotations = fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| AnnotationLayer::from_json(&json))
+            .map_err(|e| anyhow!("Failed to load annotations {}: {}", path.display(), e))
+            .or_exit(ExitCode::Capture)?;
+        frame = flatten(&frame, &annotations);
+    }
+    if let Some(rect) = &args.region {
+        frame = frame.crop_rect(rect).or_exit(ExitCode::Capture)?;
+    }
+
+    let target = args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output(config, args.format));
+    save(&frame, &target, args.format, args.quality, out)
+}
@@ -0,0 +1,497 @@
+use crate::capture::Frame;
+use crate::export::{ExportFormat, ExportOptions, Exporter};
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt;
+
+/// Resolution of a frame captured at scale 1.0.
+const BASE_DPI: f32 = 96.0;
+const INCHES_PER_METER: f32 = 39.3701;
+
+const BITMAPINFOHEADER_SIZE: u32 = 40;
+const BITMAPV5HEADER_SIZE: u32 = 124;
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+/// `'sRGB'` as the `LOGCOLORSPACE` type of a V5 header.
+const LCS_SRGB: u32 = 0x7352_4742;
+const LCS_GM_IMAGES: u32 = 4;
+
+/// Byte offsets in the CF_HTML header are written with this many digits,
+/// so the header length does not depend on them.
+const HTML_OFFSET_DIGITS: usize = 10;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Characters of Windows-1252 from 0x80 to 0x9F; `'\0'` where unassigned.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\0', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\0', '\u{017D}', '\0', '\0',
+    '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}', '\u{02DC}',
+    '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\0', '\u{017E}', '\u{0178}',
+];
+
+/// A representation of copied content other programs can paste.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardFormat {
+    /// Bottom-up 32-bit bitmap with a `BITMAPV5HEADER` and straight alpha.
+    DibV5,
+    /// Bottom-up 32-bit bitmap with a `BITMAPINFOHEADER`; most programs
+    /// ignore its alpha byte.
+    Dib,
+    Png,
+    /// CF_HTML fragment with the image as a PNG data URI.
+    Html,
+    /// NUL-terminated UTF-16LE with CRLF line breaks.
+    UnicodeText,
+    /// NUL-terminated Windows-1252 with CRLF line breaks; other characters
+    /// become `?`.
+    Text,
+}
+
+impl ClipboardFormat {
+    /// Name of formats that are registered by name rather than predefined.
+    pub fn registered_name(&self) -> Option<&'static str> {
+        match self {
+            ClipboardFormat::Png => Some("PNG"),
+            ClipboardFormat::Html => Some("HTML Format"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClipboardFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ClipboardFormat::DibV5 => "DIBV5",
+            ClipboardFormat::Dib => "DIB",
+            ClipboardFormat::Png => "PNG",
+            ClipboardFormat::Html => "HTML",
+            ClipboardFormat::UnicodeText => "Unicode text",
+            ClipboardFormat::Text => "text",
+        })
+    }
+}
+
+/// Encoded bytes in one format.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClipboardData {
+    pub format: ClipboardFormat,
+    pub bytes: Vec<u8>,
+}
+
+impl ClipboardData {
+    fn new(format: ClipboardFormat, bytes: Vec<u8>) -> Self {
+        ClipboardData { format, bytes }
+    }
+}
+
+/// `frame` in every image format, richest first.
+pub fn image_contents(frame: &Frame) -> Result<Vec<ClipboardData>> {
+    let png = encode_png(frame)?;
+    let html = encode_html(frame, &png);
+    Ok(vec![
+        ClipboardData::new(ClipboardFormat::DibV5, encode_dibv5(frame)),
+        ClipboardData::new(ClipboardFormat::Dib, encode_dib(frame)),
+        ClipboardData::new(ClipboardFormat::Png, png),
+        ClipboardData::new(ClipboardFormat::Html, html),
+    ])
+}
+
+/// `text` in every text format, richest first.
+pub fn text_contents(text: &str) -> Vec<ClipboardData> {
+    vec![
+        ClipboardData::new(ClipboardFormat::UnicodeText, encode_unicode_text(text)),
+        ClipboardData::new(ClipboardFormat::Text, encode_text(text)),
+    ]
+}
+
+pub fn encode_png(frame: &Frame) -> Result<Vec<u8>> {
+    Exporter::default().encode(frame, &ExportOptions::default_for(ExportFormat::Png))
+}
+
+/// Packed DIB: a `BITMAPINFOHEADER` followed by the pixels.
+pub fn encode_dib(frame: &Frame) -> Vec<u8> {
+    let mut out = Vec::with_capacity(BITMAPINFOHEADER_SIZE as usize + image_size(frame));
+    write_info_header(&mut out, frame, BITMAPINFOHEADER_SIZE, BI_RGB);
+    write_bottom_up_bgra(&mut out, frame);
+    out
+}
+
+/// Packed DIB with a `BITMAPV5HEADER` that declares the alpha channel and
+/// sRGB, followed by the pixels.
+pub fn encode_dibv5(frame: &Frame) -> Vec<u8> {
+    let mut out = Vec::with_capacity(BITMAPV5HEADER_SIZE as usize + image_size(frame));
+    write_info_header(&mut out, frame, BITMAPV5HEADER_SIZE, BI_BITFIELDS);
+    for mask in [0x00FF_0000u32, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000] {
+        out.extend_from_slice(&mask.to_le_bytes());
+    }
+    out.extend_from_slice(&LCS_SRGB.to_le_bytes());
+    // Endpoints and gamma only count for a calibrated color space
+    out.extend_from_slice(&[0; 36 + 12]);
+    out.extend_from_slice(&LCS_GM_IMAGES.to_le_bytes());
+    // No profile data, profile size or reserved field
+    out.extend_from_slice(&[0; 12]);
+    write_bottom_up_bgra(&mut out, frame);
+    out
+}
+
+/// CF_HTML document whose fragment is an `<img>` of `png`, the encoded
+/// `frame`, sized in CSS pixels so it pastes at the size it had on screen.
+pub fn encode_html(frame: &Frame, png: &[u8]) -> Vec<u8> {
+    let scale = if frame.scale > 0.0 { frame.scale } else { 1.0 };
+    let fragment = format!(
+        "<img src=\"data:image/png;base64,{}\" width=\"{}\" height=\"{}\">",
+        base64(png),
+        (frame.width as f32 / scale).round(),
+        (frame.height as f32 / scale).round()
+    );
+    html_document(&fragment)
+}
+
+/// Wraps `fragment` in a CF_HTML document: a header with the byte offsets
+/// of the HTML and the fragment, then the UTF-8 HTML.
+pub fn html_document(fragment: &str) -> Vec<u8> {
+    const PREFIX: &str = "<html>\r\n<body>\r\n<!--StartFragment-->";
+    const SUFFIX: &str = "<!--EndFragment-->\r\n</body>\r\n</html>";
+    let header = |start_html: usize, end_html: usize, start: usize, end: usize| {
+        format!(
+            "Version:0.9\r\nStartHTML:{:0w$}\r\nEndHTML:{:0w$}\r\n\
+             StartFragment:{:0w$}\r\nEndFragment:{:0w$}\r\n",
+            start_html,
+            end_html,
+            start,
+            end,
+            w = HTML_OFFSET_DIGITS
+        )
+    };
+
+    let start_html = header(0, 0, 0, 0).len();
+    let start_fragment = start_html + PREFIX.len();
+    let end_fragment = start_fragment + fragment.len();
+    let end_html = end_fragment + SUFFIX.len();
+    let mut out = header(start_html, end_html, start_fragment, end_fragment).into_bytes();
+    out.extend_from_slice(PREFIX.as_bytes());
+    out.extend_from_slice(fragment.as_bytes());
+    out.extend_from_slice(SUFFIX.as_bytes());
+    out
+}
+
+pub fn encode_unicode_text(text: &str) -> Vec<u8> {
+    crlf(text)
+        .encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+pub fn encode_text(text: &str) -> Vec<u8> {
+    crlf(text).chars().map(windows_1252).chain([0]).collect()
+}
+
+/// Standard base64 with padding.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, &b)| group | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3F;
+                out.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn image_size(frame: &Frame) -> usize {
+    frame.width as usize * frame.height as usize * 4
+}
+
+/// The `BITMAPINFOHEADER` fields, which a V5 header starts with too.
+fn write_info_header(out: &mut Vec<u8>, frame: &Frame, size: u32, compression: u32) {
+    let pixels_per_meter = (BASE_DPI * frame.scale * INCHES_PER_METER).round() as i32;
+    out.extend_from_slice(&size.to_le_bytes());
+    out.extend_from_slice(&(frame.width as i32).to_le_bytes());
+    // Positive height: the last row comes first
+    out.extend_from_slice(&(frame.height as i32).to_le_bytes());
+    out.extend_from_slice(&1u16.to_le_bytes());
+    out.extend_from_slice(&32u16.to_le_bytes());
+    out.extend_from_slice(&compression.to_le_bytes());
+    out.extend_from_slice(&(image_size(frame) as u32).to_le_bytes());
+    out.extend_from_slice(&pixels_per_meter.to_le_bytes());
+    out.extend_from_slice(&pixels_per_meter.to_le_bytes());
+    // Colors used and important, no palette
+    out.extend_from_slice(&[0; 8]);
+}
+
+fn write_bottom_up_bgra(out: &mut Vec<u8>, frame: &Frame) {
+    let row_bytes = frame.width as usize * 4;
+    for y in (0..frame.height as usize).rev() {
+        let row = &frame.pixels[y * frame.stride..][..row_bytes];
+        for pixel in row.chunks_exact(4) {
+            out.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+        }
+    }
+}
+
+/// Line breaks as `\r\n`, whatever they were before.
+fn crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+fn windows_1252(c: char) -> u8 {
+    match c as u32 {
+        code @ (0x00..=0x7F | 0xA0..=0xFF) => code as u8,
+        _ => WINDOWS_1252_HIGH
+            .iter()
+            .position(|&high| high == c && high != '\0')
+            .map_or(b'?', |i| 0x80 + i as u8),
+    }
+}
+
+/// Replaces the clipboard with `contents`, owned by the window `owner`.
+#[cfg(windows)]
+pub fn write(owner: windows::Win32::Foundation::HWND, contents: &[ClipboardData]) -> Result<()> {
+    win32::write(owner, contents)
+}
+
+#[cfg(windows)]
+mod win32 {
+    use super::{ClipboardData, ClipboardFormat};
+    use anyhow::{anyhow, Result};
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::{GlobalFree, HANDLE, HWND};
+    use windows::Win32::System::DataExchange::{
+        CloseClipboard, EmptyClipboard, OpenClipboard, RegisterClipboardFormatW, SetClipboardData,
+    };
+    use windows::Win32::System::Memory::{GlobalAlloc, GlobalLock, GlobalUnlock, GMEM_MOVEABLE};
+    use windows::Win32::System::Ole::{CF_DIB, CF_DIBV5, CF_TEXT, CF_UNICODETEXT};
+
+    pub fn write(owner: HWND, contents: &[ClipboardData]) -> Result<()> {
+        unsafe {
+            OpenClipboard(owner).map_err(|e| anyhow!("Opening the clipboard failed: {}", e))?;
+            let written = EmptyClipboard()
+                .map_err(anyhow::Error::from)
+                .and_then(|()| contents.iter().try_for_each(|data| set(data)));
+            let _ = CloseClipboard();
+            written
+        }
+    }
+
+    fn format_id(format: ClipboardFormat) -> Result<u32> {
+        let id = match format {
+            ClipboardFormat::DibV5 => CF_DIBV5.0 as u32,
+            ClipboardFormat::Dib => CF_DIB.0 as u32,
+            ClipboardFormat::UnicodeText => CF_UNICODETEXT.0 as u32,
+            ClipboardFormat::Text => CF_TEXT.0 as u32,
+            ClipboardFormat::Png | ClipboardFormat::Html => {
+                let name = format.registered_name().unwrap_or_default();
+                unsafe { RegisterClipboardFormatW(&HSTRING::from(name)) }
+            }
+        };
+        if id == 0 {
+            return Err(anyhow!("Registering clipboard format {} failed", format));
+        }
+        Ok(id)
+    }
+
+    /// Hands a copy of `data` to the open clipboard, which then owns it.
+    unsafe fn set(data: &ClipboardData) -> Result<()> {
+        let format = format_id(data.format)?;
+        let memory = GlobalAlloc(GMEM_MOVEABLE, data.bytes.len())?;
+        let target = GlobalLock(memory) as *mut u8;
+        if target.is_null() {
+            let _ = GlobalFree(memory);
+            return Err(anyhow!("Locking clipboard memory failed"));
+        }
+        std::ptr::copy_nonoverlapping(data.bytes.as_ptr(), target, data.bytes.len());
+        // Fails with NO_ERROR once the memory is unlocked
+        let _ = GlobalUnlock(memory);
+
+        if let Err(e) = SetClipboardData(format, HANDLE(memory.0 as isize)) {
+            let _ = GlobalFree(memory);
+            return Err(anyhow!("Copying {} failed: {}", data.format, e));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two pixels, left `[10, 20, 30, 40]` and right `[50, 60, 70, 80]` RGBA.
+    fn two_by_one() -> Frame {
+        Frame::from_rgba(2, 1, 8, vec![10, 20, 30, 40, 50, 60, 70, 80]).unwrap()
+    }
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    /// The `BITMAPINFOHEADER` fields of a 2x1 frame at scale 1.0.
+    fn assert_info_header(bytes: &[u8], size: u32, compression: u32) {
+        assert_eq!(u32_at(bytes, 0), size);
+        assert_eq!(u32_at(bytes, 4), 2);
+        assert_eq!(u32_at(bytes, 8), 1);
+        assert_eq!(u16_at(bytes, 12), 1);
+        assert_eq!(u16_at(bytes, 14), 32);
+        assert_eq!(u32_at(bytes, 16), compression);
+        assert_eq!(u32_at(bytes, 20), 8);
+        // 96 DPI
+        assert_eq!(u32_at(bytes, 24), 3780);
+        assert_eq!(u32_at(bytes, 28), 3780);
+        assert_eq!(&bytes[32..40], &[0; 8]);
+    }
+
+    /// Offset given for `key` in a CF_HTML header.
+    fn html_offset(document: &[u8], key: &str) -> usize {
+        let text = std::str::from_utf8(document).unwrap();
+        let start = text.find(&format!("{}:", key)).unwrap() + key.len() + 1;
+        text[start..start + HTML_OFFSET_DIGITS].parse().unwrap()
+    }
+
+    #[test]
+    fn dib_is_a_bitmapinfoheader_and_bgra_pixels() {
+        let dib = encode_dib(&two_by_one());
+        assert_eq!(dib.len(), 40 + 8);
+        assert_info_header(&dib, 40, BI_RGB);
+        assert_eq!(&dib[40..], &[30, 20, 10, 40, 70, 60, 50, 80]);
+    }
+
+    #[test]
+    fn dibv5_declares_alpha_and_srgb() {
+        let dib = encode_dibv5(&two_by_one());
+        assert_eq!(dib.len(), 124 + 8);
+        assert_info_header(&dib, 124, BI_BITFIELDS);
+        assert_eq!(u32_at(&dib, 40), 0x00FF_0000);
+        assert_eq!(u32_at(&dib, 44), 0x0000_FF00);
+        assert_eq!(u32_at(&dib, 48), 0x0000_00FF);
+        assert_eq!(u32_at(&dib, 52), 0xFF00_0000);
+        assert_eq!(&dib[56..60], b"BGRs");
+        assert_eq!(&dib[60..108], &[0; 48]);
+        assert_eq!(u32_at(&dib, 108), LCS_GM_IMAGES);
+        assert_eq!(&dib[112..124], &[0; 12]);
+        assert_eq!(&dib[124..], &[30, 20, 10, 40, 70, 60, 50, 80]);
+    }
+
+    #[test]
+    fn dib_rows_are_bottom_up_and_ignore_the_stride() {
+        // One pixel per row, padded to 8 bytes
+        let frame = Frame::from_rgba(
+            1,
+            2,
+            8,
+            vec![1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0],
+        )
+        .unwrap();
+        assert_eq!(&encode_dib(&frame)[40..], &[7, 6, 5, 8, 3, 2, 1, 4]);
+    }
+
+    #[test]
+    fn dib_resolution_follows_the_scale() {
+        let dib = encode_dib(&two_by_one().with_scale(1.5));
+        assert_eq!(u32_at(&dib, 24), 5669);
+    }
+
+    #[test]
+    fn html_offsets_slice_out_the_fragment() {
+        let fragment = "<p>Grüße – 5 €</p>";
+        let document = html_document(fragment);
+
+        let start_html = html_offset(&document, "StartHTML");
+        let end_html = html_offset(&document, "EndHTML");
+        let start = html_offset(&document, "StartFragment");
+        let end = html_offset(&document, "EndFragment");
+        assert_eq!(&document[start..end], fragment.as_bytes());
+        assert!(document[start_html..].starts_with(b"<html>"));
+        assert_eq!(end_html, document.len());
+        assert!(document.starts_with(b"Version:0.9\r\n"));
+    }
+
+    #[test]
+    fn html_image_has_the_css_size() {
+        let frame = Frame::new(300, 150).with_scale(1.5);
+        let png = encode_png(&frame).unwrap();
+        let document = encode_html(&frame, &png);
+        let fragment = &document
+            [html_offset(&document, "StartFragment")..html_offset(&document, "EndFragment")];
+        let expected = format!(
+            "<img src=\"data:image/png;base64,{}\" width=\"200\" height=\"100\">",
+            base64(&png)
+        );
+        assert_eq!(fragment, expected.as_bytes());
+    }
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        let cases = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(base64(input.as_bytes()), expected, "{:?}", input);
+        }
+        assert_eq!(base64(&[0xFB, 0xFF, 0xFE]), "+//+");
+    }
+
+    #[test]
+    fn unicode_text_is_utf16_with_crlf_and_nul() {
+        let bytes = encode_unicode_text("a\nb\r\nc😀");
+        let units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        let expected: Vec<u16> = "a\r\nb\r\nc😀\0".encode_utf16().collect();
+        assert_eq!(units, expected);
+        assert_eq!(&units[units.len() - 3..], &[0xD83D, 0xDE00, 0]);
+    }
+
+    #[test]
+    fn text_is_windows_1252_with_crlf_and_nul() {
+        assert_eq!(
+            encode_text("Grüße €5 – ok\n✓\u{81}"),
+            [
+                b'G', b'r', 0xFC, 0xDF, b'e', b' ', 0x80, b'5', b' ', 0x96, b' ', b'o', b'k',
+                b'\r', b'\n', b'?', b'?', 0
+            ]
+        );
+        assert_eq!(encode_text("\u{A0}ÿ"), [0xA0, 0xFF, 0]);
+    }
+
+    #[test]
+    fn contents_list_the_richest_format_first() {
+        let formats = |contents: Vec<ClipboardData>| -> Vec<ClipboardFormat> {
+            contents.into_iter().map(|c| c.format).collect()
+        };
+        assert_eq!(
+            formats(image_contents(&two_by_one()).unwrap()),
+            [
+                ClipboardFormat::DibV5,
+                ClipboardFormat::Dib,
+                ClipboardFormat::Png,
+                ClipboardFormat::Html
+            ]
+        );
+        assert_eq!(
+            formats(text_contents("x")),
+            [ClipboardFormat::UnicodeText, ClipboardFormat::Text]
+        );
+    }
+}
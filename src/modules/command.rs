@@ -0,0 +1,202 @@
+use crate::annotation::Color;
+use crate::geometry::{Point, Rect};
+use crate::lasso::SelectionShape;
+use crate::monitor::CaptureRegion;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Something the windows, or the capture they show, should do.
+///
+/// Commands serialize as their snake_case name with their fields as
+/// `args`, e.g. to log or replay them; adding a command only adds a name.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", content = "args", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum Command {
+    Show,
+    /// Captures the screen below the window and shows it.
+    AutoScreenshot,
+    TriggerScreenshot,
+    Reload,
+    Hide,
+    /// Draws the selection rectangle in client coordinates of the overlay.
+    DrawRectangle {
+        start: Point,
+        end: Point,
+    },
+    /// Draws a lasso or polygon outline in client coordinates of the
+    /// overlay; an open outline is still being drawn.
+    DrawShape {
+        points: Vec<Point>,
+        closed: bool,
+    },
+    /// Saves the bounding box of `shape`, in client coordinates of the
+    /// overlay, from the last captured screen with its annotations. Pixels
+    /// outside the shape become transparent or `fill`.
+    CaptureShape {
+        shape: SelectionShape,
+        fill: Option<Color>,
+        path: PathBuf,
+    },
+    /// Runs OCR on a rectangle, in client coordinates of the overlay, of the
+    /// last captured screen, or on all of it.
+    RecognizeText {
+        rect: Option<Rect>,
+    },
+    /// Covers e-mail addresses, IBANs, keys etc. found by OCR in the last
+    /// captured screen.
+    RedactSensitive,
+    /// Shows the seconds left of a timed capture and lets input through to
+    /// the screen below; `None` returns to the normal overlay.
+    Countdown {
+        seconds: Option<u32>,
+    },
+    /// Starts stitching captures of `rect`, in client coordinates of the
+    /// overlay, while the screen below is scrolled.
+    StartScrollCapture {
+        rect: Rect,
+    },
+    /// Captures the next frame of the running scrolling capture.
+    ScrollCaptureFrame,
+    /// Ends the scrolling capture and saves the stitched image.
+    FinishScrollCapture {
+        path: PathBuf,
+    },
+    /// Captures part of the screen straight into a file, without showing it.
+    CaptureToFile {
+        path: PathBuf,
+        region: CaptureRegion,
+    },
+    /// Copies the bounding box of `shape`, in client coordinates of the
+    /// overlay, or all of the last captured screen with its annotations to
+    /// the clipboard. Pixels outside the shape become transparent.
+    CopyImage {
+        shape: Option<SelectionShape>,
+    },
+    /// Runs OCR like [`Command::RecognizeText`] and copies the text to the
+    /// clipboard.
+    CopyText {
+        rect: Option<Rect>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// The name a command is tagged with; not having a wildcard arm makes
+    /// a new command fail to compile here until it is added to the test.
+    fn name(command: &Command) -> &'static str {
+        match command {
+            Command::Show => "show",
+            Command::AutoScreenshot => "auto_screenshot",
+            Command::TriggerScreenshot => "trigger_screenshot",
+            Command::Reload => "reload",
+            Command::Hide => "hide",
+            Command::DrawRectangle { .. } => "draw_rectangle",
+            Command::DrawShape { .. } => "draw_shape",
+            Command::CaptureShape { .. } => "capture_shape",
+            Command::RecognizeText { .. } => "recognize_text",
+            Command::RedactSensitive => "redact_sensitive",
+            Command::Countdown { .. } => "countdown",
+            Command::StartScrollCapture { .. } => "start_scroll_capture",
+            Command::ScrollCaptureFrame => "scroll_capture_frame",
+            Command::FinishScrollCapture { .. } => "finish_scroll_capture",
+            Command::CaptureToFile { .. } => "capture_to_file",
+            Command::CopyImage { .. } => "copy_image",
+            Command::CopyText { .. } => "copy_text",
+        }
+    }
+
+    fn every_command() -> Vec<Command> {
+        let rect = Rect::new(10.0, 20.5, 110.0, 80.0);
+        let triangle = vec![
+            Point { x: 1.0, y: 2.0 },
+            Point { x: 30.5, y: 2.0 },
+            Point { x: 15.0, y: 40.0 },
+        ];
+        vec![
+            Command::Show,
+            Command::AutoScreenshot,
+            Command::TriggerScreenshot,
+            Command::Reload,
+            Command::Hide,
+            Command::DrawRectangle {
+                start: Point { x: 3.0, y: 4.5 },
+                end: Point { x: -2.0, y: 9.0 },
+            },
+            Command::DrawShape {
+                points: triangle.clone(),
+                closed: false,
+            },
+            Command::CaptureShape {
+                shape: SelectionShape::Polygon { points: triangle },
+                fill: Some(Color {
+                    r: 255,
+                    g: 128,
+                    b: 0,
+                }),
+                path: PathBuf::from("captures/shape.png"),
+            },
+            Command::RecognizeText { rect: Some(rect) },
+            Command::RedactSensitive,
+            Command::Countdown { seconds: Some(3) },
+            Command::StartScrollCapture { rect },
+            Command::ScrollCaptureFrame,
+            Command::FinishScrollCapture {
+                path: PathBuf::from("scroll.png"),
+            },
+            Command::CaptureToFile {
+                path: PathBuf::from("monitor.webp"),
+                region: CaptureRegion::MonitorAt { x: -1920.0, y: 5.0 },
+            },
+            Command::CopyImage {
+                shape: Some(SelectionShape::Rect { rect }),
+            },
+            Command::CopyText { rect: None },
+        ]
+    }
+
+    #[test]
+    fn every_command_round_trips_through_json() {
+        let commands = every_command();
+        let mut names: Vec<&str> = commands.iter().map(name).collect();
+        names.sort_unstable();
+        names.dedup();
+        assert_eq!(names.len(), 17, "every command exactly once");
+
+        for command in commands {
+            let json = serde_json::to_value(&command).unwrap();
+            assert_eq!(json["command"], name(&command));
+            let text = serde_json::to_string(&command).unwrap();
+            assert_eq!(serde_json::from_str::<Command>(&text).unwrap(), command);
+        }
+    }
+
+    #[test]
+    fn commands_serialize_their_fields_as_args() {
+        assert_eq!(
+            serde_json::to_value(Command::Hide).unwrap(),
+            json!({ "command": "hide" })
+        );
+        assert_eq!(
+            serde_json::to_value(Command::Countdown { seconds: None }).unwrap(),
+            json!({ "command": "countdown", "args": { "seconds": null } })
+        );
+
+        let replayed: Command = serde_json::from_value(json!({
+            "command": "capture_to_file",
+            "args": { "path": "all.png", "region": { "type": "all_monitors" } }
+        }))
+        .unwrap();
+        assert_eq!(
+            replayed,
+            Command::CaptureToFile {
+                path: PathBuf::from("all.png"),
+                region: CaptureRegion::AllMonitors,
+            }
+        );
+        assert!(serde_json::from_value::<Command>(json!({ "command": "explode" })).is_err());
+    }
+}
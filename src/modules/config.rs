@@ -0,0 +1,1147 @@
+use crate::annotation::{Color, Stroke};
+use crate::ipc;
+use crate::selection::{Key, Modifiers, OverlayStyle, DEFAULT_OVERLAY_OPACITY};
+use crate::sensitive::{Detector, RuleSpec};
+use crate::store::{
+    Quota, DEFAULT_HISTORY_DIR, DEFAULT_HISTORY_MAX_AGE_DAYS, DEFAULT_HISTORY_MAX_SIZE_MB,
+};
+use crate::timer::{Sequence, TimerSettings, DEFAULT_INTERVAL, DELAY_PRESETS};
+use crate::undo::DEFAULT_HISTORY_DEPTH;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Directory below the per-user configuration directory.
+pub const APP_NAME: &str = "snipping_tool";
+pub const CONFIG_FILE: &str = "config.toml";
+/// Environment variable naming the configuration file to load instead of
+/// the one in the per-user configuration directory.
+pub const CONFIG_ENV: &str = "SNIPPING_TOOL_CONFIG";
+/// Prefix of environment variables overriding single settings, with `__`
+/// between section and key, e.g. `SNIPPING_TOOL_OVERLAY__OPACITY=0.7`.
+pub const ENV_PREFIX: &str = "SNIPPING_TOOL_";
+
+/// Something a key can be bound to in the overlay.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Undo,
+    Redo,
+    Screenshot,
+    Ocr,
+    ScrollCapture,
+    PickWindow,
+    Lasso,
+    Polygon,
+    /// Switches between the window bounds offered while picking windows.
+    BoundsMode,
+    CaptureMonitor,
+    CaptureAllMonitors,
+    DelayedCapture,
+    CycleDelay,
+    IntervalCapture,
+    Redact,
+    Reload,
+    /// Starts or stops recording the session for replay.
+    Record,
+    /// Copies the selection, or all of the capture, as an image.
+    CopyImage,
+    /// Copies the text recognized in the selection, or all of the capture.
+    CopyText,
+}
+
+impl Action {
+    pub const ALL: [Action; 20] = [
+        Action::Quit,
+        Action::Undo,
+        Action::Redo,
+        Action::Screenshot,
+        Action::Ocr,
+        Action::ScrollCapture,
+        Action::PickWindow,
+        Action::Lasso,
+        Action::Polygon,
+        Action::BoundsMode,
+        Action::CaptureMonitor,
+        Action::CaptureAllMonitors,
+        Action::DelayedCapture,
+        Action::CycleDelay,
+        Action::IntervalCapture,
+        Action::Redact,
+        Action::Reload,
+        Action::Record,
+        Action::CopyImage,
+        Action::CopyText,
+    ];
+
+    /// Name used in the `[keys]` section.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Screenshot => "screenshot",
+            Action::Ocr => "ocr",
+            Action::ScrollCapture => "scroll_capture",
+            Action::PickWindow => "pick_window",
+            Action::Lasso => "lasso",
+            Action::Polygon => "polygon",
+            Action::BoundsMode => "bounds_mode",
+            Action::CaptureMonitor => "capture_monitor",
+            Action::CaptureAllMonitors => "capture_all_monitors",
+            Action::DelayedCapture => "delayed_capture",
+            Action::CycleDelay => "cycle_delay",
+            Action::IntervalCapture => "interval_capture",
+            Action::Redact => "redact",
+            Action::Reload => "reload",
+            Action::Record => "record",
+            Action::CopyImage => "copy_image",
+            Action::CopyText => "copy_text",
+        }
+    }
+
+    pub fn default_binding(self) -> &'static str {
+        match self {
+            Action::Quit => "Escape",
+            Action::Undo => "Ctrl+Z",
+            Action::Redo => "Ctrl+Y",
+            Action::Screenshot => "S",
+            Action::Ocr => "O",
+            Action::ScrollCapture => "C",
+            Action::PickWindow => "W",
+            Action::Lasso => "L",
+            Action::Polygon => "P",
+            Action::BoundsMode => "B",
+            Action::CaptureMonitor => "M",
+            Action::CaptureAllMonitors => "Shift+M",
+            Action::DelayedCapture => "T",
+            Action::CycleDelay => "Shift+T",
+            Action::IntervalCapture => "I",
+            Action::Redact => "D",
+            Action::Reload => "R",
+            Action::Record => "Shift+R",
+            Action::CopyImage => "Ctrl+C",
+            Action::CopyText => "Ctrl+Shift+C",
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A key together with the modifiers that must be held, written like
+/// `Ctrl+Shift+Z`; modifiers not listed must not be held.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    pub key: Key,
+    pub modifiers: Modifiers,
+}
+
+impl FromStr for KeyBinding {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut modifiers = Modifiers::default();
+        let mut key = None;
+        for part in text.split('+').map(str::trim) {
+            if key.is_some() {
+                return Err(anyhow!("`{}`: the key must come last", text));
+            }
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers.ctrl = true,
+                "shift" => modifiers.shift = true,
+                "alt" => modifiers.alt = true,
+                _ => key = Some(parse_key(part).ok_or_else(|| anyhow!("Unknown key `{}`", part))?),
+            }
+        }
+        let key = key.ok_or_else(|| anyhow!("`{}` has no key besides modifiers", text))?;
+        Ok(KeyBinding { key, modifiers })
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.ctrl {
+            f.write_str("Ctrl+")?;
+        }
+        if self.modifiers.shift {
+            f.write_str("Shift+")?;
+        }
+        if self.modifiers.alt {
+            f.write_str("Alt+")?;
+        }
+        match self.key {
+            Key::Char(c) => write!(f, "{}", c),
+            Key::Other(code) => write!(f, "0x{:02X}", code),
+            key => write!(f, "{:?}", key),
+        }
+    }
+}
+
+/// Letters and digits as the overlay reports them, plus the named keys.
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return c
+            .is_ascii_alphanumeric()
+            .then(|| Key::Char(c.to_ascii_uppercase()));
+    }
+    match name.to_ascii_lowercase().as_str() {
+        "escape" | "esc" => Some(Key::Escape),
+        "enter" | "return" => Some(Key::Enter),
+        "left" => Some(Key::Left),
+        "right" => Some(Key::Right),
+        "up" => Some(Key::Up),
+        "down" => Some(Key::Down),
+        _ => None,
+    }
+}
+
+/// Looks up which [`Action`] a key press triggers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<KeyBinding, Action>,
+}
+
+impl Default for Keymap {
+    /// Every action on its default binding.
+    fn default() -> Self {
+        let mut keymap = Keymap::empty();
+        for action in Action::ALL {
+            let binding = action.default_binding().parse().expect("valid default");
+            keymap.bind(action, binding).expect("unique default");
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn empty() -> Self {
+        Keymap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Fails if `binding` already triggers another action.
+    pub fn bind(&mut self, action: Action, binding: KeyBinding) -> Result<&mut Self> {
+        match self.bindings.get(&binding) {
+            Some(&other) if other != action => {
+                Err(anyhow!("{} is already bound to {}", binding, other))
+            }
+            _ => {
+                self.bindings.insert(binding, action);
+                Ok(self)
+            }
+        }
+    }
+
+    pub fn action(&self, key: Key, modifiers: Modifiers) -> Option<Action> {
+        self.bindings.get(&KeyBinding { key, modifiers }).copied()
+    }
+
+    /// The bindings of `action`, in no particular order.
+    pub fn bindings(&self, action: Action) -> Vec<KeyBinding> {
+        self.bindings
+            .iter()
+            .filter(|(_, &a)| a == action)
+            .map(|(&binding, _)| binding)
+            .collect()
+    }
+}
+
+/// Parses `#RRGGBB` or `#RGB`.
+pub fn parse_color(text: &str) -> Result<Color> {
+    let invalid = || anyhow!("Invalid color `{}`, expected #RRGGBB", text);
+    let hex = text.trim().strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |digits: &str| u8::from_str_radix(digits, 16).map_err(|_| invalid());
+    match hex.len() {
+        6 => Ok(Color::rgb(
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        )),
+        3 => Ok(Color::rgb(
+            channel(&hex[0..1])? * 17,
+            channel(&hex[1..2])? * 17,
+            channel(&hex[2..3])? * 17,
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn format_color(color: Color) -> String {
+    format!("#{:02X}{:02X}{:02X}", color.r, color.g, color.b)
+}
+
+/// Serde adapter writing colors as `#RRGGBB`.
+mod hex_color {
+    use super::{format_color, parse_color};
+    use crate::annotation::Color;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format_color(*color))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        parse_color(&String::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+
+    pub mod option {
+        use super::*;
+
+        pub fn serialize<S: Serializer>(
+            color: &Option<Color>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match color {
+                Some(color) => super::serialize(color, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<Color>, D::Error> {
+            super::deserialize(deserializer).map(Some)
+        }
+    }
+}
+
+/// Look of the transparent overlay window and its selection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OverlayConfig {
+    /// Opacity of the overlay window over the frozen screen.
+    pub opacity: f32,
+    #[serde(with = "hex_color")]
+    pub fill: Color,
+    pub fill_opacity: f32,
+    #[serde(with = "hex_color")]
+    pub frame_color: Color,
+    pub frame_width: f32,
+    pub frame_opacity: f32,
+    #[serde(with = "hex_color")]
+    pub handle_color: Color,
+    pub handle_opacity: f32,
+    /// Paints pixels outside a lasso or polygon in saved selections;
+    /// they stay transparent if unset.
+    #[serde(with = "hex_color::option", skip_serializing_if = "Option::is_none")]
+    pub outside_fill: Option<Color>,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        let style = OverlayStyle::default();
+        let (fill, fill_opacity) = style.fill.unwrap_or((Color::WHITE, 1.0));
+        OverlayConfig {
+            opacity: DEFAULT_OVERLAY_OPACITY,
+            fill,
+            fill_opacity,
+            frame_color: style.frame.color,
+            frame_width: style.frame.width,
+            frame_opacity: style.frame.opacity,
+            handle_color: style.handles.0,
+            handle_opacity: style.handles.1,
+            outside_fill: None,
+        }
+    }
+}
+
+/// Where captures and the capture history go.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub directory: PathBuf,
+    /// File name prefix of the numbered captures of an interval run.
+    pub prefix: String,
+    pub history: PathBuf,
+    /// Undo steps kept per capture.
+    pub undo_depth: usize,
+    /// Limits on the history; `0` turns a limit off.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_max_age_days: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_max_size_mb: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub history_max_entries: Option<usize>,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            directory: PathBuf::from("captures"),
+            prefix: "capture".to_string(),
+            history: Config::default_history_dir(),
+            undo_depth: DEFAULT_HISTORY_DEPTH,
+            history_max_age_days: Some(DEFAULT_HISTORY_MAX_AGE_DAYS),
+            history_max_size_mb: Some(DEFAULT_HISTORY_MAX_SIZE_MB),
+            history_max_entries: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimerConfig {
+    pub delay_secs: u64,
+    pub interval_secs: u64,
+    /// Captures per interval run, unlimited if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<u32>,
+}
+
+impl Default for TimerConfig {
+    fn default() -> Self {
+        TimerConfig {
+            delay_secs: DELAY_PRESETS[0].as_secs(),
+            interval_secs: DEFAULT_INTERVAL.as_secs(),
+            limit: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SensitiveConfig {
+    /// Whether the built-in rules for e-mail addresses, IBANs etc. apply.
+    pub builtin: bool,
+    pub rules: Vec<RuleSpec>,
+}
+
+impl Default for SensitiveConfig {
+    fn default() -> Self {
+        SensitiveConfig {
+            builtin: true,
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IpcConfig {
+    /// Whether the overlay takes commands from other programs.
+    pub enabled: bool,
+    /// Pipe name or socket path instead of [`ipc::default_address`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+}
+
+impl Default for IpcConfig {
+    fn default() -> Self {
+        IpcConfig {
+            enabled: true,
+            address: None,
+        }
+    }
+}
+
+/// User settings, read from a TOML file such as
+///
+/// ```toml
+/// [overlay]
+/// opacity = 0.7
+/// frame_color = "#FF0000"
+///
+/// [keys]
+/// screenshot = "Ctrl+S"
+/// reload = ""
+/// ```
+///
+/// Everything left out keeps its default. Keys map [`Action`] names to a
+/// binding; actions not listed keep their default binding, an empty
+/// binding disables the action.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub overlay: OverlayConfig,
+    pub output: OutputConfig,
+    pub timer: TimerConfig,
+    pub keys: BTreeMap<Action, String>,
+    pub sensitive: SensitiveConfig,
+    pub ipc: IpcConfig,
+}
+
+impl Config {
+    /// `config.toml` in the per-user configuration directory, e.g.
+    /// `%APPDATA%\snipping_tool` on Windows.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(APP_NAME).join(CONFIG_FILE))
+    }
+
+    /// The history below the per-user data directory, e.g.
+    /// `%APPDATA%\snipping_tool\history` on Windows, next to the
+    /// configuration; relative to the working directory if there is none.
+    pub fn default_history_dir() -> PathBuf {
+        dirs::data_dir()
+            .map(|dir| dir.join(APP_NAME))
+            .unwrap_or_default()
+            .join(DEFAULT_HISTORY_DIR)
+    }
+
+    /// Parses and validates a configuration file's text.
+    pub fn from_toml(text: &str) -> Result<Config> {
+        let config: Config = toml::from_str(text).map_err(|e| anyhow!("{}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load_file(path: impl AsRef<Path>) -> Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Reading configuration {} failed: {}", path.display(), e))?;
+        Config::from_toml(&text)
+            .map_err(|e| anyhow!("Invalid configuration {}: {}", path.display(), e))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).map_err(|e| anyhow!("Writing configuration failed: {}", e))
+    }
+
+    /// Reports the first setting out of range, naming it.
+    pub fn validate(&self) -> Result<()> {
+        let overlay = &self.overlay;
+        for (name, value) in [
+            ("overlay.opacity", overlay.opacity),
+            ("overlay.fill_opacity", overlay.fill_opacity),
+            ("overlay.frame_opacity", overlay.frame_opacity),
+            ("overlay.handle_opacity", overlay.handle_opacity),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(anyhow!("{} must be between 0 and 1, not {}", name, value));
+            }
+        }
+        if !(overlay.frame_width.is_finite() && overlay.frame_width >= 0.0) {
+            return Err(anyhow!(
+                "overlay.frame_width must not be negative, not {}",
+                overlay.frame_width
+            ));
+        }
+
+        let output = &self.output;
+        if output.directory.as_os_str().is_empty() {
+            return Err(anyhow!("output.directory must not be empty"));
+        }
+        if output.history.as_os_str().is_empty() {
+            return Err(anyhow!("output.history must not be empty"));
+        }
+        if output.undo_depth == 0 {
+            return Err(anyhow!("output.undo_depth must be at least 1"));
+        }
+        if self.timer.interval_secs == 0 {
+            return Err(anyhow!("timer.interval_secs must be at least 1"));
+        }
+        if self.timer.limit == Some(0) {
+            return Err(anyhow!(
+                "timer.limit must be at least 1, leave it out for no limit"
+            ));
+        }
+
+        if self
+            .ipc
+            .address
+            .as_ref()
+            .is_some_and(|a| a.trim().is_empty())
+        {
+            return Err(anyhow!(
+                "ipc.address must not be empty, leave it out for the default"
+            ));
+        }
+
+        self.keymap()?;
+        self.detector()
+            .map_err(|e| anyhow!("sensitive.rules: {}", e))?;
+        Ok(())
+    }
+
+    /// Default bindings with those from `[keys]` in place.
+    pub fn keymap(&self) -> Result<Keymap> {
+        let mut keymap = Keymap::empty();
+        for action in Action::ALL {
+            let binding = self
+                .keys
+                .get(&action)
+                .map_or(action.default_binding(), String::as_str);
+            if binding.trim().is_empty() {
+                continue;
+            }
+            binding
+                .parse()
+                .and_then(|binding| keymap.bind(action, binding).map(|_| ()))
+                .map_err(|e| anyhow!("keys.{}: {}", action, e))?;
+        }
+        Ok(keymap)
+    }
+
+    pub fn overlay_style(&self) -> OverlayStyle {
+        let overlay = &self.overlay;
+        OverlayStyle {
+            dim: None,
+            fill: Some((overlay.fill, overlay.fill_opacity)),
+            frame: Stroke::new(
+                overlay.frame_color,
+                overlay.frame_width,
+                overlay.frame_opacity,
+            ),
+            handles: (overlay.handle_color, overlay.handle_opacity),
+        }
+    }
+
+    pub fn timer_settings(&self) -> TimerSettings {
+        TimerSettings {
+            delay: Duration::from_secs(self.timer.delay_secs),
+            interval: Duration::from_secs(self.timer.interval_secs),
+            limit: self.timer.limit,
+            sequence: Sequence::new(&self.output.directory, &self.output.prefix),
+        }
+    }
+
+    pub fn quota(&self) -> Quota {
+        let output = &self.output;
+        Quota {
+            max_age: output
+                .history_max_age_days
+                .filter(|&days| days > 0)
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            max_size: output
+                .history_max_size_mb
+                .filter(|&mb| mb > 0)
+                .map(|mb| mb * 1024 * 1024),
+            max_entries: output.history_max_entries.filter(|&count| count > 0),
+        }
+    }
+
+    /// Where the overlay listens for commands.
+    pub fn ipc_address(&self) -> String {
+        self.ipc
+            .address
+            .clone()
+            .unwrap_or_else(ipc::default_address)
+    }
+
+    pub fn detector(&self) -> Result<Detector> {
+        let mut detector = if self.sensitive.builtin {
+            Detector::default()
+        } else {
+            Detector::empty()
+        };
+        for rule in &self.sensitive.rules {
+            detector.add_pattern(&rule.name, &rule.pattern)?;
+        }
+        Ok(detector)
+    }
+}
+
+/// A single setting given as `section.key=value` from outside the file.
+#[derive(Clone, Debug, PartialEq)]
+struct Override {
+    key: String,
+    value: String,
+    /// Where it came from, for error messages.
+    source: String,
+}
+
+/// Reads the configuration file and applies overrides on top, first from
+/// the environment, then the ones added explicitly, e.g. from the command
+/// line.
+///
+/// Override values are read as TOML values where possible, so numbers and
+/// booleans keep their type; anything else is taken as a string and needs
+/// no quotes.
+#[derive(Clone, Debug)]
+pub struct ConfigLoader {
+    path: Option<PathBuf>,
+    env: Environment,
+    overrides: Vec<Override>,
+}
+
+/// Where [`ConfigLoader`] reads its environment variables from.
+#[derive(Clone, Debug)]
+enum Environment {
+    Process,
+    Vars(Vec<(String, String)>),
+}
+
+impl Default for ConfigLoader {
+    fn default() -> Self {
+        ConfigLoader {
+            path: None,
+            env: Environment::Process,
+            overrides: Vec::new(),
+        }
+    }
+}
+
+impl ConfigLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// File to read instead of [`CONFIG_ENV`] or [`Config::default_path`];
+    /// unlike the default file it must exist.
+    pub fn set_path(&mut self, path: impl AsRef<Path>) -> &mut Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Whether [`CONFIG_ENV`] and the [`ENV_PREFIX`] variables are read
+    /// from the process environment.
+    pub fn set_env(&mut self, enabled: bool) -> &mut Self {
+        self.env = match enabled {
+            true => Environment::Process,
+            false => Environment::Vars(Vec::new()),
+        };
+        self
+    }
+
+    /// Reads [`CONFIG_ENV`] and the [`ENV_PREFIX`] variables from `vars`
+    /// instead of the process environment.
+    pub fn set_env_vars<K, V>(&mut self, vars: impl IntoIterator<Item = (K, V)>) -> &mut Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.env = Environment::Vars(
+            vars.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        );
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> &mut Self {
+        self.overrides.push(Override {
+            key: key.to_string(),
+            value: value.to_string(),
+            source: "--set".to_string(),
+        });
+        self
+    }
+
+    /// Adds an override written as `section.key=value`.
+    pub fn set_arg(&mut self, arg: &str) -> Result<&mut Self> {
+        let (key, value) = arg
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected section.key=value, not `{}`", arg))?;
+        Ok(self.set(key.trim(), value.trim()))
+    }
+
+    /// The file [`ConfigLoader::load`] reads, if it exists.
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path
+            .clone()
+            .or_else(|| self.env_path())
+            .or_else(Config::default_path)
+    }
+
+    pub fn load(&self) -> Result<Config> {
+        let required = self.path.is_some() || self.env_path().is_some();
+        let mut table = toml::Table::new();
+        if let Some(path) = self.path().filter(|path| required || path.exists()) {
+            let text = fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Reading configuration {} failed: {}", path.display(), e))?;
+            // Checked as a whole first so errors point into the file
+            Config::from_toml(&text)
+                .and_then(|_| text.parse().map_err(|e| anyhow!("{}", e)))
+                .map(|parsed| table = parsed)
+                .map_err(|e| anyhow!("Invalid configuration {}: {}", path.display(), e))?;
+        }
+
+        let overrides = self
+            .env_overrides()
+            .into_iter()
+            .chain(self.overrides.clone());
+        for Override { key, value, source } in overrides {
+            set_value(&mut table, &key, parse_value(&value))
+                .and_then(|_| {
+                    toml::Value::Table(table.clone())
+                        .try_into::<Config>()
+                        .map_err(|e| anyhow!("{}", e))
+                })
+                .map_err(|e| anyhow!("Invalid setting {}={} ({}): {}", key, value, source, e))?;
+        }
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| anyhow!("Invalid configuration: {}", e))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn env_path(&self) -> Option<PathBuf> {
+        match &self.env {
+            Environment::Process => std::env::var_os(CONFIG_ENV).map(PathBuf::from),
+            Environment::Vars(vars) => vars
+                .iter()
+                .find(|(name, _)| name == CONFIG_ENV)
+                .map(|(_, value)| PathBuf::from(value)),
+        }
+    }
+
+    fn env_overrides(&self) -> Vec<Override> {
+        let vars = match &self.env {
+            Environment::Process => std::env::vars().collect(),
+            Environment::Vars(vars) => vars.clone(),
+        };
+        let mut overrides: Vec<Override> = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let path = name.strip_prefix(ENV_PREFIX)?;
+                path.contains("__").then(|| Override {
+                    key: path.to_ascii_lowercase().replace("__", "."),
+                    value,
+                    source: format!("environment variable {}", name),
+                })
+            })
+            .collect();
+        overrides.sort_by(|a, b| a.key.cmp(&b.key));
+        overrides
+    }
+}
+
+/// `text` as a TOML value, or as a string if it is none.
+fn parse_value(text: &str) -> toml::Value {
+    format!("value = {}", text)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(text.to_string()))
+}
+
+/// Sets the dotted `key` in `table`, creating the sections on the way.
+fn set_value(table: &mut toml::Table, key: &str, value: toml::Value) -> Result<()> {
+    let mut parts: Vec<&str> = key.split('.').map(str::trim).collect();
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(anyhow!("`{}` is not a valid setting name", key));
+    }
+    let last = parts.pop().unwrap_or_default();
+    let mut table = table;
+    for part in parts {
+        table = table
+            .entry(part)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .ok_or_else(|| anyhow!("`{}` is not a section", part))?;
+    }
+    table.insert(last.to_string(), value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(text: &str) -> KeyBinding {
+        text.parse().unwrap()
+    }
+
+    fn error(result: Result<impl fmt::Debug>) -> String {
+        result.unwrap_err().to_string()
+    }
+
+    /// A fresh directory for the files of one test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("config_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn key_binding_parses_modifiers_in_any_order() {
+        let expected = KeyBinding {
+            key: Key::Char('Z'),
+            modifiers: Modifiers {
+                shift: true,
+                ctrl: true,
+                alt: false,
+            },
+        };
+        assert_eq!(binding("Ctrl+Shift+Z"), expected);
+        assert_eq!(binding("shift + control + z"), expected);
+        assert_eq!(binding("Esc").key, Key::Escape);
+        assert_eq!(binding("return").key, Key::Enter);
+        assert_eq!(binding("7").key, Key::Char('7'));
+    }
+
+    #[test]
+    fn history_is_limited_by_default_and_zero_lifts_a_limit() {
+        let quota = Config::default().quota();
+        assert_eq!(quota.max_age, Some(Duration::from_secs(30 * 24 * 60 * 60)));
+        assert_eq!(quota.max_size, Some(1024 * 1024 * 1024));
+        assert_eq!(quota.max_entries, None);
+
+        let config = Config::from_toml(
+            "[output]\nhistory_max_age_days = 0\nhistory_max_size_mb = 0\nhistory_max_entries = 0",
+        )
+        .unwrap();
+        assert_eq!(config.quota(), Quota::default());
+    }
+
+    #[test]
+    fn history_defaults_to_the_per_user_data_directory() {
+        let history = Config::default().output.history;
+        assert!(history.ends_with(Path::new(APP_NAME).join(DEFAULT_HISTORY_DIR)));
+        if let Some(data) = dirs::data_dir() {
+            assert!(history.starts_with(data), "{}", history.display());
+        }
+    }
+
+    #[test]
+    fn key_binding_displays_canonically() {
+        assert_eq!(
+            binding("shift+ctrl+alt+left").to_string(),
+            "Ctrl+Shift+Alt+Left"
+        );
+        assert_eq!(binding("s").to_string(), "S");
+    }
+
+    #[test]
+    fn key_binding_rejects_malformed_text() {
+        assert_eq!(error("Ctrl+".parse::<KeyBinding>()), "Unknown key ``");
+        assert_eq!(
+            error("Ctrl+Shift".parse::<KeyBinding>()),
+            "`Ctrl+Shift` has no key besides modifiers"
+        );
+        assert_eq!(
+            error("Z+Ctrl".parse::<KeyBinding>()),
+            "`Z+Ctrl`: the key must come last"
+        );
+        assert_eq!(error("Ctrl+F13".parse::<KeyBinding>()), "Unknown key `F13`");
+        assert_eq!(error("ä".parse::<KeyBinding>()), "Unknown key `ä`");
+    }
+
+    #[test]
+    fn default_keymap_binds_every_action() {
+        let keymap = Keymap::default();
+        for action in Action::ALL {
+            assert_eq!(
+                keymap.bindings(action),
+                vec![binding(action.default_binding())]
+            );
+        }
+        assert_eq!(
+            keymap.action(Key::Char('Z'), binding("Ctrl+Z").modifiers),
+            Some(Action::Undo)
+        );
+        assert_eq!(keymap.action(Key::Char('Z'), Modifiers::default()), None);
+    }
+
+    #[test]
+    fn keymap_rejects_conflicting_bindings() {
+        let mut keymap = Keymap::empty();
+        keymap.bind(Action::Undo, binding("Ctrl+Z")).unwrap();
+        // Binding the same key to the same action again is fine
+        keymap.bind(Action::Undo, binding("ctrl+z")).unwrap();
+        assert_eq!(
+            error(keymap.bind(Action::Redo, binding("Ctrl+Z"))),
+            "Ctrl+Z is already bound to undo"
+        );
+        assert_eq!(
+            keymap.action(Key::Char('Z'), binding("Ctrl+Z").modifiers),
+            Some(Action::Undo)
+        );
+    }
+
+    #[test]
+    fn config_keys_replace_or_disable_defaults() {
+        let config = Config::from_toml(
+            r#"
+            [keys]
+            screenshot = "Ctrl+S"
+            reload = ""
+            "#,
+        )
+        .unwrap();
+        let keymap = config.keymap().unwrap();
+        assert_eq!(keymap.bindings(Action::Screenshot), vec![binding("Ctrl+S")]);
+        assert_eq!(keymap.bindings(Action::Reload), vec![]);
+        assert_eq!(keymap.action(Key::Char('S'), Modifiers::default()), None);
+    }
+
+    #[test]
+    fn config_key_conflicts_name_the_action() {
+        assert_eq!(
+            error(Config::from_toml("[keys]\nscreenshot = \"O\"")),
+            "keys.ocr: O is already bound to screenshot"
+        );
+        assert_eq!(
+            error(Config::from_toml("[keys]\nundo = \"Ctrl+\"")),
+            "keys.undo: Unknown key ``"
+        );
+    }
+
+    #[test]
+    fn validate_names_the_setting_out_of_range() {
+        let invalid = |change: fn(&mut Config)| {
+            let mut config = Config::default();
+            change(&mut config);
+            error(config.validate())
+        };
+        assert_eq!(
+            invalid(|c| c.overlay.opacity = 1.5),
+            "overlay.opacity must be between 0 and 1, not 1.5"
+        );
+        assert_eq!(
+            invalid(|c| c.overlay.frame_width = -1.0),
+            "overlay.frame_width must not be negative, not -1"
+        );
+        assert_eq!(
+            invalid(|c| c.output.directory = PathBuf::new()),
+            "output.directory must not be empty"
+        );
+        assert_eq!(
+            invalid(|c| c.output.undo_depth = 0),
+            "output.undo_depth must be at least 1"
+        );
+        assert_eq!(
+            invalid(|c| c.timer.interval_secs = 0),
+            "timer.interval_secs must be at least 1"
+        );
+        assert_eq!(
+            invalid(|c| c.timer.limit = Some(0)),
+            "timer.limit must be at least 1, leave it out for no limit"
+        );
+        assert_eq!(
+            invalid(|c| c.ipc.address = Some(" ".to_string())),
+            "ipc.address must not be empty, leave it out for the default"
+        );
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn from_toml_rejects_unknown_settings() {
+        assert!(Config::from_toml("[overlay]\nopacty = 0.5").is_err());
+        assert!(Config::from_toml("[overlay]\nopacity = 0.5").is_ok());
+    }
+
+    #[test]
+    fn config_round_trips_through_toml() {
+        let mut config = Config::default();
+        config.overlay.outside_fill = Some(Color::BLACK);
+        config.timer.limit = Some(3);
+        config.keys.insert(Action::Ocr, "Ctrl+O".to_string());
+        assert_eq!(
+            Config::from_toml(&config.to_toml().unwrap()).unwrap(),
+            config
+        );
+    }
+
+    #[test]
+    fn loader_applies_overrides_after_the_file_in_order() {
+        let dir = temp_dir("overrides");
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, "[timer]\ndelay_secs = 3\ninterval_secs = 7\n").unwrap();
+
+        let config = ConfigLoader::new()
+            .set_env(false)
+            .set_path(&path)
+            .set("timer.delay_secs", "5")
+            .set("timer.delay_secs", "10")
+            .set("output.prefix", "shot")
+            .load()
+            .unwrap();
+        assert_eq!(config.timer.delay_secs, 10);
+        assert_eq!(config.timer.interval_secs, 7);
+        assert_eq!(config.output.prefix, "shot");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loader_applies_explicit_overrides_after_the_environment() {
+        let dir = temp_dir("environment");
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, "[timer]\ndelay_secs = 3\ninterval_secs = 7\n").unwrap();
+        let env = [
+            (CONFIG_ENV, path.to_str().unwrap()),
+            ("SNIPPING_TOOL_TIMER__INTERVAL_SECS", "9"),
+            ("SNIPPING_TOOL_TIMER__DELAY_SECS", "4"),
+            ("SNIPPING_TOOL_VERSION", "ignored without a section"),
+            ("OTHER_TIMER__DELAY_SECS", "1"),
+        ];
+
+        let mut loader = ConfigLoader::new();
+        loader
+            .set_env_vars(env)
+            .set_arg("timer.delay_secs = 6")
+            .unwrap();
+        assert_eq!(loader.path(), Some(path.clone()));
+        let config = loader.load().unwrap();
+        assert_eq!(config.timer.interval_secs, 9);
+        assert_eq!(config.timer.delay_secs, 6);
+
+        // A file named by the environment must exist, like an explicit one
+        let missing = dir.join("missing.toml");
+        let config = ConfigLoader::new()
+            .set_env_vars([(CONFIG_ENV, missing.to_str().unwrap())])
+            .load();
+        assert!(error(config).starts_with("Reading configuration"));
+        let config = ConfigLoader::new()
+            .set_env_vars(env)
+            .set_path(&missing)
+            .load();
+        assert!(error(config).starts_with("Reading configuration"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn loader_names_the_environment_variable_of_a_bad_override() {
+        let mut loader = ConfigLoader::new();
+        loader.set_env_vars([("SNIPPING_TOOL_TIMER__INTERVAL_SECS", "soon")]);
+        let message = error(loader.load());
+        assert!(
+            message.starts_with(
+                "Invalid setting timer.interval_secs=soon \
+                 (environment variable SNIPPING_TOOL_TIMER__INTERVAL_SECS):"
+            ),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn loader_reports_where_a_bad_override_came_from() {
+        let mut loader = ConfigLoader::new();
+        loader.set_env(false).set("timer.interval_secs", "soon");
+        let message = error(loader.load());
+        assert!(
+            message.starts_with("Invalid setting timer.interval_secs=soon (--set):"),
+            "{}",
+            message
+        );
+        assert_eq!(
+            error(ConfigLoader::new().set_arg("timer")),
+            "Expected section.key=value, not `timer`"
+        );
+        let mut loader = ConfigLoader::new();
+        loader.set_env(false).set("timer..limit", "1");
+        assert!(error(loader.load()).contains("`timer..limit` is not a valid setting name"));
+    }
+
+    #[test]
+    fn override_values_keep_their_toml_type() {
+        assert_eq!(parse_value("3"), toml::Value::Integer(3));
+        assert_eq!(parse_value("true"), toml::Value::Boolean(true));
+        assert_eq!(
+            parse_value("C:\\captures"),
+            toml::Value::String("C:\\captures".to_string())
+        );
+        assert_eq!(
+            parse_value("\"quoted\""),
+            toml::Value::String("quoted".to_string())
+        );
+    }
+}
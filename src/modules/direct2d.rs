@@ -1,4 +1,10 @@
+use crate::annotation::{AnnotationLayer, Canvas, Color, Stroke};
+use crate::capture::{CaptureBackend, Frame};
 use crate::errorhandler::{handle_error, ExpectedError};
+use crate::geometry::{Point, Rect};
+use crate::lasso::draw_shape_overlay;
+use crate::selection::{draw_overlay, OverlayStyle};
+use crate::timer;
 
 use anyhow::Result;
 use core::*;
@@ -14,29 +20,33 @@ use windows::Win32::{
         Direct2D::{
             Common::{
                 D2D1_ALPHA_MODE_IGNORE, D2D1_ALPHA_MODE_PREMULTIPLIED, D2D1_COLOR_F,
-                D2D1_PIXEL_FORMAT, D2D_POINT_2F, D2D_RECT_F, D2D_SIZE_U,
+                D2D1_FIGURE_BEGIN_FILLED, D2D1_FIGURE_BEGIN_HOLLOW, D2D1_FIGURE_END_CLOSED,
+                D2D1_FIGURE_END_OPEN, D2D1_PIXEL_FORMAT, D2D_POINT_2F, D2D_RECT_F, D2D_SIZE_U,
             },
             D2D1CreateFactory, ID2D1Bitmap, ID2D1Bitmap1, ID2D1DeviceContext, ID2D1Factory,
-            ID2D1Factory1, ID2D1HwndRenderTarget, ID2D1RenderTarget,
-            D2D1_ANTIALIAS_MODE_PER_PRIMITIVE, D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR,
-            D2D1_BITMAP_OPTIONS_CANNOT_DRAW, D2D1_BITMAP_OPTIONS_CPU_READ,
-            D2D1_BITMAP_OPTIONS_TARGET, D2D1_BITMAP_PROPERTIES, D2D1_BITMAP_PROPERTIES1,
-            D2D1_BRUSH_PROPERTIES, D2D1_DEVICE_CONTEXT_OPTIONS_ENABLE_MULTITHREADED_OPTIMIZATIONS,
-            D2D1_DEVICE_CONTEXT_OPTIONS_NONE, D2D1_FACTORY_OPTIONS,
+            ID2D1Factory1, ID2D1HwndRenderTarget, ID2D1PathGeometry, ID2D1RenderTarget,
+            ID2D1SolidColorBrush, ID2D1StrokeStyle, D2D1_ANTIALIAS_MODE_PER_PRIMITIVE,
+            D2D1_BITMAP_INTERPOLATION_MODE_NEAREST_NEIGHBOR, D2D1_BITMAP_OPTIONS_CANNOT_DRAW,
+            D2D1_BITMAP_OPTIONS_CPU_READ, D2D1_BITMAP_OPTIONS_TARGET, D2D1_BITMAP_PROPERTIES,
+            D2D1_BITMAP_PROPERTIES1, D2D1_CAP_STYLE_ROUND,
+            D2D1_DEVICE_CONTEXT_OPTIONS_ENABLE_MULTITHREADED_OPTIMIZATIONS,
+            D2D1_DEVICE_CONTEXT_OPTIONS_NONE, D2D1_ELLIPSE, D2D1_FACTORY_OPTIONS,
             D2D1_FACTORY_TYPE_SINGLE_THREADED, D2D1_HWND_RENDER_TARGET_PROPERTIES,
-            D2D1_RENDER_TARGET_PROPERTIES, D2D1_UNIT_MODE_DIPS, D2D1_UNIT_MODE_PIXELS,
+            D2D1_LINE_JOIN_ROUND, D2D1_RENDER_TARGET_PROPERTIES, D2D1_STROKE_STYLE_PROPERTIES,
+            D2D1_UNIT_MODE_PIXELS,
         },
         Dxgi::Common::*,
         Dxgi::*,
         Gdi::{
-            BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, GetDC, ReleaseDC,
-            SelectObject, HBITMAP, SRCCOPY,
+            BitBlt, CreateCompatibleBitmap, CreateCompatibleDC, DeleteDC, DeleteObject, GetDC,
+            GetDIBits, ReleaseDC, SelectObject, BITMAPINFO, BITMAPINFOHEADER, BI_RGB,
+            DIB_RGB_COLORS, HBITMAP, SRCCOPY,
         },
         Gdi::{CreateRectRgn, GetUpdateRect, SetWindowRgn, ValidateRect},
         Imaging::{
-            CLSID_WICImagingFactory, GUID_WICPixelFormat32bppPBGRA, IWICBitmap,
-            IWICFormatConverter, IWICImagingFactory, WICBitmapAlphaChannelOption,
-            WICBitmapDitherTypeNone, WICBitmapPaletteTypeMedianCut,
+            CLSID_WICImagingFactory, GUID_WICPixelFormat32bppPBGRA, GUID_WICPixelFormat32bppRGBA,
+            IWICBitmap, IWICFormatConverter, IWICImagingFactory, WICBitmapDitherTypeNone,
+            WICBitmapPaletteTypeMedianCut,
         },
     },
     System::Com::{CoCreateInstance, CoInitializeEx, CLSCTX_INPROC_SERVER, COINIT_MULTITHREADED},
@@ -78,6 +88,72 @@ pub fn capture_screen_to_bitmap() -> Result<HBITMAP> {
     }
 }
 
+/// Captures the whole virtual screen with GDI `BitBlt`.
+pub struct GdiBackend;
+
+impl CaptureBackend for GdiBackend {
+    fn capture(&self) -> Result<Frame> {
+        let hb_desktop = capture_screen_to_bitmap()?;
+        let frame = bitmap_to_frame(hb_desktop);
+        unsafe {
+            let _ = DeleteObject(hb_desktop);
+        }
+
+        let (x_left, y_top) = unsafe {
+            (
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+            )
+        };
+        Ok(frame?.with_origin(x_left, y_top))
+    }
+}
+
+fn bitmap_to_frame(hbitmap: HBITMAP) -> Result<Frame> {
+    unsafe {
+        let hdc = GetDC(HWND(0));
+        handle_error("DC error", ExpectedError::Win32, || hdc.0 == 0)?;
+
+        let mut info = BITMAPINFO {
+            bmiHeader: BITMAPINFOHEADER {
+                biSize: mem::size_of::<BITMAPINFOHEADER>() as u32,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // Without a buffer GetDIBits only fills in the bitmap dimensions
+        GetDIBits(hdc, hbitmap, 0, 0, None, &mut info, DIB_RGB_COLORS);
+        let width = info.bmiHeader.biWidth as u32;
+        let height = info.bmiHeader.biHeight.unsigned_abs();
+
+        info.bmiHeader.biBitCount = 32;
+        info.bmiHeader.biCompression = BI_RGB.0;
+        info.bmiHeader.biHeight = -(height as i32); // Negative height requests top-down rows
+
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        let lines = GetDIBits(
+            hdc,
+            hbitmap,
+            0,
+            height,
+            Some(pixels.as_mut_ptr() as *mut _),
+            &mut info,
+            DIB_RGB_COLORS,
+        );
+        ReleaseDC(HWND(0), hdc);
+        handle_error("GetDIBits error", ExpectedError::Win32, || lines == 0)?;
+
+        // GDI delivers BGRX, the frame is RGBA
+        for px in pixels.chunks_exact_mut(4) {
+            px.swap(0, 2);
+            px[3] = 255;
+        }
+
+        Frame::from_rgba(width, height, width as usize * 4, pixels)
+    }
+}
+
 fn initialize_com_library() -> Result<(), anyhow::Error> {
     handle_error(
         "Failed to initialize COM library",
@@ -97,20 +173,22 @@ pub fn draw_updated_area(win: HWND) -> Result<(), anyhow::Error> {
 
     renderer?.draw_updated_area(win)
 }
-pub fn render_screen_img(win: HWND) -> std::result::Result<(), Box<dyn std::error::Error>> {
+/// Captures the screen, paints it into `win` and hands the captured frame
+/// back so the selection can later be cropped from it.
+pub fn render_screen_img(win: HWND) -> std::result::Result<Frame, Box<dyn std::error::Error>> {
     // Initialize COM library
     let _ = initialize_com_library();
 
     let hr = create_wic_imaging_factory()?;
-    // Capture screen to bitmap
-    let hb_desktop = capture_screen_to_bitmap()?;
+    // Capture screen to frame
+    let frame = GdiBackend.capture()?;
 
     let BitmapConverter {
         converter,
         width,
         height,
         ..
-    } = BitmapConverter::new(&hr, hb_desktop)?;
+    } = BitmapConverter::new(&hr, &frame)?;
 
     let Direct2DFactory { rwt, .. } = Direct2DFactory::new(win, None, None)?;
 
@@ -126,23 +204,58 @@ pub fn render_screen_img(win: HWND) -> std::result::Result<(), Box<dyn std::erro
         // Handle error
     }
 
-    Ok(())
+    Ok(frame)
 }
 
 pub fn draw_rectangle(
     win: HWND,
     start: D2D_POINT_2F,
     end: D2D_POINT_2F,
+    annotations: &AnnotationLayer,
+    style: &OverlayStyle,
 ) -> Result<(), anyhow::Error> {
-    let start_x = start.x.min(end.x);
-    let start_y = start.y.min(end.y);
-    let width = (start.x.max(end.x) - start_x) as u32;
-    let height = (start.y.max(end.y) - start_y) as u32;
+    let pixels = Rect::from_points(Point::new(start.x, start.y), Point::new(end.x, end.y)).round();
+    let width = pixels.width() as u32;
+    let height = pixels.height() as u32;
 
     let Direct2DFactory { rwt, .. } = Direct2DFactory::new(win, Some(width), Some(height))?;
     let renderer = Renderer::new(rwt, win, width, height);
 
-    renderer?.draw_rectangle(start, end)
+    renderer?.draw_rectangle(start, end, annotations, style)
+}
+
+/// Draws a lasso or polygon outline, in client coordinates, over `win`.
+pub fn draw_shape(
+    win: HWND,
+    outline: &[Point],
+    closed: bool,
+    annotations: &AnnotationLayer,
+    style: &OverlayStyle,
+) -> Result<(), anyhow::Error> {
+    let Direct2DFactory { rwt, .. } = Direct2DFactory::new(win, None, None)?;
+    let size = unsafe { rwt.GetPixelSize() };
+    let renderer = Renderer::new(rwt, win, size.width, size.height);
+
+    renderer?.draw_shape(outline, closed, annotations, style)
+}
+
+/// Clears `win` and frames `rect`, in client coordinates, e.g. the region
+/// of a scrolling capture.
+pub fn draw_region_frame(win: HWND, rect: &Rect) -> Result<(), anyhow::Error> {
+    let Direct2DFactory { rwt, .. } = Direct2DFactory::new(win, None, None)?;
+    let size = unsafe { rwt.GetPixelSize() };
+    let renderer = Renderer::new(rwt, win, size.width, size.height);
+
+    renderer?.draw_region_frame(rect)
+}
+
+/// Clears `win` and shows the seconds left of a timed capture.
+pub fn draw_countdown(win: HWND, seconds: u32) -> Result<(), anyhow::Error> {
+    let Direct2DFactory { rwt, .. } = Direct2DFactory::new(win, None, None)?;
+    let size = unsafe { rwt.GetPixelSize() };
+    let renderer = Renderer::new(rwt, win, size.width, size.height);
+
+    renderer?.draw_countdown(seconds)
 }
 
 struct Direct2DFactory {
@@ -202,9 +315,15 @@ struct BitmapConverter {
 }
 
 impl BitmapConverter {
-    fn new(hr: &IWICImagingFactory, hb_desktop: HBITMAP) -> Result<Self, anyhow::Error> {
+    fn new(hr: &IWICImagingFactory, frame: &Frame) -> Result<Self, anyhow::Error> {
         let bitmap = unsafe {
-            hr.CreateBitmapFromHBITMAP(hb_desktop, None, WICBitmapAlphaChannelOption(0))?
+            hr.CreateBitmapFromMemory(
+                frame.width,
+                frame.height,
+                &GUID_WICPixelFormat32bppRGBA,
+                frame.stride as u32,
+                &frame.pixels,
+            )?
         };
 
         let (mut width, mut height) = (0u32, 0u32);
@@ -317,83 +436,293 @@ impl Renderer {
         Ok(())
     }
 
-    fn draw_rectangle(&self, start: D2D_POINT_2F, end: D2D_POINT_2F) -> Result<(), anyhow::Error> {
+    fn draw_rectangle(
+        &self,
+        start: D2D_POINT_2F,
+        end: D2D_POINT_2F,
+        annotations: &AnnotationLayer,
+        style: &OverlayStyle,
+    ) -> Result<(), anyhow::Error> {
         unsafe { self.target.BeginDraw() };
 
-        let rectangle = Rectangle {
-            start,
-            end,
-            color: D2D1_COLOR_F {
-                r: 41.0,
-                g: 41.0,
-                b: 41.0,
-                a: 0.5,
-            }, // Standardfarbe, anpassbar
+        let size = unsafe { self.target.GetSize() };
+        let overlay = SelectionOverlay {
+            selection: Rect::from_points(Point::new(start.x, start.y), Point::new(end.x, end.y)),
+            bounds: Rect::new(0.0, 0.0, size.width, size.height),
+            style: *style,
         };
+        overlay.draw(&self.target)?;
+        Drawable::draw(annotations, &self.target)?;
 
-        let col = D2D1_COLOR_F {
-            r: 1.0,
-            g: 1.0,
-            b: 1.0,
-            a: 5.0,
-        };
+        unsafe { self.target.EndDraw(None, None)? };
+        let hr = unsafe { self.swapchain.Present(4, 0) };
 
-        let rect = D2D_RECT_F {
-            left: start.x.min(end.x),
-            top: start.y.min(end.y),
-            right: start.x.max(end.x),
-            bottom: start.y.max(end.y),
-        };
+        Ok(())
+    }
+
+    fn draw_shape(
+        &self,
+        outline: &[Point],
+        closed: bool,
+        annotations: &AnnotationLayer,
+        style: &OverlayStyle,
+    ) -> Result<(), anyhow::Error> {
+        unsafe { self.target.BeginDraw() };
 
-        let trans = Matrix3x2::identity();
-        let brushprops = D2D1_BRUSH_PROPERTIES {
-            opacity: 1.0,
-            transform: trans,
+        let size = unsafe { self.target.GetSize() };
+        let overlay = ShapeOverlay {
+            outline: outline.to_vec(),
+            closed,
+            bounds: Rect::new(0.0, 0.0, size.width, size.height),
+            style: *style,
         };
+        overlay.draw(&self.target)?;
+        Drawable::draw(annotations, &self.target)?;
 
-        let b_ptr = &brushprops as *const D2D1_BRUSH_PROPERTIES;
+        unsafe { self.target.EndDraw(None, None)? };
+        let _ = unsafe { self.swapchain.Present(1, 0) };
 
-        let brush = unsafe { self.target.CreateSolidColorBrush(&col, Some(b_ptr))? };
+        Ok(())
+    }
+
+    fn draw_region_frame(&self, rect: &Rect) -> Result<(), anyhow::Error> {
+        unsafe {
+            self.target.BeginDraw();
+            // Black is the color key, only the frame stays visible
+            self.target.Clear(Some(&D2D1_COLOR_F {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }));
+        }
 
-        let rect_ptr = &rect as *const D2D_RECT_F;
-        unsafe { self.target.FillRectangle(rect_ptr, &brush) };
-        rectangle.draw(&self.target)?;
+        // Just outside the region so it never shows up in the region itself
+        let stroke = Stroke::new(Color::RED, 2.0, 1.0);
+        D2DCanvas::new(&self.target)?.stroke_rect(&rect.inflate(stroke.width), &stroke);
 
         unsafe { self.target.EndDraw(None, None)? };
-        let hr = unsafe { self.swapchain.Present(4, 0) };
+        let _ = unsafe { self.swapchain.Present(1, 0) };
+
+        Ok(())
+    }
+
+    fn draw_countdown(&self, seconds: u32) -> Result<(), anyhow::Error> {
+        unsafe {
+            self.target.BeginDraw();
+            // Black is the color key of the overlay while counting down
+            self.target.Clear(Some(&D2D1_COLOR_F {
+                r: 0.0,
+                g: 0.0,
+                b: 0.0,
+                a: 1.0,
+            }));
+        }
+
+        let size = unsafe { self.target.GetSize() };
+        let bounds = Rect::new(0.0, 0.0, size.width, size.height);
+        timer::draw_countdown(&mut D2DCanvas::new(&self.target)?, &bounds, seconds);
+
+        unsafe { self.target.EndDraw(None, None)? };
+        let _ = unsafe { self.swapchain.Present(1, 0) };
 
         Ok(())
     }
 }
-struct Rectangle {
-    start: D2D_POINT_2F,
-    end: D2D_POINT_2F,
-    color: D2D1_COLOR_F,
+
+/// Selection frame and handles, painted the same way as by the software
+/// renderer.
+struct SelectionOverlay {
+    selection: Rect,
+    bounds: Rect,
+    style: OverlayStyle,
 }
 
-impl Drawable for Rectangle {
+impl Drawable for SelectionOverlay {
     fn draw(&self, rwt: &ID2D1DeviceContext) -> Result<(), anyhow::Error> {
-        let rect = D2D_RECT_F {
-            left: self.start.x.min(self.end.x),
-            top: self.start.y.min(self.end.y),
-            right: self.start.x.max(self.end.x),
-            bottom: self.start.y.max(self.end.y),
+        let mut canvas = D2DCanvas::new(rwt)?;
+        draw_overlay(&mut canvas, &self.bounds, &self.selection, &self.style);
+        Ok(())
+    }
+}
+
+/// Lasso or polygon outline, open while it is being drawn.
+struct ShapeOverlay {
+    outline: Vec<Point>,
+    closed: bool,
+    bounds: Rect,
+    style: OverlayStyle,
+}
+
+impl Drawable for ShapeOverlay {
+    fn draw(&self, rwt: &ID2D1DeviceContext) -> Result<(), anyhow::Error> {
+        let mut canvas = D2DCanvas::new(rwt)?;
+        draw_shape_overlay(
+            &mut canvas,
+            &self.bounds,
+            &self.outline,
+            self.closed,
+            &self.style,
+        );
+        Ok(())
+    }
+}
+
+impl Drawable for AnnotationLayer {
+    fn draw(&self, rwt: &ID2D1DeviceContext) -> Result<(), anyhow::Error> {
+        AnnotationLayer::draw(self, &mut D2DCanvas::new(rwt)?);
+        Ok(())
+    }
+}
+
+/// [`Canvas`] backed by a Direct2D device context.
+///
+/// Canvas calls cannot fail, so resources that fail to create just skip
+/// the primitive.
+struct D2DCanvas<'a> {
+    target: &'a ID2D1DeviceContext,
+    factory: ID2D1Factory,
+    round: ID2D1StrokeStyle,
+}
+
+impl<'a> D2DCanvas<'a> {
+    fn new(target: &'a ID2D1DeviceContext) -> Result<Self, anyhow::Error> {
+        let factory = unsafe { target.GetFactory()? };
+        let round = D2D1_STROKE_STYLE_PROPERTIES {
+            startCap: D2D1_CAP_STYLE_ROUND,
+            endCap: D2D1_CAP_STYLE_ROUND,
+            lineJoin: D2D1_LINE_JOIN_ROUND,
+            ..Default::default()
         };
+        let round = unsafe { factory.CreateStrokeStyle(&round, None)? };
 
-        let brush_props = D2D1_BRUSH_PROPERTIES {
-            opacity: 1.0,
+        Ok(D2DCanvas {
+            target,
+            factory,
+            round,
+        })
+    }
 
-            transform: Default::default(),
+    fn brush(&self, color: Color, opacity: f32) -> Option<ID2D1SolidColorBrush> {
+        let color = D2D1_COLOR_F {
+            r: color.r as f32 / 255.0,
+            g: color.g as f32 / 255.0,
+            b: color.b as f32 / 255.0,
+            a: opacity.clamp(0.0, 1.0),
         };
+        unsafe { self.target.CreateSolidColorBrush(&color, None).ok() }
+    }
 
-        let brush_props_ptr: *const D2D1_BRUSH_PROPERTIES = &brush_props;
-        let brush = unsafe { rwt.CreateSolidColorBrush(&self.color, Some(brush_props_ptr))? };
+    fn path(&self, points: &[Point], closed: bool) -> Option<ID2D1PathGeometry> {
+        let points: Vec<D2D_POINT_2F> = points.iter().map(|p| to_d2d_point(*p)).collect();
+        let (first, rest) = points.split_first()?;
 
+        let geometry = unsafe { self.factory.CreatePathGeometry().ok()? };
+        let sink = unsafe { geometry.Open().ok()? };
         unsafe {
-            rwt.DrawRectangle(&rect, &brush, 2.0, None);
+            if closed {
+                sink.BeginFigure(*first, D2D1_FIGURE_BEGIN_FILLED);
+            } else {
+                sink.BeginFigure(*first, D2D1_FIGURE_BEGIN_HOLLOW);
+            }
+            sink.AddLines(rest);
+            sink.EndFigure(if closed {
+                D2D1_FIGURE_END_CLOSED
+            } else {
+                D2D1_FIGURE_END_OPEN
+            });
+            sink.Close().ok()?;
         }
+        Some(geometry)
+    }
+}
 
-        Ok(())
+fn to_d2d_point(p: Point) -> D2D_POINT_2F {
+    D2D_POINT_2F { x: p.x, y: p.y }
+}
+
+fn to_d2d_rect(rect: &Rect) -> D2D_RECT_F {
+    D2D_RECT_F {
+        left: rect.left,
+        top: rect.top,
+        right: rect.right,
+        bottom: rect.bottom,
+    }
+}
+
+fn to_d2d_ellipse(rect: &Rect) -> D2D1_ELLIPSE {
+    D2D1_ELLIPSE {
+        point: D2D_POINT_2F {
+            x: (rect.left + rect.right) / 2.0,
+            y: (rect.top + rect.bottom) / 2.0,
+        },
+        radiusX: rect.width() / 2.0,
+        radiusY: rect.height() / 2.0,
+    }
+}
+
+impl Canvas for D2DCanvas<'_> {
+    fn stroke_line(&mut self, from: Point, to: Point, stroke: &Stroke) {
+        if let Some(brush) = self.brush(stroke.color, stroke.opacity) {
+            unsafe {
+                self.target.DrawLine(
+                    to_d2d_point(from),
+                    to_d2d_point(to),
+                    &brush,
+                    stroke.width,
+                    &self.round,
+                )
+            };
+        }
+    }
+
+    fn stroke_rect(&mut self, rect: &Rect, stroke: &Stroke) {
+        if let Some(brush) = self.brush(stroke.color, stroke.opacity) {
+            unsafe {
+                self.target
+                    .DrawRectangle(&to_d2d_rect(rect), &brush, stroke.width, None)
+            };
+        }
+    }
+
+    fn stroke_ellipse(&mut self, rect: &Rect, stroke: &Stroke) {
+        if let Some(brush) = self.brush(stroke.color, stroke.opacity) {
+            unsafe {
+                self.target
+                    .DrawEllipse(&to_d2d_ellipse(rect), &brush, stroke.width, None)
+            };
+        }
+    }
+
+    fn fill_rect(&mut self, rect: &Rect, color: Color, opacity: f32) {
+        if let Some(brush) = self.brush(color, opacity) {
+            unsafe { self.target.FillRectangle(&to_d2d_rect(rect), &brush) };
+        }
+    }
+
+    fn fill_ellipse(&mut self, rect: &Rect, color: Color, opacity: f32) {
+        if let Some(brush) = self.brush(color, opacity) {
+            unsafe { self.target.FillEllipse(&to_d2d_ellipse(rect), &brush) };
+        }
+    }
+
+    fn fill_polygon(&mut self, points: &[Point], color: Color, opacity: f32) {
+        if let (Some(geometry), Some(brush)) = (self.path(points, true), self.brush(color, opacity))
+        {
+            unsafe { self.target.FillGeometry(&geometry, &brush, None) };
+        }
+    }
+
+    fn stroke_polyline(&mut self, points: &[Point], stroke: &Stroke) {
+        if let (Some(geometry), Some(brush)) = (
+            self.path(points, false),
+            self.brush(stroke.color, stroke.opacity),
+        ) {
+            unsafe {
+                self.target
+                    .DrawGeometry(&geometry, &brush, stroke.width, &self.round)
+            };
+        }
     }
 }
 
@@ -517,7 +846,9 @@ fn create_render_target(
         let target = d2device
             .CreateDeviceContext(D2D1_DEVICE_CONTEXT_OPTIONS_ENABLE_MULTITHREADED_OPTIMIZATIONS)?;
 
-        target.SetUnitMode(D2D1_UNIT_MODE_DIPS);
+        // The process is per-monitor DPI aware, so client coordinates are
+        // physical pixels; DIPs would scale every selection a second time
+        target.SetUnitMode(D2D1_UNIT_MODE_PIXELS);
 
         Ok(target)
     }
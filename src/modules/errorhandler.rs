@@ -1,19 +1,29 @@
 use anyhow::{anyhow, Result};
-use windows::core::Error;
+#[cfg(windows)]
+use std::fmt::Display;
+#[cfg(windows)]
+use windows::core::{w, Error, HSTRING};
+#[cfg(windows)]
+use windows::Win32::Foundation::HWND;
+#[cfg(windows)]
+use windows::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, MB_ICONERROR, MB_ICONINFORMATION, MB_OK, MESSAGEBOX_STYLE,
+};
 
+#[cfg(windows)]
 pub enum ExpectedError {
     Win32,
     Other,
 }
 
-pub fn handle_error<T, F>(
+#[cfg(windows)]
+pub fn handle_error<F>(
     error_message: &'static str,
     expected_error: ExpectedError,
     func: F,
-) -> Result<T>
+) -> Result<()>
 where
     F: Fn() -> bool,
-    T: Default, // Annahme, dass T einen Standardwert hat, den wir im Erfolgsfall zurückgeben können.
 {
     if func() {
         match expected_error {
@@ -26,10 +36,35 @@ where
             ExpectedError::Other => Err(anyhow!(error_message)),
         }
     } else {
-        Ok(T::default()) // Gibt den Standardwert von T zurück, wenn kein Fehler auftritt.
+        Ok(())
     }
 }
 
-pub fn throw_error<T>(error_message: &'static str) -> Result<T, anyhow::Error> {
-    Err(anyhow::anyhow!(error_message))
+pub fn throw_error<T>(error_message: &'static str) -> Result<T> {
+    Err(anyhow!(error_message))
+}
+
+/// Tells the user about `status` in a message box owned by `owner`, e.g.
+/// where a capture was saved.
+#[cfg(windows)]
+pub fn show_status(owner: HWND, status: impl Display) {
+    message_box(owner, status, MB_ICONINFORMATION);
+}
+
+/// Shows `error` in a message box owned by `owner`.
+#[cfg(windows)]
+pub fn show_error(owner: HWND, error: impl Display) {
+    message_box(owner, error, MB_ICONERROR);
+}
+
+#[cfg(windows)]
+fn message_box(owner: HWND, text: impl Display, icon: MESSAGEBOX_STYLE) {
+    unsafe {
+        MessageBoxW(
+            owner,
+            &HSTRING::from(text.to_string()),
+            w!("Snipping Tool"),
+            MB_OK | icon,
+        );
+    }
 }
@@ -0,0 +1,534 @@
+use crate::annotation::AnnotationLayer;
+use crate::capture::Frame;
+use crate::raster::flatten;
+use anyhow::{anyhow, Result};
+use image::codecs::{
+    bmp::BmpEncoder,
+    jpeg::JpegEncoder,
+    png::{CompressionType, FilterType, PngEncoder},
+    tiff::TiffEncoder,
+};
+use image::ColorType;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    WebP,
+    Bmp,
+    Tiff,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PngCompression {
+    Fast,
+    Default,
+    Best,
+}
+
+/// Per-format encoder settings; the variant also selects the format.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportOptions {
+    Png {
+        compression: PngCompression,
+    },
+    /// `quality` ranges from 1 (smallest) to 100 (best).
+    Jpeg {
+        quality: u8,
+    },
+    /// `quality` (0.0 - 100.0) is ignored for lossless output.
+    WebP {
+        lossless: bool,
+        quality: f32,
+    },
+    Bmp,
+    Tiff,
+}
+
+impl ExportOptions {
+    pub fn format(&self) -> ExportFormat {
+        match self {
+            ExportOptions::Png { .. } => ExportFormat::Png,
+            ExportOptions::Jpeg { .. } => ExportFormat::Jpeg,
+            ExportOptions::WebP { .. } => ExportFormat::WebP,
+            ExportOptions::Bmp => ExportFormat::Bmp,
+            ExportOptions::Tiff => ExportFormat::Tiff,
+        }
+    }
+
+    pub fn default_for(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Png => ExportOptions::Png {
+                compression: PngCompression::Default,
+            },
+            ExportFormat::Jpeg => ExportOptions::Jpeg { quality: 90 },
+            ExportFormat::WebP => ExportOptions::WebP {
+                lossless: true,
+                quality: 90.0,
+            },
+            ExportFormat::Bmp => ExportOptions::Bmp,
+            ExportFormat::Tiff => ExportOptions::Tiff,
+        }
+    }
+}
+
+/// Encodes a frame into one file format.
+pub trait FormatEncoder: Send + Sync {
+    fn format(&self) -> ExportFormat;
+    /// Lower-case file extensions without the dot; the first one is preferred.
+    fn extensions(&self) -> &'static [&'static str];
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>>;
+}
+
+struct PngFormat;
+struct JpegFormat;
+struct WebPFormat;
+struct BmpFormat;
+struct TiffFormat;
+
+impl FormatEncoder for PngFormat {
+    fn format(&self) -> ExportFormat {
+        ExportFormat::Png
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["png"]
+    }
+
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        let compression = match options {
+            ExportOptions::Png { compression } => *compression,
+            _ => return Err(options_mismatch(self.format(), options)),
+        };
+        let compression = match compression {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        };
+
+        let mut out = Vec::new();
+        PngEncoder::new_with_quality(&mut out, compression, FilterType::Sub).encode(
+            &frame.to_image(),
+            frame.width,
+            frame.height,
+            ColorType::Rgba8,
+        )?;
+        Ok(out)
+    }
+}
+
+impl FormatEncoder for JpegFormat {
+    fn format(&self) -> ExportFormat {
+        ExportFormat::Jpeg
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["jpg", "jpeg"]
+    }
+
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        let quality = match options {
+            ExportOptions::Jpeg { quality } => (*quality).clamp(1, 100),
+            _ => return Err(options_mismatch(self.format(), options)),
+        };
+
+        let mut out = Vec::new();
+        JpegEncoder::new_with_quality(&mut out, quality).encode(
+            &flatten_on_white(frame),
+            frame.width,
+            frame.height,
+            ColorType::Rgb8,
+        )?;
+        Ok(out)
+    }
+}
+
+impl FormatEncoder for WebPFormat {
+    fn format(&self) -> ExportFormat {
+        ExportFormat::WebP
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["webp"]
+    }
+
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        let (lossless, quality) = match options {
+            ExportOptions::WebP { lossless, quality } => (*lossless, quality.clamp(0.0, 100.0)),
+            _ => return Err(options_mismatch(self.format(), options)),
+        };
+
+        let pixels = frame.to_image();
+        let encoder = webp::Encoder::from_rgba(&pixels, frame.width, frame.height);
+        let encoded = if lossless {
+            encoder.encode_lossless()
+        } else {
+            encoder.encode(quality)
+        };
+        Ok(encoded.to_vec())
+    }
+}
+
+impl FormatEncoder for BmpFormat {
+    fn format(&self) -> ExportFormat {
+        ExportFormat::Bmp
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["bmp"]
+    }
+
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        if *options != ExportOptions::Bmp {
+            return Err(options_mismatch(self.format(), options));
+        }
+
+        let mut out = Vec::new();
+        BmpEncoder::new(&mut out).encode(
+            &frame.to_image(),
+            frame.width,
+            frame.height,
+            ColorType::Rgba8,
+        )?;
+        Ok(out)
+    }
+}
+
+impl FormatEncoder for TiffFormat {
+    fn format(&self) -> ExportFormat {
+        ExportFormat::Tiff
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["tif", "tiff"]
+    }
+
+    fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        if *options != ExportOptions::Tiff {
+            return Err(options_mismatch(self.format(), options));
+        }
+
+        let mut out = Cursor::new(Vec::new());
+        TiffEncoder::new(&mut out).encode(
+            &frame.to_image(),
+            frame.width,
+            frame.height,
+            ColorType::Rgba8,
+        )?;
+        Ok(out.into_inner())
+    }
+}
+
+fn options_mismatch(format: ExportFormat, options: &ExportOptions) -> anyhow::Error {
+    anyhow!(
+        "{:?} options passed to the {:?} encoder",
+        options.format(),
+        format
+    )
+}
+
+/// JPEG has no alpha channel, so transparent pixels are composited on white.
+fn flatten_on_white(frame: &Frame) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(frame.width as usize * frame.height as usize * 3);
+    for y in 0..frame.height {
+        for px in frame.row(y).chunks_exact(4) {
+            let alpha = px[3] as u32;
+            for &channel in &px[..3] {
+                rgb.push(((channel as u32 * alpha + 255 * (255 - alpha)) / 255) as u8);
+            }
+        }
+    }
+    rgb
+}
+
+/// Registry of the available output formats.
+///
+/// Formats are looked up either explicitly through [`ExportOptions`] or by
+/// the extension of the target path.
+pub struct Exporter {
+    encoders: Vec<Box<dyn FormatEncoder>>,
+}
+
+impl Default for Exporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Exporter {
+    /// Creates a registry with PNG, JPEG, WebP, BMP and TIFF support.
+    pub fn new() -> Self {
+        let mut exporter = Exporter {
+            encoders: Vec::new(),
+        };
+        exporter
+            .register(Box::new(PngFormat))
+            .register(Box::new(JpegFormat))
+            .register(Box::new(WebPFormat))
+            .register(Box::new(BmpFormat))
+            .register(Box::new(TiffFormat));
+        exporter
+    }
+
+    /// Adds an encoder, replacing any previous one for the same format.
+    pub fn register(&mut self, encoder: Box<dyn FormatEncoder>) -> &mut Self {
+        self.encoders.retain(|e| e.format() != encoder.format());
+        self.encoders.push(encoder);
+        self
+    }
+
+    pub fn formats(&self) -> Vec<ExportFormat> {
+        self.encoders.iter().map(|e| e.format()).collect()
+    }
+
+    pub fn format_for_path(&self, path: &Path) -> Result<ExportFormat> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .ok_or_else(|| anyhow!("{} has no file extension", path.display()))?;
+        self.format_for_extension(&extension)
+    }
+
+    /// Format of a file extension such as `"png"` or `"JPG"`, without dot.
+    pub fn format_for_extension(&self, extension: &str) -> Result<ExportFormat> {
+        let extension = extension.to_ascii_lowercase();
+        self.encoders
+            .iter()
+            .find(|e| e.extensions().contains(&extension.as_str()))
+            .map(|e| e.format())
+            .ok_or_else(|| anyhow!("No encoder registered for .{}", extension))
+    }
+
+    pub fn extension_for(&self, format: ExportFormat) -> Result<&'static str> {
+        Ok(self.encoder(format)?.extensions()[0])
+    }
+
+    pub fn encode(&self, frame: &Frame, options: &ExportOptions) -> Result<Vec<u8>> {
+        self.encoder(options.format())?.encode(frame, options)
+    }
+
+    /// Writes `frame` with the default options of the format implied by the
+    /// extension of `path`.
+    pub fn save(&self, frame: &Frame, path: &Path) -> Result<()> {
+        let format = self.format_for_path(path)?;
+        self.save_with(frame, path, &ExportOptions::default_for(format))
+    }
+
+    pub fn save_with(&self, frame: &Frame, path: &Path, options: &ExportOptions) -> Result<()> {
+        let bytes = self.encode(frame, options)?;
+        fs::write(path, bytes).map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Writes `frame` with `annotations` and redactions burned into the
+    /// pixels, so nothing covered by a redaction can be recovered.
+    pub fn save_flattened(
+        &self,
+        frame: &Frame,
+        annotations: &AnnotationLayer,
+        path: &Path,
+    ) -> Result<()> {
+        self.save(&flatten(frame, annotations), path)
+    }
+
+    fn encoder(&self, format: ExportFormat) -> Result<&dyn FormatEncoder> {
+        self.encoders
+            .iter()
+            .find(|e| e.format() == format)
+            .map(|e| e.as_ref())
+            .ok_or_else(|| anyhow!("No encoder registered for {:?}", format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::Shape;
+    use crate::geometry::Rect;
+    use crate::redact::{Redaction, RedactionStyle};
+    use std::path::PathBuf;
+
+    /// Empty directory for one test, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("export_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Every pixel different, with opaque, translucent and clear ones.
+    fn gradient() -> Frame {
+        let mut frame = Frame::new(17, 9);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let alpha = [255, 128, 0][(x as usize + y as usize) % 3];
+                frame.put_pixel(x, y, [x as u8 * 15, y as u8 * 28, 77, alpha]);
+            }
+        }
+        frame
+    }
+
+    fn load(path: &Path) -> Frame {
+        Frame::from_image(image::open(path).unwrap().to_rgba8())
+    }
+
+    #[test]
+    fn lossless_formats_round_trip() {
+        let dir = TempDir::new("lossless");
+        let frame = gradient();
+        for name in ["a.png", "a.bmp", "a.tif", "a.TIFF"] {
+            let path = dir.0.join(name);
+            Exporter::new().save(&frame, &path).unwrap();
+            assert_eq!(load(&path).pixels, frame.pixels, "{}", name);
+        }
+    }
+
+    #[test]
+    fn every_png_compression_is_lossless() {
+        let dir = TempDir::new("png");
+        let frame = gradient();
+        for compression in [
+            PngCompression::Fast,
+            PngCompression::Default,
+            PngCompression::Best,
+        ] {
+            let path = dir.0.join("a.png");
+            Exporter::new()
+                .save_with(&frame, &path, &ExportOptions::Png { compression })
+                .unwrap();
+            assert_eq!(load(&path).pixels, frame.pixels, "{:?}", compression);
+        }
+    }
+
+    #[test]
+    fn lossless_webp_keeps_every_visible_pixel() {
+        let frame = gradient();
+        let bytes = Exporter::new()
+            .encode(&frame, &ExportOptions::default_for(ExportFormat::WebP))
+            .unwrap();
+        let decoded = webp::Decoder::new(&bytes).decode().unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (17, 9));
+        // libwebp drops the color of clear pixels
+        for (actual, expected) in decoded.chunks_exact(4).zip(frame.pixels.chunks_exact(4)) {
+            match expected[3] {
+                0 => assert_eq!(actual[3], 0),
+                _ => assert_eq!(actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn jpeg_composites_alpha_on_white() {
+        let dir = TempDir::new("jpeg");
+        // 16x16 blocks: clear red, half-transparent black, opaque blue
+        let mut frame = Frame::new(48, 16);
+        for y in 0..16 {
+            for x in 0..48 {
+                let pixel = match x / 16 {
+                    0 => [255, 0, 0, 0],
+                    1 => [0, 0, 0, 128],
+                    _ => [0, 0, 255, 255],
+                };
+                frame.put_pixel(x, y, pixel);
+            }
+        }
+        let path = dir.0.join("a.jpeg");
+        Exporter::new().save(&frame, &path).unwrap();
+        let decoded = load(&path);
+
+        let near = |actual: [u8; 4], expected: [u8; 3]| {
+            actual[3] == 255
+                && actual[..3]
+                    .iter()
+                    .zip(expected)
+                    .all(|(&a, e)| a.abs_diff(e) <= 8)
+        };
+        let white = decoded.pixel(8, 8);
+        let gray = decoded.pixel(24, 8);
+        let blue = decoded.pixel(40, 8);
+        assert!(near(white, [255, 255, 255]), "{:?}", white);
+        assert!(near(gray, [127, 127, 127]), "{:?}", gray);
+        assert!(near(blue, [0, 0, 255]), "{:?}", blue);
+    }
+
+    #[test]
+    fn flatten_on_white_blends_exactly() {
+        let frame = Frame::from_rgba(
+            3,
+            1,
+            12,
+            vec![200, 100, 0, 0, 200, 100, 0, 255, 0, 0, 0, 51],
+        )
+        .unwrap();
+        assert_eq!(
+            flatten_on_white(&frame),
+            [255, 255, 255, 200, 100, 0, 204, 204, 204]
+        );
+    }
+
+    #[test]
+    fn unknown_extensions_are_refused() {
+        let dir = TempDir::new("unknown");
+        let frame = gradient();
+        let path = dir.0.join("a.gif");
+        let error = Exporter::new().save(&frame, &path).unwrap_err();
+        assert_eq!(error.to_string(), "No encoder registered for .gif");
+        assert!(!path.exists());
+
+        let error = Exporter::new()
+            .save(&frame, &dir.0.join("a"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.ends_with("has no file extension"), "{}", error);
+    }
+
+    #[test]
+    fn extensions_map_to_formats() {
+        let exporter = Exporter::new();
+        assert_eq!(
+            exporter.format_for_extension("JPG").unwrap(),
+            ExportFormat::Jpeg
+        );
+        assert_eq!(
+            exporter.format_for_path(Path::new("x/shot.tif")).unwrap(),
+            ExportFormat::Tiff
+        );
+        assert_eq!(exporter.extension_for(ExportFormat::Jpeg).unwrap(), "jpg");
+        assert_eq!(exporter.formats().len(), 5);
+    }
+
+    #[test]
+    fn saved_flattened_redactions_leave_nothing_behind() {
+        let dir = TempDir::new("flattened");
+        let frame = gradient();
+        let mut annotations = AnnotationLayer::new();
+        annotations.push(
+            Shape::Redaction(Redaction::new(
+                Rect::new(0.0, 0.0, 4.0, 4.0),
+                RedactionStyle::blackout(),
+            ))
+            .into_annotation(),
+        );
+        let path = dir.0.join("a.png");
+        Exporter::new()
+            .save_flattened(&frame, &annotations, &path)
+            .unwrap();
+
+        let saved = load(&path);
+        assert_eq!(saved.pixel(2, 2), [0, 0, 0, 255]);
+        assert_eq!(saved.pixel(10, 5), frame.pixel(10, 5));
+    }
+}
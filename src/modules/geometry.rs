@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+
+/// Point in window-client coordinates unless stated otherwise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Point {
+    pub const fn new(x: f32, y: f32) -> Self {
+        Point { x, y }
+    }
+}
+
+/// Shortest distance from `p` to the segment from `a` to `b`.
+pub fn distance_to_segment(p: Point, a: Point, b: Point) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length_sq = dx * dx + dy * dy;
+    let t = if length_sq == 0.0 {
+        0.0
+    } else {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length_sq).clamp(0.0, 1.0)
+    };
+    (p.x - (a.x + t * dx)).hypot(p.y - (a.y + t * dy))
+}
+
+/// Axis-aligned rectangle with `left <= right` and `top <= bottom`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+impl Rect {
+    pub const fn new(left: f32, top: f32, right: f32, bottom: f32) -> Self {
+        Rect {
+            left,
+            top,
+            right,
+            bottom,
+        }
+    }
+
+    /// Spans the two points regardless of the drag direction.
+    pub fn from_points(a: Point, b: Point) -> Self {
+        Rect {
+            left: a.x.min(b.x),
+            top: a.y.min(b.y),
+            right: a.x.max(b.x),
+            bottom: a.y.max(b.y),
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.right - self.left
+    }
+
+    pub fn height(&self) -> f32 {
+        self.bottom - self.top
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width() <= 0.0 || self.height() <= 0.0
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        p.x >= self.left && p.x < self.right && p.y >= self.top && p.y < self.bottom
+    }
+
+    pub fn union(&self, other: &Rect) -> Rect {
+        Rect {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    pub fn translate(&self, dx: f32, dy: f32) -> Rect {
+        Rect {
+            left: self.left + dx,
+            top: self.top + dy,
+            right: self.right + dx,
+            bottom: self.bottom + dy,
+        }
+    }
+
+    /// Multiplies all coordinates by `factor`, e.g. to go from logical
+    /// units to physical pixels.
+    pub fn scale(&self, factor: f32) -> Rect {
+        Rect {
+            left: self.left * factor,
+            top: self.top * factor,
+            right: self.right * factor,
+            bottom: self.bottom * factor,
+        }
+    }
+
+    /// Moves every edge to the nearest whole number, halves rounding up.
+    ///
+    /// Edges are rounded independently, so rectangles sharing an edge still
+    /// share it afterwards and never overlap or leave a gap.
+    pub fn round(&self) -> Rect {
+        let round = |v: f32| (v + 0.5).floor();
+        Rect {
+            left: round(self.left),
+            top: round(self.top),
+            right: round(self.right),
+            bottom: round(self.bottom),
+        }
+    }
+
+    /// Grows the rectangle by `amount` on every side.
+    pub fn inflate(&self, amount: f32) -> Rect {
+        Rect {
+            left: self.left - amount,
+            top: self.top - amount,
+            right: self.right + amount,
+            bottom: self.bottom + amount,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_moves_edges_to_the_nearest_pixel() {
+        assert_eq!(
+            Rect::new(0.4, 0.5, 10.49, 10.5).round(),
+            Rect::new(0.0, 1.0, 10.0, 11.0)
+        );
+        // Halves round up on the negative side too
+        assert_eq!(
+            Rect::new(-2.5, -0.6, -0.5, 0.0).round(),
+            Rect::new(-2.0, -1.0, 0.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn rounded_neighbours_tile_at_fractional_scales() {
+        let cuts = [0.0, 3.3, 7.0, 10.5, 13.1, 21.7, 40.2, 50.0];
+        for scale in [1.25, 1.5] {
+            let tiles: Vec<Rect> = cuts
+                .windows(2)
+                .map(|edge| Rect::new(edge[0], 0.0, edge[1], 1.0).scale(scale).round())
+                .collect();
+            assert_eq!(tiles[0].left, 0.0);
+            assert_eq!(tiles.last().unwrap().right, (50.0 * scale).round());
+            for pair in tiles.windows(2) {
+                assert_eq!(pair[0].right, pair[1].left, "at scale {}", scale);
+            }
+        }
+    }
+
+    #[test]
+    fn distance_to_segment_clamps_to_the_ends() {
+        let (a, b) = (Point::new(0.0, 0.0), Point::new(10.0, 0.0));
+        assert_eq!(distance_to_segment(Point::new(5.0, 3.0), a, b), 3.0);
+        assert_eq!(distance_to_segment(Point::new(13.0, 4.0), a, b), 5.0);
+        assert_eq!(distance_to_segment(Point::new(-3.0, -4.0), a, b), 5.0);
+        // A segment without length is a point
+        assert_eq!(distance_to_segment(Point::new(3.0, 4.0), a, a), 5.0);
+    }
+}
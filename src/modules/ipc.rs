@@ -0,0 +1,610 @@
+use crate::geometry::Rect;
+use crate::monitor::CaptureRegion;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Named pipe the running overlay listens on by default.
+pub const PIPE_NAME: &str = r"\\.\pipe\snipping_tool";
+/// Socket file the running overlay listens on by default, placed in the
+/// runtime or temporary directory.
+pub const SOCKET_FILE: &str = "snipping_tool.sock";
+
+/// Where clients reach the running overlay if nothing else is configured:
+/// a named pipe on Windows, a Unix socket elsewhere.
+pub fn default_address() -> String {
+    if cfg!(windows) {
+        PIPE_NAME.to_string()
+    } else {
+        dirs::runtime_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(SOCKET_FILE)
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// What a client can ask the running overlay to do.
+///
+/// On the wire a command is its snake_case name with its fields as `args`,
+/// e.g. `{"command": "capture_region", "args": {"rect": {...}}}`. Rectangles
+/// are physical virtual-screen coordinates; captures without a `path` go to
+/// a time-stamped file in the capture directory.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", content = "args", rename_all = "snake_case")]
+pub enum IpcCommand {
+    /// Answers `"pong"`, to check the overlay is running.
+    Ping,
+    Show,
+    Hide,
+    /// Captures the screen again and shows the overlay over it.
+    Reload,
+    TriggerScreenshot,
+    /// Saves `rect` of the live screen. Answers `{"path": ...}`.
+    CaptureRegion {
+        rect: Rect,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Saves monitors of the live screen. Answers `{"path": ...}`.
+    CaptureToFile {
+        region: CaptureRegion,
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+    /// Runs OCR on `rect` of the capture shown by the overlay, on all of it
+    /// if unset. Answers `{"text": ...}`.
+    RecognizeText {
+        #[serde(default)]
+        rect: Option<Rect>,
+    },
+    /// Covers sensitive data in the capture shown by the overlay. Answers
+    /// `{"count": ..., "report": {"findings": [...], "unlocated": [...]}}`.
+    RedactSensitive,
+    /// Answers `{"path": ...}`, `null` if nothing was saved yet.
+    LastCapturePath,
+    /// Answers `{"text": ...}`, `null` if no text was recognized yet.
+    LastOcrText,
+    /// Closes the overlay.
+    Quit,
+}
+
+/// One line sent by a client. `id` is any JSON value and comes back with
+/// the response, so clients can match answers to requests.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Request {
+    pub id: Value,
+    #[serde(flatten)]
+    pub command: IpcCommand,
+}
+
+/// A request as sent, before `args` is checked against the command.
+#[derive(Deserialize)]
+struct RawRequest {
+    #[serde(default)]
+    id: Value,
+    command: String,
+    args: Option<Value>,
+}
+
+impl Request {
+    pub fn new(id: impl Into<Value>, command: IpcCommand) -> Self {
+        Request {
+            id: id.into(),
+            command,
+        }
+    }
+
+    /// Reads a request from one line of JSON; `args` may be left out for
+    /// commands without required fields.
+    pub fn parse(line: &str) -> Result<Request> {
+        let raw: RawRequest =
+            serde_json::from_str(line).map_err(|e| anyhow!("Invalid request: {}", e))?;
+        let parse = |args: Value| {
+            serde_json::from_value(json!({ "command": raw.command, "args": args }))
+                .map_err(|e| anyhow!("Invalid command {}: {}", raw.command, e))
+        };
+        let command = match raw.args {
+            Some(args) => parse(args)?,
+            None => parse(Value::Null).or_else(|_| parse(json!({})))?,
+        };
+        Ok(Request {
+            id: raw.id,
+            command,
+        })
+    }
+}
+
+/// The answer to one request: either `result` or `error` is set.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    #[serde(default)]
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Response {
+    pub fn ok(id: Value, result: Value) -> Self {
+        Response {
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    pub fn error(id: Value, error: impl ToString) -> Self {
+        Response {
+            id,
+            result: None,
+            error: Some(error.to_string()),
+        }
+    }
+
+    pub fn into_result(self) -> Result<Value> {
+        match self.error {
+            Some(error) => Err(anyhow!(error)),
+            None => Ok(self.result.unwrap_or(Value::Null)),
+        }
+    }
+}
+
+/// Carries out the commands of all connections; called from the threads
+/// serving them.
+pub trait Handler: Send + Sync {
+    fn handle(&self, command: IpcCommand) -> Result<Value>;
+}
+
+/// Answers one line; invalid requests get an error with their id, `null`
+/// if even that cannot be read.
+pub fn respond(line: &str, handler: &dyn Handler) -> Response {
+    match Request::parse(line) {
+        Ok(request) => match handler.handle(request.command) {
+            Ok(result) => Response::ok(request.id, result),
+            Err(e) => Response::error(request.id, e),
+        },
+        Err(e) => {
+            let id = serde_json::from_str::<Value>(line)
+                .ok()
+                .and_then(|request| request.get("id").cloned())
+                .unwrap_or_default();
+            Response::error(id, e)
+        }
+    }
+}
+
+/// Answers requests line by line until the client hangs up.
+pub fn serve_connection(
+    reader: impl BufRead,
+    mut writer: impl Write,
+    handler: &dyn Handler,
+) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(&line, handler);
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Starts serving `address` on a background thread, one more thread per
+/// connection. Fails right away if the address cannot be listened on,
+/// e.g. because another instance already does.
+pub fn listen(address: &str, handler: Arc<dyn Handler>) -> Result<JoinHandle<()>> {
+    #[cfg(windows)]
+    return pipe::listen(address, handler);
+    #[cfg(unix)]
+    return socket::listen(address, handler);
+    #[cfg(not(any(windows, unix)))]
+    return Err(anyhow!("No local transport to listen on {}", address));
+}
+
+/// Serves one connection on its own thread; streams like sockets read and
+/// write through shared references.
+fn serve_in_thread<S>(stream: S, handler: Arc<dyn Handler>)
+where
+    S: Send + 'static,
+    for<'a> &'a S: Read + Write,
+{
+    thread::spawn(move || {
+        if let Err(e) = serve_connection(BufReader::new(&stream), &stream, handler.as_ref()) {
+            eprintln!("Control connection failed: {}", e);
+        }
+    });
+}
+
+#[cfg(unix)]
+mod socket {
+    use super::{serve_in_thread, Handler};
+    use anyhow::{anyhow, Result};
+    use std::os::unix::fs::FileTypeExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+
+    pub fn listen(address: &str, handler: Arc<dyn Handler>) -> Result<JoinHandle<()>> {
+        let path = Path::new(address);
+        if let Ok(metadata) = path.symlink_metadata() {
+            if !metadata.file_type().is_socket() {
+                return Err(anyhow!("{} exists and is not a socket", address));
+            }
+            // A socket nobody answers on is left over from a crashed instance
+            if UnixStream::connect(path).is_err() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| anyhow!("Listening on {} failed: {}", address, e))?;
+        Ok(thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => serve_in_thread(stream, handler.clone()),
+                    Err(e) => eprintln!("Accepting control connection failed: {}", e),
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(windows)]
+mod pipe {
+    use super::{serve_in_thread, Handler};
+    use anyhow::{anyhow, Result};
+    use std::io::{self, Read, Write};
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::{
+        CloseHandle, ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED, HANDLE,
+    };
+    use windows::Win32::Storage::FileSystem::{
+        FlushFileBuffers, ReadFile, WriteFile, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+    };
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, PIPE_READMODE_BYTE,
+        PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    };
+
+    const BUFFER_SIZE: u32 = 64 * 1024;
+    /// Pause after a failed connection, so a persistent error does not spin.
+    const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    /// Server end of one pipe instance.
+    struct Pipe(HANDLE);
+
+    // The handle is owned by the one thread serving the connection
+    unsafe impl Send for Pipe {}
+    unsafe impl Sync for Pipe {}
+
+    impl Pipe {
+        /// Creates an instance of the pipe `name`; the `first` one fails if
+        /// the name is taken, e.g. by another running overlay.
+        fn create(name: &HSTRING, first: bool) -> Result<Pipe> {
+            let mut open_mode = PIPE_ACCESS_DUPLEX;
+            if first {
+                open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+            }
+            let handle = unsafe {
+                CreateNamedPipeW(
+                    name,
+                    open_mode,
+                    PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                    PIPE_UNLIMITED_INSTANCES,
+                    BUFFER_SIZE,
+                    BUFFER_SIZE,
+                    0,
+                    None,
+                )
+            };
+            if handle.is_invalid() {
+                return Err(anyhow!(
+                    "Creating pipe {} failed: {}",
+                    name,
+                    windows::core::Error::from_win32()
+                ));
+            }
+            Ok(Pipe(handle))
+        }
+
+        /// Waits for a client.
+        fn connect(&self) -> Result<()> {
+            match unsafe { ConnectNamedPipe(self.0, None) } {
+                // The client came between creating and waiting
+                Err(e) if e.code() == ERROR_PIPE_CONNECTED.to_hresult() => Ok(()),
+                result => Ok(result?),
+            }
+        }
+    }
+
+    impl Read for &Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut read = 0;
+            match unsafe { ReadFile(self.0, Some(buf), Some(&mut read), None) } {
+                Ok(()) => Ok(read as usize),
+                // The client hung up
+                Err(e) if e.code() == ERROR_BROKEN_PIPE.to_hresult() => Ok(0),
+                Err(e) => Err(io::Error::other(e)),
+            }
+        }
+    }
+
+    impl Write for &Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut written = 0;
+            unsafe { WriteFile(self.0, Some(buf), Some(&mut written), None) }
+                .map_err(io::Error::other)?;
+            Ok(written as usize)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            unsafe { FlushFileBuffers(self.0) }.map_err(io::Error::other)
+        }
+    }
+
+    impl Drop for Pipe {
+        fn drop(&mut self) {
+            unsafe {
+                let _ = DisconnectNamedPipe(self.0);
+                let _ = CloseHandle(self.0);
+            }
+        }
+    }
+
+    pub fn listen(address: &str, handler: Arc<dyn Handler>) -> Result<JoinHandle<()>> {
+        let name = HSTRING::from(address);
+        // The first instance is created here so errors reach the caller
+        let mut next = Pipe::create(&name, true)?;
+        Ok(thread::spawn(move || loop {
+            let connected = next.connect();
+            // The next instance is created before the old one is closed, so
+            // the name stays taken
+            let pipe = match Pipe::create(&name, false) {
+                Ok(pipe) => std::mem::replace(&mut next, pipe),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return;
+                }
+            };
+            match connected {
+                Ok(()) => serve_in_thread(pipe, handler.clone()),
+                // E.g. a client that hung up right away; the instance is
+                // dropped as it cannot be connected again
+                Err(e) => {
+                    eprintln!("Accepting control connection failed: {}", e);
+                    thread::sleep(RETRY_DELAY);
+                }
+            }
+        }))
+    }
+}
+
+/// Connection to a running overlay.
+pub struct Client {
+    reader: BufReader<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    next_id: u64,
+}
+
+impl Client {
+    /// Connects to the overlay listening on `address`, see
+    /// [`default_address`].
+    pub fn connect(address: &str) -> Result<Client> {
+        let error = |e: io::Error| anyhow!("Connecting to {} failed: {}", address, e);
+        #[cfg(unix)]
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = {
+            let stream = std::os::unix::net::UnixStream::connect(address).map_err(error)?;
+            (Box::new(stream.try_clone()?), Box::new(stream))
+        };
+        #[cfg(not(unix))]
+        let (reader, writer): (Box<dyn Read + Send>, Box<dyn Write + Send>) = {
+            let pipe = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(address)
+                .map_err(error)?;
+            (Box::new(pipe.try_clone()?), Box::new(pipe))
+        };
+        Ok(Client::new(reader, writer))
+    }
+
+    /// A client talking over any pair of streams.
+    pub fn new(reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>) -> Self {
+        Client {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 1,
+        }
+    }
+
+    /// Sends `command` and waits for its result.
+    pub fn request(&mut self, command: IpcCommand) -> Result<Value> {
+        let request = Request::new(self.next_id, command);
+        self.next_id += 1;
+        serde_json::to_writer(&mut self.writer, &request)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(anyhow!("Connection closed before the response"));
+        }
+        let response: Response =
+            serde_json::from_str(&line).map_err(|e| anyhow!("Invalid response: {}", e))?;
+        if response.id != request.id {
+            return Err(anyhow!(
+                "Response to request {} instead of {}",
+                response.id,
+                request.id
+            ));
+        }
+        response.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers pings and records everything else it is asked.
+    #[derive(Default)]
+    struct Stub {
+        handled: Mutex<Vec<IpcCommand>>,
+    }
+
+    impl Handler for Stub {
+        fn handle(&self, command: IpcCommand) -> Result<Value> {
+            match command {
+                IpcCommand::Ping => Ok(json!("pong")),
+                IpcCommand::Quit => Err(anyhow!("Not now")),
+                command => {
+                    self.handled.lock().unwrap().push(command);
+                    Ok(Value::Null)
+                }
+            }
+        }
+    }
+
+    fn response(line: &str) -> Value {
+        serde_json::to_value(respond(line, &Stub::default())).unwrap()
+    }
+
+    #[test]
+    fn parse_reads_commands_with_and_without_args() {
+        assert_eq!(
+            Request::parse(r#"{"id": 1, "command": "ping"}"#).unwrap(),
+            Request::new(1, IpcCommand::Ping)
+        );
+        assert_eq!(
+            Request::parse(r#"{"id": "a", "command": "hide", "args": null}"#).unwrap(),
+            Request::new("a", IpcCommand::Hide)
+        );
+        // Struct commands whose fields are all optional need no args
+        assert_eq!(
+            Request::parse(r#"{"command": "recognize_text"}"#).unwrap(),
+            Request::new(Value::Null, IpcCommand::RecognizeText { rect: None })
+        );
+        let rect = Rect::new(1.0, 2.0, 3.0, 4.0);
+        assert_eq!(
+            Request::parse(
+                r#"{"id": 2, "command": "capture_region",
+                    "args": {"rect": {"left": 1, "top": 2, "right": 3, "bottom": 4}}}"#
+            )
+            .unwrap(),
+            Request::new(2, IpcCommand::CaptureRegion { rect, path: None })
+        );
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let request = Request::new(
+            7,
+            IpcCommand::CaptureToFile {
+                region: CaptureRegion::Monitor { index: 1 },
+                path: Some(PathBuf::from("a.png")),
+            },
+        );
+        let line = serde_json::to_string(&request).unwrap();
+        assert_eq!(Request::parse(&line).unwrap(), request);
+    }
+
+    #[test]
+    fn parse_rejects_bad_requests() {
+        assert!(Request::parse("not json").is_err());
+        assert!(Request::parse(r#"{"command": "dance"}"#).is_err());
+        let missing = Request::parse(r#"{"command": "capture_region"}"#).unwrap_err();
+        assert!(missing
+            .to_string()
+            .starts_with("Invalid command capture_region"));
+    }
+
+    #[test]
+    fn respond_keeps_the_id() {
+        assert_eq!(
+            response(r#"{"id": 3, "command": "ping"}"#),
+            json!({"id": 3, "result": "pong"})
+        );
+        assert_eq!(
+            response(r#"{"id": 4, "command": "quit"}"#),
+            json!({"id": 4, "error": "Not now"})
+        );
+        let unknown = response(r#"{"id": "x", "command": "dance"}"#);
+        assert_eq!(unknown["id"], json!("x"));
+        assert!(unknown["error"].is_string());
+        let malformed = response("{");
+        assert_eq!(malformed["id"], Value::Null);
+        assert!(malformed["error"].is_string());
+    }
+
+    #[test]
+    fn serve_connection_answers_each_line() {
+        let input = concat!(
+            "{\"id\": 1, \"command\": \"ping\"}\n",
+            "\n",
+            "{\"id\": 2, \"command\": \"show\"}\n",
+        );
+        let stub = Stub::default();
+        let mut output = Vec::new();
+        serve_connection(input.as_bytes(), &mut output, &stub).unwrap();
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            responses,
+            vec![
+                json!({"id": 1, "result": "pong"}),
+                json!({"id": 2, "result": null}),
+            ]
+        );
+        assert_eq!(*stub.handled.lock().unwrap(), vec![IpcCommand::Show]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn client_talks_to_a_listening_socket() {
+        let path = std::env::temp_dir().join(format!("ipc_test_{}.sock", std::process::id()));
+        let address = path.to_string_lossy().into_owned();
+        let _ = std::fs::remove_file(&path);
+        let stub = Arc::new(Stub::default());
+        listen(&address, stub.clone()).unwrap();
+
+        let mut client = Client::connect(&address).unwrap();
+        assert_eq!(client.request(IpcCommand::Ping).unwrap(), json!("pong"));
+        assert_eq!(
+            client.request(IpcCommand::Quit).unwrap_err().to_string(),
+            "Not now"
+        );
+        client.request(IpcCommand::LastOcrText).unwrap();
+        assert_eq!(*stub.handled.lock().unwrap(), vec![IpcCommand::LastOcrText]);
+
+        // A second overlay cannot take over a socket that answers
+        assert!(listen(&address, stub).is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn listen_leaves_files_that_are_not_sockets() {
+        let path = std::env::temp_dir().join(format!("ipc_test_{}.txt", std::process::id()));
+        std::fs::write(&path, "keep").unwrap();
+        let error = listen(&path.to_string_lossy(), Arc::new(Stub::default())).unwrap_err();
+        assert!(error.to_string().ends_with("exists and is not a socket"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,735 @@
+use crate::annotation::{Canvas, Color};
+use crate::capture::Frame;
+use crate::geometry::{distance_to_segment, Point, Rect};
+use crate::raster::mask_outside;
+use crate::selection::{InputEvent, Key, MouseButton, OverlayStyle, Redraw, HANDLE_SIZE};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Largest distance in pixels a lasso point may be moved from the outline
+/// when the drawn path is simplified.
+pub const SIMPLIFY_TOLERANCE: f32 = 1.0;
+
+/// Clicking this close to the first vertex closes a polygon.
+pub const CLOSE_DISTANCE: f32 = HANDLE_SIZE;
+
+/// Pointer movements shorter than this add no lasso point.
+const MIN_LASSO_STEP: f32 = 2.0;
+
+/// Extra pixels invalidated around an outline so its stroke and the marker
+/// on the first vertex are repainted too.
+const OUTLINE_MARGIN: f32 = HANDLE_SIZE / 2.0 + 2.0;
+
+fn distance(a: Point, b: Point) -> f32 {
+    (a.x - b.x).hypot(a.y - b.y)
+}
+
+/// Bounding box of `points`, `None` if there are none.
+pub fn polygon_bounds(points: &[Point]) -> Option<Rect> {
+    points
+        .iter()
+        .map(|p| Rect::new(p.x, p.y, p.x, p.y))
+        .reduce(|a, b| a.union(&b))
+}
+
+/// Enclosed area of the closed polygon `points`, regardless of winding.
+pub fn polygon_area(points: &[Point]) -> f32 {
+    let twice: f32 = points
+        .iter()
+        .zip(points.iter().cycle().skip(1))
+        .map(|(a, b)| a.x * b.y - b.x * a.y)
+        .sum();
+    twice.abs() / 2.0
+}
+
+/// Even-odd test whether `p` lies inside the closed polygon `points`, the
+/// same rule the masks are rasterized with.
+pub fn point_in_polygon(points: &[Point], p: Point) -> bool {
+    let mut inside = false;
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        if (a.y <= p.y && p.y < b.y) || (b.y <= p.y && p.y < a.y) {
+            let x = a.x + (p.y - a.y) * (b.x - a.x) / (b.y - a.y);
+            if p.x < x {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
+/// Ramer-Douglas-Peucker simplification of the open path `points`: drops
+/// every point closer than `tolerance` to the line between the points kept
+/// around it. The first and last point are always kept.
+pub fn simplify(points: &[Point], tolerance: f32) -> Vec<Point> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    // Explicit stack instead of recursion, lasso paths can be long
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = ranges.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[first], points[last]),
+                )
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((index, d)) = farthest {
+            if d > tolerance {
+                keep[index] = true;
+                ranges.push((first, index));
+                ranges.push((index, last));
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(p, keep)| keep.then_some(*p))
+        .collect()
+}
+
+/// Outline of a selection.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SelectionShape {
+    Rect {
+        rect: Rect,
+    },
+    /// Closed polygon; the last point connects back to the first.
+    Polygon {
+        points: Vec<Point>,
+    },
+}
+
+impl SelectionShape {
+    pub fn bounds(&self) -> Rect {
+        match self {
+            SelectionShape::Rect { rect } => *rect,
+            SelectionShape::Polygon { points } => polygon_bounds(points).unwrap_or_default(),
+        }
+    }
+
+    pub fn contains(&self, p: Point) -> bool {
+        match self {
+            SelectionShape::Rect { rect } => rect.contains(p),
+            SelectionShape::Polygon { points } => point_in_polygon(points, p),
+        }
+    }
+
+    /// Moves every point through `f`, e.g. to convert between viewports.
+    /// Rectangles stay axis-aligned.
+    pub fn map(&self, f: impl Fn(Point) -> Point) -> SelectionShape {
+        match self {
+            SelectionShape::Rect { rect } => SelectionShape::Rect {
+                rect: Rect::from_points(
+                    f(Point::new(rect.left, rect.top)),
+                    f(Point::new(rect.right, rect.bottom)),
+                ),
+            },
+            SelectionShape::Polygon { points } => SelectionShape::Polygon {
+                points: points.iter().map(|p| f(*p)).collect(),
+            },
+        }
+    }
+}
+
+/// Cuts the bounding box of `shape`, in frame pixels, out of `frame`.
+///
+/// Pixels outside a polygon are made transparent or, with `fill`, painted
+/// in that color. The result keeps its position on the virtual screen.
+pub fn crop_shape(frame: &Frame, shape: &SelectionShape, fill: Option<Color>) -> Result<Frame> {
+    match shape {
+        SelectionShape::Rect { rect } => frame.crop_rect(rect),
+        SelectionShape::Polygon { points } => {
+            if points.len() < 3 {
+                return Err(anyhow!("A polygon needs at least 3 points"));
+            }
+            let mut out = frame.crop_rect(&shape.bounds())?;
+            let dx = (frame.origin.0 - out.origin.0) as f32;
+            let dy = (frame.origin.1 - out.origin.1) as f32;
+            let local: Vec<Point> = points
+                .iter()
+                .map(|p| Point::new(p.x + dx, p.y + dy))
+                .collect();
+            mask_outside(&mut out, &local, fill);
+            Ok(out)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShapeKind {
+    /// Freehand path drawn while the button is held.
+    #[default]
+    Lasso,
+    /// One vertex per click, closed by clicking the first vertex or Enter.
+    Polygon,
+}
+
+/// Result of feeding one event into the [`ShapeTool`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeUpdate {
+    /// The closed shape, `None` while drawing.
+    pub shape: Option<SelectionShape>,
+    pub redraw: Redraw,
+    /// Set once when the outline is closed.
+    pub committed: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum State {
+    Idle,
+    Drawing {
+        points: Vec<Point>,
+        /// Pointer position the open polygon follows.
+        cursor: Option<Point>,
+    },
+    Closed(Vec<Point>),
+}
+
+/// Lasso and polygon selection driven purely by [`InputEvent`]s, the
+/// freeform counterpart of [`crate::selection::SelectionModel`].
+///
+/// Lasso paths are simplified when the button is released. Outlines that
+/// enclose no area are dropped instead of being committed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShapeTool {
+    kind: ShapeKind,
+    state: State,
+    tolerance: f32,
+    fill: Option<Color>,
+}
+
+impl ShapeTool {
+    pub fn new(kind: ShapeKind) -> Self {
+        ShapeTool {
+            kind,
+            state: State::Idle,
+            tolerance: SIMPLIFY_TOLERANCE,
+            fill: None,
+        }
+    }
+
+    pub fn kind(&self) -> ShapeKind {
+        self.kind
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) -> &mut Self {
+        self.tolerance = tolerance.max(0.0);
+        self
+    }
+
+    /// Color for the pixels outside the shape; `None` makes them
+    /// transparent.
+    pub fn set_fill(&mut self, fill: Option<Color>) -> &mut Self {
+        self.fill = fill;
+        self
+    }
+
+    pub fn fill(&self) -> Option<Color> {
+        self.fill
+    }
+
+    /// The closed shape, if any.
+    pub fn shape(&self) -> Option<SelectionShape> {
+        match &self.state {
+            State::Closed(points) => Some(SelectionShape::Polygon {
+                points: points.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Points to draw: the open path up to the pointer while drawing, or
+    /// the closed outline.
+    pub fn outline(&self) -> Vec<Point> {
+        match &self.state {
+            State::Idle => Vec::new(),
+            State::Drawing { points, cursor } => points.iter().chain(cursor).copied().collect(),
+            State::Closed(points) => points.clone(),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self.state, State::Closed(_))
+    }
+
+    pub fn is_drawing(&self) -> bool {
+        matches!(self.state, State::Drawing { .. })
+    }
+
+    pub fn reset(&mut self) -> ShapeUpdate {
+        let previous = self.outline();
+        self.state = State::Idle;
+        self.update(&previous, false)
+    }
+
+    pub fn handle(&mut self, event: InputEvent) -> ShapeUpdate {
+        let previous = self.outline();
+        let committed = match (self.kind, event) {
+            (
+                _,
+                InputEvent::Key {
+                    key: Key::Escape, ..
+                },
+            ) => {
+                self.state = State::Idle;
+                false
+            }
+            (
+                ShapeKind::Lasso,
+                InputEvent::PointerDown {
+                    pos,
+                    button: MouseButton::Left,
+                },
+            ) => {
+                self.state = State::Drawing {
+                    points: vec![pos],
+                    cursor: None,
+                };
+                false
+            }
+            (ShapeKind::Lasso, InputEvent::PointerMove { pos, buttons }) if buttons.left => {
+                if let State::Drawing { points, .. } = &mut self.state {
+                    if points
+                        .last()
+                        .is_none_or(|last| distance(*last, pos) >= MIN_LASSO_STEP)
+                    {
+                        points.push(pos);
+                    }
+                }
+                false
+            }
+            (
+                ShapeKind::Lasso,
+                InputEvent::PointerUp {
+                    pos,
+                    button: MouseButton::Left,
+                },
+            ) if self.is_drawing() => {
+                let mut path = self.outline();
+                path.push(pos);
+                self.close(simplify(&path, self.tolerance))
+            }
+            (
+                ShapeKind::Polygon,
+                InputEvent::PointerDown {
+                    pos,
+                    button: MouseButton::Left,
+                },
+            ) => match &mut self.state {
+                State::Drawing { points, .. }
+                    if points.len() >= 3 && distance(points[0], pos) <= CLOSE_DISTANCE =>
+                {
+                    let points = std::mem::take(points);
+                    self.close(points)
+                }
+                State::Drawing { points, cursor } => {
+                    points.push(pos);
+                    *cursor = Some(pos);
+                    false
+                }
+                _ => {
+                    self.state = State::Drawing {
+                        points: vec![pos],
+                        cursor: Some(pos),
+                    };
+                    false
+                }
+            },
+            (ShapeKind::Polygon, InputEvent::PointerMove { pos, .. }) => {
+                if let State::Drawing { cursor, .. } = &mut self.state {
+                    *cursor = Some(pos);
+                }
+                false
+            }
+            (
+                ShapeKind::Polygon,
+                InputEvent::Key {
+                    key: Key::Enter, ..
+                },
+            ) => match &mut self.state {
+                State::Drawing { points, .. } => {
+                    let points = std::mem::take(points);
+                    self.close(points)
+                }
+                _ => false,
+            },
+            _ => false,
+        };
+        self.update(&previous, committed)
+    }
+
+    /// Closes the outline if it encloses an area, otherwise discards it.
+    /// Returns whether it was closed.
+    fn close(&mut self, mut points: Vec<Point>) -> bool {
+        points.dedup();
+        if points.len() > 1 && points.first() == points.last() {
+            points.pop();
+        }
+        if points.len() >= 3 && polygon_area(&points) >= 1.0 {
+            self.state = State::Closed(points);
+            true
+        } else {
+            self.state = State::Idle;
+            false
+        }
+    }
+
+    fn update(&self, previous: &[Point], committed: bool) -> ShapeUpdate {
+        let outline = self.outline();
+        let redraw = match (polygon_bounds(previous), polygon_bounds(&outline)) {
+            _ if previous == outline.as_slice() => Redraw::None,
+            (None, None) => Redraw::None,
+            (Some(a), Some(b)) => Redraw::Area(a.union(&b).inflate(OUTLINE_MARGIN)),
+            (Some(r), None) | (None, Some(r)) => Redraw::Area(r.inflate(OUTLINE_MARGIN)),
+        };
+
+        ShapeUpdate {
+            shape: self.shape(),
+            redraw,
+            committed,
+        }
+    }
+}
+
+/// Paints a lasso or polygon outline onto `canvas` the way
+/// [`crate::selection::draw_overlay`] paints rectangles; `bounds` is the
+/// visible area used for dimming. An open outline is only stroked, with a
+/// marker on the first vertex to show where it closes.
+pub fn draw_shape_overlay(
+    canvas: &mut dyn Canvas,
+    bounds: &Rect,
+    outline: &[Point],
+    closed: bool,
+    style: &OverlayStyle,
+) {
+    let Some(first) = outline.first().copied() else {
+        return;
+    };
+
+    if closed {
+        if let Some((color, opacity)) = style.dim {
+            // One even-odd figure of the bounds with the outline cut out;
+            // the bridge to the outline is crossed twice and cancels out
+            let corner = Point::new(bounds.left, bounds.top);
+            let mut ring = vec![
+                corner,
+                Point::new(bounds.right, bounds.top),
+                Point::new(bounds.right, bounds.bottom),
+                Point::new(bounds.left, bounds.bottom),
+                corner,
+            ];
+            ring.extend_from_slice(outline);
+            ring.push(first);
+            canvas.fill_polygon(&ring, color, opacity);
+        }
+        if let Some((color, opacity)) = style.fill {
+            canvas.fill_polygon(outline, color, opacity);
+        }
+    }
+
+    let mut path = outline.to_vec();
+    if closed {
+        path.push(first);
+    }
+    canvas.stroke_polyline(&path, &style.frame);
+
+    if !closed {
+        let (color, opacity) = style.handles;
+        let half = HANDLE_SIZE / 2.0;
+        let marker = Rect::new(
+            first.x - half,
+            first.y - half,
+            first.x + half,
+            first.y + half,
+        );
+        canvas.fill_rect(&marker, color, opacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selection::Buttons;
+
+    fn points(coords: &[(f32, f32)]) -> Vec<Point> {
+        coords.iter().map(|&(x, y)| Point::new(x, y)).collect()
+    }
+
+    /// A 30x30 square with a 10 wide notch cut in from the bottom.
+    fn notched() -> Vec<Point> {
+        points(&[
+            (0.0, 0.0),
+            (30.0, 0.0),
+            (30.0, 30.0),
+            (20.0, 30.0),
+            (20.0, 10.0),
+            (10.0, 10.0),
+            (10.0, 30.0),
+            (0.0, 30.0),
+        ])
+    }
+
+    fn down(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerDown {
+            pos: Point::new(x, y),
+            button: MouseButton::Left,
+        }
+    }
+
+    fn drag(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerMove {
+            pos: Point::new(x, y),
+            buttons: Buttons {
+                left: true,
+                ..Buttons::default()
+            },
+        }
+    }
+
+    fn up(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerUp {
+            pos: Point::new(x, y),
+            button: MouseButton::Left,
+        }
+    }
+
+    fn key(key: Key) -> InputEvent {
+        InputEvent::Key {
+            key,
+            modifiers: Default::default(),
+        }
+    }
+
+    #[test]
+    fn concave_polygons_exclude_their_notch() {
+        let shape = notched();
+        let inside = |x, y| point_in_polygon(&shape, Point::new(x, y));
+        assert!(inside(5.0, 20.0));
+        assert!(inside(25.0, 25.0));
+        assert!(inside(15.0, 5.0));
+        assert!(!inside(15.0, 20.0));
+        assert!(!inside(35.0, 5.0));
+        // Left and top edges belong to the shape, right and bottom do not,
+        // like Rect::contains
+        assert!(inside(0.0, 5.0));
+        assert!(inside(5.0, 0.0));
+        assert!(!inside(30.0, 5.0));
+        assert!(!inside(5.0, 30.0));
+
+        // Overlapping loops of a self-crossing lasso cancel out
+        let bow = points(&[(0.0, 0.0), (20.0, 20.0), (20.0, 0.0), (0.0, 20.0)]);
+        assert!(point_in_polygon(&bow, Point::new(5.0, 10.0)));
+        assert!(!point_in_polygon(&bow, Point::new(10.0, 5.0)));
+        assert!(!point_in_polygon(&[], Point::new(0.0, 0.0)));
+    }
+
+    #[test]
+    fn area_and_bounds_ignore_winding() {
+        let mut shape = notched();
+        assert_eq!(polygon_area(&shape), 700.0);
+        shape.reverse();
+        assert_eq!(polygon_area(&shape), 700.0);
+        assert_eq!(
+            polygon_bounds(&shape),
+            Some(Rect::new(0.0, 0.0, 30.0, 30.0))
+        );
+        assert_eq!(polygon_area(&points(&[(0.0, 0.0), (10.0, 10.0)])), 0.0);
+        assert_eq!(polygon_bounds(&[]), None);
+    }
+
+    #[test]
+    fn simplify_at_zero_tolerance_drops_only_collinear_points() {
+        let path = points(&[
+            (0.0, 0.0),
+            (5.0, 0.0),
+            (10.0, 0.0),
+            (10.0, 5.0),
+            (10.0, 10.0),
+            (10.1, 15.0),
+            (10.0, 20.0),
+        ]);
+        assert_eq!(
+            simplify(&path, 0.0),
+            points(&[
+                (0.0, 0.0),
+                (10.0, 0.0),
+                (10.0, 10.0),
+                (10.1, 15.0),
+                (10.0, 20.0)
+            ])
+        );
+        // A larger tolerance also smooths the wobble
+        assert_eq!(
+            simplify(&path, 1.0),
+            points(&[(0.0, 0.0), (10.0, 0.0), (10.0, 20.0)])
+        );
+        let short = points(&[(0.0, 0.0), (3.0, 3.0)]);
+        assert_eq!(simplify(&short, 100.0), short);
+    }
+
+    #[test]
+    fn crop_shape_masks_outside_the_polygon() {
+        let mut frame = Frame::new(20, 20).with_origin(100, 50);
+        for y in 0..20 {
+            for x in 0..20 {
+                frame.put_pixel(x, y, [200, 10, 10, 255]);
+            }
+        }
+        let triangle = SelectionShape::Polygon {
+            points: points(&[(2.0, 2.0), (12.0, 2.0), (2.0, 12.0)]),
+        };
+
+        let clear = crop_shape(&frame, &triangle, None).unwrap();
+        assert_eq!((clear.width, clear.height), (10, 10));
+        assert_eq!(clear.origin, (102, 52));
+        assert_eq!(clear.pixel(1, 1), [200, 10, 10, 255]);
+        assert_eq!(clear.pixel(9, 9), [0, 0, 0, 0]);
+
+        let filled = crop_shape(&frame, &triangle, Some(Color::WHITE)).unwrap();
+        assert_eq!(filled.pixel(1, 1), [200, 10, 10, 255]);
+        assert_eq!(filled.pixel(9, 9), [255, 255, 255, 255]);
+        // Pixels on the diagonal are blended, not cut hard
+        assert!(filled.pixel(5, 4)[1] > 10 && filled.pixel(5, 4)[1] < 255);
+
+        let rect = SelectionShape::Rect {
+            rect: Rect::new(2.0, 2.0, 12.0, 12.0),
+        };
+        let plain = crop_shape(&frame, &rect, Some(Color::WHITE)).unwrap();
+        assert_eq!(plain.pixel(9, 9), [200, 10, 10, 255]);
+
+        let line = SelectionShape::Polygon {
+            points: points(&[(2.0, 2.0), (12.0, 2.0)]),
+        };
+        let error = crop_shape(&frame, &line, None).unwrap_err();
+        assert_eq!(error.to_string(), "A polygon needs at least 3 points");
+        let outside = SelectionShape::Polygon {
+            points: points(&[(30.0, 30.0), (40.0, 30.0), (30.0, 40.0)]),
+        };
+        assert!(crop_shape(&frame, &outside, None).is_err());
+    }
+
+    #[test]
+    fn lasso_closes_when_released() {
+        let mut tool = ShapeTool::new(ShapeKind::Lasso);
+        tool.handle(down(0.0, 0.0));
+        for (x, y) in [
+            (10.0, 0.0),
+            (20.0, 0.0),
+            (20.0, 20.0),
+            (20.5, 20.5),
+            (0.0, 20.0),
+        ] {
+            let update = tool.handle(drag(x, y));
+            assert!(update.shape.is_none() && !update.committed);
+        }
+        // The half-pixel step added no point
+        assert_eq!(tool.outline().len(), 5);
+
+        // Released on the start point: the duplicate is dropped
+        let update = tool.handle(up(0.0, 0.0));
+        assert!(update.committed);
+        assert_eq!(
+            update.shape,
+            Some(SelectionShape::Polygon {
+                points: points(&[(0.0, 0.0), (20.0, 0.0), (20.0, 20.0), (0.0, 20.0)])
+            })
+        );
+        assert!(tool.is_closed());
+        assert_eq!(
+            update.redraw,
+            Redraw::Area(Rect::new(-6.0, -6.0, 26.0, 26.0))
+        );
+
+        assert_eq!(tool.reset().shape, None);
+        assert!(!tool.is_closed());
+    }
+
+    #[test]
+    fn lasso_without_area_is_dropped() {
+        let mut tool = ShapeTool::new(ShapeKind::Lasso);
+        tool.handle(down(0.0, 0.0));
+        tool.handle(drag(10.0, 10.0));
+        tool.handle(drag(20.0, 20.0));
+        let update = tool.handle(up(30.0, 30.0));
+        assert!(!update.committed);
+        assert_eq!(update.shape, None);
+        assert!(!tool.is_drawing());
+    }
+
+    #[test]
+    fn polygon_closes_on_the_first_vertex_or_enter() {
+        let mut tool = ShapeTool::new(ShapeKind::Polygon);
+        tool.handle(down(0.0, 0.0));
+        tool.handle(down(30.0, 0.0));
+        // Too few vertices to close yet: a click near the start adds one
+        tool.handle(down(4.0, 4.0));
+        tool.handle(down(30.0, 30.0));
+        let update = tool.handle(InputEvent::PointerMove {
+            pos: Point::new(10.0, 40.0),
+            buttons: Buttons::default(),
+        });
+        assert_eq!(tool.outline().last(), Some(&Point::new(10.0, 40.0)));
+        assert!(!update.committed);
+
+        let update = tool.handle(down(6.0, -5.0));
+        assert!(update.committed);
+        assert_eq!(
+            update.shape,
+            Some(SelectionShape::Polygon {
+                points: points(&[(0.0, 0.0), (30.0, 0.0), (4.0, 4.0), (30.0, 30.0)])
+            })
+        );
+
+        let mut tool = ShapeTool::new(ShapeKind::Polygon);
+        for (x, y) in [(0.0, 0.0), (30.0, 0.0), (30.0, 0.0), (0.0, 30.0)] {
+            tool.handle(down(x, y));
+        }
+        let update = tool.handle(key(Key::Enter));
+        assert!(update.committed);
+        // The double click left no duplicate vertex
+        assert_eq!(
+            tool.shape(),
+            Some(SelectionShape::Polygon {
+                points: points(&[(0.0, 0.0), (30.0, 0.0), (0.0, 30.0)])
+            })
+        );
+    }
+
+    #[test]
+    fn polygon_without_area_and_escape_reset() {
+        let mut tool = ShapeTool::new(ShapeKind::Polygon);
+        tool.handle(down(0.0, 0.0));
+        tool.handle(down(30.0, 0.0));
+        let update = tool.handle(key(Key::Enter));
+        assert!(!update.committed);
+        assert!(!tool.is_drawing() && !tool.is_closed());
+
+        tool.handle(down(0.0, 0.0));
+        tool.handle(down(30.0, 0.0));
+        let update = tool.handle(key(Key::Escape));
+        assert_eq!(tool.outline(), []);
+        assert_eq!(
+            update.redraw,
+            Redraw::Area(Rect::new(-6.0, -6.0, 36.0, 6.0))
+        );
+
+        // Other buttons are ignored
+        let update = tool.handle(InputEvent::PointerDown {
+            pos: Point::new(5.0, 5.0),
+            button: MouseButton::Right,
+        });
+        assert_eq!(update.redraw, Redraw::None);
+        assert!(!tool.is_drawing());
+    }
+}
@@ -1,6 +1,33 @@
 // modules/mod.rs
+pub mod annotation;
+pub mod capture;
+pub mod cli;
+pub mod clipboard;
+pub mod command;
+pub mod config;
+#[cfg(windows)]
 pub mod direct2d;
 pub mod errorhandler;
+pub mod export;
+pub mod geometry;
+pub mod ipc;
+pub mod lasso;
+pub mod monitor;
+pub mod ocr;
+pub mod picker;
+pub mod preprocess;
+pub mod raster;
+pub mod recording;
+pub mod redact;
+pub mod selection;
+pub mod sensitive;
+pub mod stitch;
+pub mod store;
+pub mod timer;
+pub mod undo;
+#[cfg(windows)]
 pub mod win_fact;
+#[cfg(windows)]
 pub mod window_controller;
+#[cfg(windows)]
 pub mod winproc;
@@ -0,0 +1,529 @@
+use crate::capture::Frame;
+use crate::geometry::{Point, Rect};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// One display, in physical virtual-screen pixels.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Monitor {
+    pub name: String,
+    pub rect: Rect,
+    /// Part of `rect` not covered by the taskbar and docked tool bars.
+    pub work_area: Rect,
+    /// Physical pixels per logical unit (1.0 = 96 DPI).
+    pub scale: f32,
+    pub primary: bool,
+}
+
+impl Monitor {
+    pub fn new(name: &str, rect: Rect, scale: f32) -> Self {
+        Monitor {
+            name: name.to_string(),
+            rect,
+            work_area: rect,
+            scale,
+            primary: false,
+        }
+    }
+
+    /// Bounds in logical units; see [`MonitorLayout`] for the convention.
+    pub fn logical_rect(&self) -> Rect {
+        Rect::new(
+            self.rect.left,
+            self.rect.top,
+            self.rect.left + self.rect.width() / self.scale,
+            self.rect.top + self.rect.height() / self.scale,
+        )
+    }
+
+    pub fn to_logical(&self, p: Point) -> Point {
+        Point::new(
+            self.rect.left + (p.x - self.rect.left) / self.scale,
+            self.rect.top + (p.y - self.rect.top) / self.scale,
+        )
+    }
+
+    pub fn to_physical(&self, p: Point) -> Point {
+        Point::new(
+            self.rect.left + (p.x - self.rect.left) * self.scale,
+            self.rect.top + (p.y - self.rect.top) * self.scale,
+        )
+    }
+}
+
+/// Squared distance from `p` to the closest point of `rect`.
+fn distance_sq(rect: &Rect, p: Point) -> f32 {
+    let dx = (rect.left - p.x).max(p.x - rect.right).max(0.0);
+    let dy = (rect.top - p.y).max(p.y - rect.bottom).max(0.0);
+    dx * dx + dy * dy
+}
+
+/// What part of the desktop a capture keeps.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CaptureRegion {
+    /// The bounding box of all monitors; gaps between them are transparent.
+    AllMonitors,
+    /// The monitor showing a physical virtual-screen point, e.g. the cursor.
+    MonitorAt { x: f32, y: f32 },
+    /// Monitor by its position in the layout.
+    Monitor { index: usize },
+    /// An area of the virtual screen in physical coordinates.
+    Rect { rect: Rect },
+}
+
+/// Arrangement of all monitors.
+///
+/// Physical coordinates are the pixels of the virtual screen as a
+/// per-monitor DPI aware process sees them; the primary monitor starts at
+/// (0, 0) and others may lie at negative positions. Logical coordinates
+/// keep the top-left corner of every monitor and divide its extent by its
+/// scale, so a point maps through the monitor that contains it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MonitorLayout {
+    monitors: Vec<Monitor>,
+}
+
+impl MonitorLayout {
+    pub fn new(monitors: Vec<Monitor>) -> Result<Self> {
+        if monitors.is_empty() {
+            return Err(anyhow!("No monitors found"));
+        }
+        if let Some(m) = monitors
+            .iter()
+            .find(|m| m.scale.is_nan() || m.scale <= 0.0 || m.rect.is_empty())
+        {
+            return Err(anyhow!(
+                "Invalid monitor {}: {:?} at scale {}",
+                m.name,
+                m.rect,
+                m.scale
+            ));
+        }
+        Ok(MonitorLayout { monitors })
+    }
+
+    /// One monitor of `width` x `height` physical pixels at the origin.
+    pub fn single(width: u32, height: u32, scale: f32) -> Self {
+        let mut monitor = Monitor::new(
+            "primary",
+            Rect::new(0.0, 0.0, width as f32, height as f32),
+            scale,
+        );
+        monitor.primary = true;
+        MonitorLayout {
+            monitors: vec![monitor],
+        }
+    }
+
+    pub fn monitors(&self) -> &[Monitor] {
+        &self.monitors
+    }
+
+    pub fn primary(&self) -> &Monitor {
+        self.monitors
+            .iter()
+            .find(|m| m.primary)
+            .unwrap_or(&self.monitors[0])
+    }
+
+    /// The virtual screen: bounding box of all monitors in physical pixels.
+    pub fn bounds(&self) -> Rect {
+        self.monitors
+            .iter()
+            .map(|m| m.rect)
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default()
+    }
+
+    pub fn logical_bounds(&self) -> Rect {
+        self.monitors
+            .iter()
+            .map(|m| m.logical_rect())
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default()
+    }
+
+    /// Monitor containing the physical point `p`.
+    pub fn monitor_at(&self, p: Point) -> Option<&Monitor> {
+        self.monitors.iter().find(|m| m.rect.contains(p))
+    }
+
+    /// Monitor containing `p` or, for points in gaps or off-screen, the
+    /// closest one.
+    pub fn nearest(&self, p: Point) -> &Monitor {
+        self.monitor_at(p).unwrap_or_else(|| {
+            self.monitors
+                .iter()
+                .min_by(|a, b| distance_sq(&a.rect, p).total_cmp(&distance_sq(&b.rect, p)))
+                .unwrap_or(&self.monitors[0])
+        })
+    }
+
+    fn nearest_logical(&self, p: Point) -> &Monitor {
+        self.monitors
+            .iter()
+            .find(|m| m.logical_rect().contains(p))
+            .unwrap_or_else(|| {
+                self.monitors
+                    .iter()
+                    .min_by(|a, b| {
+                        distance_sq(&a.logical_rect(), p)
+                            .total_cmp(&distance_sq(&b.logical_rect(), p))
+                    })
+                    .unwrap_or(&self.monitors[0])
+            })
+    }
+
+    /// Scale factor of the monitor showing the physical point `p`.
+    pub fn scale_at(&self, p: Point) -> f32 {
+        self.nearest(p).scale
+    }
+
+    pub fn to_logical(&self, p: Point) -> Point {
+        self.nearest(p).to_logical(p)
+    }
+
+    pub fn to_physical(&self, p: Point) -> Point {
+        self.nearest_logical(p).to_physical(p)
+    }
+
+    /// Converts through the monitor showing the center of `rect`, so a
+    /// rectangle spanning two monitors keeps a consistent size.
+    pub fn rect_to_logical(&self, rect: &Rect) -> Rect {
+        let monitor = self.nearest(center(rect));
+        Rect::from_points(
+            monitor.to_logical(Point::new(rect.left, rect.top)),
+            monitor.to_logical(Point::new(rect.right, rect.bottom)),
+        )
+    }
+
+    pub fn rect_to_physical(&self, rect: &Rect) -> Rect {
+        let monitor = self.nearest_logical(center(rect));
+        Rect::from_points(
+            monitor.to_physical(Point::new(rect.left, rect.top)),
+            monitor.to_physical(Point::new(rect.right, rect.bottom)),
+        )
+    }
+
+    /// Physical bounds of `region`.
+    pub fn region_bounds(&self, region: CaptureRegion) -> Result<Rect> {
+        match region {
+            CaptureRegion::AllMonitors => Ok(self.bounds()),
+            CaptureRegion::MonitorAt { x, y } => Ok(self.nearest(Point::new(x, y)).rect),
+            CaptureRegion::Monitor { index } => self
+                .monitors
+                .get(index)
+                .map(|m| m.rect)
+                .ok_or_else(|| anyhow!("No monitor {}", index)),
+            CaptureRegion::Rect { rect } => Ok(rect),
+        }
+    }
+
+    /// The monitor `region` stands for, `None` for all monitors.
+    pub fn region_monitor(&self, region: CaptureRegion) -> Option<&Monitor> {
+        match region {
+            CaptureRegion::AllMonitors => None,
+            CaptureRegion::MonitorAt { x, y } => Some(self.nearest(Point::new(x, y))),
+            CaptureRegion::Monitor { index } => self.monitors.get(index),
+            CaptureRegion::Rect { rect } => Some(self.nearest(center(&rect))),
+        }
+    }
+
+    /// Cuts `region` out of a capture of the virtual screen.
+    ///
+    /// A single monitor or area keeps the scale factor of the monitor below
+    /// its center; for all monitors the pixels no monitor shows are made
+    /// transparent.
+    pub fn extract(&self, frame: &Frame, region: CaptureRegion) -> Result<Frame> {
+        let bounds = self.region_bounds(region)?;
+        let viewport = Viewport::for_frame(frame);
+        let mut out = frame.crop_rect(&viewport.rect_to_client(&bounds))?;
+
+        match region {
+            CaptureRegion::AllMonitors => self.clear_gaps(&mut out),
+            _ => out.scale = self.nearest(center(&bounds)).scale,
+        }
+        Ok(out)
+    }
+
+    /// Makes every pixel of `frame` outside all monitors transparent.
+    pub fn clear_gaps(&self, frame: &mut Frame) {
+        let viewport = Viewport::for_frame(frame);
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let center = viewport.to_screen(Point::new(x as f32 + 0.5, y as f32 + 0.5));
+                if self.monitor_at(center).is_none() {
+                    frame.put_pixel(x, y, [0, 0, 0, 0]);
+                }
+            }
+        }
+    }
+}
+
+fn center(rect: &Rect) -> Point {
+    Point::new(
+        (rect.left + rect.right) / 2.0,
+        (rect.top + rect.bottom) / 2.0,
+    )
+}
+
+/// Pixel grid placed on the virtual screen, e.g. a window's client area
+/// or a captured frame; converts between its coordinates and physical
+/// virtual-screen coordinates.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Viewport {
+    /// Virtual-screen position of the top-left corner.
+    pub origin: Point,
+}
+
+impl Viewport {
+    pub fn new(origin: Point) -> Self {
+        Viewport { origin }
+    }
+
+    pub fn for_frame(frame: &Frame) -> Self {
+        Viewport::new(Point::new(frame.origin.0 as f32, frame.origin.1 as f32))
+    }
+
+    pub fn to_screen(&self, p: Point) -> Point {
+        Point::new(p.x + self.origin.x, p.y + self.origin.y)
+    }
+
+    pub fn to_client(&self, p: Point) -> Point {
+        Point::new(p.x - self.origin.x, p.y - self.origin.y)
+    }
+
+    pub fn rect_to_screen(&self, rect: &Rect) -> Rect {
+        rect.translate(self.origin.x, self.origin.y)
+    }
+
+    pub fn rect_to_client(&self, rect: &Rect) -> Rect {
+        rect.translate(-self.origin.x, -self.origin.y)
+    }
+
+    /// Maps a rectangle of this viewport into `other`.
+    pub fn rect_to(&self, other: &Viewport, rect: &Rect) -> Rect {
+        other.rect_to_client(&self.rect_to_screen(rect))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 100% monitor above and left of the primary one at 150% and a
+    /// smaller 125% monitor to its right:
+    ///
+    /// ```text
+    /// (-40,-10)
+    ///     +--------+
+    ///     |  left  |(0,0)------------+(60,0)---+
+    ///     |  100%  |   primary 150%  |  125%   |
+    ///     +--------+                 +---------+(80,20)
+    ///              +-----------------+(60,30)
+    /// ```
+    fn layout() -> MonitorLayout {
+        let mut primary = Monitor::new("primary", Rect::new(0.0, 0.0, 60.0, 30.0), 1.5);
+        primary.primary = true;
+        MonitorLayout::new(vec![
+            Monitor::new("left", Rect::new(-40.0, -10.0, 0.0, 20.0), 1.0),
+            primary,
+            Monitor::new("right", Rect::new(60.0, 0.0, 80.0, 20.0), 1.25),
+        ])
+        .unwrap()
+    }
+
+    /// A capture of the whole layout whose pixels hold their own
+    /// virtual-screen position, shifted to be positive.
+    fn screen() -> Frame {
+        let mut frame = Frame::new(120, 40).with_origin(-40, -10);
+        for y in 0..40 {
+            for x in 0..120 {
+                frame.put_pixel(x, y, [x as u8, y as u8, 0, 255]);
+            }
+        }
+        frame
+    }
+
+    /// Screen pixel shown at virtual-screen position (`x`, `y`).
+    fn shown(x: i32, y: i32) -> [u8; 4] {
+        [(x + 40) as u8, (y + 10) as u8, 0, 255]
+    }
+
+    fn assert_near(a: &Rect, b: &Rect) {
+        let close = |x: f32, y: f32| (x - y).abs() < 1e-3;
+        assert!(
+            close(a.left, b.left)
+                && close(a.top, b.top)
+                && close(a.right, b.right)
+                && close(a.bottom, b.bottom),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn logical_keeps_the_top_left_corner() {
+        let layout = layout();
+        let logical: Vec<Rect> = layout.monitors().iter().map(|m| m.logical_rect()).collect();
+        assert_eq!(
+            logical,
+            [
+                Rect::new(-40.0, -10.0, 0.0, 20.0),
+                Rect::new(0.0, 0.0, 40.0, 20.0),
+                Rect::new(60.0, 0.0, 76.0, 16.0),
+            ]
+        );
+        assert_eq!(layout.bounds(), Rect::new(-40.0, -10.0, 80.0, 30.0));
+        assert_eq!(layout.logical_bounds(), Rect::new(-40.0, -10.0, 76.0, 20.0));
+        assert_eq!(layout.primary().name, "primary");
+
+        for monitor in layout.monitors() {
+            let corner = Point::new(monitor.rect.left, monitor.rect.top);
+            assert_eq!(layout.to_logical(corner), corner, "{}", monitor.name);
+            assert_eq!(layout.to_physical(corner), corner, "{}", monitor.name);
+        }
+    }
+
+    #[test]
+    fn points_map_through_the_monitor_showing_them() {
+        let layout = layout();
+        assert_eq!(
+            layout.to_logical(Point::new(30.0, 15.0)),
+            Point::new(20.0, 10.0)
+        );
+        assert_eq!(
+            layout.to_logical(Point::new(-20.0, -5.0)),
+            Point::new(-20.0, -5.0)
+        );
+        assert_eq!(
+            layout.to_logical(Point::new(70.0, 10.0)),
+            Point::new(68.0, 8.0)
+        );
+        assert_eq!(
+            layout.to_physical(Point::new(68.0, 8.0)),
+            Point::new(70.0, 10.0)
+        );
+        assert_eq!(layout.scale_at(Point::new(-1.0, 0.0)), 1.0);
+        assert_eq!(layout.scale_at(Point::new(0.0, 0.0)), 1.5);
+
+        // Below the right monitor is a gap, the closest monitor takes over
+        assert!(layout.monitor_at(Point::new(70.0, 25.0)).is_none());
+        assert_eq!(layout.nearest(Point::new(70.0, 25.0)).name, "right");
+        assert_eq!(layout.nearest(Point::new(-100.0, 0.0)).name, "left");
+        assert_eq!(
+            layout.to_logical(Point::new(70.0, 25.0)),
+            Point::new(68.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn rects_round_trip_between_physical_and_logical() {
+        let layout = layout();
+        let cases = [
+            // Inside each monitor
+            (
+                Rect::new(-30.0, -5.0, -10.0, 15.0),
+                Rect::new(-30.0, -5.0, -10.0, 15.0),
+            ),
+            (
+                Rect::new(15.0, 6.0, 45.0, 24.0),
+                Rect::new(10.0, 4.0, 30.0, 16.0),
+            ),
+            (
+                Rect::new(65.0, 5.0, 75.0, 15.0),
+                Rect::new(64.0, 4.0, 72.0, 12.0),
+            ),
+            // Across the left and primary monitor, sized by the primary one
+            (
+                Rect::new(-6.0, 0.0, 30.0, 12.0),
+                Rect::new(-4.0, 0.0, 20.0, 8.0),
+            ),
+        ];
+        for (physical, logical) in cases {
+            let converted = layout.rect_to_logical(&physical);
+            assert_near(&converted, &logical);
+            assert_near(&layout.rect_to_physical(&converted), &physical);
+        }
+    }
+
+    #[test]
+    fn extract_cuts_monitors_and_areas_with_their_scale() {
+        let layout = layout();
+        let screen = screen();
+
+        let left = layout
+            .extract(&screen, CaptureRegion::Monitor { index: 0 })
+            .unwrap();
+        assert_eq!((left.width, left.height), (40, 30));
+        assert_eq!((left.origin, left.scale), ((-40, -10), 1.0));
+        assert_eq!(left.pixel(0, 0), shown(-40, -10));
+        assert_eq!(left.pixel(39, 29), shown(-1, 19));
+
+        let right = layout
+            .extract(&screen, CaptureRegion::MonitorAt { x: 70.0, y: 5.0 })
+            .unwrap();
+        assert_eq!((right.width, right.height), (20, 20));
+        assert_eq!((right.origin, right.scale), ((60, 0), 1.25));
+        assert_eq!(right.pixel(0, 0), shown(60, 0));
+
+        // An area takes the scale of the monitor below its center
+        let area = layout
+            .extract(
+                &screen,
+                CaptureRegion::Rect {
+                    rect: Rect::new(-10.0, 2.0, 20.0, 12.0),
+                },
+            )
+            .unwrap();
+        assert_eq!((area.width, area.height), (30, 10));
+        assert_eq!((area.origin, area.scale), ((-10, 2), 1.5));
+        assert_eq!(area.pixel(0, 0), shown(-10, 2));
+
+        let error = layout
+            .extract(&screen, CaptureRegion::Monitor { index: 3 })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "No monitor 3");
+    }
+
+    #[test]
+    fn all_monitors_clear_the_gaps_between_them() {
+        let layout = layout();
+        let screen = screen();
+        let all = layout.extract(&screen, CaptureRegion::AllMonitors).unwrap();
+        assert_eq!((all.width, all.height, all.origin), (120, 40, (-40, -10)));
+
+        let at = |x: i32, y: i32| all.pixel((x + 40) as u32, (y + 10) as u32);
+        for (x, y) in [(-40, -10), (-1, 19), (0, 0), (59, 29), (60, 0), (79, 19)] {
+            assert_eq!(at(x, y), shown(x, y), "({}, {})", x, y);
+        }
+        for (x, y) in [(-40, 20), (0, -1), (79, -10), (60, 20), (79, 29)] {
+            assert_eq!(at(x, y), [0, 0, 0, 0], "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn clear_gaps_uses_the_frame_origin() {
+        let layout = layout();
+        // Straddles the bottom-right corner of the primary monitor
+        let mut frame = screen().crop(90, 25, 20, 10).unwrap();
+        assert_eq!(frame.origin, (50, 15));
+        layout.clear_gaps(&mut frame);
+        assert_eq!(frame.pixel(9, 9), shown(59, 24));
+        assert_eq!(frame.pixel(10, 4), shown(60, 19));
+        assert_eq!(frame.pixel(10, 5), [0, 0, 0, 0]);
+        assert_eq!(frame.pixel(19, 9), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn invalid_monitors_are_refused() {
+        assert_eq!(
+            MonitorLayout::new(Vec::new()).unwrap_err().to_string(),
+            "No monitors found"
+        );
+        let flat = Monitor::new("flat", Rect::new(0.0, 0.0, 10.0, 10.0), 0.0);
+        assert!(MonitorLayout::new(vec![flat]).is_err());
+    }
+}
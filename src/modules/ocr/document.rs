@@ -0,0 +1,557 @@
+use crate::geometry::Rect;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Recognized word; `rect` is in capture coordinates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub rect: Rect,
+    /// 0.0 - 1.0, `None` if the engine does not report one.
+    pub confidence: Option<f32>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrLine {
+    pub rect: Rect,
+    pub confidence: Option<f32>,
+    pub words: Vec<OcrWord>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrBlock {
+    pub rect: Rect,
+    pub confidence: Option<f32>,
+    pub lines: Vec<OcrLine>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrPage {
+    /// Area that was recognized, usually the whole selection.
+    pub rect: Rect,
+    pub confidence: Option<f32>,
+    pub blocks: Vec<OcrBlock>,
+}
+
+/// Structured OCR output: pages → blocks → lines → words.
+///
+/// Bounds and confidences of the containers are derived from their
+/// children, so only words have to be filled in by an engine.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrDocument {
+    pub pages: Vec<OcrPage>,
+}
+
+fn bounds<'a>(rects: impl Iterator<Item = &'a Rect>) -> Rect {
+    rects
+        .copied()
+        .reduce(|a, b| a.union(&b))
+        .unwrap_or_default()
+}
+
+/// Average of the reported confidences, ignoring children without one.
+fn mean_confidence(values: impl Iterator<Item = Option<f32>>) -> Option<f32> {
+    let (sum, count) = values
+        .flatten()
+        .fold((0.0, 0), |(sum, count), c| (sum + c, count + 1));
+    (count > 0).then(|| sum / count as f32)
+}
+
+impl OcrLine {
+    pub fn new(words: Vec<OcrWord>) -> Self {
+        OcrLine {
+            rect: bounds(words.iter().map(|w| &w.rect)),
+            confidence: mean_confidence(words.iter().map(|w| w.confidence)),
+            words,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+impl OcrBlock {
+    pub fn new(lines: Vec<OcrLine>) -> Self {
+        OcrBlock {
+            rect: bounds(lines.iter().map(|l| &l.rect)),
+            confidence: mean_confidence(lines.iter().flat_map(|l| &l.words).map(|w| w.confidence)),
+            lines,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.lines
+            .iter()
+            .map(|l| l.text())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl OcrPage {
+    pub fn new(rect: Rect, blocks: Vec<OcrBlock>) -> Self {
+        OcrPage {
+            rect,
+            confidence: mean_confidence(
+                blocks
+                    .iter()
+                    .flat_map(|b| &b.lines)
+                    .flat_map(|l| &l.words)
+                    .map(|w| w.confidence),
+            ),
+            blocks,
+        }
+    }
+}
+
+impl OcrDocument {
+    pub fn words(&self) -> impl Iterator<Item = &OcrWord> {
+        self.lines().flat_map(|l| l.words.iter())
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &OcrLine> {
+        self.pages
+            .iter()
+            .flat_map(|p| &p.blocks)
+            .flat_map(|b| &b.lines)
+    }
+
+    /// Plain text: lines separated by newlines, blocks by an empty line.
+    pub fn text(&self) -> String {
+        self.pages
+            .iter()
+            .flat_map(|p| &p.blocks)
+            .map(|b| b.text())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Shifts every rectangle, e.g. from selection into capture coordinates.
+    pub fn translate(self, dx: f32, dy: f32) -> Self {
+        self.map_rects(|rect| rect.translate(dx, dy))
+    }
+
+    /// Scales every rectangle around the origin, e.g. back from an
+    /// upscaled OCR input.
+    pub fn scale(self, factor: f32) -> Self {
+        self.map_rects(|rect| rect.scale(factor))
+    }
+
+    /// Replaces every rectangle by `f` of it.
+    pub fn map_rects(mut self, f: impl Fn(&Rect) -> Rect) -> Self {
+        for page in &mut self.pages {
+            page.rect = f(&page.rect);
+            for block in &mut page.blocks {
+                block.rect = f(&block.rect);
+                for line in &mut block.lines {
+                    line.rect = f(&line.rect);
+                    for word in &mut line.words {
+                        word.rect = f(&word.rect);
+                    }
+                }
+            }
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// hOCR 1.2 (XHTML with `ocr_page`/`ocr_carea`/`ocr_line`/`ocrx_word`).
+    pub fn to_hocr(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(
+            "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \
+             \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n",
+        );
+        out.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\" xml:lang=\"en\" lang=\"en\">\n");
+        out.push_str(" <head>\n  <title></title>\n");
+        out.push_str("  <meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>\n");
+        out.push_str("  <meta name=\"ocr-system\" content=\"snipping_tool\"/>\n");
+        out.push_str(
+            "  <meta name=\"ocr-capabilities\" content=\"ocr_page ocr_carea ocr_line ocrx_word\"/>\n",
+        );
+        out.push_str(" </head>\n <body>\n");
+
+        for (p, page) in self.pages.iter().enumerate() {
+            let p = p + 1;
+            let _ = writeln!(
+                out,
+                "  <div class=\"ocr_page\" id=\"page_{}\" title=\"{}; ppageno {}\">",
+                p,
+                hocr_bbox(&page.rect),
+                p - 1
+            );
+            for (b, block) in page.blocks.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "   <div class=\"ocr_carea\" id=\"block_{}_{}\" title=\"{}\">",
+                    p,
+                    b + 1,
+                    hocr_bbox(&block.rect)
+                );
+                for (l, line) in block.lines.iter().enumerate() {
+                    let _ = writeln!(
+                        out,
+                        "    <span class=\"ocr_line\" id=\"line_{}_{}_{}\" title=\"{}\">",
+                        p,
+                        b + 1,
+                        l + 1,
+                        hocr_bbox(&line.rect)
+                    );
+                    for (w, word) in line.words.iter().enumerate() {
+                        let mut title = hocr_bbox(&word.rect);
+                        if let Some(confidence) = word.confidence {
+                            let _ = write!(title, "; x_wconf {}", (confidence * 100.0).round());
+                        }
+                        let _ = writeln!(
+                            out,
+                            "     <span class=\"ocrx_word\" id=\"word_{}_{}_{}_{}\" title=\"{}\">{}</span>",
+                            p,
+                            b + 1,
+                            l + 1,
+                            w + 1,
+                            title,
+                            escape_xml(&word.text)
+                        );
+                    }
+                    out.push_str("    </span>\n");
+                }
+                out.push_str("   </div>\n");
+            }
+            out.push_str("  </div>\n");
+        }
+
+        out.push_str(" </body>\n</html>\n");
+        out
+    }
+
+    /// ALTO 4 XML with pixel measurements.
+    pub fn to_alto(&self) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(
+            "<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\" \
+             xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" \
+             xsi:schemaLocation=\"http://www.loc.gov/standards/alto/ns-v4# \
+             http://www.loc.gov/alto/v4/alto-4-2.xsd\">\n",
+        );
+        out.push_str("  <Description>\n    <MeasurementUnit>pixel</MeasurementUnit>\n");
+        out.push_str("    <OCRProcessing ID=\"ocr_0\">\n      <ocrProcessingStep>\n");
+        out.push_str(
+            "        <processingSoftware>\n          <softwareName>snipping_tool</softwareName>\n",
+        );
+        out.push_str(
+            "        </processingSoftware>\n      </ocrProcessingStep>\n    </OCRProcessing>\n",
+        );
+        out.push_str("  </Description>\n  <Layout>\n");
+
+        for (p, page) in self.pages.iter().enumerate() {
+            let p = p + 1;
+            let (_, _, width, height) = pixel_box(&page.rect);
+            let _ = writeln!(
+                out,
+                "    <Page ID=\"page_{}\" PHYSICAL_IMG_NR=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\">",
+                p, p, width, height
+            );
+            let _ = writeln!(out, "      <PrintSpace {}>", alto_box(&page.rect));
+            for (b, block) in page.blocks.iter().enumerate() {
+                let _ = writeln!(
+                    out,
+                    "        <TextBlock ID=\"block_{}_{}\" {}>",
+                    p,
+                    b + 1,
+                    alto_box(&block.rect)
+                );
+                for (l, line) in block.lines.iter().enumerate() {
+                    let _ = writeln!(
+                        out,
+                        "          <TextLine ID=\"line_{}_{}_{}\" {}>",
+                        p,
+                        b + 1,
+                        l + 1,
+                        alto_box(&line.rect)
+                    );
+                    for (w, word) in line.words.iter().enumerate() {
+                        if w > 0 {
+                            let previous = &line.words[w - 1].rect;
+                            let gap = Rect::new(
+                                previous.right,
+                                previous.top,
+                                word.rect.left,
+                                previous.bottom,
+                            );
+                            let (hpos, vpos, width, _) = pixel_box(&gap);
+                            let _ = writeln!(
+                                out,
+                                "            <SP WIDTH=\"{}\" HPOS=\"{}\" VPOS=\"{}\"/>",
+                                width, hpos, vpos
+                            );
+                        }
+                        let confidence = word
+                            .confidence
+                            .map(|c| format!(" WC=\"{:.2}\"", c))
+                            .unwrap_or_default();
+                        let _ = writeln!(
+                            out,
+                            "            <String ID=\"word_{}_{}_{}_{}\" {}{} CONTENT=\"{}\"/>",
+                            p,
+                            b + 1,
+                            l + 1,
+                            w + 1,
+                            alto_box(&word.rect),
+                            confidence,
+                            escape_xml(&word.text)
+                        );
+                    }
+                    out.push_str("          </TextLine>\n");
+                }
+                out.push_str("        </TextBlock>\n");
+            }
+            out.push_str("      </PrintSpace>\n    </Page>\n");
+        }
+
+        out.push_str("  </Layout>\n</alto>\n");
+        out
+    }
+}
+
+/// Whole pixels covered by `rect` as `(x, y, width, height)`.
+fn pixel_box(rect: &Rect) -> (i64, i64, i64, i64) {
+    let left = rect.left.floor() as i64;
+    let top = rect.top.floor() as i64;
+    let right = rect.right.ceil() as i64;
+    let bottom = rect.bottom.ceil() as i64;
+    (left, top, (right - left).max(0), (bottom - top).max(0))
+}
+
+fn hocr_bbox(rect: &Rect) -> String {
+    let (x, y, width, height) = pixel_box(rect);
+    format!("bbox {} {} {} {}", x, y, x + width, y + height)
+}
+
+fn alto_box(rect: &Rect) -> String {
+    let (x, y, width, height) = pixel_box(rect);
+    format!(
+        "HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"",
+        x, y, width, height
+    )
+}
+
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(text: &str, rect: Rect, confidence: Option<f32>) -> OcrWord {
+        OcrWord {
+            text: text.to_string(),
+            rect,
+            confidence,
+        }
+    }
+
+    /// Two blocks: two lines with a word that needs escaping, then one
+    /// word further down.
+    fn document() -> OcrDocument {
+        let first = OcrBlock::new(vec![
+            OcrLine::new(vec![
+                word("Hello", Rect::new(10.0, 10.0, 50.0, 25.0), Some(0.9)),
+                word("<&\">", Rect::new(55.5, 10.0, 80.2, 25.0), Some(0.754)),
+            ]),
+            OcrLine::new(vec![word("x", Rect::new(10.0, 30.0, 20.0, 45.0), None)]),
+        ]);
+        let second = OcrBlock::new(vec![OcrLine::new(vec![word(
+            "end",
+            Rect::new(100.0, 40.0, 130.0, 55.0),
+            Some(0.5),
+        )])]);
+        OcrDocument {
+            pages: vec![OcrPage::new(
+                Rect::new(0.0, 0.0, 200.0, 60.0),
+                vec![first, second],
+            )],
+        }
+    }
+
+    #[test]
+    fn hocr_output_is_pinned() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xml:lang="en" lang="en">
+ <head>
+  <title></title>
+  <meta http-equiv="Content-Type" content="text/html;charset=utf-8"/>
+  <meta name="ocr-system" content="snipping_tool"/>
+  <meta name="ocr-capabilities" content="ocr_page ocr_carea ocr_line ocrx_word"/>
+ </head>
+ <body>
+  <div class="ocr_page" id="page_1" title="bbox 0 0 200 60; ppageno 0">
+   <div class="ocr_carea" id="block_1_1" title="bbox 10 10 81 45">
+    <span class="ocr_line" id="line_1_1_1" title="bbox 10 10 81 25">
+     <span class="ocrx_word" id="word_1_1_1_1" title="bbox 10 10 50 25; x_wconf 90">Hello</span>
+     <span class="ocrx_word" id="word_1_1_1_2" title="bbox 55 10 81 25; x_wconf 75">&lt;&amp;&quot;&gt;</span>
+    </span>
+    <span class="ocr_line" id="line_1_1_2" title="bbox 10 30 20 45">
+     <span class="ocrx_word" id="word_1_1_2_1" title="bbox 10 30 20 45">x</span>
+    </span>
+   </div>
+   <div class="ocr_carea" id="block_1_2" title="bbox 100 40 130 55">
+    <span class="ocr_line" id="line_1_2_1" title="bbox 100 40 130 55">
+     <span class="ocrx_word" id="word_1_2_1_1" title="bbox 100 40 130 55; x_wconf 50">end</span>
+    </span>
+   </div>
+  </div>
+ </body>
+</html>
+"#;
+        assert_eq!(document().to_hocr(), expected);
+    }
+
+    #[test]
+    fn alto_output_is_pinned() {
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?>
+<alto xmlns="http://www.loc.gov/standards/alto/ns-v4#" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance" xsi:schemaLocation="http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/alto/v4/alto-4-2.xsd">
+  <Description>
+    <MeasurementUnit>pixel</MeasurementUnit>
+    <OCRProcessing ID="ocr_0">
+      <ocrProcessingStep>
+        <processingSoftware>
+          <softwareName>snipping_tool</softwareName>
+        </processingSoftware>
+      </ocrProcessingStep>
+    </OCRProcessing>
+  </Description>
+  <Layout>
+    <Page ID="page_1" PHYSICAL_IMG_NR="1" WIDTH="200" HEIGHT="60">
+      <PrintSpace HPOS="0" VPOS="0" WIDTH="200" HEIGHT="60">
+        <TextBlock ID="block_1_1" HPOS="10" VPOS="10" WIDTH="71" HEIGHT="35">
+          <TextLine ID="line_1_1_1" HPOS="10" VPOS="10" WIDTH="71" HEIGHT="15">
+            <String ID="word_1_1_1_1" HPOS="10" VPOS="10" WIDTH="40" HEIGHT="15" WC="0.90" CONTENT="Hello"/>
+            <SP WIDTH="6" HPOS="50" VPOS="10"/>
+            <String ID="word_1_1_1_2" HPOS="55" VPOS="10" WIDTH="26" HEIGHT="15" WC="0.75" CONTENT="&lt;&amp;&quot;&gt;"/>
+          </TextLine>
+          <TextLine ID="line_1_1_2" HPOS="10" VPOS="30" WIDTH="10" HEIGHT="15">
+            <String ID="word_1_1_2_1" HPOS="10" VPOS="30" WIDTH="10" HEIGHT="15" CONTENT="x"/>
+          </TextLine>
+        </TextBlock>
+        <TextBlock ID="block_1_2" HPOS="100" VPOS="40" WIDTH="30" HEIGHT="15">
+          <TextLine ID="line_1_2_1" HPOS="100" VPOS="40" WIDTH="30" HEIGHT="15">
+            <String ID="word_1_2_1_1" HPOS="100" VPOS="40" WIDTH="30" HEIGHT="15" WC="0.50" CONTENT="end"/>
+          </TextLine>
+        </TextBlock>
+      </PrintSpace>
+    </Page>
+  </Layout>
+</alto>
+"#;
+        assert_eq!(document().to_alto(), expected);
+    }
+
+    #[test]
+    fn xml_escaping_covers_markup_and_quotes() {
+        assert_eq!(
+            escape_xml("a<b>&\"c\" 'd' é"),
+            "a&lt;b&gt;&amp;&quot;c&quot; &apos;d&apos; é"
+        );
+    }
+
+    #[test]
+    fn empty_documents_still_form_valid_wrappers() {
+        let empty = OcrDocument::default();
+        assert!(empty.to_hocr().contains(" <body>\n </body>\n"));
+        assert!(empty.to_alto().contains("  <Layout>\n  </Layout>\n"));
+        assert_eq!(empty.text(), "");
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let document = document();
+        let json = document.to_json().unwrap();
+        assert_eq!(OcrDocument::from_json(&json).unwrap(), document);
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let word = &value["pages"][0]["blocks"][0]["lines"][1]["words"][0];
+        assert_eq!(word["text"], "x");
+        assert_eq!(word["confidence"], serde_json::Value::Null);
+        assert_eq!(word["rect"]["right"], 20.0);
+        assert!(OcrDocument::from_json("{\"pages\": 1}").is_err());
+    }
+
+    #[test]
+    fn text_joins_words_lines_and_blocks() {
+        let document = document();
+        assert_eq!(document.text(), "Hello <&\">\nx\n\nend");
+        assert_eq!(document.pages[0].blocks[0].lines[0].text(), "Hello <&\">");
+        assert_eq!(document.lines().count(), 3);
+        assert_eq!(
+            document
+                .words()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>(),
+            ["Hello", "<&\">", "x", "end"]
+        );
+
+        // Blocks of the next page follow after an empty line as well
+        let mut two_pages = document.clone();
+        two_pages.pages.push(document.pages[0].clone());
+        assert_eq!(
+            two_pages.text(),
+            "Hello <&\">\nx\n\nend\n\nHello <&\">\nx\n\nend"
+        );
+    }
+
+    #[test]
+    fn containers_derive_bounds_and_confidence_from_words() {
+        let document = document();
+        let page = &document.pages[0];
+        let block = &page.blocks[0];
+        assert_eq!(block.rect, Rect::new(10.0, 10.0, 80.2, 45.0));
+        assert_eq!(block.lines[0].rect, Rect::new(10.0, 10.0, 80.2, 25.0));
+        assert!((block.confidence.unwrap() - 0.827).abs() < 1e-6);
+        // Words without a confidence are left out of the average
+        assert_eq!(block.lines[1].confidence, None);
+        assert!((page.confidence.unwrap() - 0.718).abs() < 1e-6);
+        assert_eq!(OcrLine::new(Vec::new()).rect, Rect::default());
+    }
+
+    #[test]
+    fn rects_are_mapped_everywhere() {
+        let moved = document().scale(2.0).translate(5.0, -10.0);
+        assert_eq!(moved.pages[0].rect, Rect::new(5.0, -10.0, 405.0, 110.0));
+        assert_eq!(
+            moved.pages[0].blocks[1].rect,
+            Rect::new(205.0, 70.0, 265.0, 100.0)
+        );
+        assert_eq!(
+            moved.words().last().unwrap().rect,
+            Rect::new(205.0, 70.0, 265.0, 100.0)
+        );
+    }
+}
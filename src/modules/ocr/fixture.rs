@@ -0,0 +1,40 @@
+use super::{OcrDocument, OcrEngine};
+use crate::capture::Frame;
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::Path;
+
+/// Answers every frame with the same stored document, e.g. one written by
+/// [`OcrDocument::to_json`], so the OCR plumbing can run without an engine.
+///
+/// The pixels are not looked at; rectangles come back exactly as stored,
+/// so preprocessing that enlarges the frame shrinks them accordingly.
+#[derive(Clone, Debug, Default)]
+pub struct FixtureEngine {
+    document: OcrDocument,
+}
+
+impl FixtureEngine {
+    pub fn new(document: OcrDocument) -> Self {
+        FixtureEngine { document }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to load OCR fixture {}: {}", path.display(), e))?;
+        let document = OcrDocument::from_json(&json)
+            .map_err(|e| anyhow!("Invalid OCR fixture {}: {}", path.display(), e))?;
+        Ok(FixtureEngine { document })
+    }
+}
+
+impl OcrEngine for FixtureEngine {
+    fn name(&self) -> &'static str {
+        "fixture"
+    }
+
+    fn recognize(&self, _frame: &Frame) -> Result<OcrDocument> {
+        Ok(self.document.clone())
+    }
+}
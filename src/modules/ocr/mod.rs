@@ -0,0 +1,65 @@
+mod document;
+mod fixture;
+mod tesseract;
+#[cfg(windows)]
+mod win;
+
+pub use document::{OcrBlock, OcrDocument, OcrLine, OcrPage, OcrWord};
+pub use fixture::FixtureEngine;
+pub use tesseract::{parse_tsv, TesseractEngine};
+#[cfg(windows)]
+pub use win::WinOcrEngine;
+
+use crate::capture::{CaptureBackend, Frame};
+use crate::geometry::Rect;
+use anyhow::Result;
+
+/// Text recognition on a captured frame.
+///
+/// Implementations report rectangles in pixels of the frame they are given.
+pub trait OcrEngine {
+    fn name(&self) -> &'static str;
+    fn recognize(&self, frame: &Frame) -> Result<OcrDocument>;
+}
+
+/// Windows OCR on Windows, a local `tesseract` everywhere else.
+pub fn default_engine() -> Box<dyn OcrEngine> {
+    #[cfg(windows)]
+    {
+        Box::new(WinOcrEngine::default())
+    }
+    #[cfg(not(windows))]
+    {
+        Box::new(TesseractEngine::default())
+    }
+}
+
+/// Runs OCR on `selection` (in frame pixels) or on the whole frame.
+///
+/// The returned rectangles are in the coordinates of `frame`, not of the
+/// cropped selection.
+pub fn recognize_region(
+    engine: &dyn OcrEngine,
+    frame: &Frame,
+    selection: Option<Rect>,
+) -> Result<OcrDocument> {
+    match selection {
+        Some(rect) => {
+            let region = frame.crop_rect(&rect)?;
+            let dx = (region.origin.0 - frame.origin.0) as f32;
+            let dy = (region.origin.1 - frame.origin.1) as f32;
+            Ok(engine.recognize(&region)?.translate(dx, dy))
+        }
+        None => engine.recognize(frame),
+    }
+}
+
+/// Captures the screen and recognizes the text inside `selection`.
+pub fn capture_and_recognize(
+    backend: &dyn CaptureBackend,
+    engine: &dyn OcrEngine,
+    selection: Option<Rect>,
+) -> Result<OcrDocument> {
+    let frame = backend.capture()?;
+    recognize_region(engine, &frame, selection)
+}
@@ -0,0 +1,276 @@
+use super::{OcrBlock, OcrDocument, OcrEngine, OcrLine, OcrPage, OcrWord};
+use crate::capture::Frame;
+use crate::export::{ExportOptions, Exporter, PngCompression};
+use crate::geometry::Rect;
+use anyhow::{anyhow, Result};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// TSV row level tesseract uses for single words.
+const WORD_LEVEL: &str = "5";
+
+/// Runs a local `tesseract` binary and parses its TSV output.
+///
+/// The frame is piped in as PNG, so no temporary files are involved.
+pub struct TesseractEngine {
+    binary: PathBuf,
+    language: String,
+    page_segmentation: Option<u8>,
+}
+
+impl Default for TesseractEngine {
+    fn default() -> Self {
+        TesseractEngine {
+            binary: PathBuf::from("tesseract"),
+            language: String::from("eng"),
+            page_segmentation: None,
+        }
+    }
+}
+
+impl TesseractEngine {
+    pub fn set_binary(&mut self, binary: impl Into<PathBuf>) -> &mut Self {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Tesseract language code(s), e.g. `"deu"` or `"eng+deu"`.
+    pub fn set_language(&mut self, language: &str) -> &mut Self {
+        self.language = language.to_string();
+        self
+    }
+
+    /// Tesseract `--psm` mode; `None` keeps the tesseract default.
+    pub fn set_page_segmentation(&mut self, psm: Option<u8>) -> &mut Self {
+        self.page_segmentation = psm;
+        self
+    }
+}
+
+impl OcrEngine for TesseractEngine {
+    fn name(&self) -> &'static str {
+        "tesseract"
+    }
+
+    fn recognize(&self, frame: &Frame) -> Result<OcrDocument> {
+        let png = Exporter::new().encode(
+            frame,
+            &ExportOptions::Png {
+                compression: PngCompression::Fast,
+            },
+        )?;
+
+        let mut command = Command::new(&self.binary);
+        command.args(["stdin", "stdout", "-l", &self.language]);
+        if let Some(psm) = self.page_segmentation {
+            command.args(["--psm", &psm.to_string()]);
+        }
+        command.arg("tsv");
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start {}: {}", self.binary.display(), e))?;
+
+        // Dropping stdin after writing closes the pipe so tesseract starts working
+        child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("tesseract stdin not available"))?
+            .write_all(&png)?;
+
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            return Err(anyhow!(
+                "tesseract failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let bounds = Rect::new(0.0, 0.0, frame.width as f32, frame.height as f32);
+        parse_tsv(&String::from_utf8_lossy(&output.stdout), bounds)
+    }
+}
+
+/// Builds a single-page document from the word rows of tesseract's TSV
+/// output, keeping tesseract's block and line grouping.
+pub fn parse_tsv(tsv: &str, page: Rect) -> Result<OcrDocument> {
+    let mut blocks: Vec<(&str, Vec<OcrLine>)> = Vec::new();
+    // (block, paragraph, line) number of the line being collected
+    let mut current_line: Option<(&str, &str, &str)> = None;
+    let mut words = Vec::new();
+
+    for (number, row) in tsv.lines().enumerate().skip(1) {
+        let fields: Vec<&str> = row.split('\t').collect();
+        if fields.len() < 12 {
+            continue;
+        }
+        if fields[0] != WORD_LEVEL || fields[11].trim().is_empty() {
+            continue;
+        }
+
+        let number_at = |i: usize| -> Result<f32> {
+            fields[i]
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| anyhow!("Invalid number {:?} in TSV row {}", fields[i], number + 1))
+        };
+        let (left, top) = (number_at(6)?, number_at(7)?);
+        let rect = Rect::new(left, top, left + number_at(8)?, top + number_at(9)?);
+        let confidence = number_at(10)?;
+
+        let key = (fields[2], fields[3], fields[4]);
+        if current_line != Some(key) {
+            if let Some((block, _, _)) = current_line {
+                push_line(&mut blocks, block, std::mem::take(&mut words));
+            }
+            current_line = Some(key);
+        }
+
+        words.push(OcrWord {
+            text: fields[11].to_string(),
+            rect,
+            confidence: (confidence >= 0.0).then_some(confidence / 100.0),
+        });
+    }
+    if let Some((block, _, _)) = current_line {
+        push_line(&mut blocks, block, words);
+    }
+
+    let blocks = blocks
+        .into_iter()
+        .map(|(_, lines)| OcrBlock::new(lines))
+        .collect();
+    Ok(OcrDocument {
+        pages: vec![OcrPage::new(page, blocks)],
+    })
+}
+
+fn push_line<'a>(blocks: &mut Vec<(&'a str, Vec<OcrLine>)>, block: &'a str, words: Vec<OcrWord>) {
+    let line = OcrLine::new(words);
+    match blocks.last_mut() {
+        Some((id, lines)) if *id == block => lines.push(line),
+        _ => blocks.push((block, vec![line])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str =
+        "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext";
+
+    fn tsv(rows: &[&str]) -> String {
+        std::iter::once(HEADER)
+            .chain(rows.iter().copied())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn page() -> Rect {
+        Rect::new(0.0, 0.0, 200.0, 100.0)
+    }
+
+    #[test]
+    fn groups_word_rows_by_block_and_line() {
+        let input = tsv(&[
+            "1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t",
+            "2\t1\t1\t0\t0\t0\t10\t10\t90\t30\t-1\t",
+            "3\t1\t1\t1\t0\t0\t10\t10\t90\t30\t-1\t",
+            "4\t1\t1\t1\t1\t0\t10\t10\t90\t12\t-1\t",
+            "5\t1\t1\t1\t1\t1\t10\t10\t40\t12\t96.5\tHello",
+            "5\t1\t1\t1\t1\t2\t55\t10\t45\t12\t91\tworld",
+            "4\t1\t1\t1\t2\t0\t10\t28\t30\t12\t-1\t",
+            "5\t1\t1\t1\t2\t1\t10\t28\t30\t12\t88\tagain",
+            "2\t1\t2\t0\t0\t0\t10\t60\t50\t12\t-1\t",
+            "3\t1\t2\t1\t0\t0\t10\t60\t50\t12\t-1\t",
+            "4\t1\t2\t1\t1\t0\t10\t60\t50\t12\t-1\t",
+            "5\t1\t2\t1\t1\t1\t10\t60\t50\t12\t75\tfooter",
+        ]);
+
+        let document = parse_tsv(&input, page()).unwrap();
+
+        assert_eq!(document.pages.len(), 1);
+        assert_eq!(document.pages[0].rect, page());
+        let blocks = &document.pages[0].blocks;
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].lines.len(), 2);
+        assert_eq!(blocks[1].lines.len(), 1);
+        assert_eq!(document.text(), "Hello world\nagain\n\nfooter");
+
+        let hello = &blocks[0].lines[0].words[0];
+        assert_eq!(hello.rect, Rect::new(10.0, 10.0, 50.0, 22.0));
+        assert_eq!(hello.confidence, Some(0.965));
+        assert_eq!(blocks[0].lines[0].rect, Rect::new(10.0, 10.0, 100.0, 22.0));
+    }
+
+    #[test]
+    fn non_word_rows_never_become_words() {
+        // Levels 1-4 carry conf -1 and no text; even with text they are not words
+        let input = tsv(&[
+            "1\t1\t0\t0\t0\t0\t0\t0\t200\t100\t-1\t",
+            "2\t1\t1\t0\t0\t0\t10\t10\t90\t30\t-1\tblock",
+            "4\t1\t1\t1\t1\t0\t10\t10\t90\t12\t-1\tline",
+        ]);
+
+        let document = parse_tsv(&input, page()).unwrap();
+
+        assert_eq!(document.pages.len(), 1);
+        assert!(document.pages[0].blocks.is_empty());
+        assert_eq!(document.words().count(), 0);
+        assert_eq!(document.pages[0].confidence, None);
+    }
+
+    #[test]
+    fn skips_empty_words_and_keeps_missing_confidence() {
+        let input = tsv(&[
+            "5\t1\t1\t1\t1\t1\t10\t10\t40\t12\t90\tkept",
+            "5\t1\t1\t1\t1\t2\t55\t10\t5\t12\t95\t ",
+            "5\t1\t1\t1\t1\t3\t60\t10\t5\t12\t95\t",
+            "5\t1\t1\t1\t1\t4\t70\t10\t30\t12\t-1\tunsure",
+        ]);
+
+        let document = parse_tsv(&input, page()).unwrap();
+
+        let words: Vec<_> = document.words().collect();
+        assert_eq!(words.len(), 2);
+        assert_eq!(words[0].confidence, Some(0.9));
+        assert_eq!(words[1].text, "unsure");
+        assert_eq!(words[1].confidence, None);
+        // Missing confidences do not drag the line average down
+        assert_eq!(document.pages[0].blocks[0].lines[0].confidence, Some(0.9));
+    }
+
+    #[test]
+    fn ignores_short_rows_and_empty_input() {
+        let input = tsv(&[
+            "5\t1\t1\t1\t1\t1\t10\t10",
+            "",
+            "5\t1\t1\t1\t1\t1\t10\t10\t40\t12\t90\tword",
+        ]);
+        let document = parse_tsv(&input, page()).unwrap();
+        assert_eq!(document.text(), "word");
+
+        let empty = parse_tsv("", page()).unwrap();
+        assert!(empty.pages[0].blocks.is_empty());
+        let header_only = parse_tsv(HEADER, page()).unwrap();
+        assert!(header_only.pages[0].blocks.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_numbers_with_the_row() {
+        let input = tsv(&[
+            "5\t1\t1\t1\t1\t1\t10\t10\t40\t12\t90\tfine",
+            "5\t1\t1\t1\t1\t2\t55\tx\t45\t12\t91\tbroken",
+        ]);
+
+        let error = parse_tsv(&input, page()).unwrap_err();
+
+        assert_eq!(error.to_string(), "Invalid number \"x\" in TSV row 3");
+    }
+}
@@ -0,0 +1,113 @@
+use super::{OcrBlock, OcrDocument, OcrEngine, OcrLine, OcrPage, OcrWord};
+use crate::capture::Frame;
+use crate::geometry::Rect;
+use anyhow::{anyhow, Result};
+use image::imageops::{self, FilterType};
+use windows::core::HSTRING;
+use windows::Globalization::Language;
+use windows::Graphics::Imaging::{BitmapAlphaMode, BitmapPixelFormat, SoftwareBitmap};
+use windows::Media::Ocr::OcrEngine as WinRtOcrEngine;
+use windows::Storage::Streams::DataWriter;
+
+/// Windows.Media.Ocr, called on the frame's pixels in memory.
+///
+/// Words carry the bounding boxes Windows reports; it reports no
+/// confidence.
+#[derive(Default)]
+pub struct WinOcrEngine {
+    language: Option<String>,
+}
+
+impl WinOcrEngine {
+    /// BCP-47 language tag such as `"de-DE"`; the user profile language is
+    /// used otherwise.
+    pub fn set_language(&mut self, language: &str) -> &mut Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    fn engine(&self) -> Result<WinRtOcrEngine> {
+        match &self.language {
+            Some(tag) => {
+                let language = Language::CreateLanguage(&HSTRING::from(tag.as_str()))?;
+                WinRtOcrEngine::TryCreateFromLanguage(&language)
+                    .map_err(|e| anyhow!("No Windows OCR for language {}: {}", tag, e))
+            }
+            None => WinRtOcrEngine::TryCreateFromUserProfileLanguages()
+                .map_err(|e| anyhow!("No Windows OCR for the profile languages: {}", e)),
+        }
+    }
+}
+
+/// `frame` as a BGRA bitmap no larger than `max_dimension`, and the factor
+/// it was shrunk by.
+fn software_bitmap(frame: &Frame, max_dimension: u32) -> Result<(SoftwareBitmap, f32)> {
+    let largest = frame.width.max(frame.height);
+    let scale = if max_dimension > 0 && largest > max_dimension {
+        max_dimension as f32 / largest as f32
+    } else {
+        1.0
+    };
+    let width = ((frame.width as f32 * scale) as u32).max(1);
+    let height = ((frame.height as f32 * scale) as u32).max(1);
+    let mut image = frame.to_image();
+    if scale < 1.0 {
+        image = imageops::resize(&image, width, height, FilterType::Triangle);
+    }
+
+    let mut bgra = image.into_raw();
+    for pixel in bgra.chunks_exact_mut(4) {
+        pixel.swap(0, 2);
+    }
+    let writer = DataWriter::new()?;
+    writer.WriteBytes(&bgra)?;
+    let bitmap = SoftwareBitmap::CreateCopyWithAlphaFromBuffer(
+        &writer.DetachBuffer()?,
+        BitmapPixelFormat::Bgra8,
+        width as i32,
+        height as i32,
+        BitmapAlphaMode::Ignore,
+    )?;
+    Ok((bitmap, scale))
+}
+
+impl OcrEngine for WinOcrEngine {
+    fn name(&self) -> &'static str {
+        "windows"
+    }
+
+    fn recognize(&self, frame: &Frame) -> Result<OcrDocument> {
+        let engine = self.engine()?;
+        let (bitmap, scale) = software_bitmap(frame, WinRtOcrEngine::MaxImageDimension()?)?;
+        let result = engine
+            .RecognizeAsync(&bitmap)?
+            .get()
+            .map_err(|e| anyhow!("Windows OCR failed: {}", e))?;
+
+        let mut lines = Vec::new();
+        for line in result.Lines()? {
+            let mut words = Vec::new();
+            for word in line.Words()? {
+                let rect = word.BoundingRect()?;
+                words.push(OcrWord {
+                    text: word.Text()?.to_string_lossy(),
+                    rect: Rect::new(
+                        rect.X / scale,
+                        rect.Y / scale,
+                        (rect.X + rect.Width) / scale,
+                        (rect.Y + rect.Height) / scale,
+                    ),
+                    confidence: None,
+                });
+            }
+            if !words.is_empty() {
+                lines.push(OcrLine::new(words));
+            }
+        }
+
+        let bounds = Rect::new(0.0, 0.0, frame.width as f32, frame.height as f32);
+        Ok(OcrDocument {
+            pages: vec![OcrPage::new(bounds, vec![OcrBlock::new(lines)])],
+        })
+    }
+}
@@ -0,0 +1,301 @@
+use crate::geometry::{Point, Rect};
+use serde::{Deserialize, Serialize};
+
+/// Window as reported by the platform, in virtual-screen coordinates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// Platform handle, e.g. the `HWND` value.
+    pub id: u64,
+    /// `None` for top-level windows.
+    pub parent: Option<u64>,
+    pub title: String,
+    /// Outer bounds, including invisible resize borders and the drop
+    /// shadow where the system draws one.
+    pub rect: Rect,
+    /// Visible frame if it differs from `rect`.
+    pub frame: Option<Rect>,
+    /// Client area without title bar and borders.
+    pub client: Option<Rect>,
+    /// Stacking position among windows with the same parent, 0 = topmost.
+    pub z_order: u32,
+}
+
+/// Which bounds of a window are captured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BoundsMode {
+    /// Everything the window covers, shadow included.
+    Shadow,
+    /// The visible window with its title bar and border.
+    #[default]
+    Frame,
+    /// Only the content area.
+    Client,
+}
+
+impl BoundsMode {
+    /// Next mode in the order shadow, frame, client.
+    pub fn next(self) -> Self {
+        match self {
+            BoundsMode::Shadow => BoundsMode::Frame,
+            BoundsMode::Frame => BoundsMode::Client,
+            BoundsMode::Client => BoundsMode::Shadow,
+        }
+    }
+}
+
+impl WindowInfo {
+    pub fn new(id: u64, title: &str, rect: Rect, z_order: u32) -> Self {
+        WindowInfo {
+            id,
+            parent: None,
+            title: title.to_string(),
+            rect,
+            frame: None,
+            client: None,
+            z_order,
+        }
+    }
+
+    /// Bounds to capture; falls back to the next larger bounds the
+    /// platform did not report.
+    pub fn bounds(&self, mode: BoundsMode) -> Rect {
+        let frame = self.frame.unwrap_or(self.rect);
+        match mode {
+            BoundsMode::Shadow => self.rect,
+            BoundsMode::Frame => frame,
+            BoundsMode::Client => self.client.unwrap_or(frame),
+        }
+    }
+
+    /// Only the visible frame reacts to the pointer, not the shadow.
+    pub fn contains(&self, p: Point) -> bool {
+        self.frame.unwrap_or(self.rect).contains(p)
+    }
+}
+
+/// Finds the window under the pointer in a snapshot of the window list.
+///
+/// Knows nothing about the platform: the list is taken once when picking
+/// starts, so windows opened later are not offered.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowPicker {
+    windows: Vec<WindowInfo>,
+    mode: BoundsMode,
+    /// Whether child controls are picked instead of their top-level window.
+    children: bool,
+    hovered: Option<u64>,
+}
+
+impl WindowPicker {
+    pub fn new(windows: Vec<WindowInfo>) -> Self {
+        WindowPicker {
+            windows,
+            ..Default::default()
+        }
+    }
+
+    pub fn set_bounds_mode(&mut self, mode: BoundsMode) -> &mut Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn bounds_mode(&self) -> BoundsMode {
+        self.mode
+    }
+
+    pub fn set_children(&mut self, children: bool) -> &mut Self {
+        self.children = children;
+        self
+    }
+
+    pub fn windows(&self) -> &[WindowInfo] {
+        &self.windows
+    }
+
+    pub fn window(&self, id: u64) -> Option<&WindowInfo> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    /// Topmost top-level window at `p` or, when picking children, the
+    /// deepest control inside it.
+    pub fn hit_test(&self, p: Point) -> Option<&WindowInfo> {
+        let mut found = self.topmost(None, p)?;
+        if self.children {
+            while let Some(child) = self.topmost(Some(found.id), p) {
+                found = child;
+            }
+        }
+        Some(found)
+    }
+
+    /// Tracks the window under the pointer; returns whether it changed.
+    pub fn hover(&mut self, p: Point) -> bool {
+        let hovered = self.hit_test(p).map(|w| w.id);
+        let changed = hovered != self.hovered;
+        self.hovered = hovered;
+        changed
+    }
+
+    pub fn hovered(&self) -> Option<&WindowInfo> {
+        self.hovered.and_then(|id| self.window(id))
+    }
+
+    /// Bounds of the hovered window to highlight.
+    pub fn highlight(&self) -> Option<Rect> {
+        self.hovered().map(|w| w.bounds(self.mode))
+    }
+
+    /// Bounds to capture for a click at `p`.
+    pub fn pick(&self, p: Point) -> Option<Rect> {
+        self.hit_test(p)
+            .map(|w| w.bounds(self.mode))
+            .filter(|rect| !rect.is_empty())
+    }
+
+    fn topmost(&self, parent: Option<u64>, p: Point) -> Option<&WindowInfo> {
+        self.windows
+            .iter()
+            .filter(|w| w.parent == parent && w.contains(p))
+            .min_by_key(|w| w.z_order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn child(id: u64, parent: u64, title: &str, rect: Rect, z_order: u32) -> WindowInfo {
+        WindowInfo {
+            parent: Some(parent),
+            ..WindowInfo::new(id, title, rect, z_order)
+        }
+    }
+
+    /// A desktop with a dialog above an editor above a maximised window.
+    /// The editor has a shadow, a toolbar above a text area with a scroll
+    /// bar, and a panel both of them cover.
+    fn windows() -> Vec<WindowInfo> {
+        let mut editor = WindowInfo::new(1, "Editor", Rect::new(0.0, 0.0, 400.0, 300.0), 1);
+        editor.frame = Some(Rect::new(8.0, 0.0, 392.0, 292.0));
+        editor.client = Some(Rect::new(8.0, 30.0, 392.0, 292.0));
+        let mut minimised = WindowInfo::new(5, "Tray", Rect::new(600.0, 600.0, 700.0, 700.0), 0);
+        minimised.client = Some(Rect::new(650.0, 650.0, 650.0, 650.0));
+        vec![
+            WindowInfo::new(3, "Background", Rect::new(0.0, 0.0, 1000.0, 800.0), 5),
+            editor,
+            child(12, 1, "Panel", Rect::new(8.0, 30.0, 392.0, 292.0), 2),
+            child(10, 1, "Toolbar", Rect::new(8.0, 30.0, 392.0, 60.0), 0),
+            child(11, 1, "Text", Rect::new(8.0, 60.0, 392.0, 292.0), 1),
+            child(
+                20,
+                11,
+                "Scroll bar",
+                Rect::new(372.0, 60.0, 392.0, 292.0),
+                0,
+            ),
+            WindowInfo::new(2, "Dialog", Rect::new(100.0, 100.0, 200.0, 200.0), 0),
+            minimised,
+        ]
+    }
+
+    fn hit(picker: &WindowPicker, x: f32, y: f32) -> Option<&str> {
+        picker.hit_test(Point::new(x, y)).map(|w| w.title.as_str())
+    }
+
+    #[test]
+    fn topmost_window_wins() {
+        let picker = WindowPicker::new(windows());
+        assert_eq!(hit(&picker, 150.0, 150.0), Some("Dialog"));
+        assert_eq!(hit(&picker, 50.0, 50.0), Some("Editor"));
+        assert_eq!(hit(&picker, 900.0, 700.0), Some("Background"));
+        assert_eq!(hit(&picker, 2000.0, 10.0), None);
+        // The shadow left of the frame belongs to the window below
+        assert_eq!(hit(&picker, 4.0, 100.0), Some("Background"));
+        assert_eq!(hit(&picker, 396.0, 296.0), Some("Background"));
+    }
+
+    #[test]
+    fn list_order_does_not_matter() {
+        let mut reversed = windows();
+        reversed.reverse();
+        let picker = WindowPicker::new(windows());
+        let reversed = WindowPicker::new(reversed);
+        for (x, y) in [(150.0, 150.0), (50.0, 50.0), (4.0, 100.0), (380.0, 100.0)] {
+            assert_eq!(hit(&picker, x, y), hit(&reversed, x, y), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn children_are_picked_down_to_the_deepest() {
+        let mut picker = WindowPicker::new(windows());
+        picker.set_children(true);
+        assert_eq!(hit(&picker, 50.0, 40.0), Some("Toolbar"));
+        assert_eq!(hit(&picker, 50.0, 100.0), Some("Text"));
+        assert_eq!(hit(&picker, 380.0, 100.0), Some("Scroll bar"));
+        // Title bar: no child there
+        assert_eq!(hit(&picker, 50.0, 10.0), Some("Editor"));
+        // Children of covered windows are not reached
+        assert_eq!(hit(&picker, 150.0, 150.0), Some("Dialog"));
+
+        picker.set_children(false);
+        assert_eq!(hit(&picker, 380.0, 100.0), Some("Editor"));
+    }
+
+    #[test]
+    fn bounds_follow_the_mode_and_fall_back() {
+        let picker = WindowPicker::new(windows());
+        let editor = picker.window(1).unwrap();
+        assert_eq!(editor.bounds(BoundsMode::Shadow), editor.rect);
+        assert_eq!(
+            editor.bounds(BoundsMode::Frame),
+            Rect::new(8.0, 0.0, 392.0, 292.0)
+        );
+        assert_eq!(
+            editor.bounds(BoundsMode::Client),
+            Rect::new(8.0, 30.0, 392.0, 292.0)
+        );
+        let dialog = picker.window(2).unwrap();
+        for mode in [BoundsMode::Shadow, BoundsMode::Frame, BoundsMode::Client] {
+            assert_eq!(dialog.bounds(mode), dialog.rect);
+        }
+
+        assert_eq!(picker.bounds_mode(), BoundsMode::Frame);
+        assert_eq!(BoundsMode::Frame.next(), BoundsMode::Client);
+        assert_eq!(BoundsMode::Client.next(), BoundsMode::Shadow);
+        assert_eq!(BoundsMode::Shadow.next(), BoundsMode::Frame);
+    }
+
+    #[test]
+    fn pick_skips_empty_bounds() {
+        let mut picker = WindowPicker::new(windows());
+        let tray = Point::new(650.0, 650.0);
+        assert_eq!(
+            picker.pick(tray),
+            Some(Rect::new(600.0, 600.0, 700.0, 700.0))
+        );
+        picker.set_bounds_mode(BoundsMode::Client);
+        assert_eq!(picker.pick(tray), None);
+        assert_eq!(
+            picker.pick(Point::new(50.0, 10.0)),
+            Some(Rect::new(8.0, 30.0, 392.0, 292.0))
+        );
+    }
+
+    #[test]
+    fn hover_reports_changes_and_highlights() {
+        let mut picker = WindowPicker::new(windows());
+        assert!(picker.hover(Point::new(50.0, 50.0)));
+        assert!(!picker.hover(Point::new(60.0, 60.0)));
+        assert_eq!(picker.hovered().unwrap().id, 1);
+        assert_eq!(picker.highlight(), Some(Rect::new(8.0, 0.0, 392.0, 292.0)));
+        picker.set_bounds_mode(BoundsMode::Shadow);
+        assert_eq!(picker.highlight(), Some(Rect::new(0.0, 0.0, 400.0, 300.0)));
+
+        assert!(picker.hover(Point::new(150.0, 150.0)));
+        assert_eq!(picker.hovered().unwrap().title, "Dialog");
+        assert!(picker.hover(Point::new(-5.0, -5.0)));
+        assert_eq!(picker.hovered(), None);
+        assert_eq!(picker.highlight(), None);
+    }
+}
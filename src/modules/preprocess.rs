@@ -0,0 +1,870 @@
+use crate::capture::Frame;
+use crate::geometry::{Point, Rect};
+use crate::ocr::{OcrDocument, OcrEngine};
+use anyhow::Result;
+
+const BLACK: [u8; 4] = [0, 0, 0, 255];
+const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+/// Longer side of the downsampled image the skew angle is measured on.
+const DESKEW_DETECT_SIZE: u32 = 1024;
+
+/// ITU-R BT.601 luma of an RGBA pixel.
+fn luma(px: [u8; 4]) -> u8 {
+    ((px[0] as u32 * 299 + px[1] as u32 * 587 + px[2] as u32 * 114) / 1000) as u8
+}
+
+fn map_pixels(frame: &Frame, f: impl Fn([u8; 4]) -> [u8; 4]) -> Frame {
+    let mut out = Frame::new(frame.width, frame.height)
+        .with_origin(frame.origin.0, frame.origin.1)
+        .with_scale(frame.scale);
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            out.put_pixel(x, y, f(frame.pixel(x, y)));
+        }
+    }
+    out
+}
+
+/// Where a step moves the content of an image: the point `(x, y)` of its
+/// input, in continuous pixel coordinates, ends up at
+/// `(xx * x + xy * y + tx, yx * x + yy * y + ty)` of its output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub xx: f32,
+    pub xy: f32,
+    pub yx: f32,
+    pub yy: f32,
+    pub tx: f32,
+    pub ty: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::IDENTITY
+    }
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        xx: 1.0,
+        xy: 0.0,
+        yx: 0.0,
+        yy: 1.0,
+        tx: 0.0,
+        ty: 0.0,
+    };
+
+    pub fn scale(factor: f32) -> Self {
+        Transform {
+            xx: factor,
+            yy: factor,
+            ..Transform::IDENTITY
+        }
+    }
+
+    /// Turns the content by `angle` degrees counter-clockwise around
+    /// `center`, as [`Deskew`] does to straighten text skewed by `angle`.
+    pub fn rotation(angle: f32, center: Point) -> Self {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Transform {
+            xx: cos,
+            xy: sin,
+            yx: -sin,
+            yy: cos,
+            tx: center.x - cos * center.x - sin * center.y,
+            ty: center.y + sin * center.x - cos * center.y,
+        }
+    }
+
+    /// `self` followed by `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            xx: next.xx * self.xx + next.xy * self.yx,
+            xy: next.xx * self.xy + next.xy * self.yy,
+            yx: next.yx * self.xx + next.yy * self.yx,
+            yy: next.yx * self.xy + next.yy * self.yy,
+            tx: next.xx * self.tx + next.xy * self.ty + next.tx,
+            ty: next.yx * self.tx + next.yy * self.ty + next.ty,
+        }
+    }
+
+    /// The transform moving points back; the identity if there is none.
+    pub fn inverse(&self) -> Transform {
+        let determinant = self.xx * self.yy - self.xy * self.yx;
+        if determinant == 0.0 || !determinant.is_finite() {
+            return Transform::IDENTITY;
+        }
+        let (xx, xy) = (self.yy / determinant, -self.xy / determinant);
+        let (yx, yy) = (-self.yx / determinant, self.xx / determinant);
+        Transform {
+            xx,
+            xy,
+            yx,
+            yy,
+            tx: -(xx * self.tx + xy * self.ty),
+            ty: -(yx * self.tx + yy * self.ty),
+        }
+    }
+
+    pub fn apply(&self, p: Point) -> Point {
+        Point::new(
+            self.xx * p.x + self.xy * p.y + self.tx,
+            self.yx * p.x + self.yy * p.y + self.ty,
+        )
+    }
+
+    /// Bounding box of the transformed corners of `rect`.
+    pub fn apply_rect(&self, rect: &Rect) -> Rect {
+        let corners = [
+            Point::new(rect.left, rect.top),
+            Point::new(rect.right, rect.top),
+            Point::new(rect.left, rect.bottom),
+            Point::new(rect.right, rect.bottom),
+        ]
+        .map(|p| self.apply(p));
+        corners
+            .iter()
+            .skip(1)
+            .fold(Rect::from_points(corners[0], corners[0]), |bounds, &p| {
+                bounds.union(&Rect::from_points(p, p))
+            })
+    }
+}
+
+/// One stage of the image cleanup that runs before OCR.
+pub trait PreprocessStep {
+    fn name(&self) -> &'static str;
+    fn apply(&self, frame: &Frame) -> Frame;
+    /// Factor by which the step enlarges the image.
+    fn scale_factor(&self) -> u32 {
+        1
+    }
+    /// Runs the step and tells where it moved the content; steps that
+    /// rotate or move it override this.
+    fn apply_mapped(&self, frame: &Frame) -> (Frame, Transform) {
+        (
+            self.apply(frame),
+            Transform::scale(self.scale_factor() as f32),
+        )
+    }
+}
+
+pub struct Grayscale;
+
+impl PreprocessStep for Grayscale {
+    fn name(&self) -> &'static str {
+        "grayscale"
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        map_pixels(frame, |px| {
+            let l = luma(px);
+            [l, l, l, px[3]]
+        })
+    }
+}
+
+/// Inverts the frame if its average brightness is below `threshold`, so
+/// dark-mode UIs end up as dark text on a light background.
+pub struct AutoInvert {
+    pub threshold: u8,
+}
+
+impl Default for AutoInvert {
+    fn default() -> Self {
+        AutoInvert { threshold: 128 }
+    }
+}
+
+impl PreprocessStep for AutoInvert {
+    fn name(&self) -> &'static str {
+        "auto-invert"
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        let pixels = frame.width as u64 * frame.height as u64;
+        if pixels == 0 {
+            return frame.clone();
+        }
+
+        let mut sum = 0u64;
+        for y in 0..frame.height {
+            for px in frame.row(y).chunks_exact(4) {
+                sum += luma([px[0], px[1], px[2], px[3]]) as u64;
+            }
+        }
+
+        if sum / pixels < self.threshold as u64 {
+            map_pixels(frame, |px| [255 - px[0], 255 - px[1], 255 - px[2], px[3]])
+        } else {
+            frame.clone()
+        }
+    }
+}
+
+/// Reduces the frame to pure black and white.
+pub enum Binarize {
+    /// One global threshold chosen by Otsu's method.
+    Otsu,
+    /// Compares every pixel with the mean of the `window` x `window` pixels
+    /// around it; pixels darker than that mean minus `offset` become black.
+    Adaptive { window: u32, offset: i32 },
+}
+
+/// Threshold separating the two luma classes with maximal between-class
+/// variance.
+pub fn otsu_threshold(frame: &Frame) -> u8 {
+    let mut histogram = [0u64; 256];
+    for y in 0..frame.height {
+        for px in frame.row(y).chunks_exact(4) {
+            histogram[luma([px[0], px[1], px[2], px[3]]) as usize] += 1;
+        }
+    }
+
+    let total: u64 = histogram.iter().sum();
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| i as f64 * n as f64)
+        .sum();
+
+    let (mut background, mut weighted_background) = (0u64, 0f64);
+    let (mut best, mut best_variance) = (0u8, -1f64);
+    for (level, &count) in histogram.iter().enumerate() {
+        background += count;
+        if background == 0 {
+            continue;
+        }
+        let foreground = total - background;
+        if foreground == 0 {
+            break;
+        }
+
+        weighted_background += level as f64 * count as f64;
+        let mean_background = weighted_background / background as f64;
+        let mean_foreground = (weighted_total - weighted_background) / foreground as f64;
+        let variance =
+            background as f64 * foreground as f64 * (mean_background - mean_foreground).powi(2);
+        if variance > best_variance {
+            best_variance = variance;
+            best = level as u8;
+        }
+    }
+    best
+}
+
+impl PreprocessStep for Binarize {
+    fn name(&self) -> &'static str {
+        match self {
+            Binarize::Otsu => "binarize-otsu",
+            Binarize::Adaptive { .. } => "binarize-adaptive",
+        }
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        match self {
+            Binarize::Otsu => {
+                let threshold = otsu_threshold(frame);
+                map_pixels(frame, |px| if luma(px) > threshold { WHITE } else { BLACK })
+            }
+            Binarize::Adaptive { window, offset } => adaptive_threshold(frame, *window, *offset),
+        }
+    }
+}
+
+fn adaptive_threshold(frame: &Frame, window: u32, offset: i32) -> Frame {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+
+    // Summed-area table with an extra zero row and column
+    let mut integral = vec![0u64; (w + 1) * (h + 1)];
+    for y in 0..h {
+        let mut row_sum = 0u64;
+        for x in 0..w {
+            row_sum += luma(frame.pixel(x as u32, y as u32)) as u64;
+            integral[(y + 1) * (w + 1) + x + 1] = integral[y * (w + 1) + x + 1] + row_sum;
+        }
+    }
+
+    let half = (window.max(1) / 2) as usize;
+    let mut out = Frame::new(frame.width, frame.height)
+        .with_origin(frame.origin.0, frame.origin.1)
+        .with_scale(frame.scale);
+    for y in 0..h {
+        let (top, bottom) = (y.saturating_sub(half), (y + half + 1).min(h));
+        for x in 0..w {
+            let (left, right) = (x.saturating_sub(half), (x + half + 1).min(w));
+            let sum = integral[bottom * (w + 1) + right] + integral[top * (w + 1) + left]
+                - integral[top * (w + 1) + right]
+                - integral[bottom * (w + 1) + left];
+            let mean = (sum / ((right - left) * (bottom - top)) as u64) as i32;
+
+            let value = luma(frame.pixel(x as u32, y as u32)) as i32;
+            let px = if value < mean - offset { BLACK } else { WHITE };
+            out.put_pixel(x as u32, y as u32, px);
+        }
+    }
+    out
+}
+
+/// Enlarges the frame by an integer factor, replicating pixels so that
+/// binarized edges stay sharp.
+pub struct Upscale {
+    pub factor: u32,
+}
+
+impl PreprocessStep for Upscale {
+    fn name(&self) -> &'static str {
+        "upscale"
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        let factor = self.factor.max(1);
+        let mut out = Frame::new(frame.width * factor, frame.height * factor)
+            .with_origin(frame.origin.0, frame.origin.1)
+            .with_scale(frame.scale * factor as f32);
+        for y in 0..out.height {
+            for x in 0..out.width {
+                out.put_pixel(x, y, frame.pixel(x / factor, y / factor));
+            }
+        }
+        out
+    }
+
+    fn scale_factor(&self) -> u32 {
+        self.factor.max(1)
+    }
+}
+
+/// Laplacian sharpening; `amount` of 1.0 is a moderate effect.
+pub struct Sharpen {
+    pub amount: f32,
+}
+
+impl PreprocessStep for Sharpen {
+    fn name(&self) -> &'static str {
+        "sharpen"
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        let (w, h) = (frame.width as i64, frame.height as i64);
+        let sample =
+            |x: i64, y: i64| frame.pixel(x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32);
+
+        let mut out = frame.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let center = sample(x, y);
+                let neighbours = [
+                    sample(x - 1, y),
+                    sample(x + 1, y),
+                    sample(x, y - 1),
+                    sample(x, y + 1),
+                ];
+                let mut px = center;
+                for c in 0..3 {
+                    let around: f32 = neighbours.iter().map(|n| n[c] as f32).sum();
+                    let value = center[c] as f32 * (1.0 + 4.0 * self.amount) - around * self.amount;
+                    px[c] = value.round().clamp(0.0, 255.0) as u8;
+                }
+                out.put_pixel(x as u32, y as u32, px);
+            }
+        }
+        out
+    }
+}
+
+/// Straightens slightly rotated text.
+///
+/// The skew angle is the one within `max_angle` degrees whose horizontal
+/// projection of dark pixels has the highest variance, i.e. where text
+/// lines and gaps separate best.
+pub struct Deskew {
+    pub max_angle: f32,
+    pub step: f32,
+}
+
+impl Default for Deskew {
+    fn default() -> Self {
+        Deskew {
+            max_angle: 5.0,
+            step: 0.25,
+        }
+    }
+}
+
+impl Deskew {
+    /// Skew of the text in degrees, clockwise positive.
+    ///
+    /// Measured on a copy shrunk to [`DESKEW_DETECT_SIZE`] where a block is
+    /// dark if any of its pixels is, so thin strokes survive.
+    pub fn detect_angle(&self, frame: &Frame) -> f32 {
+        let threshold = otsu_threshold(frame);
+        let block = frame
+            .width
+            .max(frame.height)
+            .div_ceil(DESKEW_DETECT_SIZE)
+            .max(1);
+        let (width, height) = (frame.width.div_ceil(block), frame.height.div_ceil(block));
+        let mut dark_blocks = vec![false; width as usize * height as usize];
+        for y in 0..frame.height {
+            let row = (y / block * width) as usize;
+            for (x, px) in frame.row(y).chunks_exact(4).enumerate() {
+                if luma([px[0], px[1], px[2], px[3]]) <= threshold {
+                    dark_blocks[row + x / block as usize] = true;
+                }
+            }
+        }
+        let dark: Vec<(f32, f32)> = dark_blocks
+            .iter()
+            .enumerate()
+            .filter(|(_, &dark)| dark)
+            .map(|(i, _)| ((i % width as usize) as f32, (i / width as usize) as f32))
+            .collect();
+        // Nothing or everything dark: no text structure to measure
+        if dark.is_empty() || dark.len() == dark_blocks.len() {
+            return 0.0;
+        }
+
+        let diagonal = ((width as f32).hypot(height as f32)).ceil() as usize;
+        let steps = (self.max_angle / self.step.max(0.01)).round() as i32;
+        let mut best = (0.0f32, f64::MIN);
+        for i in -steps..=steps {
+            let angle = i as f32 * self.step;
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let last = diagonal as i64 * 2;
+            let mut bins = vec![0u32; last as usize + 1];
+            for &(x, y) in &dark {
+                let row = (y * cos - x * sin).round() as i64 + diagonal as i64;
+                bins[row.clamp(0, last) as usize] += 1;
+            }
+            let mean = dark.len() as f64 / bins.len() as f64;
+            let variance: f64 = bins.iter().map(|&b| (b as f64 - mean).powi(2)).sum();
+            // Prefer the smaller correction on ties
+            if variance > best.1 || (variance == best.1 && angle.abs() < best.0.abs()) {
+                best = (angle, variance);
+            }
+        }
+        best.0
+    }
+}
+
+impl PreprocessStep for Deskew {
+    fn name(&self) -> &'static str {
+        "deskew"
+    }
+
+    fn apply(&self, frame: &Frame) -> Frame {
+        self.apply_mapped(frame).0
+    }
+
+    fn apply_mapped(&self, frame: &Frame) -> (Frame, Transform) {
+        let angle = self.detect_angle(frame);
+        if angle == 0.0 {
+            return (frame.clone(), Transform::IDENTITY);
+        }
+        let center = Point::new(frame.width as f32 / 2.0, frame.height as f32 / 2.0);
+        (rotate(frame, angle), Transform::rotation(angle, center))
+    }
+}
+
+/// Rotates around the center by `angle` degrees with bilinear sampling,
+/// filling uncovered corners with white.
+fn rotate(frame: &Frame, angle: f32) -> Frame {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let (cx, cy) = (frame.width as f32 / 2.0, frame.height as f32 / 2.0);
+    let mut out = frame.clone();
+
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+            let sx = dx * cos - dy * sin + cx - 0.5;
+            let sy = dx * sin + dy * cos + cy - 0.5;
+            out.put_pixel(x, y, sample_bilinear(frame, sx, sy));
+        }
+    }
+    out
+}
+
+fn sample_bilinear(frame: &Frame, x: f32, y: f32) -> [u8; 4] {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let fetch = |xi: f32, yi: f32| {
+        if xi < 0.0 || yi < 0.0 || xi >= frame.width as f32 || yi >= frame.height as f32 {
+            WHITE
+        } else {
+            frame.pixel(xi as u32, yi as u32)
+        }
+    };
+
+    let (a, b) = (fetch(x0, y0), fetch(x0 + 1.0, y0));
+    let (c, d) = (fetch(x0, y0 + 1.0), fetch(x0 + 1.0, y0 + 1.0));
+    let mut px = [0u8; 4];
+    for i in 0..4 {
+        let top = a[i] as f32 * (1.0 - fx) + b[i] as f32 * fx;
+        let bottom = c[i] as f32 * (1.0 - fx) + d[i] as f32 * fx;
+        px[i] = (top * (1.0 - fy) + bottom * fy).round() as u8;
+    }
+    px
+}
+
+/// Ordered chain of [`PreprocessStep`]s.
+#[derive(Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn PreprocessStep>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Pipeline { steps: Vec::new() }
+    }
+
+    /// Chain tuned for small UI text: grayscale, dark-mode inversion, 2x
+    /// upscale, sharpen, deskew and Otsu binarization.
+    pub fn for_screen_text() -> Self {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add(Grayscale)
+            .add(AutoInvert::default())
+            .add(Upscale { factor: 2 })
+            .add(Sharpen { amount: 0.5 })
+            .add(Deskew::default())
+            .add(Binarize::Otsu);
+        pipeline
+    }
+
+    pub fn add(&mut self, step: impl PreprocessStep + 'static) -> &mut Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    pub fn steps(&self) -> Vec<&'static str> {
+        self.steps.iter().map(|s| s.name()).collect()
+    }
+
+    /// Total enlargement of all steps, needed to map OCR rectangles back.
+    pub fn scale_factor(&self) -> u32 {
+        self.steps.iter().map(|s| s.scale_factor()).product()
+    }
+
+    pub fn run(&self, frame: &Frame) -> Frame {
+        self.steps
+            .iter()
+            .fold(frame.clone(), |frame, step| step.apply(&frame))
+    }
+
+    /// Runs all steps and tells where they moved the content altogether.
+    pub fn run_mapped(&self, frame: &Frame) -> (Frame, Transform) {
+        self.steps.iter().fold(
+            (frame.clone(), Transform::IDENTITY),
+            |(frame, transform), step| {
+                let (frame, moved) = step.apply_mapped(&frame);
+                (frame, transform.then(&moved))
+            },
+        )
+    }
+}
+
+/// Runs a [`Pipeline`] in front of another engine and maps the recognized
+/// rectangles back onto the unprocessed frame.
+pub struct PreprocessedEngine {
+    pipeline: Pipeline,
+    engine: Box<dyn OcrEngine>,
+}
+
+impl PreprocessedEngine {
+    pub fn new(pipeline: Pipeline, engine: Box<dyn OcrEngine>) -> Self {
+        PreprocessedEngine { pipeline, engine }
+    }
+
+    pub fn pipeline(&self) -> &Pipeline {
+        &self.pipeline
+    }
+}
+
+impl OcrEngine for PreprocessedEngine {
+    fn name(&self) -> &'static str {
+        self.engine.name()
+    }
+
+    fn recognize(&self, frame: &Frame) -> Result<OcrDocument> {
+        let (processed, transform) = self.pipeline.run_mapped(frame);
+        let back = transform.inverse();
+        let mut document = self
+            .engine
+            .recognize(&processed)?
+            .map_rects(|rect| back.apply_rect(rect));
+        // A turned page maps back larger than the frame it came from
+        let bounds = Rect::new(0.0, 0.0, frame.width as f32, frame.height as f32);
+        for page in &mut document.pages {
+            page.rect = bounds;
+        }
+        Ok(document)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::{OcrBlock, OcrLine, OcrPage, OcrWord};
+
+    fn filled(width: u32, height: u32, px: [u8; 4]) -> Frame {
+        let mut frame = Frame::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                frame.put_pixel(x, y, px);
+            }
+        }
+        frame
+    }
+
+    fn fill_rect(frame: &mut Frame, rect: (u32, u32, u32, u32), px: [u8; 4]) {
+        for y in rect.1..rect.3 {
+            for x in rect.0..rect.2 {
+                frame.put_pixel(x, y, px);
+            }
+        }
+    }
+
+    /// Black bars like lines of text on white.
+    fn text_lines(width: u32, height: u32) -> Frame {
+        let mut frame = filled(width, height, WHITE);
+        for top in (20..height - 20).step_by(24) {
+            fill_rect(&mut frame, (20, top, width - 20, top + 8), BLACK);
+        }
+        frame
+    }
+
+    /// Bounds of the black pixels.
+    fn dark_bounds(frame: &Frame) -> Option<Rect> {
+        let mut bounds: Option<Rect> = None;
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if luma(frame.pixel(x, y)) < 128 {
+                    let px = Rect::new(x as f32, y as f32, x as f32 + 1.0, y as f32 + 1.0);
+                    bounds = Some(bounds.map_or(px, |b| b.union(&px)));
+                }
+            }
+        }
+        bounds
+    }
+
+    /// Reports one word covering the black pixels of what it is given.
+    struct BlobEngine;
+
+    impl OcrEngine for BlobEngine {
+        fn name(&self) -> &'static str {
+            "blob"
+        }
+
+        fn recognize(&self, frame: &Frame) -> Result<OcrDocument> {
+            let word = OcrWord {
+                text: "blob".to_string(),
+                rect: dark_bounds(frame).unwrap_or_default(),
+                confidence: None,
+            };
+            let bounds = Rect::new(0.0, 0.0, frame.width as f32, frame.height as f32);
+            Ok(OcrDocument {
+                pages: vec![OcrPage::new(
+                    bounds,
+                    vec![OcrBlock::new(vec![OcrLine::new(vec![word])])],
+                )],
+            })
+        }
+    }
+
+    fn assert_close(a: Point, b: Point) {
+        assert!(
+            (a.x - b.x).abs() < 1e-3 && (a.y - b.y).abs() < 1e-3,
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn grayscale_keeps_alpha() {
+        let out = Grayscale.apply(&filled(2, 1, [255, 0, 0, 100]));
+        assert_eq!(out.pixel(1, 0), [76, 76, 76, 100]);
+    }
+
+    #[test]
+    fn auto_invert_only_inverts_dark_frames() {
+        let dark = AutoInvert::default().apply(&filled(2, 2, [20, 30, 40, 255]));
+        assert_eq!(dark.pixel(0, 0), [235, 225, 215, 255]);
+        let light = filled(2, 2, [200, 200, 200, 255]);
+        assert_eq!(AutoInvert::default().apply(&light), light);
+    }
+
+    #[test]
+    fn otsu_separates_two_levels() {
+        let mut frame = filled(4, 4, [200, 200, 200, 255]);
+        fill_rect(&mut frame, (0, 0, 2, 4), [40, 40, 40, 255]);
+        let threshold = otsu_threshold(&frame);
+        assert!((40..200).contains(&threshold), "{}", threshold);
+
+        let out = Binarize::Otsu.apply(&frame);
+        assert_eq!(out.pixel(0, 0), BLACK);
+        assert_eq!(out.pixel(3, 3), WHITE);
+    }
+
+    #[test]
+    fn adaptive_binarize_follows_a_gradient_background() {
+        // Text a little darker than a background that darkens to the right
+        let mut frame = Frame::new(40, 10);
+        for y in 0..10 {
+            for x in 0..40 {
+                let level = 250 - x as u8 * 4;
+                frame.put_pixel(x, y, [level, level, level, 255]);
+            }
+        }
+        fill_rect(&mut frame, (4, 4, 6, 6), [200, 200, 200, 255]);
+        fill_rect(&mut frame, (34, 4, 36, 6), [60, 60, 60, 255]);
+
+        let out = Binarize::Adaptive {
+            window: 9,
+            offset: 10,
+        }
+        .apply(&frame);
+        assert_eq!(out.pixel(4, 4), BLACK);
+        assert_eq!(out.pixel(34, 4), BLACK);
+        // A global threshold would turn the whole dark side black
+        assert_eq!(out.pixel(34, 0), WHITE);
+        assert_eq!(out.pixel(4, 0), WHITE);
+    }
+
+    #[test]
+    fn upscale_replicates_pixels() {
+        let mut frame = filled(2, 1, WHITE).with_scale(1.5);
+        frame.put_pixel(1, 0, BLACK);
+        let (out, transform) = Upscale { factor: 3 }.apply_mapped(&frame);
+        assert_eq!((out.width, out.height, out.scale), (6, 3, 4.5));
+        assert_eq!(out.pixel(2, 2), WHITE);
+        assert_eq!(out.pixel(3, 0), BLACK);
+        assert_eq!(transform, Transform::scale(3.0));
+    }
+
+    #[test]
+    fn sharpen_raises_contrast_at_edges() {
+        let mut frame = filled(4, 1, [100, 100, 100, 255]);
+        fill_rect(&mut frame, (2, 0, 4, 1), [150, 150, 150, 255]);
+        let out = Sharpen { amount: 1.0 }.apply(&frame);
+        assert!(out.pixel(1, 0)[0] < 100);
+        assert!(out.pixel(2, 0)[0] > 150);
+        assert_eq!(out.pixel(0, 0)[0], 100);
+    }
+
+    #[test]
+    fn deskew_finds_the_angle_of_rotated_text() {
+        let straight = text_lines(400, 300);
+        let deskew = Deskew::default();
+        assert_eq!(deskew.detect_angle(&straight), 0.0);
+        for angle in [-3.0, 2.0, 4.5] {
+            // Turning straight text by `angle` skews it by `-angle`
+            let skewed = rotate(&straight, angle);
+            let detected = deskew.detect_angle(&skewed);
+            assert!(
+                (detected + angle).abs() <= deskew.step,
+                "skewed by {}, detected {}",
+                -angle,
+                detected
+            );
+        }
+    }
+
+    #[test]
+    fn deskew_measures_large_frames_downsampled() {
+        // Thin strokes must survive shrinking 4K to the detection size
+        let mut frame = filled(3840, 400, WHITE);
+        for top in (20..380).step_by(24) {
+            fill_rect(&mut frame, (20, top, 3820, top + 2), BLACK);
+        }
+        let skewed = rotate(&frame, 1.0);
+        let detected = Deskew::default().detect_angle(&skewed);
+        assert!((detected + 1.0).abs() <= 0.25, "{}", detected);
+    }
+
+    #[test]
+    fn deskew_leaves_blank_frames_alone() {
+        let blank = filled(20, 20, WHITE);
+        let (out, transform) = Deskew::default().apply_mapped(&blank);
+        assert_eq!(out, blank);
+        assert_eq!(transform, Transform::IDENTITY);
+    }
+
+    #[test]
+    fn transforms_invert_and_compose() {
+        let center = Point::new(50.0, 30.0);
+        let transform = Transform::scale(2.0).then(&Transform::rotation(4.0, center));
+        let p = Point::new(12.0, 7.5);
+        assert_close(transform.inverse().apply(transform.apply(p)), p);
+        assert_close(Transform::rotation(4.0, center).apply(center), center);
+        assert_close(Transform::scale(2.0).apply(p), Point::new(24.0, 15.0));
+        // Rotations add up
+        let twice = Transform::rotation(2.0, center).then(&Transform::rotation(2.0, center));
+        assert_close(twice.apply(p), Transform::rotation(4.0, center).apply(p));
+    }
+
+    #[test]
+    fn transform_matches_the_rotated_pixels() {
+        let mut frame = filled(100, 60, WHITE);
+        fill_rect(&mut frame, (70, 40, 72, 42), BLACK);
+        let rotated = rotate(&frame, 5.0);
+        let moved = Transform::rotation(5.0, Point::new(50.0, 30.0)).apply(Point::new(71.0, 41.0));
+        let found = dark_bounds(&rotated).unwrap();
+        assert!(found.contains(moved), "{:?} not in {:?}", moved, found);
+    }
+
+    #[test]
+    fn preprocessed_engine_maps_word_boxes_back_through_deskew() {
+        let frame = rotate(&text_lines(400, 300), -3.0);
+        let expected = dark_bounds(&frame).unwrap();
+
+        let mut pipeline = Pipeline::new();
+        pipeline.add(Upscale { factor: 2 }).add(Deskew::default());
+        let document = PreprocessedEngine::new(pipeline, Box::new(BlobEngine))
+            .recognize(&frame)
+            .unwrap();
+        let word = document.words().next().unwrap().rect;
+        // The straightened box maps back to the bounds of the skewed bars,
+        // give or take the corners the straight box adds
+        assert!(word.left <= expected.left + 2.0 && word.right >= expected.right - 2.0);
+        assert!(word.top <= expected.top + 2.0 && word.bottom >= expected.bottom - 2.0);
+        assert!(
+            word.width() < expected.width() + 30.0,
+            "{:?} / {:?}",
+            word,
+            expected
+        );
+        assert!(
+            word.height() < expected.height() + 30.0,
+            "{:?} / {:?}",
+            word,
+            expected
+        );
+        assert_eq!(document.pages[0].rect, Rect::new(0.0, 0.0, 400.0, 300.0));
+    }
+
+    #[test]
+    fn pipeline_scale_factor_multiplies() {
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add(Upscale { factor: 2 })
+            .add(Upscale { factor: 3 });
+        assert_eq!(pipeline.scale_factor(), 6);
+        let (out, transform) = pipeline.run_mapped(&filled(1, 1, WHITE));
+        assert_eq!(out.width, 6);
+        assert_close(transform.apply(Point::new(1.0, 1.0)), Point::new(6.0, 6.0));
+        assert_eq!(
+            Pipeline::for_screen_text().steps(),
+            vec![
+                "grayscale",
+                "auto-invert",
+                "upscale",
+                "sharpen",
+                "deskew",
+                "binarize-otsu"
+            ]
+        );
+    }
+}
@@ -0,0 +1,918 @@
+use crate::annotation::{AnnotationLayer, Canvas, Color, Shape, Stroke};
+use crate::capture::Frame;
+use crate::geometry::{distance_to_segment, Point, Rect};
+use crate::selection::{draw_overlay, OverlayStyle};
+
+/// Vertical samples per pixel row when filling polygons.
+const POLYGON_SUBSAMPLES: usize = 4;
+
+/// Glyph cell of the built-in font in font units: 5x7 glyph plus one unit
+/// of spacing on the right and bottom.
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const CELL_WIDTH: f32 = 6.0;
+const CELL_HEIGHT: f32 = 8.0;
+
+/// Pixel bounds of `rect` clipped to a `width` x `height` image, as
+/// `(left, top, right, bottom)` with exclusive right and bottom.
+pub(crate) fn pixel_span(rect: &Rect, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+    let left = rect.left.floor().max(0.0) as u32;
+    let top = rect.top.floor().max(0.0) as u32;
+    let right = (rect.right.ceil().max(0.0) as u32).min(width);
+    let bottom = (rect.bottom.ceil().max(0.0) as u32).min(height);
+    (left < right && top < bottom).then_some((left, top, right, bottom))
+}
+
+/// Area of the pixel centered at `center` covered by the axis-aligned `rect`.
+fn box_coverage(center: Point, rect: &Rect) -> f32 {
+    let (x, y) = (center.x - 0.5, center.y - 0.5);
+    let overlap_x = (rect.right.min(x + 1.0) - rect.left.max(x)).max(0.0);
+    let overlap_y = (rect.bottom.min(y + 1.0) - rect.top.max(y)).max(0.0);
+    overlap_x * overlap_y
+}
+
+/// Approximate signed distance from `p` to the outline of the ellipse
+/// inscribed in `rect`, negative inside.
+fn ellipse_distance(p: Point, rect: &Rect) -> f32 {
+    let rx = (rect.width() / 2.0).max(f32::EPSILON);
+    let ry = (rect.height() / 2.0).max(f32::EPSILON);
+    let dx = p.x - (rect.left + rx);
+    let dy = p.y - (rect.top + ry);
+
+    // First-order estimate f / |grad f| of the implicit equation
+    let f = (dx / rx).powi(2) + (dy / ry).powi(2) - 1.0;
+    let gradient = (2.0 * dx / (rx * rx)).hypot(2.0 * dy / (ry * ry));
+    if gradient > 0.0 {
+        f / gradient
+    } else {
+        -rx.min(ry)
+    }
+}
+
+/// Per-pixel coverage collected before blending, so overlapping parts of
+/// one primitive are not blended twice.
+struct Mask {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+}
+
+impl Mask {
+    fn new((left, top, right, bottom): (u32, u32, u32, u32)) -> Self {
+        let (width, height) = (right - left, bottom - top);
+        Mask {
+            left,
+            top,
+            width,
+            height,
+            coverage: vec![0.0; width as usize * height as usize],
+        }
+    }
+
+    /// Raises the coverage inside `area` to `coverage(pixel center)`, for
+    /// shapes whose parts overlap.
+    fn stamp(&mut self, area: &Rect, coverage: impl Fn(Point) -> f32) {
+        self.combine(area, |old, new| old.max(new), coverage);
+    }
+
+    /// Adds `coverage(pixel center)` inside `area`, for disjoint parts that
+    /// share pixels along their edges.
+    fn add(&mut self, area: &Rect, coverage: impl Fn(Point) -> f32) {
+        self.combine(area, |old, new| (old + new).min(1.0), coverage);
+    }
+
+    fn combine(
+        &mut self,
+        area: &Rect,
+        combine: impl Fn(f32, f32) -> f32,
+        coverage: impl Fn(Point) -> f32,
+    ) {
+        let local = area.translate(-(self.left as f32), -(self.top as f32));
+        if let Some((left, top, right, bottom)) = pixel_span(&local, self.width, self.height) {
+            for y in top..bottom {
+                for x in left..right {
+                    let center =
+                        Point::new((self.left + x) as f32 + 0.5, (self.top + y) as f32 + 0.5);
+                    let value = &mut self.coverage[(y * self.width + x) as usize];
+                    *value = combine(*value, coverage(center).clamp(0.0, 1.0));
+                }
+            }
+        }
+    }
+}
+
+/// Even-odd coverage of the closed polygon `points` over the pixels of
+/// `span`, sampled on [`POLYGON_SUBSAMPLES`] rows per pixel.
+fn polygon_mask(points: &[Point], span: (u32, u32, u32, u32)) -> Mask {
+    let mut mask = Mask::new(span);
+    if points.len() < 3 {
+        return mask;
+    }
+    let mut crossings = Vec::new();
+    for row in 0..mask.height {
+        for sample in 0..POLYGON_SUBSAMPLES {
+            let y = (mask.top + row) as f32 + (sample as f32 + 0.5) / POLYGON_SUBSAMPLES as f32;
+
+            crossings.clear();
+            for (i, a) in points.iter().enumerate() {
+                let b = points[(i + 1) % points.len()];
+                if (a.y <= y && y < b.y) || (b.y <= y && y < a.y) {
+                    crossings.push(a.x + (y - a.y) * (b.x - a.x) / (b.y - a.y));
+                }
+            }
+            crossings.sort_by(f32::total_cmp);
+
+            for pair in crossings.chunks_exact(2) {
+                let start = (pair[0] - mask.left as f32).max(0.0);
+                let end = (pair[1] - mask.left as f32).min(mask.width as f32);
+                if start >= end {
+                    continue;
+                }
+                for x in start.floor() as u32..(end.ceil() as u32).min(mask.width) {
+                    let overlap = end.min(x as f32 + 1.0) - start.max(x as f32);
+                    mask.coverage[(row * mask.width + x) as usize] +=
+                        overlap / POLYGON_SUBSAMPLES as f32;
+                }
+            }
+        }
+    }
+    for coverage in &mut mask.coverage {
+        *coverage = coverage.min(1.0);
+    }
+    mask
+}
+
+/// Software implementation of [`Canvas`] drawing into an RGBA [`Frame`].
+///
+/// Coordinates are frame pixels with `(0, 0)` at the top-left corner of the
+/// first pixel. All primitives are anti-aliased and composited with
+/// source-over blending.
+pub struct RasterCanvas<'a> {
+    frame: &'a mut Frame,
+}
+
+impl<'a> RasterCanvas<'a> {
+    pub fn new(frame: &'a mut Frame) -> Self {
+        RasterCanvas { frame }
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0.0, 0.0, self.frame.width as f32, self.frame.height as f32)
+    }
+
+    /// Composites `color` at `opacity * coverage` over the pixel at `(x, y)`.
+    fn blend(&mut self, x: u32, y: u32, color: Color, alpha: f32) {
+        let alpha = alpha.clamp(0.0, 1.0);
+        if alpha <= 0.0 {
+            return;
+        }
+
+        let dst = self.frame.pixel(x, y);
+        let dst_alpha = dst[3] as f32 / 255.0;
+        let out_alpha = alpha + dst_alpha * (1.0 - alpha);
+        let mix = |src: u8, dst: u8| {
+            let value = (src as f32 * alpha + dst as f32 * dst_alpha * (1.0 - alpha)) / out_alpha;
+            value.round().clamp(0.0, 255.0) as u8
+        };
+        self.frame.put_pixel(
+            x,
+            y,
+            [
+                mix(color.r, dst[0]),
+                mix(color.g, dst[1]),
+                mix(color.b, dst[2]),
+                (out_alpha * 255.0).round() as u8,
+            ],
+        );
+    }
+
+    fn blend_mask(&mut self, mask: &Mask, color: Color, opacity: f32) {
+        for y in 0..mask.height {
+            for x in 0..mask.width {
+                let coverage = mask.coverage[(y * mask.width + x) as usize];
+                if coverage > 0.0 {
+                    self.blend(mask.left + x, mask.top + y, color, opacity * coverage);
+                }
+            }
+        }
+    }
+
+    /// Blends `color` wherever `coverage(pixel center)` is positive inside
+    /// `area`.
+    fn paint(&mut self, area: &Rect, color: Color, opacity: f32, coverage: impl Fn(Point) -> f32) {
+        if let Some(span) = pixel_span(area, self.frame.width, self.frame.height) {
+            let mut mask = Mask::new(span);
+            mask.stamp(area, coverage);
+            self.blend_mask(&mask, color, opacity);
+        }
+    }
+
+    /// Fills the whole frame, replacing its content.
+    pub fn clear(&mut self, color: Color, opacity: f32) {
+        let alpha = (opacity.clamp(0.0, 1.0) * 255.0).round() as u8;
+        for y in 0..self.frame.height {
+            for x in 0..self.frame.width {
+                self.frame
+                    .put_pixel(x, y, [color.r, color.g, color.b, alpha]);
+            }
+        }
+    }
+
+    /// Size of `text` in pixels when drawn at `size` pixels per line.
+    pub fn text_size(text: &str, size: f32) -> (f32, f32) {
+        let scale = size / CELL_HEIGHT;
+        let columns = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+        let rows = text.lines().count().max(1);
+        (
+            columns as f32 * CELL_WIDTH * scale,
+            rows as f32 * CELL_HEIGHT * scale,
+        )
+    }
+
+    /// Draws `text` with the built-in 5x7 font, `origin` being the top-left
+    /// corner of the first line. Characters outside printable ASCII are
+    /// drawn as `?`. Returns the covered area.
+    pub fn draw_text(
+        &mut self,
+        origin: Point,
+        text: &str,
+        size: f32,
+        color: Color,
+        opacity: f32,
+    ) -> Rect {
+        let (width, height) = Self::text_size(text, size);
+        let area = Rect::new(origin.x, origin.y, origin.x + width, origin.y + height);
+
+        let Some(span) = pixel_span(&area, self.frame.width, self.frame.height) else {
+            return area;
+        };
+        let mut mask = Mask::new(span);
+        for dot in glyph_dots(origin, text, size) {
+            mask.add(&dot, |p| box_coverage(p, &dot));
+        }
+        self.blend_mask(&mask, color, opacity);
+        area
+    }
+}
+
+impl Canvas for RasterCanvas<'_> {
+    fn stroke_line(&mut self, from: Point, to: Point, stroke: &Stroke) {
+        self.stroke_polyline(&[from, to], stroke);
+    }
+
+    fn stroke_rect(&mut self, rect: &Rect, stroke: &Stroke) {
+        let half = stroke.width / 2.0;
+        let outer = rect.inflate(half);
+        let inner = rect.inflate(-half);
+        self.paint(&outer, stroke.color, stroke.opacity, |p| {
+            let inside = if inner.is_empty() {
+                0.0
+            } else {
+                box_coverage(p, &inner)
+            };
+            box_coverage(p, &outer) - inside
+        });
+    }
+
+    fn stroke_ellipse(&mut self, rect: &Rect, stroke: &Stroke) {
+        let half = stroke.width / 2.0;
+        self.paint(
+            &rect.inflate(half + 1.0),
+            stroke.color,
+            stroke.opacity,
+            |p| half + 0.5 - ellipse_distance(p, rect).abs(),
+        );
+    }
+
+    fn fill_rect(&mut self, rect: &Rect, color: Color, opacity: f32) {
+        self.paint(rect, color, opacity, |p| box_coverage(p, rect));
+    }
+
+    fn fill_ellipse(&mut self, rect: &Rect, color: Color, opacity: f32) {
+        self.paint(&rect.inflate(1.0), color, opacity, |p| {
+            0.5 - ellipse_distance(p, rect)
+        });
+    }
+
+    /// Even-odd scanline fill with [`POLYGON_SUBSAMPLES`] rows per pixel and
+    /// exact horizontal coverage.
+    fn fill_polygon(&mut self, points: &[Point], color: Color, opacity: f32) {
+        if points.len() < 3 {
+            return;
+        }
+        let area = points
+            .iter()
+            .map(|p| Rect::new(p.x, p.y, p.x, p.y))
+            .reduce(|a, b| a.union(&b))
+            .unwrap_or_default();
+        let Some(span) = pixel_span(&area, self.frame.width, self.frame.height) else {
+            return;
+        };
+
+        let mask = polygon_mask(points, span);
+        self.blend_mask(&mask, color, opacity);
+    }
+
+    /// Round-capped, round-joined polyline; overlapping segments share one
+    /// coverage mask so translucent strokes stay even.
+    fn stroke_polyline(&mut self, points: &[Point], stroke: &Stroke) {
+        let reach = stroke.width / 2.0 + 1.0;
+        let Some(area) = points
+            .iter()
+            .map(|p| Rect::new(p.x, p.y, p.x, p.y))
+            .reduce(|a, b| a.union(&b))
+        else {
+            return;
+        };
+        let Some(span) = pixel_span(&area.inflate(reach), self.frame.width, self.frame.height)
+        else {
+            return;
+        };
+
+        let half = stroke.width / 2.0;
+        let mut mask = Mask::new(span);
+        let segments: Vec<(Point, Point)> = match points {
+            [single] => vec![(*single, *single)],
+            _ => points.windows(2).map(|s| (s[0], s[1])).collect(),
+        };
+        for (a, b) in segments {
+            let bounds = Rect::from_points(a, b).inflate(reach);
+            mask.stamp(&bounds, |p| half + 0.5 - distance_to_segment(p, a, b));
+        }
+        self.blend_mask(&mask, stroke.color, stroke.opacity);
+    }
+}
+
+/// Draws `annotations` bottom to top into `frame`, destroying the pixels
+/// under redactions instead of drawing their placeholders.
+fn burn_in(frame: &mut Frame, annotations: &AnnotationLayer) {
+    for annotation in annotations.iter() {
+        match annotation.to_shape() {
+            Shape::Redaction(redaction) => redaction.apply(frame),
+            _ => annotation.draw(&mut RasterCanvas::new(frame)),
+        }
+    }
+}
+
+/// Copy of `frame` with `annotations` burned in, e.g. for export.
+///
+/// Redactions are applied to the pixels, so nothing of the covered
+/// content is left in the result.
+pub fn flatten(frame: &Frame, annotations: &AnnotationLayer) -> Frame {
+    let mut out = frame.clone();
+    burn_in(&mut out, annotations);
+    out
+}
+
+/// Renders what the overlay window shows on top of `frame`: the selection
+/// with its frame and handles, then the annotations.
+pub fn render_overlay(
+    frame: &Frame,
+    selection: Option<&Rect>,
+    annotations: &AnnotationLayer,
+    style: &OverlayStyle,
+) -> Frame {
+    let mut out = frame.clone();
+    if let Some(selection) = selection {
+        let mut canvas = RasterCanvas::new(&mut out);
+        let bounds = canvas.bounds();
+        draw_overlay(&mut canvas, &bounds, selection, style);
+    }
+    burn_in(&mut out, annotations);
+    out
+}
+
+/// Clears every pixel outside the closed polygon `points`: made
+/// transparent, or painted over with `fill` if given. Pixels on the outline
+/// keep the share the polygon covers, so the edge stays smooth.
+pub fn mask_outside(frame: &mut Frame, points: &[Point], fill: Option<Color>) {
+    if frame.width == 0 || frame.height == 0 {
+        return;
+    }
+    let mut mask = polygon_mask(points, (0, 0, frame.width, frame.height));
+
+    match fill {
+        Some(color) => {
+            for coverage in &mut mask.coverage {
+                *coverage = 1.0 - *coverage;
+            }
+            RasterCanvas::new(frame).blend_mask(&mask, color, 1.0);
+        }
+        None => {
+            for y in 0..frame.height {
+                for x in 0..frame.width {
+                    let coverage = mask.coverage[(y * frame.width + x) as usize];
+                    if coverage < 1.0 {
+                        let mut pixel = frame.pixel(x, y);
+                        pixel[3] = (pixel[3] as f32 * coverage).round() as u8;
+                        // Hidden pixels must not keep their color in the file
+                        if pixel[3] == 0 {
+                            pixel = [0, 0, 0, 0];
+                        }
+                        frame.put_pixel(x, y, pixel);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Draws `text` with the built-in font on any canvas by filling each dot
+/// of the glyphs; use a `size` that is a multiple of 8 so neighbouring dots
+/// meet on whole pixels. Returns the covered area.
+pub fn fill_text(
+    canvas: &mut dyn Canvas,
+    origin: Point,
+    text: &str,
+    size: f32,
+    color: Color,
+    opacity: f32,
+) -> Rect {
+    for dot in glyph_dots(origin, text, size) {
+        canvas.fill_rect(&dot, color, opacity);
+    }
+    let (width, height) = RasterCanvas::text_size(text, size);
+    Rect::new(origin.x, origin.y, origin.x + width, origin.y + height)
+}
+
+/// Squares making up the set pixels of `text` at `size` pixels per line.
+fn glyph_dots(origin: Point, text: &str, size: f32) -> Vec<Rect> {
+    let scale = size / CELL_HEIGHT;
+    let mut dots = Vec::new();
+    for (row, line) in text.lines().enumerate() {
+        for (column, c) in line.chars().enumerate() {
+            let cell_x = origin.x + column as f32 * CELL_WIDTH * scale;
+            let cell_y = origin.y + row as f32 * CELL_HEIGHT * scale;
+
+            for (gx, bits) in glyph(c).iter().enumerate() {
+                for gy in 0..GLYPH_HEIGHT {
+                    if bits & (1 << gy) != 0 {
+                        dots.push(Rect::new(
+                            cell_x + gx as f32 * scale,
+                            cell_y + gy as f32 * scale,
+                            cell_x + (gx + 1) as f32 * scale,
+                            cell_y + (gy + 1) as f32 * scale,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    dots
+}
+
+fn glyph(c: char) -> &'static [u8; GLYPH_WIDTH] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    &FONT_5X7[index]
+}
+
+/// Printable ASCII (0x20 - 0x7E), one byte per column, bit 0 at the top.
+const FONT_5X7: [[u8; GLYPH_WIDTH]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], // ' '
+    [0x00, 0x00, 0x5F, 0x00, 0x00], // !
+    [0x00, 0x07, 0x00, 0x07, 0x00], // "
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], // #
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12], // $
+    [0x23, 0x13, 0x08, 0x64, 0x62], // %
+    [0x36, 0x49, 0x55, 0x22, 0x50], // &
+    [0x00, 0x05, 0x03, 0x00, 0x00], // '
+    [0x00, 0x1C, 0x22, 0x41, 0x00], // (
+    [0x00, 0x41, 0x22, 0x1C, 0x00], // )
+    [0x08, 0x2A, 0x1C, 0x2A, 0x08], // *
+    [0x08, 0x08, 0x3E, 0x08, 0x08], // +
+    [0x00, 0x50, 0x30, 0x00, 0x00], // ,
+    [0x08, 0x08, 0x08, 0x08, 0x08], // -
+    [0x00, 0x60, 0x60, 0x00, 0x00], // .
+    [0x20, 0x10, 0x08, 0x04, 0x02], // /
+    [0x3E, 0x51, 0x49, 0x45, 0x3E], // 0
+    [0x00, 0x42, 0x7F, 0x40, 0x00], // 1
+    [0x42, 0x61, 0x51, 0x49, 0x46], // 2
+    [0x21, 0x41, 0x45, 0x4B, 0x31], // 3
+    [0x18, 0x14, 0x12, 0x7F, 0x10], // 4
+    [0x27, 0x45, 0x45, 0x45, 0x39], // 5
+    [0x3C, 0x4A, 0x49, 0x49, 0x30], // 6
+    [0x01, 0x71, 0x09, 0x05, 0x03], // 7
+    [0x36, 0x49, 0x49, 0x49, 0x36], // 8
+    [0x06, 0x49, 0x49, 0x29, 0x1E], // 9
+    [0x00, 0x36, 0x36, 0x00, 0x00], // :
+    [0x00, 0x56, 0x36, 0x00, 0x00], // ;
+    [0x08, 0x14, 0x22, 0x41, 0x00], // <
+    [0x14, 0x14, 0x14, 0x14, 0x14], // =
+    [0x00, 0x41, 0x22, 0x14, 0x08], // >
+    [0x02, 0x01, 0x51, 0x09, 0x06], // ?
+    [0x32, 0x49, 0x79, 0x41, 0x3E], // @
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], // A
+    [0x7F, 0x49, 0x49, 0x49, 0x36], // B
+    [0x3E, 0x41, 0x41, 0x41, 0x22], // C
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], // D
+    [0x7F, 0x49, 0x49, 0x49, 0x41], // E
+    [0x7F, 0x09, 0x09, 0x09, 0x01], // F
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], // G
+    [0x7F, 0x08, 0x08, 0x08, 0x7F], // H
+    [0x00, 0x41, 0x7F, 0x41, 0x00], // I
+    [0x20, 0x40, 0x41, 0x3F, 0x01], // J
+    [0x7F, 0x08, 0x14, 0x22, 0x41], // K
+    [0x7F, 0x40, 0x40, 0x40, 0x40], // L
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], // M
+    [0x7F, 0x04, 0x08, 0x10, 0x7F], // N
+    [0x3E, 0x41, 0x41, 0x41, 0x3E], // O
+    [0x7F, 0x09, 0x09, 0x09, 0x06], // P
+    [0x3E, 0x41, 0x51, 0x21, 0x5E], // Q
+    [0x7F, 0x09, 0x19, 0x29, 0x46], // R
+    [0x46, 0x49, 0x49, 0x49, 0x31], // S
+    [0x01, 0x01, 0x7F, 0x01, 0x01], // T
+    [0x3F, 0x40, 0x40, 0x40, 0x3F], // U
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], // V
+    [0x3F, 0x40, 0x38, 0x40, 0x3F], // W
+    [0x63, 0x14, 0x08, 0x14, 0x63], // X
+    [0x07, 0x08, 0x70, 0x08, 0x07], // Y
+    [0x61, 0x51, 0x49, 0x45, 0x43], // Z
+    [0x00, 0x7F, 0x41, 0x41, 0x00], // [
+    [0x02, 0x04, 0x08, 0x10, 0x20], // \
+    [0x00, 0x41, 0x41, 0x7F, 0x00], // ]
+    [0x04, 0x02, 0x01, 0x02, 0x04], // ^
+    [0x40, 0x40, 0x40, 0x40, 0x40], // _
+    [0x00, 0x01, 0x02, 0x04, 0x00], // `
+    [0x20, 0x54, 0x54, 0x54, 0x78], // a
+    [0x7F, 0x48, 0x44, 0x44, 0x38], // b
+    [0x38, 0x44, 0x44, 0x44, 0x20], // c
+    [0x38, 0x44, 0x44, 0x48, 0x7F], // d
+    [0x38, 0x54, 0x54, 0x54, 0x18], // e
+    [0x08, 0x7E, 0x09, 0x01, 0x02], // f
+    [0x0C, 0x52, 0x52, 0x52, 0x3E], // g
+    [0x7F, 0x08, 0x04, 0x04, 0x78], // h
+    [0x00, 0x44, 0x7D, 0x40, 0x00], // i
+    [0x20, 0x40, 0x44, 0x3D, 0x00], // j
+    [0x7F, 0x10, 0x28, 0x44, 0x00], // k
+    [0x00, 0x41, 0x7F, 0x40, 0x00], // l
+    [0x7C, 0x04, 0x18, 0x04, 0x78], // m
+    [0x7C, 0x08, 0x04, 0x04, 0x78], // n
+    [0x38, 0x44, 0x44, 0x44, 0x38], // o
+    [0x7C, 0x14, 0x14, 0x14, 0x08], // p
+    [0x08, 0x14, 0x14, 0x18, 0x7C], // q
+    [0x7C, 0x08, 0x04, 0x04, 0x08], // r
+    [0x48, 0x54, 0x54, 0x54, 0x20], // s
+    [0x04, 0x3F, 0x44, 0x40, 0x20], // t
+    [0x3C, 0x40, 0x40, 0x20, 0x7C], // u
+    [0x1C, 0x20, 0x40, 0x20, 0x1C], // v
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], // w
+    [0x44, 0x28, 0x10, 0x28, 0x44], // x
+    [0x0C, 0x50, 0x50, 0x50, 0x3C], // y
+    [0x44, 0x64, 0x54, 0x4C, 0x44], // z
+    [0x00, 0x08, 0x36, 0x41, 0x00], // {
+    [0x00, 0x00, 0x7F, 0x00, 0x00], // |
+    [0x00, 0x41, 0x36, 0x08, 0x00], // }
+    [0x08, 0x04, 0x08, 0x10, 0x08], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::{Line, Rectangle};
+    use crate::redact::{Redaction, RedactionStyle};
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const CLEAR: [u8; 4] = [0, 0, 0, 0];
+
+    fn filled(width: u32, height: u32, rgba: [u8; 4]) -> Frame {
+        let mut frame = Frame::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                frame.put_pixel(x, y, rgba);
+            }
+        }
+        frame
+    }
+
+    /// Pixels whose color differs from `background`, as `(x, y)`.
+    fn touched(frame: &Frame, background: [u8; 4]) -> Vec<(u32, u32)> {
+        let mut touched = Vec::new();
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                if frame.pixel(x, y) != background {
+                    touched.push((x, y));
+                }
+            }
+        }
+        touched
+    }
+
+    fn block(left: u32, top: u32, right: u32, bottom: u32) -> Vec<(u32, u32)> {
+        (top..bottom)
+            .flat_map(|y| (left..right).map(move |x| (x, y)))
+            .collect()
+    }
+
+    fn stroke(width: f32, opacity: f32) -> Stroke {
+        Stroke::new(Color::BLACK, width, opacity)
+    }
+
+    #[test]
+    fn blending_is_source_over() {
+        let mut frame = filled(2, 1, WHITE);
+        frame.put_pixel(1, 0, CLEAR);
+        RasterCanvas::new(&mut frame).fill_rect(
+            &Rect::new(0.0, 0.0, 2.0, 1.0),
+            Color::rgb(255, 0, 0),
+            0.5,
+        );
+        assert_eq!(frame.pixel(0, 0), [255, 128, 128, 255]);
+        // Over nothing the color stays and only the alpha drops
+        assert_eq!(frame.pixel(1, 0), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn fill_rect_covers_whole_and_partial_pixels() {
+        let mut frame = filled(6, 4, WHITE);
+        RasterCanvas::new(&mut frame).fill_rect(&Rect::new(1.0, 1.0, 3.5, 3.0), Color::BLACK, 1.0);
+        assert_eq!(frame.pixel(1, 1), BLACK);
+        assert_eq!(frame.pixel(2, 2), BLACK);
+        assert_eq!(frame.pixel(3, 1), [128, 128, 128, 255]);
+        let mut expected = block(1, 1, 4, 3);
+        expected.sort_by_key(|&(x, y)| (y, x));
+        assert_eq!(touched(&frame, WHITE), expected);
+    }
+
+    #[test]
+    fn stroke_rect_is_centered_on_the_outline() {
+        let mut frame = filled(10, 10, WHITE);
+        RasterCanvas::new(&mut frame)
+            .stroke_rect(&Rect::new(2.0, 2.0, 8.0, 8.0), &stroke(2.0, 1.0));
+        let outer = block(1, 1, 9, 9);
+        let inner = block(3, 3, 7, 7);
+        let ring: Vec<_> = outer.into_iter().filter(|p| !inner.contains(p)).collect();
+        assert_eq!(touched(&frame, WHITE), ring);
+        assert!(ring.iter().all(|&(x, y)| frame.pixel(x, y) == BLACK));
+    }
+
+    #[test]
+    fn horizontal_line_covers_its_width() {
+        let mut frame = filled(14, 10, WHITE);
+        RasterCanvas::new(&mut frame).stroke_line(
+            Point::new(3.0, 5.0),
+            Point::new(11.0, 5.0),
+            &stroke(2.0, 1.0),
+        );
+        for x in 3..11 {
+            assert_eq!(frame.pixel(x, 4), BLACK, "{}", x);
+            assert_eq!(frame.pixel(x, 5), BLACK, "{}", x);
+            assert_eq!(frame.pixel(x, 3), WHITE, "{}", x);
+            assert_eq!(frame.pixel(x, 6), WHITE, "{}", x);
+        }
+        // Round caps reach one pixel past the ends
+        assert_ne!(frame.pixel(2, 4), WHITE);
+        assert_eq!(frame.pixel(0, 4), WHITE);
+    }
+
+    #[test]
+    fn translucent_polyline_is_even_where_it_overlaps_itself() {
+        let mut frame = filled(16, 10, WHITE);
+        RasterCanvas::new(&mut frame).stroke_polyline(
+            &[
+                Point::new(2.0, 5.0),
+                Point::new(13.0, 5.0),
+                Point::new(2.0, 5.0),
+            ],
+            &stroke(2.0, 0.5),
+        );
+        assert_eq!(frame.pixel(7, 5), [128, 128, 128, 255]);
+        assert_eq!(frame.pixel(7, 4), frame.pixel(7, 5));
+        assert_eq!(frame.pixel(12, 5), frame.pixel(7, 5));
+    }
+
+    #[test]
+    fn ellipses_are_symmetric_and_inside_their_box() {
+        let mut frame = filled(21, 13, WHITE);
+        let rect = Rect::new(2.0, 2.0, 19.0, 11.0);
+        RasterCanvas::new(&mut frame).fill_ellipse(&rect, Color::BLACK, 1.0);
+        assert_eq!(frame.pixel(10, 6), BLACK);
+        assert_eq!(frame.pixel(2, 2), WHITE);
+        assert_eq!(frame.pixel(18, 10), WHITE);
+        for y in 0..13 {
+            for x in 0..21 {
+                assert_eq!(frame.pixel(x, y), frame.pixel(20 - x, y), "({}, {})", x, y);
+                assert_eq!(frame.pixel(x, y), frame.pixel(x, 12 - y), "({}, {})", x, y);
+            }
+        }
+        assert!(touched(&frame, WHITE)
+            .iter()
+            .all(|&(x, y)| (1..20).contains(&x) && (1..12).contains(&y)));
+
+        let mut outline = filled(21, 13, WHITE);
+        RasterCanvas::new(&mut outline).stroke_ellipse(&rect, &stroke(1.0, 1.0));
+        assert_eq!(outline.pixel(10, 6), WHITE);
+        assert_ne!(outline.pixel(10, 2), WHITE);
+    }
+
+    #[test]
+    fn square_polygon_matches_fill_rect() {
+        let rect = Rect::new(1.25, 2.0, 6.75, 5.5);
+        let mut polygon = filled(8, 8, WHITE);
+        RasterCanvas::new(&mut polygon).fill_polygon(
+            &[
+                Point::new(rect.left, rect.top),
+                Point::new(rect.right, rect.top),
+                Point::new(rect.right, rect.bottom),
+                Point::new(rect.left, rect.bottom),
+            ],
+            Color::BLACK,
+            1.0,
+        );
+        let mut filled_rect = filled(8, 8, WHITE);
+        RasterCanvas::new(&mut filled_rect).fill_rect(&rect, Color::BLACK, 1.0);
+        assert_eq!(polygon.pixels, filled_rect.pixels);
+    }
+
+    #[test]
+    fn triangle_covers_half_of_its_box() {
+        let mut frame = filled(8, 8, CLEAR);
+        RasterCanvas::new(&mut frame).fill_polygon(
+            &[
+                Point::new(0.0, 0.0),
+                Point::new(8.0, 0.0),
+                Point::new(0.0, 8.0),
+            ],
+            Color::BLACK,
+            1.0,
+        );
+        let alpha: u32 = touched(&frame, CLEAR)
+            .iter()
+            .map(|&(x, y)| frame.pixel(x, y)[3] as u32)
+            .sum();
+        assert!((alpha as f32 / 255.0 - 32.0).abs() < 0.5, "{}", alpha);
+        assert_eq!(frame.pixel(1, 1), BLACK);
+        assert_eq!(frame.pixel(6, 6), CLEAR);
+    }
+
+    #[test]
+    fn text_uses_the_5x7_font() {
+        let mut frame = filled(12, 8, WHITE);
+        let area = RasterCanvas::new(&mut frame).draw_text(
+            Point::new(0.0, 0.0),
+            ".",
+            8.0,
+            Color::BLACK,
+            1.0,
+        );
+        assert_eq!(area, Rect::new(0.0, 0.0, 6.0, 8.0));
+        assert_eq!(touched(&frame, WHITE), [(1, 5), (2, 5), (1, 6), (2, 6)]);
+
+        // Twice the size doubles every dot
+        let mut large = filled(12, 16, WHITE);
+        RasterCanvas::new(&mut large).draw_text(Point::new(0.0, 0.0), ".", 16.0, Color::BLACK, 1.0);
+        assert_eq!(touched(&large, WHITE), block(2, 10, 6, 14));
+    }
+
+    #[test]
+    fn text_size_counts_the_longest_line() {
+        assert_eq!(RasterCanvas::text_size("ab\ncde", 8.0), (18.0, 16.0));
+        assert_eq!(RasterCanvas::text_size("", 16.0), (0.0, 16.0));
+    }
+
+    #[test]
+    fn unknown_characters_are_drawn_as_question_marks() {
+        let draw = |text: &str| {
+            let mut frame = filled(6, 8, WHITE);
+            RasterCanvas::new(&mut frame).draw_text(
+                Point::new(0.0, 0.0),
+                text,
+                8.0,
+                Color::BLACK,
+                1.0,
+            );
+            frame
+        };
+        assert_eq!(draw("é").pixels, draw("?").pixels);
+        assert_ne!(draw("a").pixels, draw("?").pixels);
+    }
+
+    #[test]
+    fn fill_text_matches_draw_text() {
+        let mut drawn = filled(40, 20, WHITE);
+        RasterCanvas::new(&mut drawn).draw_text(
+            Point::new(2.0, 3.0),
+            "Hi!\n42",
+            8.0,
+            Color::BLACK,
+            1.0,
+        );
+        let mut filled_text = filled(40, 20, WHITE);
+        fill_text(
+            &mut RasterCanvas::new(&mut filled_text),
+            Point::new(2.0, 3.0),
+            "Hi!\n42",
+            8.0,
+            Color::BLACK,
+            1.0,
+        );
+        assert_eq!(drawn.pixels, filled_text.pixels);
+    }
+
+    #[test]
+    fn flatten_draws_bottom_to_top_and_destroys_redacted_pixels() {
+        let mut frame = filled(12, 12, WHITE);
+        frame.put_pixel(2, 2, [10, 200, 30, 255]);
+        let mut annotations = AnnotationLayer::new();
+        annotations.push(
+            Shape::Redaction(Redaction::new(
+                Rect::new(0.0, 0.0, 6.0, 6.0),
+                RedactionStyle::blackout(),
+            ))
+            .into_annotation(),
+        );
+        annotations.push(
+            Shape::Line(Line {
+                from: Point::new(0.0, 3.0),
+                to: Point::new(12.0, 3.0),
+                stroke: Stroke::new(Color::WHITE, 2.0, 1.0),
+            })
+            .into_annotation(),
+        );
+
+        let out = flatten(&frame, &annotations);
+        assert_eq!(out.pixel(2, 2), WHITE);
+        assert_eq!(out.pixel(1, 1), BLACK);
+        assert_eq!(out.pixel(8, 8), WHITE);
+        assert_eq!(out.pixel(8, 3), WHITE);
+        // The source frame is left alone
+        assert_eq!(frame.pixel(1, 1), WHITE);
+    }
+
+    #[test]
+    fn overlay_dims_outside_the_selection_below_the_annotations() {
+        let frame = filled(20, 20, WHITE);
+        let style = OverlayStyle {
+            dim: Some((Color::BLACK, 0.5)),
+            fill: None,
+            frame: stroke(1.0, 0.0),
+            handles: (Color::BLACK, 0.0),
+        };
+        let mut annotations = AnnotationLayer::new();
+        annotations.push(
+            Shape::Rectangle(Rectangle {
+                rect: Rect::new(1.0, 1.0, 3.0, 3.0),
+                stroke: Stroke::new(Color::rgb(255, 0, 0), 2.0, 1.0),
+            })
+            .into_annotation(),
+        );
+        let selection = Rect::new(5.0, 5.0, 15.0, 15.0);
+
+        let out = render_overlay(&frame, Some(&selection), &annotations, &style);
+        assert_eq!(out.pixel(10, 10), WHITE);
+        assert_eq!(out.pixel(5, 5), WHITE);
+        assert_eq!(out.pixel(4, 10), [128, 128, 128, 255]);
+        assert_eq!(out.pixel(18, 18), [128, 128, 128, 255]);
+        assert_eq!(out.pixel(1, 1), [255, 0, 0, 255]);
+
+        let plain = render_overlay(&frame, None, &AnnotationLayer::new(), &style);
+        assert_eq!(plain.pixels, frame.pixels);
+    }
+
+    #[test]
+    fn default_overlay_fills_and_frames_the_selection() {
+        let frame = filled(40, 40, CLEAR);
+        let selection = Rect::new(10.0, 10.0, 30.0, 30.0);
+        let out = render_overlay(
+            &frame,
+            Some(&selection),
+            &AnnotationLayer::new(),
+            &OverlayStyle::default(),
+        );
+        assert_eq!(out.pixel(15, 15), WHITE);
+        // Half-opaque frame just outside, handles blended on top of it
+        assert_eq!(out.pixel(15, 9), [255, 255, 255, 128]);
+        assert_eq!(out.pixel(20, 9), [255, 255, 255, 192]);
+        assert_eq!(out.pixel(7, 7), [255, 255, 255, 128]);
+        assert_eq!(out.pixel(2, 2), CLEAR);
+    }
+
+    #[test]
+    fn mask_outside_clears_or_fills_around_the_polygon() {
+        let square = [
+            Point::new(2.0, 2.0),
+            Point::new(6.0, 2.0),
+            Point::new(6.0, 6.0),
+            Point::new(2.0, 6.0),
+        ];
+        let mut cleared = filled(8, 8, BLACK);
+        mask_outside(&mut cleared, &square, None);
+        assert_eq!(touched(&cleared, CLEAR), block(2, 2, 6, 6));
+
+        let mut painted = filled(8, 8, BLACK);
+        mask_outside(&mut painted, &square, Some(Color::WHITE));
+        assert_eq!(touched(&painted, WHITE), block(2, 2, 6, 6));
+    }
+
+    #[test]
+    fn clear_replaces_every_pixel() {
+        let mut frame = filled(3, 2, BLACK);
+        RasterCanvas::new(&mut frame).clear(Color::rgb(1, 2, 3), 0.5);
+        assert!(touched(&frame, [1, 2, 3, 128]).is_empty());
+    }
+}
@@ -0,0 +1,403 @@
+use crate::annotation::Color;
+use crate::capture::Frame;
+use crate::cli::Platform;
+use crate::config::Config;
+use crate::export::Exporter;
+use crate::geometry::{Point, Rect};
+use crate::lasso::{crop_shape, SelectionShape, ShapeKind, ShapeTool};
+use crate::monitor::{CaptureRegion, Viewport};
+use crate::ocr::recognize_region;
+use crate::preprocess::{Pipeline, PreprocessedEngine};
+use crate::raster::flatten;
+use crate::redact::RedactionStyle;
+use crate::selection::{Buttons, InputEvent, MouseButton, SelectionModel, SelectionUpdate};
+use crate::timer::{Clock, SystemClock};
+use crate::undo::{Document, Edit, Editor};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// One step of a capture session, as the overlay took it.
+///
+/// Input only drives the selection; everything read from or written to
+/// the screen or disk is its own event with all it needs, so a replay does
+/// not depend on the pointer, the windows open or the time of day.
+/// Coordinates are client coordinates of the overlay.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// The screen was captured and shown by the overlay, whose client area
+    /// starts at `origin` of the virtual screen.
+    Capture {
+        origin: Point,
+    },
+    /// Pointer or key input on the overlay.
+    Input {
+        event: InputEvent,
+    },
+    /// Switches between selecting rectangles and a lasso or polygon.
+    ToggleShape {
+        kind: ShapeKind,
+    },
+    /// The selection was set other than by input, e.g. by picking a window.
+    Select {
+        rect: Option<Rect>,
+    },
+    /// The selection was cleared without an undo step, e.g. on reload.
+    ResetSelection,
+    Undo,
+    Redo,
+    /// Saves `shape` of the capture with its annotations.
+    SaveShape {
+        shape: SelectionShape,
+        fill: Option<Color>,
+        path: PathBuf,
+    },
+    /// Runs OCR on `rect` of the capture.
+    RecognizeText {
+        rect: Rect,
+    },
+    /// Covers sensitive data found by OCR in the capture.
+    RedactSensitive,
+    /// Saves `region` of a fresh capture of the screen.
+    CaptureToFile {
+        region: CaptureRegion,
+        path: PathBuf,
+    },
+}
+
+/// A [`SessionEvent`] with when it happened.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Milliseconds since the recording started.
+    pub at: u64,
+    #[serde(flatten)]
+    pub event: SessionEvent,
+}
+
+/// Writes session events to a JSON-lines file as they happen, so a crash
+/// keeps everything recorded so far.
+pub struct Recorder {
+    writer: Box<dyn Write + Send>,
+    clock: Box<dyn Clock>,
+    recorded: usize,
+}
+
+impl Recorder {
+    pub fn new(writer: Box<dyn Write + Send>, clock: Box<dyn Clock>) -> Self {
+        Recorder {
+            writer,
+            clock,
+            recorded: 0,
+        }
+    }
+
+    /// Records to a new file at `path`, creating its directory.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let file = File::create(path)
+            .map_err(|e| anyhow!("Creating recording {} failed: {}", path.display(), e))?;
+        Ok(Recorder::new(
+            Box::new(BufWriter::new(file)),
+            Box::new(SystemClock::default()),
+        ))
+    }
+
+    /// Writes `event`. Pointer moves without a held button only change the
+    /// hover feedback and are skipped, so a recording holds the drags but
+    /// not every pass of the mouse over the overlay.
+    pub fn record(&mut self, event: SessionEvent) -> Result<()> {
+        if let SessionEvent::Input {
+            event: InputEvent::PointerMove { buttons, .. },
+        } = &event
+        {
+            if *buttons == Buttons::default() {
+                return Ok(());
+            }
+        }
+        let event = RecordedEvent {
+            at: self.clock.now().as_millis() as u64,
+            event,
+        };
+        serde_json::to_writer(&mut self.writer, &event)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        self.recorded += 1;
+        Ok(())
+    }
+
+    /// Number of events recorded so far.
+    pub fn recorded(&self) -> usize {
+        self.recorded
+    }
+}
+
+/// Reads the events written by a [`Recorder`]; blank lines are skipped.
+pub fn read_session(reader: impl BufRead) -> Result<Vec<RecordedEvent>> {
+    let mut events = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let event =
+            serde_json::from_str(&line).map_err(|e| anyhow!("Line {}: {}", index + 1, e))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+pub fn load_session(path: impl AsRef<Path>) -> Result<Vec<RecordedEvent>> {
+    let path = path.as_ref();
+    let file = File::open(path)
+        .map_err(|e| anyhow!("Opening recording {} failed: {}", path.display(), e))?;
+    read_session(BufReader::new(file)).map_err(|e| anyhow!("{}: {}", path.display(), e))
+}
+
+/// What replaying an event produced.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReplayOutput {
+    Saved {
+        path: PathBuf,
+    },
+    Text {
+        text: String,
+    },
+    /// Number of redactions added.
+    Redacted {
+        count: usize,
+    },
+}
+
+impl fmt::Display for ReplayOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayOutput::Saved { path } => write!(f, "Saved {}", path.display()),
+            ReplayOutput::Text { text } => write!(f, "{}", text),
+            ReplayOutput::Redacted { count } => write!(f, "Redacted {} areas", count),
+        }
+    }
+}
+
+/// How fast a replay goes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Pace {
+    /// One event after the other without waiting.
+    #[default]
+    Immediate,
+    /// Waits between events as long as when they were recorded.
+    Recorded,
+}
+
+/// Plays a recorded session against the screen, monitors and OCR engine of
+/// a [`Platform`], e.g. fixture images, the way the overlay handles it.
+pub struct Replayer<'a> {
+    platform: &'a Platform,
+    config: &'a Config,
+    output_dir: Option<PathBuf>,
+    pace: Pace,
+    /// Overlay client area in virtual-screen coordinates.
+    viewport: Viewport,
+    selection: SelectionModel,
+    shape: Option<ShapeTool>,
+    editor: Editor,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(platform: &'a Platform, config: &'a Config) -> Self {
+        Replayer {
+            platform,
+            config,
+            output_dir: None,
+            pace: Pace::default(),
+            viewport: Viewport::default(),
+            selection: SelectionModel::new(),
+            shape: None,
+            editor: Editor::new(config.output.undo_depth),
+        }
+    }
+
+    /// Saves files into `directory` under their recorded names instead of
+    /// their recorded paths.
+    pub fn set_output_dir(&mut self, directory: impl AsRef<Path>) -> &mut Self {
+        self.output_dir = Some(directory.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn set_pace(&mut self, pace: Pace) -> &mut Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Current selection, in client coordinates of the overlay.
+    pub fn selection(&self) -> Option<Rect> {
+        self.selection.selection()
+    }
+
+    /// The capture with its annotations.
+    pub fn document(&self) -> &Document {
+        self.editor.document()
+    }
+
+    /// Replays `events` in order and returns what they produced; stops at
+    /// the first event that fails.
+    pub fn replay(&mut self, events: &[RecordedEvent]) -> Result<Vec<ReplayOutput>> {
+        let start = Instant::now();
+        let mut outputs = Vec::new();
+        for (index, recorded) in events.iter().enumerate() {
+            if self.pace == Pace::Recorded {
+                let due = Duration::from_millis(recorded.at);
+                if let Some(wait) = due.checked_sub(start.elapsed()) {
+                    std::thread::sleep(wait);
+                }
+            }
+            let output = self
+                .step(&recorded.event)
+                .map_err(|e| anyhow!("Event {} at {} ms: {}", index + 1, recorded.at, e))?;
+            outputs.extend(output);
+        }
+        Ok(outputs)
+    }
+
+    /// Replays one event.
+    pub fn step(&mut self, event: &SessionEvent) -> Result<Option<ReplayOutput>> {
+        match event {
+            SessionEvent::Capture { origin } => {
+                let frame = self.platform.backend.capture()?;
+                self.viewport = Viewport::new(*origin);
+                self.editor.load(frame);
+            }
+            SessionEvent::Input { event } => match self.shape.as_mut() {
+                Some(tool) => {
+                    tool.handle(*event);
+                }
+                None => {
+                    let update = self.selection.handle(*event);
+                    self.record_selection(*event, update)?;
+                }
+            },
+            SessionEvent::ToggleShape { kind } => {
+                self.shape = match self.shape.take() {
+                    Some(active) if active.kind() == *kind => None,
+                    _ => Some(ShapeTool::new(*kind)),
+                };
+            }
+            SessionEvent::Select { rect } => {
+                let update = self.selection.select(*rect);
+                self.set_selection(update.selection)?;
+            }
+            SessionEvent::ResetSelection => {
+                self.selection.reset();
+            }
+            SessionEvent::Undo => self.step_history(false)?,
+            SessionEvent::Redo => self.step_history(true)?,
+            SessionEvent::SaveShape { shape, fill, path } => {
+                let document = self.editor.document();
+                let frame = document
+                    .frame
+                    .as_ref()
+                    .ok_or_else(|| anyhow!("No screen captured yet"))?;
+                let target = Viewport::for_frame(frame);
+                let shape = shape.map(|p| target.to_client(self.viewport.to_screen(p)));
+                let out = crop_shape(&flatten(frame, &document.annotations), &shape, *fill)?;
+                return self.save(&out, path).map(Some);
+            }
+            SessionEvent::RecognizeText { rect } => {
+                let frame = self.frame()?;
+                let rect = self.viewport.rect_to(&Viewport::for_frame(frame), rect);
+                let engine =
+                    PreprocessedEngine::new(Pipeline::for_screen_text(), (self.platform.engine)());
+                let text = recognize_region(&engine, frame, Some(rect))?.text();
+                return Ok(Some(ReplayOutput::Text { text }));
+            }
+            SessionEvent::RedactSensitive => {
+                let engine =
+                    PreprocessedEngine::new(Pipeline::for_screen_text(), (self.platform.engine)());
+                let report = self.config.detector()?.detect(&engine, self.frame()?)?;
+                let edit =
+                    report.redaction_edit(self.editor.document(), RedactionStyle::blackout());
+                self.editor.apply(edit)?;
+                return Ok(Some(ReplayOutput::Redacted {
+                    count: report.findings.len(),
+                }));
+            }
+            SessionEvent::CaptureToFile { region, path } => {
+                let screen = self.platform.backend.capture()?;
+                let layout = (self.platform.layout)(&screen)?;
+                let frame = layout.extract(&screen, *region)?;
+                return self.save(&frame, path).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Undo (`redo == false`) or redo one step and restore its selection.
+    fn step_history(&mut self, redo: bool) -> Result<()> {
+        let stepped = if redo {
+            self.editor.redo()?
+        } else {
+            self.editor.undo()?
+        };
+        if stepped {
+            self.selection.select(self.editor.document().selection);
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> Result<&Frame> {
+        self.editor
+            .document()
+            .frame
+            .as_ref()
+            .ok_or_else(|| anyhow!("No screen captured yet"))
+    }
+
+    /// Mirrors selection changes into the undo history like the overlay
+    /// does; a drag becomes one undo step.
+    fn record_selection(&mut self, event: InputEvent, update: SelectionUpdate) -> Result<()> {
+        if let InputEvent::PointerDown {
+            button: MouseButton::Left,
+            ..
+        } = event
+        {
+            self.editor.begin_coalescing();
+        }
+        self.set_selection(update.selection)?;
+        if let InputEvent::PointerUp {
+            button: MouseButton::Left,
+            ..
+        } = event
+        {
+            self.editor.end_coalescing();
+        }
+        Ok(())
+    }
+
+    fn set_selection(&mut self, after: Option<Rect>) -> Result<()> {
+        let before = self.editor.document().selection;
+        if after != before {
+            self.editor.apply(Edit::SetSelection { before, after })?;
+        }
+        Ok(())
+    }
+
+    fn save(&self, frame: &Frame, path: &Path) -> Result<ReplayOutput> {
+        let path = match (&self.output_dir, path.file_name()) {
+            (Some(directory), Some(name)) => directory.join(name),
+            _ => path.to_path_buf(),
+        };
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        Exporter::default().save(frame, &path)?;
+        Ok(ReplayOutput::Saved { path })
+    }
+}
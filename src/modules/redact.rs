@@ -0,0 +1,461 @@
+use crate::annotation::{Annotation, AnnotationKind, Canvas, Color, Shape, Stroke};
+use crate::capture::Frame;
+use crate::geometry::{Point, Rect};
+use crate::raster::pixel_span;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_BLOCK_SIZE: u32 = 12;
+pub const DEFAULT_BLUR_RADIUS: f32 = 12.0;
+
+/// Opacity of the placeholder shown where pixels cannot be edited live.
+const PREVIEW_OPACITY: f32 = 0.6;
+
+/// How a redacted area is made unreadable.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "style", rename_all = "snake_case")]
+pub enum RedactionStyle {
+    Blackout {
+        color: Color,
+    },
+    /// Averages square blocks of `block_size` pixels.
+    Pixelate {
+        block_size: u32,
+    },
+    /// Gaussian blur; only pixels inside the area are sampled, so nothing
+    /// from outside bleeds in and nothing from inside survives at the edge.
+    Blur {
+        radius: f32,
+    },
+}
+
+impl RedactionStyle {
+    pub fn blackout() -> Self {
+        RedactionStyle::Blackout {
+            color: Color::BLACK,
+        }
+    }
+
+    pub fn pixelate() -> Self {
+        RedactionStyle::Pixelate {
+            block_size: DEFAULT_BLOCK_SIZE,
+        }
+    }
+
+    pub fn blur() -> Self {
+        RedactionStyle::Blur {
+            radius: DEFAULT_BLUR_RADIUS,
+        }
+    }
+}
+
+/// Area whose pixels are destroyed when the capture is flattened.
+///
+/// Unlike other annotations a redaction never survives as an overlay: the
+/// exported image only contains the processed pixels. On canvases that
+/// cannot read pixels it is drawn as a placeholder.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Redaction {
+    pub rect: Rect,
+    pub style: RedactionStyle,
+    /// Outline of the placeholder while editing.
+    pub stroke: Stroke,
+}
+
+impl Redaction {
+    pub fn new(rect: Rect, style: RedactionStyle) -> Self {
+        Redaction {
+            rect,
+            style,
+            stroke: Stroke::new(Color::WHITE, 1.0, PREVIEW_OPACITY),
+        }
+    }
+
+    /// Overwrites the covered pixels of `frame`.
+    pub fn apply(&self, frame: &mut Frame) {
+        match self.style {
+            RedactionStyle::Blackout { color } => blackout(frame, &self.rect, color),
+            RedactionStyle::Pixelate { block_size } => pixelate(frame, &self.rect, block_size),
+            RedactionStyle::Blur { radius } => blur(frame, &self.rect, radius),
+        }
+    }
+}
+
+impl Annotation for Redaction {
+    fn kind(&self) -> AnnotationKind {
+        AnnotationKind::Redaction
+    }
+
+    fn stroke(&self) -> &Stroke {
+        &self.stroke
+    }
+
+    fn set_stroke(&mut self, stroke: Stroke) {
+        self.stroke = stroke;
+    }
+
+    fn bounds(&self) -> Rect {
+        self.rect
+    }
+
+    /// The whole area is solid, not only its outline.
+    fn hit_test(&self, p: Point, tolerance: f32) -> bool {
+        self.rect.inflate(tolerance).contains(p)
+    }
+
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.rect = self.rect.translate(dx, dy);
+    }
+
+    fn draw(&self, canvas: &mut dyn Canvas) {
+        match self.style {
+            RedactionStyle::Blackout { color } => canvas.fill_rect(&self.rect, color, 1.0),
+            _ => {
+                canvas.fill_rect(&self.rect, Color::rgb(128, 128, 128), PREVIEW_OPACITY);
+                canvas.stroke_rect(&self.rect, &self.stroke);
+            }
+        }
+    }
+
+    fn to_shape(&self) -> Shape {
+        Shape::Redaction(self.clone())
+    }
+}
+
+/// Fills `rect` with an opaque color.
+pub fn blackout(frame: &mut Frame, rect: &Rect, color: Color) {
+    let Some((left, top, right, bottom)) = pixel_span(rect, frame.width, frame.height) else {
+        return;
+    };
+    for y in top..bottom {
+        for x in left..right {
+            frame.put_pixel(x, y, [color.r, color.g, color.b, 255]);
+        }
+    }
+}
+
+/// Replaces every `block_size` square of `rect`, counted from its top-left
+/// corner, with its average color.
+///
+/// A remainder of at most half a block at the right or bottom joins the
+/// block before it, so no pixel is left to average only with itself.
+pub fn pixelate(frame: &mut Frame, rect: &Rect, block_size: u32) {
+    let Some((left, top, right, bottom)) = pixel_span(rect, frame.width, frame.height) else {
+        return;
+    };
+    let block = block_size.max(1);
+    let rows = block_edges(top, bottom, block);
+    let columns = block_edges(left, right, block);
+
+    for row in rows.windows(2) {
+        let (block_top, block_bottom) = (row[0], row[1]);
+        for column in columns.windows(2) {
+            let (block_left, block_right) = (column[0], column[1]);
+
+            let mut sum = [0u64; 4];
+            for y in block_top..block_bottom {
+                for x in block_left..block_right {
+                    for (total, value) in sum.iter_mut().zip(frame.pixel(x, y)) {
+                        *total += value as u64;
+                    }
+                }
+            }
+            let count = ((block_right - block_left) * (block_bottom - block_top)) as u64;
+            let average = sum.map(|total| ((total + count / 2) / count) as u8);
+
+            for y in block_top..block_bottom {
+                for x in block_left..block_right {
+                    frame.put_pixel(x, y, average);
+                }
+            }
+        }
+    }
+}
+
+/// Block boundaries from `start` to `end`, both included.
+fn block_edges(start: u32, end: u32, block: u32) -> Vec<u32> {
+    let mut edges: Vec<u32> = (start..end).step_by(block as usize).collect();
+    if edges.len() > 1 && (end - edges[edges.len() - 1]) * 2 <= block {
+        edges.pop();
+    }
+    edges.push(end);
+    edges
+}
+
+/// Separable Gaussian blur of `rect` with sigma `radius / 2`, clamping
+/// samples to the blurred area.
+pub fn blur(frame: &mut Frame, rect: &Rect, radius: f32) {
+    let Some((left, top, right, bottom)) = pixel_span(rect, frame.width, frame.height) else {
+        return;
+    };
+    if radius <= 0.0 {
+        return;
+    }
+
+    let kernel = gaussian_kernel(radius);
+    let half = (kernel.len() / 2) as i64;
+    let (width, height) = ((right - left) as usize, (bottom - top) as usize);
+
+    let mut pixels: Vec<[f32; 4]> = Vec::with_capacity(width * height);
+    for y in top..bottom {
+        for x in left..right {
+            pixels.push(frame.pixel(x, y).map(|c| c as f32));
+        }
+    }
+
+    let convolve = |source: &[[f32; 4]], horizontal: bool| -> Vec<[f32; 4]> {
+        let mut out = vec![[0.0; 4]; source.len()];
+        for y in 0..height {
+            for x in 0..width {
+                let mut acc = [0.0f32; 4];
+                for (i, weight) in kernel.iter().enumerate() {
+                    let offset = i as i64 - half;
+                    let (sx, sy) = if horizontal {
+                        ((x as i64 + offset).clamp(0, width as i64 - 1) as usize, y)
+                    } else {
+                        (x, (y as i64 + offset).clamp(0, height as i64 - 1) as usize)
+                    };
+                    let sample = source[sy * width + sx];
+                    for c in 0..4 {
+                        acc[c] += sample[c] * weight;
+                    }
+                }
+                out[y * width + x] = acc;
+            }
+        }
+        out
+    };
+    let pixels = convolve(&convolve(&pixels, true), false);
+
+    for y in 0..height {
+        for x in 0..width {
+            let px = pixels[y * width + x].map(|c| c.round().clamp(0.0, 255.0) as u8);
+            frame.put_pixel(left + x as u32, top + y as u32, px);
+        }
+    }
+}
+
+fn gaussian_kernel(radius: f32) -> Vec<f32> {
+    let sigma = (radius / 2.0).max(0.5);
+    let half = radius.ceil() as i64;
+    let weights: Vec<f32> = (-half..=half)
+        .map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f32 = weights.iter().sum();
+    weights.into_iter().map(|w| w / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DARK: [u8; 4] = [30, 60, 90, 255];
+    const LIGHT: [u8; 4] = [220, 200, 180, 255];
+
+    /// Alternating dark and light pixels, `inverted` swaps them.
+    fn checkerboard(width: u32, height: u32, inverted: bool) -> Frame {
+        let mut frame = Frame::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let dark = (x + y) % 2 == 0;
+                frame.put_pixel(x, y, if dark != inverted { DARK } else { LIGHT });
+            }
+        }
+        frame
+    }
+
+    fn row(width: u32, values: &[u8]) -> Frame {
+        let mut frame = Frame::new(width, 1);
+        for (x, &v) in values.iter().enumerate() {
+            frame.put_pixel(x as u32, 0, [v, v, v, 255]);
+        }
+        frame
+    }
+
+    fn gray(frame: &Frame) -> Vec<u8> {
+        (0..frame.width).map(|x| frame.pixel(x, 0)[0]).collect()
+    }
+
+    #[test]
+    fn blackout_covers_every_touched_pixel() {
+        let mut frame = checkerboard(6, 4, false);
+        blackout(
+            &mut frame,
+            &Rect::new(1.5, 0.2, 3.1, 2.0),
+            Color::rgb(1, 2, 3),
+        );
+        for y in 0..4 {
+            for x in 0..6 {
+                let inside = (1..4).contains(&x) && y < 2;
+                assert_eq!(
+                    frame.pixel(x, y) == [1, 2, 3, 255],
+                    inside,
+                    "({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pixelate_averages_blocks_from_the_area_corner() {
+        // Blocks start at x = 1, the last one is cut short; halves round up
+        let mut frame = row(9, &[9, 0, 10, 20, 32, 40, 50, 99, 7]);
+        pixelate(&mut frame, &Rect::new(1.0, 0.0, 8.0, 1.0), 4);
+        assert_eq!(gray(&frame), [9, 16, 16, 16, 16, 63, 63, 63, 7]);
+
+        // A remainder of half a block or less joins the block before it
+        let mut frame = row(6, &[0, 10, 20, 32, 40, 7]);
+        pixelate(&mut frame, &Rect::new(0.0, 0.0, 5.0, 1.0), 4);
+        assert_eq!(gray(&frame), [20, 20, 20, 20, 20, 7]);
+
+        let mut frame = checkerboard(4, 4, false);
+        pixelate(&mut frame, &Rect::new(0.0, 0.0, 4.0, 4.0), 2);
+        let average = [125, 130, 135, 255];
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(frame.pixel(x, y), average);
+            }
+        }
+
+        let mut frame = row(3, &[1, 2, 4]);
+        pixelate(&mut frame, &Rect::new(0.0, 0.0, 3.0, 1.0), 0);
+        assert_eq!(gray(&frame), [1, 2, 4], "block size 0 acts as 1");
+    }
+
+    #[test]
+    fn gaussian_kernel_is_normalized_and_symmetric() {
+        let kernel = gaussian_kernel(2.0);
+        // sigma = 1: e^(-i^2 / 2) for i in -2..=2, normalized
+        let raw = [
+            (-2.0f32).exp(),
+            (-0.5f32).exp(),
+            1.0,
+            (-0.5f32).exp(),
+            (-2.0f32).exp(),
+        ];
+        let total: f32 = raw.iter().sum();
+        assert_eq!(kernel.len(), 5);
+        for (weight, expected) in kernel.iter().zip(raw) {
+            assert!((weight - expected / total).abs() < 1e-6, "{:?}", kernel);
+        }
+        assert!((kernel[2] - 0.4026).abs() < 1e-4);
+
+        for radius in [0.3, 1.0, 4.5, 12.0] {
+            let kernel = gaussian_kernel(radius);
+            assert_eq!(kernel.len(), 2 * radius.ceil() as usize + 1);
+            assert!((kernel.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+            assert!(kernel.iter().eq(kernel.iter().rev()));
+        }
+    }
+
+    #[test]
+    fn blur_spreads_by_the_kernel_and_clamps_at_the_edges() {
+        let mut frame = row(5, &[0, 0, 255, 0, 0]);
+        blur(&mut frame, &Rect::new(0.0, 0.0, 5.0, 1.0), 2.0);
+        assert_eq!(gray(&frame), [14, 62, 103, 62, 14]);
+
+        // Edge samples repeat the border pixel instead of reading outside
+        let mut frame = row(5, &[255, 0, 0, 0, 0]);
+        blur(&mut frame, &Rect::new(0.0, 0.0, 5.0, 1.0), 2.0);
+        assert_eq!(gray(&frame), [179, 76, 14, 0, 0]);
+        assert!(frame.row(0).chunks(4).all(|px| px[3] == 255));
+
+        let mut frame = row(5, &[1, 2, 3, 4, 5]);
+        blur(&mut frame, &Rect::new(0.0, 0.0, 5.0, 1.0), 0.0);
+        assert_eq!(gray(&frame), [1, 2, 3, 4, 5], "radius 0 keeps the pixels");
+    }
+
+    #[test]
+    fn blur_samples_only_inside_the_area() {
+        // A bright area in a black frame stays exactly as bright
+        let mut frame = Frame::new(12, 12);
+        for y in 0..12 {
+            for x in 0..12 {
+                let inside = (3..9).contains(&x) && (3..9).contains(&y);
+                frame.put_pixel(x, y, if inside { LIGHT } else { [0, 0, 0, 255] });
+            }
+        }
+        let before = frame.clone();
+        blur(&mut frame, &Rect::new(3.0, 3.0, 9.0, 9.0), 4.0);
+        assert_eq!(frame, before);
+    }
+
+    #[test]
+    fn areas_are_clipped_to_the_frame() {
+        for style in [
+            RedactionStyle::blackout(),
+            RedactionStyle::Pixelate { block_size: 2 },
+            RedactionStyle::Blur { radius: 2.0 },
+        ] {
+            let mut frame = checkerboard(6, 6, false);
+            let original = frame.clone();
+            Redaction::new(Rect::new(-5.0, -5.0, 3.0, 3.0), style).apply(&mut frame);
+            for y in 0..6 {
+                for x in 0..6 {
+                    let changed = frame.pixel(x, y) != original.pixel(x, y);
+                    assert_eq!(changed, x < 3 && y < 3, "{:?} at ({}, {})", style, x, y);
+                }
+            }
+
+            // Entirely outside, nothing happens
+            let mut frame = original.clone();
+            Redaction::new(Rect::new(6.0, 0.0, 20.0, 6.0), style).apply(&mut frame);
+            Redaction::new(Rect::new(-9.0, -9.0, -1.0, -1.0), style).apply(&mut frame);
+            assert_eq!(frame, original);
+        }
+    }
+
+    #[test]
+    fn no_original_pixel_survives_inside_the_area() {
+        let area = Rect::new(4.0, 4.0, 28.0, 28.0);
+        for style in [
+            RedactionStyle::blackout(),
+            RedactionStyle::pixelate(),
+            RedactionStyle::blur(),
+        ] {
+            let mut frame = checkerboard(32, 32, false);
+            Redaction::new(area, style).apply(&mut frame);
+            for y in 0..32 {
+                for x in 0..32 {
+                    let inside = area.contains(Point::new(x as f32, y as f32));
+                    let pixel = frame.pixel(x, y);
+                    assert_eq!(
+                        pixel != DARK && pixel != LIGHT,
+                        inside,
+                        "{:?} at ({}, {})",
+                        style,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn different_originals_flatten_to_the_same_pixels() {
+        let area = Rect::new(0.0, 0.0, 24.0, 24.0);
+        for style in [RedactionStyle::blackout(), RedactionStyle::pixelate()] {
+            let mut a = checkerboard(24, 24, false);
+            let mut b = checkerboard(24, 24, true);
+            assert_ne!(a, b);
+            let redaction = Redaction::new(area, style);
+            redaction.apply(&mut a);
+            redaction.apply(&mut b);
+            assert_eq!(a, b, "{:?}", style);
+        }
+    }
+
+    #[test]
+    fn redactions_are_hit_anywhere_inside() {
+        let mut redaction =
+            Redaction::new(Rect::new(10.0, 10.0, 30.0, 20.0), RedactionStyle::blur());
+        assert!(redaction.hit_test(Point::new(20.0, 15.0), 0.0));
+        assert!(redaction.hit_test(Point::new(8.0, 15.0), 2.0));
+        assert!(!redaction.hit_test(Point::new(8.0, 15.0), 1.0));
+        redaction.translate(5.0, 5.0);
+        assert_eq!(redaction.bounds(), Rect::new(15.0, 15.0, 35.0, 25.0));
+        assert_eq!(redaction.kind(), AnnotationKind::Redaction);
+    }
+}
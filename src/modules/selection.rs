@@ -0,0 +1,840 @@
+use crate::annotation::{Canvas, Color, Stroke};
+use crate::geometry::{Point, Rect};
+use serde::{Deserialize, Serialize};
+
+/// Edge length of the square drag handles drawn on the selection frame.
+pub const HANDLE_SIZE: f32 = 8.0;
+
+/// Extra pixels invalidated around a selection so its frame and handles are
+/// repainted too.
+const FRAME_MARGIN: f32 = HANDLE_SIZE / 2.0 + 2.0;
+
+/// Opacity of the overlay window over the frozen screen.
+pub const DEFAULT_OVERLAY_OPACITY: f32 = 0.55;
+
+const NUDGE_STEP: f32 = 1.0;
+const NUDGE_STEP_SHIFT: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Buttons held down while the pointer moves.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Buttons {
+    pub left: bool,
+    pub right: bool,
+    pub middle: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Key {
+    Escape,
+    Enter,
+    Left,
+    Right,
+    Up,
+    Down,
+    Char(char),
+    /// Any other key, carrying the platform key code.
+    Other(u32),
+}
+
+/// Platform-neutral input the overlay window procedures translate into.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    PointerDown { pos: Point, button: MouseButton },
+    PointerMove { pos: Point, buttons: Buttons },
+    PointerUp { pos: Point, button: MouseButton },
+    Key { key: Key, modifiers: Modifiers },
+}
+
+/// Which part of the window has to be repainted after an event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Redraw {
+    None,
+    Area(Rect),
+    Full,
+}
+
+/// One of the eight resize handles around a selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    pub const ALL: [Handle; 8] = [
+        Handle::TopLeft,
+        Handle::Top,
+        Handle::TopRight,
+        Handle::Right,
+        Handle::BottomRight,
+        Handle::Bottom,
+        Handle::BottomLeft,
+        Handle::Left,
+    ];
+
+    /// Center of the handle on the frame of `rect`.
+    pub fn position(&self, rect: &Rect) -> Point {
+        let mid_x = (rect.left + rect.right) / 2.0;
+        let mid_y = (rect.top + rect.bottom) / 2.0;
+        match self {
+            Handle::TopLeft => Point::new(rect.left, rect.top),
+            Handle::Top => Point::new(mid_x, rect.top),
+            Handle::TopRight => Point::new(rect.right, rect.top),
+            Handle::Right => Point::new(rect.right, mid_y),
+            Handle::BottomRight => Point::new(rect.right, rect.bottom),
+            Handle::Bottom => Point::new(mid_x, rect.bottom),
+            Handle::BottomLeft => Point::new(rect.left, rect.bottom),
+            Handle::Left => Point::new(rect.left, mid_y),
+        }
+    }
+
+    /// Square the handle occupies, used for drawing and hit-testing.
+    pub fn bounds(&self, rect: &Rect) -> Rect {
+        let c = self.position(rect);
+        let half = HANDLE_SIZE / 2.0;
+        Rect::new(c.x - half, c.y - half, c.x + half, c.y + half)
+    }
+
+    /// Moves the edges this handle controls by `(dx, dy)`.
+    ///
+    /// Dragging past the opposite edge flips the rectangle instead of
+    /// producing negative sizes.
+    pub fn resize(&self, rect: &Rect, dx: f32, dy: f32) -> Rect {
+        let (mut left, mut top, mut right, mut bottom) =
+            (rect.left, rect.top, rect.right, rect.bottom);
+        match self {
+            Handle::TopLeft | Handle::Left | Handle::BottomLeft => left += dx,
+            Handle::TopRight | Handle::Right | Handle::BottomRight => right += dx,
+            Handle::Top | Handle::Bottom => {}
+        }
+        match self {
+            Handle::TopLeft | Handle::Top | Handle::TopRight => top += dy,
+            Handle::BottomLeft | Handle::Bottom | Handle::BottomRight => bottom += dy,
+            Handle::Left | Handle::Right => {}
+        }
+        Rect::from_points(Point::new(left, top), Point::new(right, bottom))
+    }
+}
+
+/// What lies under the pointer relative to the current selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HitZone {
+    Handle(Handle),
+    Inside,
+    Outside,
+}
+
+/// Cursor the overlay should show for a [`HitZone`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CursorShape {
+    Crosshair,
+    Move,
+    ResizeNorthSouth,
+    ResizeEastWest,
+    ResizeNorthWestSouthEast,
+    ResizeNorthEastSouthWest,
+}
+
+impl HitZone {
+    pub fn cursor(&self) -> CursorShape {
+        match self {
+            HitZone::Outside => CursorShape::Crosshair,
+            HitZone::Inside => CursorShape::Move,
+            HitZone::Handle(Handle::Top | Handle::Bottom) => CursorShape::ResizeNorthSouth,
+            HitZone::Handle(Handle::Left | Handle::Right) => CursorShape::ResizeEastWest,
+            HitZone::Handle(Handle::TopLeft | Handle::BottomRight) => {
+                CursorShape::ResizeNorthWestSouthEast
+            }
+            HitZone::Handle(Handle::TopRight | Handle::BottomLeft) => {
+                CursorShape::ResizeNorthEastSouthWest
+            }
+        }
+    }
+}
+
+/// Handles win over the interior so small selections stay resizable.
+pub fn hit_test(rect: &Rect, p: Point) -> HitZone {
+    Handle::ALL
+        .iter()
+        .find(|handle| handle.bounds(rect).contains(p))
+        .map(|handle| HitZone::Handle(*handle))
+        .unwrap_or(if rect.contains(p) {
+            HitZone::Inside
+        } else {
+            HitZone::Outside
+        })
+}
+
+/// Result of feeding one event into the [`SelectionModel`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SelectionUpdate {
+    pub selection: Option<Rect>,
+    pub redraw: Redraw,
+    /// Set once when a drag, resize or move finishes with a non-empty
+    /// rectangle.
+    pub committed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    Dragging {
+        start: Point,
+        current: Point,
+    },
+    Resizing {
+        handle: Handle,
+        original: Rect,
+        anchor: Point,
+        current: Rect,
+    },
+    Moving {
+        original: Rect,
+        anchor: Point,
+        current: Rect,
+    },
+    Selected(Rect),
+}
+
+/// Rubber-band selection driven purely by [`InputEvent`]s.
+///
+/// Holds no window handles, so the whole drag flow can be replayed without
+/// a window and reset between captures. A finished selection can still be
+/// resized by its handles, moved by dragging inside it or nudged with the
+/// arrow keys until it is reset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectionModel {
+    state: State,
+}
+
+impl Default for SelectionModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SelectionModel {
+    pub const fn new() -> Self {
+        SelectionModel { state: State::Idle }
+    }
+
+    /// Normalized rectangle of the running drag or the finished selection.
+    pub fn selection(&self) -> Option<Rect> {
+        match self.state {
+            State::Idle => None,
+            State::Dragging { start, current } => Some(Rect::from_points(start, current)),
+            State::Resizing { current, .. } | State::Moving { current, .. } => Some(current),
+            State::Selected(rect) => Some(rect),
+        }
+    }
+
+    /// True while any pointer drag (new selection, resize, move) is running.
+    pub fn is_dragging(&self) -> bool {
+        !matches!(self.state, State::Idle | State::Selected(_))
+    }
+
+    /// Classifies `pos` against the finished selection for cursor feedback.
+    pub fn hit_test(&self, pos: Point) -> HitZone {
+        match self.state {
+            State::Selected(rect) => hit_test(&rect, pos),
+            _ => HitZone::Outside,
+        }
+    }
+
+    pub fn reset(&mut self) -> SelectionUpdate {
+        let previous = self.selection();
+        self.state = State::Idle;
+        self.update(previous, false)
+    }
+
+    /// Replaces the selection, e.g. when it is restored by undo. Empty
+    /// rectangles clear it.
+    pub fn select(&mut self, rect: Option<Rect>) -> SelectionUpdate {
+        let previous = self.selection();
+        self.state = match rect {
+            Some(rect) if !rect.is_empty() => State::Selected(rect),
+            _ => State::Idle,
+        };
+        self.update(previous, false)
+    }
+
+    pub fn handle(&mut self, event: InputEvent) -> SelectionUpdate {
+        let previous = self.selection();
+
+        match event {
+            InputEvent::PointerDown {
+                pos,
+                button: MouseButton::Left,
+            } => {
+                self.state = match self.hit_test(pos) {
+                    HitZone::Handle(handle) => State::Resizing {
+                        handle,
+                        original: previous.unwrap_or_default(),
+                        anchor: pos,
+                        current: previous.unwrap_or_default(),
+                    },
+                    HitZone::Inside => State::Moving {
+                        original: previous.unwrap_or_default(),
+                        anchor: pos,
+                        current: previous.unwrap_or_default(),
+                    },
+                    HitZone::Outside => State::Dragging {
+                        start: pos,
+                        current: pos,
+                    },
+                };
+                self.update(previous, false)
+            }
+            InputEvent::PointerMove { pos, buttons } if buttons.left => {
+                self.drag_to(pos);
+                self.update(previous, false)
+            }
+            InputEvent::PointerUp {
+                pos,
+                button: MouseButton::Left,
+            } if self.is_dragging() => {
+                self.drag_to(pos);
+                match self.selection() {
+                    Some(rect) if !rect.is_empty() => {
+                        self.state = State::Selected(rect);
+                        self.update(previous, true)
+                    }
+                    _ => {
+                        self.state = State::Idle;
+                        self.update(previous, false)
+                    }
+                }
+            }
+            InputEvent::Key {
+                key: Key::Escape, ..
+            } => self.reset(),
+            InputEvent::Key { key, modifiers } => {
+                match (self.state, nudge_offset(key, modifiers)) {
+                    (State::Selected(rect), Some((dx, dy))) => {
+                        self.state = State::Selected(rect.translate(dx, dy));
+                        self.update(previous, true)
+                    }
+                    _ => self.unchanged(),
+                }
+            }
+            _ => self.unchanged(),
+        }
+    }
+
+    fn drag_to(&mut self, pos: Point) {
+        self.state = match self.state {
+            State::Dragging { start, .. } => State::Dragging {
+                start,
+                current: pos,
+            },
+            State::Resizing {
+                handle,
+                original,
+                anchor,
+                ..
+            } => State::Resizing {
+                handle,
+                original,
+                anchor,
+                current: handle.resize(&original, pos.x - anchor.x, pos.y - anchor.y),
+            },
+            State::Moving {
+                original, anchor, ..
+            } => State::Moving {
+                original,
+                anchor,
+                current: original.translate(pos.x - anchor.x, pos.y - anchor.y),
+            },
+            state => state,
+        };
+    }
+
+    fn unchanged(&self) -> SelectionUpdate {
+        SelectionUpdate {
+            selection: self.selection(),
+            redraw: Redraw::None,
+            committed: false,
+        }
+    }
+
+    fn update(&self, previous: Option<Rect>, committed: bool) -> SelectionUpdate {
+        let selection = self.selection();
+        let redraw = match (previous, selection) {
+            (None, None) => Redraw::None,
+            (Some(a), Some(b)) if a == b => Redraw::None,
+            (Some(a), Some(b)) => Redraw::Area(a.union(&b).inflate(FRAME_MARGIN)),
+            (Some(r), None) | (None, Some(r)) => Redraw::Area(r.inflate(FRAME_MARGIN)),
+        };
+
+        SelectionUpdate {
+            selection,
+            redraw,
+            committed,
+        }
+    }
+}
+
+fn nudge_offset(key: Key, modifiers: Modifiers) -> Option<(f32, f32)> {
+    let step = if modifiers.shift {
+        NUDGE_STEP_SHIFT
+    } else {
+        NUDGE_STEP
+    };
+    match key {
+        Key::Left => Some((-step, 0.0)),
+        Key::Right => Some((step, 0.0)),
+        Key::Up => Some((0.0, -step)),
+        Key::Down => Some((0.0, step)),
+        _ => None,
+    }
+}
+
+/// Look of the selection overlay.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OverlayStyle {
+    /// Tint over everything outside the selection, with its opacity.
+    pub dim: Option<(Color, f32)>,
+    /// Fill inside the selection, with its opacity.
+    pub fill: Option<(Color, f32)>,
+    pub frame: Stroke,
+    pub handles: (Color, f32),
+}
+
+impl Default for OverlayStyle {
+    /// The look of the transparent overlay window: the selection is filled
+    /// so the layered window lets it through, framed and with handles.
+    fn default() -> Self {
+        OverlayStyle {
+            dim: None,
+            fill: Some((Color::WHITE, 1.0)),
+            frame: Stroke::new(Color::WHITE, 2.0, 0.5),
+            handles: (Color::WHITE, 0.5),
+        }
+    }
+}
+
+impl OverlayStyle {
+    /// Darkens the surroundings and leaves the selection untouched, for
+    /// rendering the overlay into a captured image.
+    pub fn dimmed() -> Self {
+        OverlayStyle {
+            dim: Some((Color::BLACK, 0.5)),
+            fill: None,
+            frame: Stroke::new(Color::WHITE, 2.0, 1.0),
+            handles: (Color::WHITE, 1.0),
+        }
+    }
+}
+
+/// Paints `selection` with its frame and resize handles onto `canvas`;
+/// `bounds` is the visible area used for dimming.
+pub fn draw_overlay(
+    canvas: &mut dyn Canvas,
+    bounds: &Rect,
+    selection: &Rect,
+    style: &OverlayStyle,
+) {
+    if let Some((color, opacity)) = style.dim {
+        let top = selection.top.clamp(bounds.top, bounds.bottom);
+        let bottom = selection.bottom.clamp(top, bounds.bottom);
+        let left = selection.left.clamp(bounds.left, bounds.right);
+        let right = selection.right.clamp(left, bounds.right);
+        for area in [
+            Rect::new(bounds.left, bounds.top, bounds.right, top),
+            Rect::new(bounds.left, bottom, bounds.right, bounds.bottom),
+            Rect::new(bounds.left, top, left, bottom),
+            Rect::new(right, top, bounds.right, bottom),
+        ] {
+            if !area.is_empty() {
+                canvas.fill_rect(&area, color, opacity);
+            }
+        }
+    }
+
+    if let Some((color, opacity)) = style.fill {
+        canvas.fill_rect(selection, color, opacity);
+    }
+    canvas.stroke_rect(selection, &style.frame);
+
+    let (color, opacity) = style.handles;
+    for handle in Handle::ALL {
+        canvas.fill_rect(&handle.bounds(selection), color, opacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn down(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerDown {
+            pos: Point::new(x, y),
+            button: MouseButton::Left,
+        }
+    }
+
+    fn move_to(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerMove {
+            pos: Point::new(x, y),
+            buttons: Buttons {
+                left: true,
+                ..Buttons::default()
+            },
+        }
+    }
+
+    fn up(x: f32, y: f32) -> InputEvent {
+        InputEvent::PointerUp {
+            pos: Point::new(x, y),
+            button: MouseButton::Left,
+        }
+    }
+
+    fn key(key: Key) -> InputEvent {
+        InputEvent::Key {
+            key,
+            modifiers: Modifiers::default(),
+        }
+    }
+
+    /// A model with (10, 10)-(60, 30) selected by dragging.
+    fn selected() -> SelectionModel {
+        let mut model = SelectionModel::new();
+        for event in [down(10.0, 10.0), move_to(60.0, 30.0), up(60.0, 30.0)] {
+            model.handle(event);
+        }
+        model
+    }
+
+    #[test]
+    fn dragging_selects_and_commits_on_release() {
+        let mut model = SelectionModel::new();
+        let update = model.handle(down(10.0, 10.0));
+        assert_eq!(update.selection, Some(Rect::new(10.0, 10.0, 10.0, 10.0)));
+        assert_eq!(update.redraw, Redraw::Area(Rect::new(4.0, 4.0, 16.0, 16.0)));
+        assert!(!update.committed);
+        assert!(model.is_dragging());
+
+        let update = model.handle(move_to(50.0, 40.0));
+        assert_eq!(update.selection, Some(Rect::new(10.0, 10.0, 50.0, 40.0)));
+        assert_eq!(update.redraw, Redraw::Area(Rect::new(4.0, 4.0, 56.0, 46.0)));
+        assert!(!update.committed);
+
+        // The release position counts even without a move before it
+        let update = model.handle(up(60.0, 30.0));
+        assert_eq!(update.selection, Some(Rect::new(10.0, 10.0, 60.0, 30.0)));
+        assert!(update.committed);
+        assert!(!model.is_dragging());
+        assert_eq!(model, selected());
+    }
+
+    #[test]
+    fn dragging_up_and_left_is_normalized() {
+        let mut model = SelectionModel::new();
+        model.handle(down(60.0, 30.0));
+        model.handle(move_to(30.0, 20.0));
+        let update = model.handle(up(10.0, 10.0));
+        assert_eq!(update.selection, Some(Rect::new(10.0, 10.0, 60.0, 30.0)));
+        assert!(update.committed);
+    }
+
+    #[test]
+    fn a_click_selects_nothing() {
+        let mut model = SelectionModel::new();
+        model.handle(down(20.0, 20.0));
+        let update = model.handle(up(20.0, 20.0));
+        assert_eq!(update.selection, None);
+        assert!(!update.committed);
+        assert_eq!(model, SelectionModel::new());
+
+        // Clicking outside a selection starts over and clears it
+        let mut model = selected();
+        model.handle(down(100.0, 100.0));
+        let update = model.handle(up(100.0, 100.0));
+        assert_eq!(update.selection, None);
+        assert!(!update.committed);
+    }
+
+    #[test]
+    fn dragging_inside_moves_the_selection() {
+        let mut model = selected();
+        model.handle(down(30.0, 20.0));
+        assert!(model.is_dragging());
+        let update = model.handle(move_to(40.0, 25.0));
+        assert_eq!(update.selection, Some(Rect::new(20.0, 15.0, 70.0, 35.0)));
+        assert!(!update.committed);
+        let update = model.handle(up(45.0, 15.0));
+        assert_eq!(update.selection, Some(Rect::new(25.0, 5.0, 75.0, 25.0)));
+        assert!(update.committed);
+    }
+
+    #[test]
+    fn other_buttons_and_hovering_change_nothing() {
+        let mut model = selected();
+        let before = model.clone();
+        for event in [
+            InputEvent::PointerDown {
+                pos: Point::new(100.0, 100.0),
+                button: MouseButton::Right,
+            },
+            InputEvent::PointerMove {
+                pos: Point::new(90.0, 90.0),
+                buttons: Buttons::default(),
+            },
+            up(90.0, 90.0),
+            key(Key::Enter),
+        ] {
+            let update = model.handle(event);
+            assert_eq!(update.redraw, Redraw::None);
+            assert!(!update.committed);
+        }
+        assert_eq!(model, before);
+    }
+
+    #[test]
+    fn reset_and_escape_clear_the_selection() {
+        let mut model = selected();
+        let update = model.reset();
+        assert_eq!(update.selection, None);
+        assert_eq!(update.redraw, Redraw::Area(Rect::new(4.0, 4.0, 66.0, 36.0)));
+        assert!(!update.committed);
+        assert_eq!(model.reset().redraw, Redraw::None);
+
+        // Escape cancels a running drag; the release is then ignored
+        model.handle(down(10.0, 10.0));
+        model.handle(move_to(40.0, 40.0));
+        assert_eq!(model.handle(key(Key::Escape)).selection, None);
+        assert!(!model.is_dragging());
+        let update = model.handle(up(40.0, 40.0));
+        assert_eq!(update.selection, None);
+        assert!(!update.committed);
+    }
+
+    #[test]
+    fn select_replaces_or_clears() {
+        let mut model = SelectionModel::new();
+        let rect = Rect::new(5.0, 5.0, 25.0, 15.0);
+        let update = model.select(Some(rect));
+        assert_eq!(update.selection, Some(rect));
+        assert!(!update.committed);
+        assert!(!model.is_dragging());
+        assert_eq!(model.hit_test(Point::new(15.0, 10.0)), HitZone::Inside);
+
+        assert_eq!(
+            model.select(Some(Rect::new(5.0, 5.0, 5.0, 15.0))).selection,
+            None
+        );
+        model.select(Some(rect));
+        assert_eq!(model.select(None).selection, None);
+    }
+
+    fn shifted(key: Key) -> InputEvent {
+        InputEvent::Key {
+            key,
+            modifiers: Modifiers {
+                shift: true,
+                ..Modifiers::default()
+            },
+        }
+    }
+
+    fn handle_at(rect: &Rect, x: f32, y: f32) -> Option<Handle> {
+        match hit_test(rect, Point::new(x, y)) {
+            HitZone::Handle(handle) => Some(handle),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn handles_are_hit_around_their_position() {
+        let rect = Rect::new(100.0, 100.0, 200.0, 160.0);
+        for handle in Handle::ALL {
+            let p = handle.position(&rect);
+            assert_eq!(hit_test(&rect, p), HitZone::Handle(handle));
+            // The square reaches half the handle size out of the frame
+            assert_eq!(
+                hit_test(&rect, Point::new(p.x - 4.0, p.y - 4.0)),
+                HitZone::Handle(handle)
+            );
+            assert_eq!(
+                hit_test(&rect, Point::new(p.x + 3.9, p.y + 3.9)),
+                HitZone::Handle(handle)
+            );
+        }
+        assert_eq!(handle_at(&rect, 150.0, 96.0), Some(Handle::Top));
+        assert_eq!(handle_at(&rect, 204.0, 130.0), None);
+        assert_eq!(hit_test(&rect, Point::new(104.0, 104.0)), HitZone::Inside);
+        assert_eq!(hit_test(&rect, Point::new(150.0, 130.0)), HitZone::Inside);
+        assert_eq!(hit_test(&rect, Point::new(95.9, 130.0)), HitZone::Outside);
+        assert_eq!(hit_test(&rect, Point::new(130.0, 160.0)), HitZone::Outside);
+    }
+
+    #[test]
+    fn handles_win_over_the_interior_of_small_selections() {
+        let rect = Rect::new(0.0, 0.0, 6.0, 6.0);
+        assert_eq!(handle_at(&rect, 3.0, 3.0), Some(Handle::TopLeft));
+        assert!(handle_at(&rect, 5.0, 5.0).is_some());
+        assert!((0..6)
+            .all(|x| (0..6)
+                .all(|y| hit_test(&rect, Point::new(x as f32, y as f32)) != HitZone::Inside)));
+    }
+
+    #[test]
+    fn cursors_match_the_zones() {
+        assert_eq!(HitZone::Outside.cursor(), CursorShape::Crosshair);
+        assert_eq!(HitZone::Inside.cursor(), CursorShape::Move);
+        assert_eq!(
+            HitZone::Handle(Handle::Bottom).cursor(),
+            CursorShape::ResizeNorthSouth
+        );
+        assert_eq!(
+            HitZone::Handle(Handle::Left).cursor(),
+            CursorShape::ResizeEastWest
+        );
+        assert_eq!(
+            HitZone::Handle(Handle::BottomRight).cursor(),
+            CursorShape::ResizeNorthWestSouthEast
+        );
+        assert_eq!(
+            HitZone::Handle(Handle::TopRight).cursor(),
+            CursorShape::ResizeNorthEastSouthWest
+        );
+    }
+
+    #[test]
+    fn only_a_finished_selection_is_hit() {
+        let mut model = SelectionModel::new();
+        assert_eq!(model.hit_test(Point::new(10.0, 10.0)), HitZone::Outside);
+        model.handle(down(10.0, 10.0));
+        model.handle(move_to(60.0, 30.0));
+        assert_eq!(model.hit_test(Point::new(30.0, 20.0)), HitZone::Outside);
+        model.handle(up(60.0, 30.0));
+        assert_eq!(model.hit_test(Point::new(30.0, 20.0)), HitZone::Inside);
+        assert_eq!(
+            model.hit_test(Point::new(60.0, 30.0)),
+            HitZone::Handle(Handle::BottomRight)
+        );
+    }
+
+    #[test]
+    fn handles_move_only_their_edges() {
+        let rect = Rect::new(100.0, 100.0, 200.0, 160.0);
+        let cases = [
+            (Handle::TopLeft, Rect::new(110.0, 105.0, 200.0, 160.0)),
+            (Handle::Top, Rect::new(100.0, 105.0, 200.0, 160.0)),
+            (Handle::TopRight, Rect::new(100.0, 105.0, 210.0, 160.0)),
+            (Handle::Right, Rect::new(100.0, 100.0, 210.0, 160.0)),
+            (Handle::BottomRight, Rect::new(100.0, 100.0, 210.0, 165.0)),
+            (Handle::Bottom, Rect::new(100.0, 100.0, 200.0, 165.0)),
+            (Handle::BottomLeft, Rect::new(110.0, 100.0, 200.0, 165.0)),
+            (Handle::Left, Rect::new(110.0, 100.0, 200.0, 160.0)),
+        ];
+        for (handle, expected) in cases {
+            assert_eq!(handle.resize(&rect, 10.0, 5.0), expected, "{:?}", handle);
+        }
+    }
+
+    #[test]
+    fn resizing_past_the_opposite_edge_flips() {
+        let rect = Rect::new(100.0, 100.0, 200.0, 160.0);
+        assert_eq!(
+            Handle::Left.resize(&rect, 150.0, 0.0),
+            Rect::new(200.0, 100.0, 250.0, 160.0)
+        );
+        assert_eq!(
+            Handle::Top.resize(&rect, 0.0, 80.0),
+            Rect::new(100.0, 160.0, 200.0, 180.0)
+        );
+
+        // Through the model: the bottom-right corner dragged above and left
+        // of the top-left one
+        let mut model = SelectionModel::new();
+        model.select(Some(rect));
+        model.handle(down(201.0, 161.0));
+        let update = model.handle(move_to(51.0, 61.0));
+        assert_eq!(update.selection, Some(Rect::new(50.0, 60.0, 100.0, 100.0)));
+        let update = model.handle(up(51.0, 61.0));
+        assert_eq!(update.selection, Some(Rect::new(50.0, 60.0, 100.0, 100.0)));
+        assert!(update.committed);
+        // The flipped selection has its handles in the new places
+        assert_eq!(
+            model.hit_test(Point::new(50.0, 60.0)),
+            HitZone::Handle(Handle::TopLeft)
+        );
+    }
+
+    #[test]
+    fn resizing_to_nothing_clears_the_selection() {
+        let mut model = SelectionModel::new();
+        model.select(Some(Rect::new(100.0, 100.0, 200.0, 160.0)));
+        model.handle(down(200.0, 130.0));
+        let update = model.handle(up(100.0, 130.0));
+        assert_eq!(update.selection, None);
+        assert!(!update.committed);
+    }
+
+    #[test]
+    fn arrow_keys_nudge_by_one_or_ten_with_shift() {
+        let mut model = selected();
+        let update = model.handle(key(Key::Right));
+        assert_eq!(update.selection, Some(Rect::new(11.0, 10.0, 61.0, 30.0)));
+        assert_eq!(update.redraw, Redraw::Area(Rect::new(4.0, 4.0, 67.0, 36.0)));
+        assert!(update.committed);
+
+        let update = model.handle(shifted(Key::Down));
+        assert_eq!(update.selection, Some(Rect::new(11.0, 20.0, 61.0, 40.0)));
+        assert!(update.committed);
+        model.handle(key(Key::Up));
+        model.handle(shifted(Key::Left));
+        assert_eq!(model.selection(), Some(Rect::new(1.0, 19.0, 51.0, 39.0)));
+
+        // Ctrl keeps the small step, letters do nothing
+        let update = model.handle(InputEvent::Key {
+            key: Key::Left,
+            modifiers: Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            },
+        });
+        assert_eq!(update.selection, Some(Rect::new(0.0, 19.0, 50.0, 39.0)));
+        let update = model.handle(key(Key::Char('a')));
+        assert_eq!(update.redraw, Redraw::None);
+        assert!(!update.committed);
+    }
+
+    #[test]
+    fn nothing_is_nudged_without_a_finished_selection() {
+        let mut model = SelectionModel::new();
+        assert_eq!(model.handle(key(Key::Right)).selection, None);
+        model.handle(down(10.0, 10.0));
+        model.handle(move_to(30.0, 30.0));
+        let update = model.handle(shifted(Key::Right));
+        assert_eq!(update.selection, Some(Rect::new(10.0, 10.0, 30.0, 30.0)));
+        assert!(!update.committed);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::annotation::Shape;
+use crate::capture::Frame;
+use crate::geometry::Rect;
+use crate::ocr::{OcrDocument, OcrEngine, OcrLine};
+use crate::redact::{Redaction, RedactionStyle};
+use crate::undo::{Document, Edit};
+use anyhow::{anyhow, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Pixels added around matched words so anti-aliased glyph edges are
+/// covered too.
+const REDACTION_PADDING: f32 = 2.0;
+
+/// Pattern for one kind of sensitive text.
+pub struct Rule {
+    name: String,
+    pattern: Regex,
+    /// Extra check on the matched text, e.g. a checksum.
+    validate: Option<fn(&str) -> bool>,
+}
+
+/// User-supplied rule as read from configuration.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSpec {
+    pub name: String,
+    pub pattern: String,
+}
+
+impl Rule {
+    pub fn new(name: &str, pattern: &str) -> Result<Self> {
+        let pattern =
+            Regex::new(pattern).map_err(|e| anyhow!("Invalid pattern for rule {}: {}", name, e))?;
+        Ok(Rule {
+            name: name.to_string(),
+            pattern,
+            validate: None,
+        })
+    }
+
+    pub fn from_spec(spec: &RuleSpec) -> Result<Self> {
+        Self::new(&spec.name, &spec.pattern)
+    }
+
+    fn validated(mut self, validate: fn(&str) -> bool) -> Self {
+        self.validate = Some(validate);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn matches<'a, 't: 'a>(&'a self, text: &'t str) -> impl Iterator<Item = regex::Match<'t>> + 'a {
+        self.pattern
+            .find_iter(text)
+            .filter(|m| self.validate.is_none_or(|validate| validate(m.as_str())))
+    }
+}
+
+/// E-mail addresses, IBANs, phone numbers, payment card numbers and
+/// common API key formats.
+pub fn builtin_rules() -> Vec<Rule> {
+    let rule = |name, pattern| Rule::new(name, pattern).expect("built-in pattern");
+    vec![
+        rule(
+            "email",
+            r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+        ),
+        rule(
+            "iban",
+            r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]{4}){2,7}(?: ?[A-Z0-9]{1,4})?\b",
+        )
+        .validated(iban_checksum_ok),
+        rule("card", r"\b\d(?:[ -]?\d){12,18}\b").validated(luhn_ok),
+        rule("phone", r"(?:\+|\b0)\d[\d /()-]{6,}\d\b"),
+        rule("aws-key", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+        rule("github-token", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+        rule("stripe-key", r"\b[sprk]k_(?:live|test)_[A-Za-z0-9]{16,}\b"),
+        rule("bearer-token", r"\bBearer [A-Za-z0-9._~+/-]{20,}=*"),
+    ]
+}
+
+/// ISO 13616 mod-97 check.
+fn iban_checksum_ok(text: &str) -> bool {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.len() < 15 {
+        return false;
+    }
+    let (head, tail) = compact.split_at(4);
+    let mut remainder = 0u32;
+    for c in tail.chars().chain(head.chars()) {
+        let value = match c.to_digit(36) {
+            Some(v) => v,
+            None => return false,
+        };
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+/// Luhn checksum used by payment cards.
+fn luhn_ok(text: &str) -> bool {
+    let digits: Vec<u32> = text.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2, d * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => d,
+        })
+        .sum();
+    digits.len() >= 13 && sum.is_multiple_of(10)
+}
+
+/// One match of a rule in the recognized text.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Finding {
+    pub rule: String,
+    pub text: String,
+    /// Union of the matched words, in frame pixels.
+    pub rect: Rect,
+}
+
+/// Everything a [`Detector`] found, without changing the capture.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub findings: Vec<Finding>,
+    /// Findings covering the whole page, as reported by engines without
+    /// word positions. They are listed but never redacted.
+    #[serde(default)]
+    pub unlocated: Vec<Finding>,
+}
+
+impl Report {
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty() && self.unlocated.is_empty()
+    }
+
+    /// One redaction per finding, slightly larger than the matched words.
+    pub fn redactions(&self, style: RedactionStyle) -> Vec<Redaction> {
+        self.findings
+            .iter()
+            .map(|f| Redaction::new(f.rect.inflate(REDACTION_PADDING), style))
+            .collect()
+    }
+
+    /// Single undoable edit adding all proposed redactions.
+    pub fn redaction_edit(&self, document: &Document, style: RedactionStyle) -> Edit {
+        let start = document.annotations.len();
+        Edit::Batch(
+            self.redactions(style)
+                .into_iter()
+                .enumerate()
+                .map(|(i, redaction)| Edit::AddAnnotation {
+                    index: start + i,
+                    shape: Shape::Redaction(redaction),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for Report {
+    /// One line per finding: rule, position and the matched text.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No sensitive data found");
+        }
+        for finding in &self.findings {
+            writeln!(
+                f,
+                "{:<14} {:>5},{:<5} {:>4}x{:<4} {}",
+                finding.rule,
+                finding.rect.left.round(),
+                finding.rect.top.round(),
+                finding.rect.width().round(),
+                finding.rect.height().round(),
+                finding.text
+            )?;
+        }
+        for finding in &self.unlocated {
+            writeln!(
+                f,
+                "{:<14} {:>17} {}",
+                finding.rule, "(no position)", finding.text
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `rect` spans all of a non-empty `page`.
+fn covers(rect: &Rect, page: &Rect) -> bool {
+    !page.is_empty()
+        && rect.left <= page.left
+        && rect.top <= page.top
+        && rect.right >= page.right
+        && rect.bottom >= page.bottom
+}
+
+/// Matches OCR output against a set of [`Rule`]s.
+///
+/// Rules run on whole lines, with words joined by single spaces, so
+/// values split across words such as `DE89 3704 0044` are still found.
+pub struct Detector {
+    rules: Vec<Rule>,
+}
+
+impl Default for Detector {
+    fn default() -> Self {
+        Detector {
+            rules: builtin_rules(),
+        }
+    }
+}
+
+impl Detector {
+    /// Detector without any rules.
+    pub fn empty() -> Self {
+        Detector { rules: Vec::new() }
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn add_pattern(&mut self, name: &str, pattern: &str) -> Result<&mut Self> {
+        Ok(self.add_rule(Rule::new(name, pattern)?))
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|r| r.name())
+    }
+
+    /// Dry run over an already recognized document.
+    pub fn scan(&self, document: &OcrDocument) -> Report {
+        let mut report = Report::default();
+        for page in &document.pages {
+            let mut findings = Vec::new();
+            for line in page.blocks.iter().flat_map(|b| &b.lines) {
+                self.scan_line(line, &mut findings);
+            }
+            // Redacting these would black out the whole capture
+            let (unlocated, located) = findings
+                .into_iter()
+                .partition::<Vec<_>, _>(|f| covers(&f.rect, &page.rect));
+            report.findings.extend(located);
+            report.unlocated.extend(unlocated);
+        }
+        report
+    }
+
+    /// Runs OCR on `frame` and scans the result.
+    pub fn detect(&self, engine: &dyn OcrEngine, frame: &Frame) -> Result<Report> {
+        Ok(self.scan(&engine.recognize(frame)?))
+    }
+
+    fn scan_line(&self, line: &OcrLine, findings: &mut Vec<Finding>) {
+        // Byte range of every word inside the joined line text
+        let mut text = String::new();
+        let mut spans = Vec::with_capacity(line.words.len());
+        for word in &line.words {
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            let start = text.len();
+            text.push_str(&word.text);
+            spans.push((start, text.len()));
+        }
+
+        // Earlier rules win, e.g. digits of an IBAN are not reported again
+        // as a phone number.
+        let mut claimed: Vec<(usize, usize)> = Vec::new();
+        for rule in &self.rules {
+            for found in rule.matches(&text) {
+                if claimed
+                    .iter()
+                    .any(|&(start, end)| start <= found.start() && found.end() <= end)
+                {
+                    continue;
+                }
+                claimed.push((found.start(), found.end()));
+                let rect = line
+                    .words
+                    .iter()
+                    .zip(&spans)
+                    .filter(|(_, &(start, end))| start < found.end() && found.start() < end)
+                    .map(|(word, _)| word.rect)
+                    .reduce(|a, b| a.union(&b));
+                if let Some(rect) = rect {
+                    findings.push(Finding {
+                        rule: rule.name.clone(),
+                        text: found.as_str().to_string(),
+                        rect,
+                    });
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::{OcrBlock, OcrPage, OcrWord};
+
+    const PAGE: Rect = Rect {
+        left: 0.0,
+        top: 0.0,
+        right: 800.0,
+        bottom: 200.0,
+    };
+
+    /// Line of 10px-wide-per-character words on row `y`, one space apart.
+    fn line(y: f32, text: &str) -> OcrLine {
+        let mut x = 10.0;
+        let words = text
+            .split(' ')
+            .map(|word| {
+                let width = word.len() as f32 * 10.0;
+                let rect = Rect::new(x, y, x + width, y + 20.0);
+                x += width + 10.0;
+                OcrWord {
+                    text: word.to_string(),
+                    rect,
+                    confidence: Some(0.9),
+                }
+            })
+            .collect();
+        OcrLine::new(words)
+    }
+
+    fn document(lines: Vec<OcrLine>) -> OcrDocument {
+        OcrDocument {
+            pages: vec![OcrPage::new(PAGE, vec![OcrBlock::new(lines)])],
+        }
+    }
+
+    #[test]
+    fn iban_split_across_words_is_one_finding() {
+        let report = Detector::default().scan(&document(vec![line(
+            10.0,
+            "IBAN: DE89 3704 0044 0532 0130 00 bitte",
+        )]));
+
+        assert_eq!(report.findings.len(), 1);
+        let finding = &report.findings[0];
+        assert_eq!(finding.rule, "iban");
+        assert_eq!(finding.text, "DE89 3704 0044 0532 0130 00");
+        // From the start of "DE89" to the end of "00"
+        assert_eq!(finding.rect, Rect::new(70.0, 10.0, 340.0, 30.0));
+        assert!(report.unlocated.is_empty());
+    }
+
+    #[test]
+    fn card_numbers_need_a_valid_luhn_checksum() {
+        let report = Detector::default().scan(&document(vec![
+            line(10.0, "Karte 4111 1111 1111 1111"),
+            line(40.0, "Karte 4111 1111 1111 1112"),
+        ]));
+
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].rule, "card");
+        assert_eq!(report.findings[0].text, "4111 1111 1111 1111");
+        assert_eq!(report.findings[0].rect.top, 10.0);
+    }
+
+    #[test]
+    fn custom_rules_run_after_the_builtin_ones() {
+        let mut detector = Detector::default();
+        detector.add_pattern("ticket", r"\bTICKET-\d+\b").unwrap();
+        let report = detector.scan(&document(vec![line(
+            10.0,
+            "see TICKET-4711 from max@example.com",
+        )]));
+
+        let rules: Vec<_> = report.findings.iter().map(|f| f.rule.as_str()).collect();
+        assert_eq!(rules, ["email", "ticket"]);
+        assert_eq!(report.findings[1].text, "TICKET-4711");
+        assert_eq!(report.findings[1].rect, Rect::new(50.0, 10.0, 160.0, 30.0));
+    }
+
+    #[test]
+    fn invalid_custom_patterns_are_rejected() {
+        let error = Detector::empty().add_pattern("broken", "(").err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid pattern for rule broken"));
+    }
+
+    #[test]
+    fn findings_covering_the_page_are_never_redacted() {
+        // Engines without word geometry report the page as every word's box
+        let mut words = line(10.0, "max@example.com").words;
+        words[0].rect = PAGE;
+        let report = Detector::default().scan(&document(vec![OcrLine::new(words)]));
+
+        assert!(report.findings.is_empty());
+        assert_eq!(report.unlocated.len(), 1);
+        assert_eq!(report.unlocated[0].rule, "email");
+        assert!(!report.is_empty());
+        assert!(report.redactions(RedactionStyle::blackout()).is_empty());
+        assert!(report.to_string().contains("(no position) max@example.com"));
+    }
+
+    #[test]
+    fn redactions_pad_the_matched_words() {
+        let report = Detector::default().scan(&document(vec![line(10.0, "max@example.com")]));
+        let redactions = report.redactions(RedactionStyle::blackout());
+
+        assert_eq!(redactions.len(), 1);
+        assert_eq!(
+            redactions[0].rect,
+            Rect::new(10.0, 10.0, 160.0, 30.0).inflate(REDACTION_PADDING)
+        );
+    }
+
+    #[test]
+    fn clean_text_gives_an_empty_report() {
+        let report = Detector::default().scan(&document(vec![line(10.0, "Nothing to see here")]));
+        assert!(report.is_empty());
+        assert_eq!(report.to_string(), "No sensitive data found\n");
+    }
+}
@@ -0,0 +1,400 @@
+use crate::capture::Frame;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// Rows two consecutive frames must share at least for a match.
+pub const DEFAULT_MIN_OVERLAP: u32 = 16;
+
+/// Share of overlapping rows that may differ, e.g. for a blinking caret.
+pub const DEFAULT_TOLERANCE: f32 = 0.02;
+
+/// FNV-1a hash of one row of pixel bytes.
+fn hash_row(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Parts of every frame left out when frames are matched.
+///
+/// `top` and `bottom` cover headers and footers that stay in place while
+/// the content scrolls; the stitched image shows them once, the header of
+/// the first frame at the top and the footer of the last at the bottom.
+/// `left` and `right` cover scroll bars and other columns that change on
+/// every scroll step; they are still copied into the result.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Margins {
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+/// What [`Stitcher::push`] did with a frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StitchStep {
+    /// First frame, taken as a whole.
+    Started,
+    /// The content scrolled by `rows`, which were appended.
+    Appended { rows: u32 },
+    /// Nothing scrolled; the frame was dropped.
+    Unchanged,
+}
+
+/// Hashes of the rows between the top and bottom margins, over the columns
+/// between the left and right margins.
+pub fn row_hashes(frame: &Frame, margins: &Margins) -> Vec<u64> {
+    let left = (margins.left.min(frame.width) * 4) as usize;
+    let right = (frame.width.saturating_sub(margins.right) * 4) as usize;
+    let bottom = frame.height.saturating_sub(margins.bottom);
+    (margins.top.min(bottom)..bottom)
+        .map(|y| hash_row(&frame.row(y)[left..right.max(left)]))
+        .collect()
+}
+
+/// Finds how many rows the content moved up from `previous` to `next`,
+/// given the row hashes of two equally tall frames.
+///
+/// The largest overlap of at least `min_overlap` rows wins in which at
+/// most `tolerance` of the rows differ; `Some(0)` means nothing moved.
+/// Overlaps made up only of identical rows, e.g. blank space, match at any
+/// offset, so the smallest offset is assumed for them.
+pub fn find_offset(
+    previous: &[u64],
+    next: &[u64],
+    min_overlap: u32,
+    tolerance: f32,
+) -> Option<u32> {
+    let height = previous.len().min(next.len());
+    let min_overlap = (min_overlap as usize).clamp(1, height.max(1));
+    (0..=height.checked_sub(min_overlap)?).find_map(|offset| {
+        let overlap = height - offset;
+        let allowed = (overlap as f32 * tolerance.max(0.0)) as usize;
+        let mismatches = previous[offset..offset + overlap]
+            .iter()
+            .zip(&next[..overlap])
+            .filter(|(a, b)| a != b)
+            .take(allowed + 1)
+            .count();
+        (mismatches <= allowed).then_some(offset as u32)
+    })
+}
+
+/// Glues frames of the same screen region, taken while it scrolls down,
+/// into one tall image without repeating rows.
+///
+/// Each frame is matched against the one before by hashing its rows, so
+/// only exact pixel matches count; [`Stitcher::set_tolerance`] allows a few
+/// rows to differ. Frames that cannot be matched are rejected and the
+/// next one is matched against the last accepted frame again.
+#[derive(Clone, Debug)]
+pub struct Stitcher {
+    min_overlap: u32,
+    tolerance: f32,
+    margins: Margins,
+    /// Stitched rows without the footer, tightly packed.
+    pixels: Vec<u8>,
+    rows: u32,
+    /// Where the first frame was taken and at which scale.
+    origin: (i32, i32),
+    scale: f32,
+    last: Option<Frame>,
+    hashes: Vec<u64>,
+}
+
+impl Default for Stitcher {
+    fn default() -> Self {
+        Stitcher {
+            min_overlap: DEFAULT_MIN_OVERLAP,
+            tolerance: DEFAULT_TOLERANCE,
+            margins: Margins::default(),
+            pixels: Vec::new(),
+            rows: 0,
+            origin: (0, 0),
+            scale: 1.0,
+            last: None,
+            hashes: Vec::new(),
+        }
+    }
+}
+
+impl Stitcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_min_overlap(&mut self, rows: u32) -> &mut Self {
+        self.min_overlap = rows.max(1);
+        self
+    }
+
+    pub fn set_tolerance(&mut self, tolerance: f32) -> &mut Self {
+        self.tolerance = tolerance.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Only takes effect for the next run, see [`Stitcher::reset`].
+    pub fn set_margins(&mut self, margins: Margins) -> &mut Self {
+        if self.last.is_none() {
+            self.margins = margins;
+        }
+        self
+    }
+
+    /// Height of the image stitched so far, footer included.
+    pub fn height(&self) -> u32 {
+        self.rows + self.last.as_ref().map_or(0, |frame| self.footer(frame))
+    }
+
+    /// Starts over, keeping the settings.
+    pub fn reset(&mut self) {
+        self.pixels.clear();
+        self.rows = 0;
+        self.last = None;
+        self.hashes.clear();
+    }
+
+    /// Adds the next frame; fails if its size differs from the first one or
+    /// it shares too little with the previous frame.
+    pub fn push(&mut self, frame: &Frame) -> Result<StitchStep> {
+        let Some(last) = &self.last else {
+            if frame.height <= self.margins.top + self.margins.bottom {
+                return Err(anyhow!(
+                    "Frame of {} rows leaves nothing between the margins",
+                    frame.height
+                ));
+            }
+            self.append(frame, 0, frame.height - self.margins.bottom);
+            self.hashes = row_hashes(frame, &self.margins);
+            self.origin = frame.origin;
+            self.scale = frame.scale;
+            self.last = Some(frame.clone());
+            return Ok(StitchStep::Started);
+        };
+
+        if (frame.width, frame.height) != (last.width, last.height) {
+            return Err(anyhow!(
+                "Frame of {}x{} does not match the first frame of {}x{}",
+                frame.width,
+                frame.height,
+                last.width,
+                last.height
+            ));
+        }
+
+        let hashes = row_hashes(frame, &self.margins);
+        let offset = find_offset(&self.hashes, &hashes, self.min_overlap, self.tolerance)
+            .ok_or_else(|| anyhow!("No overlap with the previous frame, scrolled too far?"))?;
+        if offset == 0 {
+            return Ok(StitchStep::Unchanged);
+        }
+
+        let end = frame.height - self.margins.bottom;
+        self.append(frame, end - offset, end);
+        self.hashes = hashes;
+        self.last = Some(frame.clone());
+        Ok(StitchStep::Appended { rows: offset })
+    }
+
+    /// The stitched image: the rows so far plus the footer of the last
+    /// frame. Placed where the first frame was taken.
+    pub fn finish(&self) -> Result<Frame> {
+        let Some(last) = &self.last else {
+            return Err(anyhow!("No frames to stitch"));
+        };
+
+        let mut pixels = self.pixels.clone();
+        for y in last.height - self.footer(last)..last.height {
+            pixels.extend_from_slice(last.row(y));
+        }
+        let out = Frame::from_rgba(last.width, self.height(), last.width as usize * 4, pixels)?;
+        Ok(out
+            .with_origin(self.origin.0, self.origin.1)
+            .with_scale(self.scale))
+    }
+
+    fn footer(&self, frame: &Frame) -> u32 {
+        self.margins.bottom.min(frame.height)
+    }
+
+    fn append(&mut self, frame: &Frame, from: u32, to: u32) {
+        for y in from..to {
+            self.pixels.extend_from_slice(frame.row(y));
+        }
+        self.rows += to - from;
+    }
+}
+
+/// Stitches `frames` in order with the default settings, skipping frames
+/// that did not scroll.
+pub fn stitch(frames: &[Frame]) -> Result<Frame> {
+    let mut stitcher = Stitcher::new();
+    for (index, frame) in frames.iter().enumerate() {
+        stitcher
+            .push(frame)
+            .map_err(|e| anyhow!("Frame {}: {}", index, e))?;
+    }
+    stitcher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 12;
+    const HEADER: u32 = 5;
+    const FOOTER: u32 = 4;
+    /// Content rows one frame shows.
+    const VIEW: u32 = 50;
+
+    /// A page whose rows all differ from each other.
+    fn page(height: u32) -> Frame {
+        let mut frame = Frame::new(WIDTH, height);
+        for y in 0..height {
+            for x in 0..WIDTH {
+                frame.put_pixel(x, y, [y as u8, (y * 7 + x) as u8, x as u8 * 20, 255]);
+            }
+        }
+        frame
+    }
+
+    /// What a window showing `content` scrolled down by `offset` rows
+    /// looks like, with a fixed header and footer when `chrome` is set and
+    /// a two pixel wide scroll bar whose thumb follows the offset.
+    fn window(content: &Frame, offset: u32, chrome: bool) -> Frame {
+        let (header, footer) = if chrome { (HEADER, FOOTER) } else { (0, 0) };
+        let mut frame = Frame::new(WIDTH, header + VIEW + footer);
+        for y in 0..frame.height {
+            for x in 0..WIDTH {
+                let pixel = if y < header {
+                    [200, 0, y as u8, 255]
+                } else if y >= header + VIEW {
+                    [0, 200, y as u8, 255]
+                } else if chrome && x >= WIDTH - 2 {
+                    let thumb = (header + offset / 4..header + offset / 4 + 10).contains(&y);
+                    if thumb {
+                        [90, 90, 90, 255]
+                    } else {
+                        [230, 230, 230, 255]
+                    }
+                } else {
+                    content.pixel(x, y - header + offset)
+                };
+                frame.put_pixel(x, y, pixel);
+            }
+        }
+        frame.with_origin(-30, 40).with_scale(1.5)
+    }
+
+    /// The offsets to take windows at: steps of at most 34 rows, so at
+    /// least 16 rows overlap, and one frame that did not scroll.
+    const OFFSETS: [u32; 7] = [0, 30, 30, 60, 94, 128, 150];
+
+    #[test]
+    fn stitch_rebuilds_the_scrolled_page() {
+        let content = page(200);
+        let frames: Vec<Frame> = OFFSETS
+            .iter()
+            .map(|&o| window(&content, o, false))
+            .collect();
+        let out = stitch(&frames).unwrap();
+        assert_eq!((out.width, out.height), (WIDTH, 200));
+        assert_eq!(out.pixels, content.pixels);
+        assert_eq!((out.origin, out.scale), ((-30, 40), 1.5));
+    }
+
+    #[test]
+    fn margins_keep_header_and_footer_once() {
+        let content = page(200);
+        let mut stitcher = Stitcher::new();
+        stitcher.set_margins(Margins {
+            top: HEADER,
+            bottom: FOOTER,
+            left: 0,
+            right: 2,
+        });
+        let steps: Vec<StitchStep> = OFFSETS
+            .iter()
+            .map(|&o| stitcher.push(&window(&content, o, true)).unwrap())
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                StitchStep::Started,
+                StitchStep::Appended { rows: 30 },
+                StitchStep::Unchanged,
+                StitchStep::Appended { rows: 30 },
+                StitchStep::Appended { rows: 34 },
+                StitchStep::Appended { rows: 34 },
+                StitchStep::Appended { rows: 22 },
+            ]
+        );
+        assert_eq!(stitcher.height(), HEADER + 200 + FOOTER);
+
+        let out = stitcher.finish().unwrap();
+        let first = window(&content, 0, true);
+        let last = window(&content, 150, true);
+        assert_eq!((out.width, out.height), (WIDTH, HEADER + 200 + FOOTER));
+        for y in 0..HEADER {
+            assert_eq!(out.row(y), first.row(y), "header row {}", y);
+        }
+        for y in 0..200 {
+            // The scroll bar columns come from whichever frame added the row
+            let columns = (WIDTH as usize - 2) * 4;
+            assert_eq!(
+                out.row(HEADER + y)[..columns],
+                content.row(y)[..columns],
+                "content row {}",
+                y
+            );
+        }
+        for y in 0..FOOTER {
+            let from = HEADER + VIEW + y;
+            assert_eq!(
+                out.row(HEADER + 200 + y),
+                last.row(from),
+                "footer row {}",
+                y
+            );
+        }
+    }
+
+    #[test]
+    fn unmatched_frames_are_rejected_and_skipped() {
+        let content = page(200);
+        let mut stitcher = Stitcher::new();
+        stitcher.push(&window(&content, 0, false)).unwrap();
+        let error = stitcher.push(&window(&content, 40, false)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No overlap with the previous frame, scrolled too far?"
+        );
+        // The next frame is matched against the last accepted one again
+        assert_eq!(
+            stitcher.push(&window(&content, 20, false)).unwrap(),
+            StitchStep::Appended { rows: 20 }
+        );
+
+        let error = stitcher.push(&page(10)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Frame of 12x10 does not match the first frame of 12x50"
+        );
+
+        let error = stitch(&[window(&content, 0, false), window(&content, 45, false)]).unwrap_err();
+        assert!(error.to_string().starts_with("Frame 1: No overlap"));
+        assert!(stitch(&[]).is_err());
+    }
+
+    #[test]
+    fn find_offset_tolerates_a_few_changed_rows() {
+        let previous: Vec<u64> = (0..40).collect();
+        let mut next: Vec<u64> = (10..50).collect();
+        assert_eq!(find_offset(&previous, &next, 16, 0.0), Some(10));
+        next[3] = 999;
+        assert_eq!(find_offset(&previous, &next, 16, 0.0), None);
+        assert_eq!(find_offset(&previous, &next, 16, 0.05), Some(10));
+        assert_eq!(find_offset(&previous, &previous, 16, 0.0), Some(0));
+    }
+}
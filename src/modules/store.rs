@@ -0,0 +1,717 @@
+use crate::annotation::{AnnotationKind, AnnotationLayer, Shape};
+use crate::capture::{CaptureBackend, FixtureBackend, Frame};
+use crate::export::Exporter;
+use crate::geometry::Rect;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Directory below the per-user data directory the history is kept in
+/// unless configured otherwise.
+pub const DEFAULT_HISTORY_DIR: &str = "history";
+
+/// Captures older than this are pruned unless configured otherwise.
+pub const DEFAULT_HISTORY_MAX_AGE_DAYS: u64 = 30;
+
+/// Megabytes the history may take unless configured otherwise.
+pub const DEFAULT_HISTORY_MAX_SIZE_MB: u64 = 1024;
+
+/// Longest edge of a thumbnail in pixels.
+pub const THUMBNAIL_SIZE: u32 = 256;
+
+const CATALOG_FILE: &str = "catalog.json";
+const CATALOG_VERSION: u32 = 1;
+const ORIGINAL_FILE: &str = "original.png";
+const THUMBNAIL_FILE: &str = "thumbnail.png";
+const ANNOTATIONS_FILE: &str = "annotations.json";
+
+/// Catalog record of one stored capture.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Also the name of the entry's directory.
+    pub id: String,
+    /// Seconds since the Unix epoch.
+    pub created: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Position on the virtual screen and scale factor of the capture.
+    pub origin: (i32, i32),
+    pub scale: f32,
+    /// Last selection, in pixels of the original.
+    pub selection: Option<Rect>,
+    /// Name of the monitor if only one was captured.
+    pub monitor: Option<String>,
+    /// Recognized text, if OCR was run.
+    pub ocr_text: Option<String>,
+    /// Number of annotations in the stored document.
+    pub annotations: usize,
+    /// Bytes taken by all files of the entry.
+    pub size: u64,
+}
+
+impl HistoryEntry {
+    pub fn created_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.created)
+    }
+
+    /// True if the OCR text contains every whitespace-separated term of
+    /// `query`, ignoring case.
+    pub fn matches(&self, query: &str) -> bool {
+        let Some(text) = &self.ocr_text else {
+            return false;
+        };
+        let text = text.to_lowercase();
+        query
+            .split_whitespace()
+            .all(|term| text.contains(&term.to_lowercase()))
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Catalog {
+    version: u32,
+    /// Oldest first.
+    entries: Vec<HistoryEntry>,
+}
+
+/// A capture to add to the history.
+#[derive(Clone, Debug)]
+pub struct NewCapture<'a> {
+    pub frame: &'a Frame,
+    pub selection: Option<Rect>,
+    pub monitor: Option<String>,
+    pub ocr_text: Option<String>,
+    pub annotations: Option<&'a AnnotationLayer>,
+    pub created: SystemTime,
+}
+
+impl<'a> NewCapture<'a> {
+    /// A capture of `frame` taken now, with nothing else known yet.
+    pub fn new(frame: &'a Frame) -> Self {
+        NewCapture {
+            frame,
+            selection: None,
+            monitor: None,
+            ocr_text: None,
+            annotations: None,
+            created: SystemTime::now(),
+        }
+    }
+}
+
+/// Limits [`HistoryStore::prune`] enforces; `None` means unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    pub max_age: Option<Duration>,
+    /// Bytes all entries may take together.
+    pub max_size: Option<u64>,
+    pub max_entries: Option<usize>,
+}
+
+/// Past captures on disk, one directory per capture with the original
+/// image, a thumbnail and the annotation document, indexed by a JSON
+/// catalog in the root directory.
+///
+/// Redactions are burned into the stored original and thumbnail, so
+/// nothing they cover is kept on disk.
+///
+/// The catalog is rewritten after every change; files of entries that
+/// are not in the catalog are ignored.
+#[derive(Debug)]
+pub struct HistoryStore {
+    root: PathBuf,
+    catalog: Catalog,
+}
+
+impl HistoryStore {
+    /// Opens the history in `root`, creating the directory if needed.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        fs::create_dir_all(&root)?;
+
+        let path = root.join(CATALOG_FILE);
+        let catalog = if path.exists() {
+            let catalog: Catalog = serde_json::from_str(&fs::read_to_string(&path)?)
+                .map_err(|e| anyhow!("Damaged catalog {}: {}", path.display(), e))?;
+            if catalog.version > CATALOG_VERSION {
+                return Err(anyhow!(
+                    "Catalog {} has unknown version {}",
+                    path.display(),
+                    catalog.version
+                ));
+            }
+            catalog
+        } else {
+            Catalog {
+                version: CATALOG_VERSION,
+                entries: Vec::new(),
+            }
+        };
+        Ok(HistoryStore { root, catalog })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// All entries, oldest first.
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.catalog.entries
+    }
+
+    /// All entries, newest first.
+    pub fn list(&self) -> Vec<&HistoryEntry> {
+        self.catalog.entries.iter().rev().collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&HistoryEntry> {
+        self.catalog.entries.iter().find(|e| e.id == id)
+    }
+
+    /// Entries whose OCR text matches `query`, newest first; see
+    /// [`HistoryEntry::matches`].
+    pub fn search(&self, query: &str) -> Vec<&HistoryEntry> {
+        self.list()
+            .into_iter()
+            .filter(|entry| entry.matches(query))
+            .collect()
+    }
+
+    /// Bytes taken by all entries.
+    pub fn size(&self) -> u64 {
+        self.catalog.entries.iter().map(|e| e.size).sum()
+    }
+
+    pub fn original_path(&self, id: &str) -> PathBuf {
+        self.root.join(id).join(ORIGINAL_FILE)
+    }
+
+    pub fn thumbnail_path(&self, id: &str) -> PathBuf {
+        self.root.join(id).join(THUMBNAIL_FILE)
+    }
+
+    /// Stores `capture` and returns its catalog entry.
+    pub fn add(&mut self, capture: &NewCapture) -> Result<HistoryEntry> {
+        let created = capture
+            .created
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+        let id = self.unused_id(created);
+        let directory = self.root.join(&id);
+        fs::create_dir_all(&directory)?;
+
+        let frame = capture.frame;
+        match capture.annotations.filter(|a| has_redactions(a)) {
+            Some(annotations) => {
+                let mut redacted = frame.clone();
+                redact(&mut redacted, annotations);
+                write_images(&directory, &redacted)?;
+            }
+            None => write_images(&directory, frame)?,
+        }
+
+        let mut entry = HistoryEntry {
+            id,
+            created,
+            width: frame.width,
+            height: frame.height,
+            origin: frame.origin,
+            scale: frame.scale,
+            selection: capture.selection,
+            monitor: capture.monitor.clone(),
+            ocr_text: capture.ocr_text.clone(),
+            annotations: 0,
+            size: 0,
+        };
+        if let Some(annotations) = capture.annotations {
+            self.write_annotations(&mut entry, annotations)?;
+        }
+        entry.size = directory_size(&directory)?;
+
+        self.catalog.entries.push(entry.clone());
+        self.save()?;
+        Ok(entry)
+    }
+
+    /// The original capture, placed where it was taken.
+    pub fn load_original(&self, id: &str) -> Result<Frame> {
+        let entry = self.entry(id)?;
+        FixtureBackend::new(self.original_path(id))
+            .set_origin(entry.origin.0, entry.origin.1)
+            .set_scale(entry.scale)
+            .capture()
+    }
+
+    pub fn load_thumbnail(&self, id: &str) -> Result<Frame> {
+        self.entry(id)?;
+        FixtureBackend::new(self.thumbnail_path(id)).capture()
+    }
+
+    /// The stored annotations, empty if none were stored.
+    pub fn load_annotations(&self, id: &str) -> Result<AnnotationLayer> {
+        self.entry(id)?;
+        let path = self.root.join(id).join(ANNOTATIONS_FILE);
+        if !path.exists() {
+            return Ok(AnnotationLayer::new());
+        }
+        AnnotationLayer::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn set_ocr_text(&mut self, id: &str, text: &str) -> Result<()> {
+        self.entry_mut(id)?.ocr_text = Some(text.to_string());
+        self.save()
+    }
+
+    /// Replaces the selection and annotations stored with a capture and
+    /// burns its redactions into the stored images. Redactions that were
+    /// stored before are already burned in and are not applied again, so
+    /// saving the same document twice does not blur it twice.
+    pub fn set_document(
+        &mut self,
+        id: &str,
+        selection: Option<Rect>,
+        annotations: &AnnotationLayer,
+    ) -> Result<()> {
+        let mut entry = self.entry(id)?.clone();
+        entry.selection = selection;
+        let stored: Vec<Shape> = self
+            .load_annotations(id)?
+            .iter()
+            .map(|a| a.to_shape())
+            .collect();
+        let mut added = AnnotationLayer::new();
+        for shape in annotations.iter().map(|a| a.to_shape()) {
+            if !stored.contains(&shape) {
+                added.push(shape.into_annotation());
+            }
+        }
+        if has_redactions(&added) {
+            let mut original = self.load_original(id)?;
+            redact(&mut original, &added);
+            write_images(&self.root.join(id), &original)?;
+        }
+        self.write_annotations(&mut entry, annotations)?;
+        entry.size = directory_size(&self.root.join(id))?;
+        *self.entry_mut(id)? = entry;
+        self.save()
+    }
+
+    /// Removes a capture with all its files; returns `false` if there was
+    /// no such entry.
+    pub fn delete(&mut self, id: &str) -> Result<bool> {
+        let Some(index) = self.catalog.entries.iter().position(|e| e.id == id) else {
+            return Ok(false);
+        };
+        self.catalog.entries.remove(index);
+        self.save()?;
+
+        let directory = self.root.join(id);
+        if directory.exists() {
+            fs::remove_dir_all(directory)?;
+        }
+        Ok(true)
+    }
+
+    /// Deletes entries older than the quota allows as of `now`, then the
+    /// oldest ones until the size and count fit. Returns the deleted ids.
+    pub fn prune(&mut self, quota: &Quota, now: SystemTime) -> Result<Vec<String>> {
+        let mut doomed: Vec<String> = Vec::new();
+        if let Some(max_age) = quota.max_age {
+            doomed.extend(
+                self.catalog
+                    .entries
+                    .iter()
+                    .filter(|e| {
+                        now.duration_since(e.created_at())
+                            .is_ok_and(|age| age > max_age)
+                    })
+                    .map(|e| e.id.clone()),
+            );
+        }
+
+        let mut kept: Vec<&HistoryEntry> = self
+            .catalog
+            .entries
+            .iter()
+            .filter(|e| !doomed.contains(&e.id))
+            .collect();
+        kept.sort_by_key(|e| e.created);
+        let mut size: u64 = kept.iter().map(|e| e.size).sum();
+        let mut oldest = kept.into_iter();
+        let mut count = self.catalog.entries.len() - doomed.len();
+        while quota.max_size.is_some_and(|max| size > max)
+            || quota.max_entries.is_some_and(|max| count > max)
+        {
+            let Some(entry) = oldest.next() else {
+                break;
+            };
+            size -= entry.size;
+            count -= 1;
+            doomed.push(entry.id.clone());
+        }
+
+        for id in &doomed {
+            self.delete(id)?;
+        }
+        Ok(doomed)
+    }
+
+    fn entry(&self, id: &str) -> Result<&HistoryEntry> {
+        self.get(id)
+            .ok_or_else(|| anyhow!("No capture {} in the history", id))
+    }
+
+    fn entry_mut(&mut self, id: &str) -> Result<&mut HistoryEntry> {
+        self.catalog
+            .entries
+            .iter_mut()
+            .find(|e| e.id == id)
+            .ok_or_else(|| anyhow!("No capture {} in the history", id))
+    }
+
+    /// `<seconds>-<n>`, counting up for captures within the same second.
+    fn unused_id(&self, created: u64) -> String {
+        (1..)
+            .map(|n| format!("{}-{:03}", created, n))
+            .find(|id| self.get(id).is_none() && !self.root.join(id).exists())
+            .expect("unbounded range")
+    }
+
+    fn write_annotations(
+        &self,
+        entry: &mut HistoryEntry,
+        annotations: &AnnotationLayer,
+    ) -> Result<()> {
+        let path = self.root.join(&entry.id).join(ANNOTATIONS_FILE);
+        if annotations.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        } else {
+            fs::write(path, annotations.to_json()?)?;
+        }
+        entry.annotations = annotations.len();
+        Ok(())
+    }
+
+    /// Writes the catalog next to the old one first, so a crash never
+    /// leaves a half-written catalog behind.
+    fn save(&self) -> Result<()> {
+        let path = self.root.join(CATALOG_FILE);
+        let temporary = path.with_extension("json.tmp");
+        fs::write(&temporary, serde_json::to_string_pretty(&self.catalog)?)?;
+        fs::rename(&temporary, &path)?;
+        Ok(())
+    }
+}
+
+fn has_redactions(annotations: &AnnotationLayer) -> bool {
+    annotations
+        .iter()
+        .any(|a| a.kind() == AnnotationKind::Redaction)
+}
+
+/// Destroys the pixels under every redaction in `annotations`.
+fn redact(frame: &mut Frame, annotations: &AnnotationLayer) {
+    for annotation in annotations.iter() {
+        if let Shape::Redaction(redaction) = annotation.to_shape() {
+            redaction.apply(frame);
+        }
+    }
+}
+
+fn write_images(directory: &Path, frame: &Frame) -> Result<()> {
+    let exporter = Exporter::default();
+    exporter.save(frame, &directory.join(ORIGINAL_FILE))?;
+    exporter.save(&thumbnail(frame), &directory.join(THUMBNAIL_FILE))
+}
+
+/// `frame` scaled down to fit [`THUMBNAIL_SIZE`]; small frames are kept.
+fn thumbnail(frame: &Frame) -> Frame {
+    let longest = frame.width.max(frame.height);
+    if longest <= THUMBNAIL_SIZE {
+        return frame.clone();
+    }
+    let factor = THUMBNAIL_SIZE as f32 / longest as f32;
+    let width = ((frame.width as f32 * factor).round() as u32).max(1);
+    let height = ((frame.height as f32 * factor).round() as u32).max(1);
+    Frame::from_image(image::imageops::thumbnail(&frame.to_image(), width, height))
+}
+
+fn directory_size(directory: &Path) -> Result<u64> {
+    let mut size = 0;
+    for file in fs::read_dir(directory)? {
+        let metadata = file?.metadata()?;
+        if metadata.is_file() {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::{Redaction, RedactionStyle};
+
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    /// Empty directory for one test, removed again when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("store_{}_{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            TempDir(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn white_frame(width: u32, height: u32) -> Frame {
+        let mut frame = Frame::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                frame.put_pixel(x, y, WHITE);
+            }
+        }
+        frame
+    }
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    fn add(store: &mut HistoryStore, created: u64, text: Option<&str>) -> HistoryEntry {
+        store
+            .add(&NewCapture {
+                ocr_text: text.map(str::to_string),
+                created: at(created),
+                ..NewCapture::new(&white_frame(20, 10))
+            })
+            .unwrap()
+    }
+
+    fn blackout(rect: Rect) -> AnnotationLayer {
+        let mut annotations = AnnotationLayer::new();
+        annotations.push(
+            Shape::Redaction(Redaction::new(rect, RedactionStyle::blackout())).into_annotation(),
+        );
+        annotations
+    }
+
+    #[test]
+    fn added_captures_survive_reopening() {
+        let dir = TempDir::new("reopen");
+        let frame = white_frame(600, 300).with_origin(-1920, 0).with_scale(1.5);
+        let id = {
+            let mut store = HistoryStore::open(&dir.0).unwrap();
+            let entry = store
+                .add(&NewCapture {
+                    selection: Some(Rect::new(1.0, 2.0, 3.0, 4.0)),
+                    monitor: Some("DISPLAY2".to_string()),
+                    created: at(1_000),
+                    ..NewCapture::new(&frame)
+                })
+                .unwrap();
+            assert_eq!(entry.id, "1000-001");
+            assert!(entry.size > 0);
+            entry.id
+        };
+
+        let store = HistoryStore::open(&dir.0).unwrap();
+        let entry = store.get(&id).unwrap();
+        assert_eq!((entry.width, entry.height), (600, 300));
+        assert_eq!(entry.monitor.as_deref(), Some("DISPLAY2"));
+        assert_eq!(entry.selection, Some(Rect::new(1.0, 2.0, 3.0, 4.0)));
+
+        let original = store.load_original(&id).unwrap();
+        assert_eq!(original.pixels, frame.pixels);
+        assert_eq!(original.origin, (-1920, 0));
+        assert_eq!(original.scale, 1.5);
+        let thumbnail = store.load_thumbnail(&id).unwrap();
+        assert_eq!((thumbnail.width, thumbnail.height), (THUMBNAIL_SIZE, 128));
+    }
+
+    #[test]
+    fn captures_within_one_second_get_distinct_ids() {
+        let dir = TempDir::new("ids");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let first = add(&mut store, 5, None);
+        let second = add(&mut store, 5, None);
+        assert_eq!((first.id.as_str(), second.id.as_str()), ("5-001", "5-002"));
+        let listed: Vec<_> = store.list().iter().map(|e| e.id.as_str()).collect();
+        assert_eq!(listed, ["5-002", "5-001"]);
+    }
+
+    #[test]
+    fn search_needs_every_term_ignoring_case() {
+        let dir = TempDir::new("search");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let invoice = add(&mut store, 1, Some("Invoice 2024 total 99 EUR"));
+        add(&mut store, 2, Some("Meeting notes"));
+        add(&mut store, 3, None);
+        let reminder = add(&mut store, 4, Some("Reminder: invoice overdue"));
+
+        let found =
+            |query| -> Vec<String> { store.search(query).iter().map(|e| e.id.clone()).collect() };
+        assert_eq!(found("INVOICE"), [reminder.id.as_str(), &invoice.id]);
+        assert_eq!(found("invoice total"), [invoice.id.as_str()]);
+        assert!(found("invoice minutes").is_empty());
+    }
+
+    #[test]
+    fn delete_removes_entry_and_files() {
+        let dir = TempDir::new("delete");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let entry = add(&mut store, 1, None);
+        assert!(store.original_path(&entry.id).exists());
+
+        assert!(store.delete(&entry.id).unwrap());
+        assert!(!dir.0.join(&entry.id).exists());
+        assert!(store.get(&entry.id).is_none());
+        assert!(!store.delete(&entry.id).unwrap());
+        assert!(HistoryStore::open(&dir.0).unwrap().entries().is_empty());
+        assert!(store.load_original(&entry.id).is_err());
+    }
+
+    #[test]
+    fn prune_drops_old_entries_then_the_oldest_beyond_the_limits() {
+        let dir = TempDir::new("prune");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let ids: Vec<String> = (1..=5).map(|i| add(&mut store, i * 100, None).id).collect();
+        let size = store.get(&ids[0]).unwrap().size;
+
+        // Nothing to do without limits
+        assert!(store
+            .prune(&Quota::default(), at(10_000))
+            .unwrap()
+            .is_empty());
+
+        let age = Quota {
+            max_age: Some(Duration::from_secs(250)),
+            ..Quota::default()
+        };
+        assert_eq!(store.prune(&age, at(400)).unwrap(), [ids[0].clone()]);
+
+        let count = Quota {
+            max_entries: Some(3),
+            ..Quota::default()
+        };
+        assert_eq!(store.prune(&count, at(500)).unwrap(), [ids[1].clone()]);
+
+        let bytes = Quota {
+            max_size: Some(size * 2),
+            ..Quota::default()
+        };
+        assert_eq!(store.prune(&bytes, at(500)).unwrap(), [ids[2].clone()]);
+        let kept: Vec<_> = store.entries().iter().map(|e| e.id.clone()).collect();
+        assert_eq!(kept, ids[3..]);
+        assert!(!dir.0.join(&ids[2]).exists());
+    }
+
+    #[test]
+    fn redactions_are_burned_into_the_stored_images() {
+        let dir = TempDir::new("redact");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let frame = white_frame(20, 10);
+        let annotations = blackout(Rect::new(0.0, 0.0, 5.0, 5.0));
+        let entry = store
+            .add(&NewCapture {
+                annotations: Some(&annotations),
+                ..NewCapture::new(&frame)
+            })
+            .unwrap();
+        assert_eq!(entry.annotations, 1);
+        let original = store.load_original(&entry.id).unwrap();
+        assert_eq!(original.pixel(2, 2), BLACK);
+        assert_eq!(original.pixel(10, 2), WHITE);
+        assert_eq!(store.load_thumbnail(&entry.id).unwrap().pixel(2, 2), BLACK);
+        assert_eq!(store.load_annotations(&entry.id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn redactions_added_later_are_burned_in_and_stay_after_undo() {
+        let dir = TempDir::new("later");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let entry = add(&mut store, 1, None);
+
+        let annotations = blackout(Rect::new(10.0, 0.0, 20.0, 10.0));
+        store.set_document(&entry.id, None, &annotations).unwrap();
+        assert_eq!(store.load_original(&entry.id).unwrap().pixel(15, 5), BLACK);
+        assert_eq!(store.get(&entry.id).unwrap().annotations, 1);
+
+        // Removing the redaction again must not bring the content back
+        store
+            .set_document(&entry.id, None, &AnnotationLayer::new())
+            .unwrap();
+        assert_eq!(store.load_original(&entry.id).unwrap().pixel(15, 5), BLACK);
+        assert_eq!(store.get(&entry.id).unwrap().annotations, 0);
+        assert!(store.load_annotations(&entry.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn saving_the_same_redactions_again_leaves_the_images_alone() {
+        let dir = TempDir::new("resave");
+        let mut store = HistoryStore::open(&dir.0).unwrap();
+        let mut frame = white_frame(20, 10);
+        for y in 0..10 {
+            frame.put_pixel(10, y, BLACK);
+        }
+        let entry = store.add(&NewCapture::new(&frame)).unwrap();
+
+        let mut annotations = AnnotationLayer::new();
+        annotations.push(
+            Shape::Redaction(Redaction::new(
+                Rect::new(5.0, 0.0, 15.0, 10.0),
+                RedactionStyle::blur(),
+            ))
+            .into_annotation(),
+        );
+        store.set_document(&entry.id, None, &annotations).unwrap();
+        let blurred = store.load_original(&entry.id).unwrap();
+        assert_ne!(blurred.pixel(10, 5), BLACK);
+
+        let selection = Some(Rect::new(1.0, 1.0, 4.0, 4.0));
+        store
+            .set_document(&entry.id, selection, &annotations)
+            .unwrap();
+        assert_eq!(store.load_original(&entry.id).unwrap(), blurred);
+        assert_eq!(store.get(&entry.id).unwrap().selection, selection);
+
+        // Another redaction is still burned in
+        annotations.push(
+            Shape::Redaction(Redaction::new(
+                Rect::new(0.0, 0.0, 2.0, 2.0),
+                RedactionStyle::blackout(),
+            ))
+            .into_annotation(),
+        );
+        store.set_document(&entry.id, None, &annotations).unwrap();
+        let original = store.load_original(&entry.id).unwrap();
+        assert_eq!(original.pixel(1, 1), BLACK);
+        assert_eq!(original.pixel(10, 5), blurred.pixel(10, 5));
+    }
+
+    #[test]
+    fn newer_catalog_versions_are_refused() {
+        let dir = TempDir::new("version");
+        fs::create_dir_all(&dir.0).unwrap();
+        fs::write(
+            dir.0.join(CATALOG_FILE),
+            r#"{"version": 99, "entries": []}"#,
+        )
+        .unwrap();
+        let error = HistoryStore::open(&dir.0).unwrap_err().to_string();
+        assert!(error.ends_with("has unknown version 99"), "{}", error);
+    }
+}
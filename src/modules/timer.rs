@@ -0,0 +1,548 @@
+use crate::annotation::{Canvas, Color, Stroke};
+use crate::geometry::{Point, Rect};
+use crate::raster::{fill_text, RasterCanvas};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Delays offered for delayed captures, cycled through in this order.
+pub const DELAY_PRESETS: [Duration; 3] = [
+    Duration::from_secs(3),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Diameter of the countdown badge and height of its digits, in pixels.
+const BADGE_SIZE: f32 = 160.0;
+const DIGIT_SIZE: f32 = 64.0;
+
+/// Monotonic time source of a [`CaptureTimer`].
+pub trait Clock: Send {
+    /// Time elapsed since an arbitrary but fixed starting point.
+    fn now(&self) -> Duration;
+}
+
+/// Wall-clock time since the clock was created.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+/// Clock that only moves when told to; clones share the same time, so a
+/// copy can be kept to drive a timer that owns the other one.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<Duration>>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+
+    pub fn set(&self, now: Duration) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimerMode {
+    /// One capture after `delay`.
+    Delayed { delay: Duration },
+    /// A capture every `period`, stopping after `limit` captures if set.
+    Interval {
+        period: Duration,
+        limit: Option<u32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimerEvent {
+    /// Whole seconds left until the next capture changed.
+    Countdown { seconds: u32 },
+    /// Time to capture; `index` counts captures of this run from 1.
+    Capture { index: u32 },
+    /// The run is over, either done or cancelled.
+    Finished,
+}
+
+/// Schedules delayed and interval captures without touching any window.
+///
+/// The caller polls the timer whenever it wakes up, at the latest after
+/// [`CaptureTimer::next_wakeup`], and acts on the returned events.
+/// Interval captures stay on the grid set by the start time; periods
+/// missed because the timer was polled late are skipped, not caught up.
+pub struct CaptureTimer {
+    clock: Box<dyn Clock>,
+    mode: Option<TimerMode>,
+    started: Duration,
+    /// Captures taken in this run.
+    captured: u32,
+    /// Periods of an interval run that have passed, taken or skipped.
+    periods: u32,
+    /// Last countdown value handed out.
+    shown: Option<u32>,
+}
+
+impl CaptureTimer {
+    pub fn new(clock: Box<dyn Clock>) -> Self {
+        CaptureTimer {
+            clock,
+            mode: None,
+            started: Duration::ZERO,
+            captured: 0,
+            periods: 0,
+            shown: None,
+        }
+    }
+
+    /// Starts a new run, replacing one in progress.
+    pub fn start(&mut self, mode: TimerMode) -> Result<()> {
+        match mode {
+            TimerMode::Interval { period, .. } if period.is_zero() => {
+                return Err(anyhow!("Capture interval must not be zero"))
+            }
+            TimerMode::Interval { limit: Some(0), .. } => {
+                return Err(anyhow!("Capture limit must not be zero"))
+            }
+            _ => {}
+        }
+        self.mode = Some(mode);
+        self.started = self.clock.now();
+        self.captured = 0;
+        self.periods = 0;
+        self.shown = None;
+        Ok(())
+    }
+
+    /// Stops the current run; returns `false` if none was running.
+    pub fn cancel(&mut self) -> bool {
+        self.shown = None;
+        self.mode.take().is_some()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    pub fn mode(&self) -> Option<TimerMode> {
+        self.mode
+    }
+
+    /// Time left until the next capture.
+    pub fn remaining(&self) -> Option<Duration> {
+        let due = match self.mode? {
+            TimerMode::Delayed { delay } => delay,
+            TimerMode::Interval { period, .. } => period * (self.periods + 1),
+        };
+        Some(due.saturating_sub(self.elapsed()))
+    }
+
+    /// Seconds left until the next capture, rounded up.
+    pub fn countdown(&self) -> Option<u32> {
+        self.remaining()
+            .map(|remaining| remaining.as_secs_f64().ceil() as u32)
+    }
+
+    /// How long the caller may sleep before the next event is due.
+    pub fn next_wakeup(&self) -> Option<Duration> {
+        let remaining = self.remaining()?;
+        let seconds = self.countdown()?;
+        // Until the countdown drops to the next lower whole second
+        let tick = remaining.saturating_sub(Duration::from_secs(seconds.saturating_sub(1) as u64));
+        Some(tick.min(remaining))
+    }
+
+    /// Returns the events that became due since the last poll.
+    pub fn poll(&mut self) -> Vec<TimerEvent> {
+        let mut events = Vec::new();
+        let Some(mode) = self.mode else {
+            return events;
+        };
+
+        if self.remaining().is_some_and(|r| r.is_zero()) {
+            self.captured += 1;
+            self.shown = None;
+            events.push(TimerEvent::Capture {
+                index: self.captured,
+            });
+
+            let done = match mode {
+                TimerMode::Delayed { .. } => true,
+                TimerMode::Interval { period, limit } => {
+                    let passed = (self.elapsed().as_nanos() / period.as_nanos()) as u32;
+                    self.periods = passed.max(self.periods + 1);
+                    limit.is_some_and(|limit| self.captured >= limit)
+                }
+            };
+            if done {
+                self.cancel();
+                events.push(TimerEvent::Finished);
+                return events;
+            }
+        }
+
+        if let Some(seconds) = self.countdown() {
+            if self.shown != Some(seconds) {
+                self.shown = Some(seconds);
+                events.push(TimerEvent::Countdown { seconds });
+            }
+        }
+        events
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_sub(self.started)
+    }
+}
+
+/// User choices for timed captures.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimerSettings {
+    pub delay: Duration,
+    pub interval: Duration,
+    /// Captures per interval run, unlimited if `None`.
+    pub limit: Option<u32>,
+    pub sequence: Sequence,
+}
+
+impl Default for TimerSettings {
+    fn default() -> Self {
+        TimerSettings {
+            delay: DELAY_PRESETS[0],
+            interval: DEFAULT_INTERVAL,
+            limit: None,
+            sequence: Sequence::new("captures", "capture"),
+        }
+    }
+}
+
+impl TimerSettings {
+    /// Switches to the next of [`DELAY_PRESETS`], starting over after the
+    /// last one.
+    pub fn cycle_delay(&mut self) -> Duration {
+        self.delay = DELAY_PRESETS
+            .iter()
+            .copied()
+            .find(|&preset| preset > self.delay)
+            .unwrap_or(DELAY_PRESETS[0]);
+        self.delay
+    }
+
+    pub fn delayed(&self) -> TimerMode {
+        TimerMode::Delayed { delay: self.delay }
+    }
+
+    pub fn interval(&self) -> TimerMode {
+        TimerMode::Interval {
+            period: self.interval,
+            limit: self.limit,
+        }
+    }
+}
+
+/// File names for the numbered captures of an interval run, e.g.
+/// `captures/capture-0001.png`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence {
+    directory: PathBuf,
+    prefix: String,
+    extension: String,
+}
+
+impl Sequence {
+    pub fn new(directory: impl AsRef<Path>, prefix: &str) -> Self {
+        Sequence {
+            directory: directory.as_ref().to_path_buf(),
+            prefix: prefix.to_string(),
+            extension: "png".to_string(),
+        }
+    }
+
+    pub fn set_extension(&mut self, extension: &str) -> &mut Self {
+        self.extension = extension.trim_start_matches('.').to_string();
+        self
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn path(&self, index: u32) -> PathBuf {
+        self.directory
+            .join(format!("{}-{:04}.{}", self.prefix, index, self.extension))
+    }
+}
+
+/// Draws the seconds left as a round badge in the middle of `bounds`.
+pub fn draw_countdown(canvas: &mut dyn Canvas, bounds: &Rect, seconds: u32) {
+    let center = Point::new(
+        (bounds.left + bounds.right) / 2.0,
+        (bounds.top + bounds.bottom) / 2.0,
+    );
+    let half = BADGE_SIZE / 2.0;
+    let badge = Rect::new(
+        center.x - half,
+        center.y - half,
+        center.x + half,
+        center.y + half,
+    );
+    canvas.fill_ellipse(&badge, Color::rgb(32, 32, 32), 0.85);
+    canvas.stroke_ellipse(&badge, &Stroke::new(Color::WHITE, 3.0, 1.0));
+
+    let text = seconds.to_string();
+    let (width, height) = RasterCanvas::text_size(&text, DIGIT_SIZE);
+    // The glyph cell has one empty column on the right; center the ink
+    let origin = Point::new(
+        (center.x - width / 2.0 + DIGIT_SIZE / 16.0).round(),
+        (center.y - height / 2.0 + DIGIT_SIZE / 16.0).round(),
+    );
+    fill_text(canvas, origin, &text, DIGIT_SIZE, Color::WHITE, 1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer() -> (CaptureTimer, ManualClock) {
+        let clock = ManualClock::new();
+        (CaptureTimer::new(Box::new(clock.clone())), clock)
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn delayed_capture_counts_down_whole_seconds() {
+        let (mut timer, clock) = timer();
+        timer
+            .start(TimerMode::Delayed {
+                delay: Duration::from_secs(3),
+            })
+            .unwrap();
+        assert_eq!(timer.poll(), [TimerEvent::Countdown { seconds: 3 }]);
+        assert_eq!(timer.next_wakeup(), Some(ms(1000)));
+
+        clock.advance(ms(500));
+        assert!(timer.poll().is_empty());
+        assert_eq!(timer.next_wakeup(), Some(ms(500)));
+
+        clock.advance(ms(500));
+        assert_eq!(timer.poll(), [TimerEvent::Countdown { seconds: 2 }]);
+        clock.advance(ms(1999));
+        assert_eq!(timer.poll(), [TimerEvent::Countdown { seconds: 1 }]);
+        assert_eq!(timer.remaining(), Some(ms(1)));
+        assert_eq!(timer.next_wakeup(), Some(ms(1)));
+
+        clock.advance(ms(1));
+        assert_eq!(
+            timer.poll(),
+            [TimerEvent::Capture { index: 1 }, TimerEvent::Finished]
+        );
+        assert!(!timer.is_running());
+        assert_eq!(timer.next_wakeup(), None);
+        clock.advance(ms(5000));
+        assert!(timer.poll().is_empty());
+    }
+
+    #[test]
+    fn interval_stops_at_its_limit() {
+        let (mut timer, clock) = timer();
+        let sequence = Sequence::new("captures", "shot");
+        timer
+            .start(TimerMode::Interval {
+                period: Duration::from_secs(2),
+                limit: Some(3),
+            })
+            .unwrap();
+        assert_eq!(timer.poll(), [TimerEvent::Countdown { seconds: 2 }]);
+
+        let mut paths = Vec::new();
+        for _ in 0..5 {
+            clock.advance(Duration::from_secs(2));
+            for event in timer.poll() {
+                if let TimerEvent::Capture { index } = event {
+                    paths.push(sequence.path(index));
+                }
+            }
+        }
+        assert_eq!(
+            paths,
+            [
+                Path::new("captures/shot-0001.png"),
+                Path::new("captures/shot-0002.png"),
+                Path::new("captures/shot-0003.png"),
+            ]
+        );
+        assert!(!timer.is_running());
+    }
+
+    #[test]
+    fn interval_events_between_captures() {
+        let (mut timer, clock) = timer();
+        timer
+            .start(TimerMode::Interval {
+                period: Duration::from_secs(2),
+                limit: Some(2),
+            })
+            .unwrap();
+        timer.poll();
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(
+            timer.poll(),
+            [
+                TimerEvent::Capture { index: 1 },
+                TimerEvent::Countdown { seconds: 2 }
+            ]
+        );
+        clock.advance(ms(1000));
+        assert_eq!(timer.poll(), [TimerEvent::Countdown { seconds: 1 }]);
+        clock.advance(ms(1000));
+        assert_eq!(
+            timer.poll(),
+            [TimerEvent::Capture { index: 2 }, TimerEvent::Finished]
+        );
+    }
+
+    #[test]
+    fn late_polls_skip_missed_periods_and_stay_on_the_grid() {
+        let (mut timer, clock) = timer();
+        timer
+            .start(TimerMode::Interval {
+                period: Duration::from_secs(1),
+                limit: None,
+            })
+            .unwrap();
+        clock.advance(ms(3500));
+        assert_eq!(
+            timer.poll(),
+            [
+                TimerEvent::Capture { index: 1 },
+                TimerEvent::Countdown { seconds: 1 }
+            ]
+        );
+        assert_eq!(timer.remaining(), Some(ms(500)));
+        clock.advance(ms(500));
+        assert_eq!(timer.poll()[0], TimerEvent::Capture { index: 2 });
+        assert!(timer.is_running());
+    }
+
+    #[test]
+    fn invalid_intervals_are_refused_and_runs_restart() {
+        let (mut timer, clock) = timer();
+        let error = timer
+            .start(TimerMode::Interval {
+                period: Duration::ZERO,
+                limit: None,
+            })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Capture interval must not be zero");
+        let error = timer
+            .start(TimerMode::Interval {
+                period: Duration::from_secs(1),
+                limit: Some(0),
+            })
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Capture limit must not be zero");
+        assert!(!timer.is_running());
+        assert!(!timer.cancel());
+
+        let mode = TimerMode::Interval {
+            period: Duration::from_secs(1),
+            limit: None,
+        };
+        timer.start(mode).unwrap();
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(timer.poll()[0], TimerEvent::Capture { index: 1 });
+
+        // Starting again counts from the new start time and from 1
+        clock.advance(ms(300));
+        timer.start(mode).unwrap();
+        assert_eq!(timer.mode(), Some(mode));
+        assert_eq!(timer.remaining(), Some(Duration::from_secs(1)));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(timer.poll()[0], TimerEvent::Capture { index: 1 });
+
+        assert!(timer.cancel());
+        clock.advance(Duration::from_secs(5));
+        assert!(timer.poll().is_empty());
+    }
+
+    #[test]
+    fn settings_cycle_through_the_delay_presets() {
+        let mut settings = TimerSettings::default();
+        let cycled: Vec<Duration> = (0..4).map(|_| settings.cycle_delay()).collect();
+        assert_eq!(
+            cycled,
+            [
+                Duration::from_secs(5),
+                Duration::from_secs(10),
+                Duration::from_secs(3),
+                Duration::from_secs(5),
+            ]
+        );
+        // A delay between the presets moves to the next larger one
+        settings.delay = Duration::from_secs(4);
+        assert_eq!(settings.cycle_delay(), Duration::from_secs(5));
+        assert_eq!(
+            settings.delayed(),
+            TimerMode::Delayed {
+                delay: Duration::from_secs(5)
+            }
+        );
+
+        settings.limit = Some(10);
+        assert_eq!(
+            settings.interval(),
+            TimerMode::Interval {
+                period: DEFAULT_INTERVAL,
+                limit: Some(10)
+            }
+        );
+    }
+
+    #[test]
+    fn sequence_numbers_files_in_its_directory() {
+        let mut sequence = Sequence::new("out/timed", "capture");
+        assert_eq!(sequence.directory(), Path::new("out/timed"));
+        assert_eq!(
+            sequence.path(1),
+            Path::new("out/timed").join("capture-0001.png")
+        );
+        sequence.set_extension(".jpg");
+        assert_eq!(
+            sequence.path(42),
+            Path::new("out/timed").join("capture-0042.jpg")
+        );
+        assert_eq!(
+            sequence.path(12345),
+            Path::new("out/timed").join("capture-12345.jpg")
+        );
+    }
+}
@@ -0,0 +1,683 @@
+use crate::annotation::{AnnotationLayer, Color, Shape};
+use crate::capture::Frame;
+use crate::geometry::Rect;
+use crate::lasso::{crop_shape, SelectionShape};
+use crate::preprocess::PreprocessStep;
+use anyhow::{anyhow, Result};
+use std::collections::VecDeque;
+
+/// Undo steps kept unless configured otherwise.
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// Everything the user can edit on one capture.
+#[derive(Clone, Debug, Default)]
+pub struct Document {
+    pub frame: Option<Frame>,
+    /// Last committed selection, in frame pixels.
+    pub selection: Option<Rect>,
+    pub annotations: AnnotationLayer,
+}
+
+impl Document {
+    pub fn new(frame: Frame) -> Self {
+        Document {
+            frame: Some(frame),
+            selection: None,
+            annotations: AnnotationLayer::new(),
+        }
+    }
+}
+
+/// One invertible change to a [`Document`].
+#[derive(Clone, Debug)]
+pub enum Edit {
+    SetSelection {
+        before: Option<Rect>,
+        after: Option<Rect>,
+    },
+    AddAnnotation {
+        index: usize,
+        shape: Shape,
+    },
+    RemoveAnnotation {
+        index: usize,
+        shape: Shape,
+    },
+    MoveAnnotation {
+        index: usize,
+        dx: f32,
+        dy: f32,
+    },
+    /// Any other change to one annotation, e.g. its stroke.
+    ReplaceAnnotation {
+        index: usize,
+        before: Shape,
+        after: Shape,
+    },
+    /// Swaps the image, e.g. for a crop or a filter. Annotations are moved
+    /// by `(dx, dy)` so they stay on the same content.
+    ReplaceFrame {
+        before: Frame,
+        after: Frame,
+        dx: f32,
+        dy: f32,
+    },
+    /// Several edits undone and redone as one step.
+    Batch(Vec<Edit>),
+}
+
+impl Edit {
+    /// Adds `shape` on top of the existing annotations.
+    pub fn add_annotation(document: &Document, shape: Shape) -> Edit {
+        Edit::AddAnnotation {
+            index: document.annotations.len(),
+            shape,
+        }
+    }
+
+    pub fn remove_annotation(document: &Document, index: usize) -> Result<Edit> {
+        let shape = document
+            .annotations
+            .get(index)
+            .ok_or_else(|| anyhow!("No annotation at index {}", index))?
+            .to_shape();
+        Ok(Edit::RemoveAnnotation { index, shape })
+    }
+
+    /// Crops the frame to `rect` and clears the selection.
+    pub fn crop(document: &Document, rect: &Rect) -> Result<Edit> {
+        let before = document
+            .frame
+            .clone()
+            .ok_or_else(|| anyhow!("Nothing captured to crop"))?;
+        let after = before.crop_rect(rect)?;
+        let dx = (before.origin.0 - after.origin.0) as f32;
+        let dy = (before.origin.1 - after.origin.1) as f32;
+
+        Ok(Edit::Batch(vec![
+            Edit::SetSelection {
+                before: document.selection,
+                after: None,
+            },
+            Edit::ReplaceFrame {
+                before,
+                after,
+                dx,
+                dy,
+            },
+        ]))
+    }
+
+    /// Crops the frame to the bounding box of `shape` and clears the
+    /// selection; pixels outside a polygon are cleared as by [`crop_shape`].
+    pub fn crop_shape(
+        document: &Document,
+        shape: &SelectionShape,
+        fill: Option<Color>,
+    ) -> Result<Edit> {
+        let before = document
+            .frame
+            .clone()
+            .ok_or_else(|| anyhow!("Nothing captured to crop"))?;
+        let after = crop_shape(&before, shape, fill)?;
+        let dx = (before.origin.0 - after.origin.0) as f32;
+        let dy = (before.origin.1 - after.origin.1) as f32;
+
+        Ok(Edit::Batch(vec![
+            Edit::SetSelection {
+                before: document.selection,
+                after: None,
+            },
+            Edit::ReplaceFrame {
+                before,
+                after,
+                dx,
+                dy,
+            },
+        ]))
+    }
+
+    /// Runs `step` over the whole frame.
+    pub fn filter(document: &Document, step: &dyn PreprocessStep) -> Result<Edit> {
+        let before = document
+            .frame
+            .clone()
+            .ok_or_else(|| anyhow!("Nothing captured to filter"))?;
+        let after = step.apply(&before);
+        Ok(Edit::ReplaceFrame {
+            before,
+            after,
+            dx: 0.0,
+            dy: 0.0,
+        })
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Edit::SetSelection { .. } => "selection",
+            Edit::AddAnnotation { .. } => "add annotation",
+            Edit::RemoveAnnotation { .. } => "delete annotation",
+            Edit::MoveAnnotation { .. } => "move annotation",
+            Edit::ReplaceAnnotation { .. } => "edit annotation",
+            Edit::ReplaceFrame { .. } => "edit image",
+            Edit::Batch(edits) => edits.last().map(|e| e.label()).unwrap_or("batch"),
+        }
+    }
+
+    pub fn inverse(&self) -> Edit {
+        match self.clone() {
+            Edit::SetSelection { before, after } => Edit::SetSelection {
+                before: after,
+                after: before,
+            },
+            Edit::AddAnnotation { index, shape } => Edit::RemoveAnnotation { index, shape },
+            Edit::RemoveAnnotation { index, shape } => Edit::AddAnnotation { index, shape },
+            Edit::MoveAnnotation { index, dx, dy } => Edit::MoveAnnotation {
+                index,
+                dx: -dx,
+                dy: -dy,
+            },
+            Edit::ReplaceAnnotation {
+                index,
+                before,
+                after,
+            } => Edit::ReplaceAnnotation {
+                index,
+                before: after,
+                after: before,
+            },
+            Edit::ReplaceFrame {
+                before,
+                after,
+                dx,
+                dy,
+            } => Edit::ReplaceFrame {
+                before: after,
+                after: before,
+                dx: -dx,
+                dy: -dy,
+            },
+            Edit::Batch(edits) => Edit::Batch(edits.iter().rev().map(|e| e.inverse()).collect()),
+        }
+    }
+
+    pub fn apply(&self, document: &mut Document) -> Result<()> {
+        let annotations = &mut document.annotations;
+        match self {
+            Edit::SetSelection { after, .. } => document.selection = *after,
+            Edit::AddAnnotation { index, shape } => {
+                if *index > annotations.len() {
+                    return Err(anyhow!("Annotation index {} out of range", index));
+                }
+                annotations.insert(*index, shape.clone().into_annotation());
+            }
+            Edit::RemoveAnnotation { index, .. } => {
+                annotations
+                    .remove(*index)
+                    .ok_or_else(|| anyhow!("No annotation at index {}", index))?;
+            }
+            Edit::MoveAnnotation { index, dx, dy } => annotations
+                .get_mut(*index)
+                .ok_or_else(|| anyhow!("No annotation at index {}", index))?
+                .translate(*dx, *dy),
+            Edit::ReplaceAnnotation { index, after, .. } => {
+                annotations
+                    .replace(*index, after.clone().into_annotation())
+                    .ok_or_else(|| anyhow!("No annotation at index {}", index))?;
+            }
+            Edit::ReplaceFrame { after, dx, dy, .. } => {
+                document.frame = Some(after.clone());
+                for index in 0..annotations.len() {
+                    if let Some(annotation) = annotations.get_mut(index) {
+                        annotation.translate(*dx, *dy);
+                    }
+                }
+            }
+            Edit::Batch(edits) => {
+                for (applied, edit) in edits.iter().enumerate() {
+                    if let Err(e) = edit.apply(document) {
+                        // Leave the document as it was so the failed batch
+                        // needs no undo step
+                        for done in edits[..applied].iter().rev() {
+                            done.inverse().apply(document)?;
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// True if applying the edit changes nothing.
+    pub fn is_noop(&self) -> bool {
+        match self {
+            Edit::SetSelection { before, after } => before == after,
+            Edit::MoveAnnotation { dx, dy, .. } => *dx == 0.0 && *dy == 0.0,
+            Edit::ReplaceAnnotation { before, after, .. } => before == after,
+            Edit::Batch(edits) => edits.iter().all(|e| e.is_noop()),
+            _ => false,
+        }
+    }
+
+    /// Folds a following edit of the same drag into this one.
+    ///
+    /// Returns `false` if the two cannot be combined.
+    fn merge(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (Edit::SetSelection { after, .. }, Edit::SetSelection { after: next, .. }) => {
+                *after = *next;
+                true
+            }
+            (
+                Edit::MoveAnnotation { index, dx, dy },
+                Edit::MoveAnnotation {
+                    index: next_index,
+                    dx: next_dx,
+                    dy: next_dy,
+                },
+            ) if index == next_index => {
+                *dx += next_dx;
+                *dy += next_dy;
+                true
+            }
+            (
+                Edit::ReplaceAnnotation { index, after, .. },
+                Edit::ReplaceAnnotation {
+                    index: next_index,
+                    after: next,
+                    ..
+                },
+            ) if index == next_index => {
+                *after = next.clone();
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Undo and redo stacks of already applied [`Edit`]s.
+///
+/// While coalescing is on, edits that can be merged into the newest step
+/// are folded into it, so a whole drag undoes at once.
+#[derive(Clone, Debug)]
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    depth: usize,
+    coalescing: bool,
+    /// Whether the newest undo step may still absorb edits.
+    open: bool,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_DEPTH)
+    }
+}
+
+impl History {
+    pub fn new(depth: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+            coalescing: false,
+            open: false,
+        }
+    }
+
+    /// Maximum number of undo steps; the oldest ones are dropped first.
+    pub fn set_depth(&mut self, depth: usize) -> &mut Self {
+        self.depth = depth;
+        self.trim();
+        self
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Starts merging the following edits into a single undo step.
+    pub fn begin_coalescing(&mut self) {
+        self.coalescing = true;
+        self.open = false;
+    }
+
+    pub fn end_coalescing(&mut self) {
+        self.coalescing = false;
+        self.open = false;
+    }
+
+    /// Stores an edit that has already been applied.
+    pub fn record(&mut self, edit: Edit) {
+        self.redo.clear();
+
+        if self.coalescing && self.open {
+            if let Some(top) = self.undo.back_mut() {
+                if top.merge(&edit) {
+                    if top.is_noop() {
+                        self.undo.pop_back();
+                        self.open = false;
+                    }
+                    return;
+                }
+            }
+        }
+
+        if edit.is_noop() {
+            return;
+        }
+        self.undo.push_back(edit);
+        self.open = self.coalescing;
+        self.trim();
+    }
+
+    /// Reverts the newest step; returns `false` if there is nothing to undo.
+    pub fn undo(&mut self, document: &mut Document) -> Result<bool> {
+        let Some(edit) = self.undo.pop_back() else {
+            return Ok(false);
+        };
+        if let Err(e) = edit.inverse().apply(document) {
+            self.undo.push_back(edit);
+            return Err(e);
+        }
+        self.redo.push(edit);
+        self.open = false;
+        Ok(true)
+    }
+
+    /// Reapplies the newest undone step; returns `false` if there is none.
+    pub fn redo(&mut self, document: &mut Document) -> Result<bool> {
+        let Some(edit) = self.redo.pop() else {
+            return Ok(false);
+        };
+        if let Err(e) = edit.apply(document) {
+            self.redo.push(edit);
+            return Err(e);
+        }
+        self.undo.push_back(edit);
+        self.open = false;
+        self.trim();
+        Ok(true)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn undo_label(&self) -> Option<&'static str> {
+        self.undo.back().map(|e| e.label())
+    }
+
+    pub fn redo_label(&self) -> Option<&'static str> {
+        self.redo.last().map(|e| e.label())
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.open = false;
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+}
+
+/// A [`Document`] together with its [`History`]; all changes go through
+/// [`Editor::apply`] so they can be undone.
+#[derive(Clone, Debug, Default)]
+pub struct Editor {
+    document: Document,
+    history: History,
+}
+
+impl Editor {
+    pub fn new(depth: usize) -> Self {
+        Editor {
+            document: Document::default(),
+            history: History::new(depth),
+        }
+    }
+
+    pub fn document(&self) -> &Document {
+        &self.document
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn history_mut(&mut self) -> &mut History {
+        &mut self.history
+    }
+
+    /// Starts over with a new capture; the history is cleared.
+    pub fn load(&mut self, frame: Frame) {
+        self.document = Document::new(frame);
+        self.history.clear();
+    }
+
+    pub fn apply(&mut self, edit: Edit) -> Result<()> {
+        edit.apply(&mut self.document)?;
+        self.history.record(edit);
+        Ok(())
+    }
+
+    pub fn undo(&mut self) -> Result<bool> {
+        self.history.undo(&mut self.document)
+    }
+
+    pub fn redo(&mut self) -> Result<bool> {
+        self.history.redo(&mut self.document)
+    }
+
+    pub fn begin_coalescing(&mut self) {
+        self.history.begin_coalescing();
+    }
+
+    pub fn end_coalescing(&mut self) {
+        self.history.end_coalescing();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annotation::{Rectangle, Stroke};
+
+    fn rect(left: f32, top: f32, right: f32, bottom: f32) -> Option<Rect> {
+        Some(Rect::new(left, top, right, bottom))
+    }
+
+    fn rectangle(left: f32) -> Shape {
+        Shape::Rectangle(Rectangle {
+            rect: Rect::new(left, 0.0, left + 10.0, 10.0),
+            stroke: Stroke::new(Color::RED, 2.0, 1.0),
+        })
+    }
+
+    fn select(editor: &mut Editor, after: Option<Rect>) {
+        let before = editor.document().selection;
+        editor.apply(Edit::SetSelection { before, after }).unwrap();
+    }
+
+    #[test]
+    fn inverse_undoes_every_edit() {
+        let mut document = Document::new(Frame::new(4, 4));
+        document.annotations.push(rectangle(0.0).into_annotation());
+        let edits = [
+            Edit::SetSelection {
+                before: None,
+                after: rect(1.0, 1.0, 2.0, 2.0),
+            },
+            Edit::add_annotation(&document, rectangle(5.0)),
+            Edit::remove_annotation(&document, 0).unwrap(),
+            Edit::MoveAnnotation {
+                index: 0,
+                dx: 3.0,
+                dy: -1.0,
+            },
+            Edit::ReplaceAnnotation {
+                index: 0,
+                before: rectangle(0.0),
+                after: rectangle(7.0),
+            },
+            Edit::crop(&document, &Rect::new(1.0, 1.0, 3.0, 3.0)).unwrap(),
+        ];
+        for edit in edits {
+            let mut changed = document.clone();
+            edit.apply(&mut changed).unwrap();
+            edit.inverse().apply(&mut changed).unwrap();
+            assert_eq!(changed.selection, document.selection, "{}", edit.label());
+            assert_eq!(changed.frame, document.frame, "{}", edit.label());
+            assert_eq!(
+                changed.annotations.shapes(),
+                document.annotations.shapes(),
+                "{}",
+                edit.label()
+            );
+        }
+    }
+
+    #[test]
+    fn failed_batch_leaves_the_document_unchanged() {
+        let mut editor = Editor::new(10);
+        editor.load(Frame::new(4, 4));
+        let batch = Edit::Batch(vec![
+            Edit::SetSelection {
+                before: None,
+                after: rect(0.0, 0.0, 1.0, 1.0),
+            },
+            Edit::AddAnnotation {
+                index: 0,
+                shape: rectangle(0.0),
+            },
+            Edit::RemoveAnnotation {
+                index: 5,
+                shape: rectangle(0.0),
+            },
+        ]);
+        assert!(editor.apply(batch).is_err());
+        assert_eq!(editor.document().selection, None);
+        assert!(editor.document().annotations.is_empty());
+        assert!(!editor.history().can_undo());
+    }
+
+    #[test]
+    fn batch_undoes_as_one_step() {
+        let mut editor = Editor::new(10);
+        editor.load(Frame::new(4, 4));
+        select(&mut editor, rect(1.0, 1.0, 3.0, 3.0));
+        let crop = Edit::crop(editor.document(), &Rect::new(1.0, 1.0, 3.0, 3.0)).unwrap();
+        editor.apply(crop).unwrap();
+        assert_eq!(editor.document().selection, None);
+        assert_eq!(editor.document().frame.as_ref().unwrap().width, 2);
+        assert_eq!(editor.history().undo_label(), Some("edit image"));
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.document().selection, rect(1.0, 1.0, 3.0, 3.0));
+        assert_eq!(editor.document().frame.as_ref().unwrap().width, 4);
+        assert!(editor.redo().unwrap());
+        assert_eq!(editor.document().frame.as_ref().unwrap().width, 2);
+    }
+
+    #[test]
+    fn coalescing_merges_a_drag_into_one_step() {
+        let mut editor = Editor::new(10);
+        editor.begin_coalescing();
+        for right in [2.0, 3.0, 4.0] {
+            select(&mut editor, rect(0.0, 0.0, right, 4.0));
+        }
+        editor.end_coalescing();
+        select(&mut editor, rect(0.0, 0.0, 5.0, 5.0));
+
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.document().selection, rect(0.0, 0.0, 4.0, 4.0));
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.document().selection, None);
+        assert!(!editor.undo().unwrap());
+    }
+
+    #[test]
+    fn coalesced_moves_add_up_and_vanish_when_back_at_the_start() {
+        let mut editor = Editor::new(10);
+        editor.load(Frame::new(4, 4));
+        editor
+            .apply(Edit::add_annotation(editor.document(), rectangle(0.0)))
+            .unwrap();
+        let drag = |editor: &mut Editor, moves: &[(f32, f32)]| {
+            editor.begin_coalescing();
+            for &(dx, dy) in moves {
+                editor
+                    .apply(Edit::MoveAnnotation { index: 0, dx, dy })
+                    .unwrap();
+            }
+            editor.end_coalescing();
+        };
+
+        drag(&mut editor, &[(1.0, 0.0), (2.0, 1.0)]);
+        assert_eq!(editor.history().undo_label(), Some("move annotation"));
+        assert!(editor.undo().unwrap());
+        assert_eq!(editor.document().annotations.shapes(), vec![rectangle(0.0)]);
+
+        drag(&mut editor, &[(1.0, 1.0), (-1.0, -1.0)]);
+        assert_eq!(editor.history().undo_label(), Some("add annotation"));
+    }
+
+    #[test]
+    fn noop_edits_are_not_recorded() {
+        let mut history = History::new(10);
+        history.record(Edit::SetSelection {
+            before: None,
+            after: None,
+        });
+        history.record(Edit::MoveAnnotation {
+            index: 0,
+            dx: 0.0,
+            dy: 0.0,
+        });
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut editor = Editor::new(10);
+        select(&mut editor, rect(0.0, 0.0, 1.0, 1.0));
+        editor.undo().unwrap();
+        assert!(editor.history().can_redo());
+        select(&mut editor, rect(0.0, 0.0, 2.0, 2.0));
+        assert!(!editor.history().can_redo());
+    }
+
+    #[test]
+    fn depth_limit_drops_the_oldest_steps() {
+        let mut editor = Editor::new(3);
+        for right in 1..=5 {
+            select(&mut editor, rect(0.0, 0.0, right as f32, 1.0));
+        }
+        let mut undone = 0;
+        while editor.undo().unwrap() {
+            undone += 1;
+        }
+        assert_eq!(undone, 3);
+        assert_eq!(editor.document().selection, rect(0.0, 0.0, 2.0, 1.0));
+
+        editor.history_mut().set_depth(1);
+        let mut redone = 0;
+        while editor.redo().unwrap() {
+            redone += 1;
+        }
+        assert_eq!(redone, 3);
+        assert!(editor.undo().unwrap());
+        assert!(!editor.undo().unwrap());
+    }
+}
@@ -1,30 +1,53 @@
 use crate::{
-    direct2d::{draw_rectangle, draw_updated_area, render_screen_img},
+    annotation::AnnotationLayer,
+    capture::Frame,
+    direct2d::{
+        draw_countdown, draw_rectangle, draw_region_frame, draw_shape, draw_updated_area,
+        render_screen_img,
+    },
     errorhandler::{handle_error, throw_error, ExpectedError},
+    geometry::{Point, Rect},
+    monitor::{Monitor, MonitorLayout, Viewport},
+    picker::WindowInfo,
+    selection::{OverlayStyle, DEFAULT_OVERLAY_OPACITY},
 };
-use std::{os::raw::c_void, time::Instant};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, mem, os::raw::c_void, time::Instant};
 use windows::{
     core::{w, Error, PCWSTR},
     Win32::{
-        Foundation::{COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+        Foundation::{BOOL, COLORREF, HINSTANCE, HWND, LPARAM, LRESULT, POINT, RECT, TRUE, WPARAM},
         Graphics::Direct2D::Common::D2D_POINT_2F,
+        Graphics::Dwm::{DwmGetWindowAttribute, DWMWA_CLOAKED, DWMWA_EXTENDED_FRAME_BOUNDS},
         Graphics::Gdi::{
-            CreateSolidBrush, RedrawWindow, RDW_ERASE, RDW_INVALIDATE, RDW_NOINTERNALPAINT,
+            ClientToScreen, CreateSolidBrush, EnumDisplayMonitors, GetMonitorInfoW, RedrawWindow,
+            HDC, HMONITOR, MONITORINFO, MONITORINFOEXW, RDW_ERASE, RDW_INVALIDATE,
+            RDW_NOINTERNALPAINT,
         },
         System::LibraryLoader::GetModuleHandleW,
+        System::Threading::GetCurrentProcessId,
+        UI::HiDpi::{
+            GetDpiForMonitor, SetProcessDpiAwarenessContext,
+            DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2, MDT_EFFECTIVE_DPI,
+        },
         UI::WindowsAndMessaging::{
-            CreateWindowExW, DefWindowProcW, DestroyWindow, GetSystemMetrics, LoadCursorW,
-            RegisterClassW, SetForegroundWindow, SetLayeredWindowAttributes, SetWindowPos,
-            ShowWindow, CS_HREDRAW, CS_OWNDC, CS_VREDRAW, CW_USEDEFAULT, HMENU, HWND_TOPMOST,
-            IDC_ARROW, IDC_CROSS, LWA_ALPHA, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
-            SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, SWP_NOMOVE, SWP_NOSIZE, SW_HIDE, SW_SHOW,
-            WINDOW_EX_STYLE, WINDOW_STYLE, WNDCLASSW, WS_EX_COMPOSITED, WS_EX_LAYERED, WS_POPUP,
+            CreateWindowExW, DefWindowProcW, DestroyWindow, EnumChildWindows, EnumWindows,
+            GetAncestor, GetClientRect, GetSystemMetrics, GetWindowLongPtrW, GetWindowRect,
+            GetWindowTextW, GetWindowThreadProcessId, IsIconic, IsWindowVisible, LoadCursorW,
+            RegisterClassW, SetForegroundWindow, SetLayeredWindowAttributes, SetWindowLongPtrW,
+            SetWindowPos, ShowWindow, CS_HREDRAW, CS_OWNDC, CS_VREDRAW, CW_USEDEFAULT, GA_PARENT,
+            GWL_EXSTYLE, HMENU, HWND_TOPMOST, IDC_ARROW, IDC_CROSS, LWA_ALPHA, LWA_COLORKEY,
+            MONITORINFOF_PRIMARY, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN, SM_XVIRTUALSCREEN,
+            SM_YVIRTUALSCREEN, SWP_NOMOVE, SWP_NOSIZE, SW_HIDE, SW_SHOW, WINDOW_EX_STYLE,
+            WINDOW_STYLE, WNDCLASSW, WS_EX_COMPOSITED, WS_EX_LAYERED, WS_EX_TRANSPARENT, WS_POPUP,
         },
     },
 };
 pub struct Window {
     pub hwnd: HWND,
     pub window_type: WindowType,
+    /// Opacity while the window is layered, see [`Window::make_transparent`].
+    pub opacity: f32,
 }
 
 impl Window {
@@ -58,16 +81,41 @@ impl Window {
         self.show();
     }
 
-    pub fn draw_rectangle(&self, start: D2D_POINT_2F, end: D2D_POINT_2F) {
-        draw_rectangle(self.hwnd, start, end);
+    pub fn draw_rectangle(
+        &self,
+        start: D2D_POINT_2F,
+        end: D2D_POINT_2F,
+        annotations: &AnnotationLayer,
+        style: &OverlayStyle,
+    ) {
+        draw_rectangle(self.hwnd, start, end, annotations, style);
+    }
+
+    pub fn draw_shape(
+        &self,
+        outline: &[Point],
+        closed: bool,
+        annotations: &AnnotationLayer,
+        style: &OverlayStyle,
+    ) {
+        if let Err(e) = draw_shape(self.hwnd, outline, closed, annotations, style) {
+            eprintln!("Drawing selection failed: {}", e);
+        }
     }
 
-    pub fn auto_screenshot(&self) {
+    pub fn auto_screenshot(&self) -> Option<Frame> {
         let start = Instant::now();
-        let _ = render_screen_img(self.hwnd);
+        let frame = render_screen_img(self.hwnd);
         let duration = start.elapsed(); // Get the elapsed time
 
         println!("Time elapsed in render_screen_img is: {:?}", duration);
+        match frame {
+            Ok(frame) => Some(frame),
+            Err(e) => {
+                eprintln!("Screenshot failed: {}", e);
+                None
+            }
+        }
     }
 
     pub fn trigger_screenshot(&self) {
@@ -91,7 +139,7 @@ impl Window {
             SetLayeredWindowAttributes(
                 self.hwnd,
                 COLORREF(0x000000),
-                (0.55 * 255.0) as u8,
+                (self.opacity.clamp(0.0, 1.0) * 255.0).round() as u8,
                 LWA_ALPHA,
             )
         }
@@ -102,6 +150,39 @@ impl Window {
             ShowWindow(self.hwnd, SW_SHOW);
         }
     }
+
+    /// While enabled the window lets mouse input through to the windows
+    /// below and only its non-black pixels are visible, so the screen can be
+    /// used normally, e.g. during the countdown of a timed capture.
+    pub fn set_passthrough(&self, enabled: bool) -> Result<(), Error> {
+        unsafe {
+            let style = GetWindowLongPtrW(self.hwnd, GWL_EXSTYLE);
+            let flag = WS_EX_TRANSPARENT.0 as isize;
+            let style = if enabled { style | flag } else { style & !flag };
+            SetWindowLongPtrW(self.hwnd, GWL_EXSTYLE, style);
+        }
+        if enabled {
+            unsafe { SetLayeredWindowAttributes(self.hwnd, COLORREF(0x000000), 255, LWA_COLORKEY) }
+        } else {
+            self.make_transparent()
+        }
+    }
+
+    pub fn viewport(&self) -> Viewport {
+        client_viewport(self.hwnd)
+    }
+
+    pub fn draw_region_frame(&self, rect: &Rect) {
+        if let Err(e) = draw_region_frame(self.hwnd, rect) {
+            eprintln!("Drawing region frame failed: {}", e);
+        }
+    }
+
+    pub fn draw_countdown(&self, seconds: u32) {
+        if let Err(e) = draw_countdown(self.hwnd, seconds) {
+            eprintln!("Drawing countdown failed: {}", e);
+        }
+    }
 }
 
 impl Drop for Window {
@@ -114,6 +195,197 @@ impl Drop for Window {
         }
     }
 }
+/// Maps between client coordinates of `hwnd` and virtual-screen pixels.
+pub fn client_viewport(hwnd: HWND) -> Viewport {
+    let mut origin = POINT::default();
+    unsafe {
+        let _ = ClientToScreen(hwnd, &mut origin);
+    }
+    Viewport::new(Point::new(origin.x as f32, origin.y as f32))
+}
+
+/// Makes all coordinates physical pixels, so captures, window rectangles and
+/// mouse positions agree on monitors with different scale factors. Must run
+/// before the first window is created.
+pub fn enable_dpi_awareness() {
+    unsafe {
+        if let Err(e) = SetProcessDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE_V2) {
+            eprintln!("Enabling per-monitor DPI awareness failed: {}", e);
+        }
+    }
+}
+
+/// Current arrangement of the monitors in physical pixels.
+pub fn monitor_layout() -> Result<MonitorLayout, anyhow::Error> {
+    let mut monitors: Vec<Monitor> = Vec::new();
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            HDC(0),
+            None,
+            Some(collect_monitor),
+            LPARAM(&mut monitors as *mut Vec<Monitor> as isize),
+        );
+    }
+    MonitorLayout::new(monitors)
+}
+
+unsafe extern "system" fn collect_monitor(
+    hmonitor: HMONITOR,
+    _hdc: HDC,
+    _clip: *mut RECT,
+    state: LPARAM,
+) -> BOOL {
+    let monitors = &mut *(state.0 as *mut Vec<Monitor>);
+    let mut info = MONITORINFOEXW {
+        monitorInfo: MONITORINFO {
+            cbSize: mem::size_of::<MONITORINFOEXW>() as u32,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    if !GetMonitorInfoW(hmonitor, &mut info.monitorInfo).as_bool() {
+        return TRUE;
+    }
+
+    let (mut dpi_x, mut dpi_y) = (96, 96);
+    let _ = GetDpiForMonitor(hmonitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y);
+    let length = info
+        .szDevice
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(info.szDevice.len());
+
+    let mut monitor = Monitor::new(
+        &String::from_utf16_lossy(&info.szDevice[..length]),
+        to_rect(&info.monitorInfo.rcMonitor),
+        dpi_x as f32 / 96.0,
+    );
+    monitor.work_area = to_rect(&info.monitorInfo.rcWork);
+    monitor.primary = info.monitorInfo.dwFlags & MONITORINFOF_PRIMARY != 0;
+    monitors.push(monitor);
+    TRUE
+}
+
+/// Visible windows of other processes with their child controls, for the
+/// window picker. Top-level windows come first, top to bottom.
+pub fn enumerate_windows() -> Result<Vec<WindowInfo>, anyhow::Error> {
+    let mut collector = WindowCollector::default();
+    let state = LPARAM(&mut collector as *mut WindowCollector as isize);
+    unsafe {
+        EnumWindows(Some(collect_top_level), state)?;
+        let top_level: Vec<u64> = collector.windows.iter().map(|w| w.id).collect();
+        for id in top_level {
+            let _ = EnumChildWindows(HWND(id as isize), Some(collect_child), state);
+        }
+    }
+    Ok(collector.windows)
+}
+
+#[derive(Default)]
+struct WindowCollector {
+    windows: Vec<WindowInfo>,
+    /// Windows seen so far per parent, which is the z-order of the next one.
+    siblings: HashMap<Option<u64>, u32>,
+}
+
+impl WindowCollector {
+    fn push(&mut self, hwnd: HWND, parent: Option<u64>) {
+        let Some(mut info) = (unsafe { window_info(hwnd) }) else {
+            return;
+        };
+        let z_order = self.siblings.entry(parent).or_insert(0);
+        info.parent = parent;
+        info.z_order = *z_order;
+        *z_order += 1;
+        self.windows.push(info);
+    }
+}
+
+unsafe extern "system" fn collect_top_level(hwnd: HWND, state: LPARAM) -> BOOL {
+    let collector = &mut *(state.0 as *mut WindowCollector);
+    let mut process = 0;
+    GetWindowThreadProcessId(hwnd, Some(&mut process));
+    // The overlay itself covers the whole screen
+    if process != GetCurrentProcessId() && !IsIconic(hwnd).as_bool() && !is_cloaked(hwnd) {
+        collector.push(hwnd, None);
+    }
+    TRUE
+}
+
+unsafe extern "system" fn collect_child(hwnd: HWND, state: LPARAM) -> BOOL {
+    let collector = &mut *(state.0 as *mut WindowCollector);
+    let parent = GetAncestor(hwnd, GA_PARENT);
+    collector.push(hwnd, Some(parent.0 as u64));
+    TRUE
+}
+
+/// Windows hidden by DWM, e.g. apps on other virtual desktops, still
+/// report themselves visible.
+unsafe fn is_cloaked(hwnd: HWND) -> bool {
+    let mut cloaked = 0u32;
+    DwmGetWindowAttribute(
+        hwnd,
+        DWMWA_CLOAKED,
+        &mut cloaked as *mut u32 as *mut c_void,
+        mem::size_of::<u32>() as u32,
+    )
+    .is_ok()
+        && cloaked != 0
+}
+
+unsafe fn window_info(hwnd: HWND) -> Option<WindowInfo> {
+    if !IsWindowVisible(hwnd).as_bool() {
+        return None;
+    }
+    let mut outer = RECT::default();
+    GetWindowRect(hwnd, &mut outer).ok()?;
+    let rect = to_rect(&outer);
+    if rect.is_empty() {
+        return None;
+    }
+
+    let mut frame = RECT::default();
+    let frame = DwmGetWindowAttribute(
+        hwnd,
+        DWMWA_EXTENDED_FRAME_BOUNDS,
+        &mut frame as *mut RECT as *mut c_void,
+        mem::size_of::<RECT>() as u32,
+    )
+    .ok()
+    .map(|_| to_rect(&frame))
+    .filter(|frame| *frame != rect && !frame.is_empty());
+
+    let mut client = RECT::default();
+    let mut origin = POINT::default();
+    let client = (GetClientRect(hwnd, &mut client).is_ok()
+        && ClientToScreen(hwnd, &mut origin).as_bool())
+    .then(|| to_rect(&client).translate(origin.x as f32, origin.y as f32))
+    .filter(|client| !client.is_empty());
+
+    let mut title = [0u16; 512];
+    let length = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+
+    Some(WindowInfo {
+        frame,
+        client,
+        ..WindowInfo::new(
+            hwnd.0 as u64,
+            &String::from_utf16_lossy(&title[..length]),
+            rect,
+            0,
+        )
+    })
+}
+
+fn to_rect(rect: &RECT) -> Rect {
+    Rect::new(
+        rect.left as f32,
+        rect.top as f32,
+        rect.right as f32,
+        rect.bottom as f32,
+    )
+}
+
 #[derive(Debug)]
 pub struct WINDOWPROPS {
     pub dwexstyle: WINDOW_EX_STYLE,
@@ -209,6 +481,7 @@ impl WindowTemplate {
         Ok(Window {
             hwnd,
             window_type: builder.window_type,
+            opacity: builder.opacity,
         })
     }
 }
@@ -300,9 +573,11 @@ pub struct WindowBuilder {
     ) -> LRESULT,
 
     window_type: WindowType,
+    opacity: f32,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WindowType {
     Transparent,
     Opaque,
@@ -325,6 +600,7 @@ impl WindowBuilder {
         WindowBuilder {
             window_proc: default_window_proc,
             window_type: WindowType::None,
+            opacity: DEFAULT_OVERLAY_OPACITY,
         }
     }
 
@@ -333,6 +609,12 @@ impl WindowBuilder {
         self
     }
 
+    /// Opacity of a transparent window, from 0.0 to 1.0.
+    pub fn set_opacity(&mut self, opacity: f32) -> &mut Self {
+        self.opacity = opacity;
+        self
+    }
+
     pub fn set_window_proc(
         &mut self,
         window_proc: unsafe extern "system" fn(
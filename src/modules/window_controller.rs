@@ -1,29 +1,266 @@
+use crate::annotation::Color;
+use crate::capture::{CaptureBackend, Frame};
+use crate::clipboard::{self, ClipboardData, ClipboardFormat};
+pub use crate::command::Command;
+use crate::config::Config;
+use crate::direct2d::GdiBackend;
 use crate::errorhandler::throw_error;
-use crate::win_fact::{Window, WindowType};
+use crate::export::Exporter;
+use crate::geometry::Rect;
+use crate::lasso::{crop_shape, SelectionShape};
+use crate::monitor::{CaptureRegion, Viewport};
+use crate::ocr::{default_engine, recognize_region};
+use crate::preprocess::{Pipeline, PreprocessedEngine};
+use crate::raster::flatten;
+use crate::redact::RedactionStyle;
+use crate::sensitive::Report;
+use crate::stitch::{StitchStep, Stitcher};
+use crate::store::{HistoryStore, NewCapture, Quota};
+use crate::undo::{Edit, Editor, DEFAULT_HISTORY_DEPTH};
+use crate::win_fact::{monitor_layout, Window, WindowType};
+use anyhow::anyhow;
 use once_cell::sync::Lazy;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError};
 use std::sync::Arc;
 use std::sync::{Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
 use windows::Win32::Graphics::Direct2D::Common::D2D_POINT_2F;
+use windows::Win32::UI::WindowsAndMessaging::{PostMessageW, WM_APP};
 
 pub static CONTROLLER: Lazy<Arc<WindowController>> =
     Lazy::new(|| Arc::new(WindowController::new()));
 
-pub enum Command {
-    Show,
-    AutoScreenshot,
-    TriggerScreenshot,
-    Reload,
-    Hide,
-    DrawRectangle {
-        start: D2D_POINT_2F,
-        end: D2D_POINT_2F,
+/// What came of a [`Command`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandOutput {
+    Done,
+    /// The screen now shown by the overlay; serialized without its pixels.
+    Captured {
+        #[serde(serialize_with = "frame_summary")]
+        frame: Frame,
     },
+    Saved {
+        path: PathBuf,
+    },
+    Text {
+        text: String,
+    },
+    /// Number of redactions added and what they cover.
+    Redacted {
+        count: usize,
+        report: Report,
+    },
+    /// What the last frame of a scrolling capture added.
+    Scrolled {
+        step: StitchStep,
+    },
+    /// The stitched image of a scrolling capture was saved.
+    Stitched {
+        path: PathBuf,
+        height: u32,
+    },
+    /// The formats placed on the clipboard.
+    Copied {
+        formats: Vec<ClipboardFormat>,
+    },
+}
+
+fn frame_summary<S: Serializer>(frame: &Frame, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct Summary {
+        width: u32,
+        height: u32,
+        origin: (i32, i32),
+        scale: f32,
+    }
+    Summary {
+        width: frame.width,
+        height: frame.height,
+        origin: frame.origin,
+        scale: frame.scale,
+    }
+    .serialize(serializer)
+}
+
+impl fmt::Display for CommandOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandOutput::Done => write!(f, "Done"),
+            CommandOutput::Captured { frame } => {
+                write!(f, "Captured {}x{}", frame.width, frame.height)
+            }
+            CommandOutput::Saved { path } => write!(f, "Saved {}", path.display()),
+            CommandOutput::Text { text } => write!(f, "{}", text),
+            CommandOutput::Redacted { count, report } => {
+                write!(f, "{}Redacted {} areas", report, count)
+            }
+            CommandOutput::Scrolled { step } => match step {
+                StitchStep::Started => write!(f, "Started scrolling capture"),
+                StitchStep::Appended { rows } => write!(f, "Scrolled {} rows", rows),
+                StitchStep::Unchanged => write!(f, "Nothing scrolled"),
+            },
+            CommandOutput::Stitched { path, height } => {
+                write!(f, "Saved {} ({} rows)", path.display(), height)
+            }
+            CommandOutput::Copied { formats } => {
+                let names: Vec<String> = formats.iter().map(ToString::to_string).collect();
+                write!(f, "Copied as {}", names.join(", "))
+            }
+        }
+    }
+}
+
+/// Puts `contents` on the clipboard on behalf of `owner`.
+fn copy(owner: HWND, contents: &[ClipboardData]) -> Result<CommandOutput, anyhow::Error> {
+    clipboard::write(owner, contents).map_err(|e| anyhow!("Copying failed: {}", e))?;
+    Ok(CommandOutput::Copied {
+        formats: contents.iter().map(|data| data.format).collect(),
+    })
+}
+
+/// Posted to the transparent window when work from other threads is
+/// queued, see [`WindowController::run_pending`].
+pub const WM_RUN_PENDING: u32 = WM_APP + 1;
+
+/// Work queued for the thread of the windows.
+type Job = Box<dyn FnOnce(&WindowController) + Send>;
+
+/// The result of work handed to the thread of the windows, see
+/// [`WindowController::submit`]. Waiting for it on that thread never ends.
+pub struct Completion<T = CommandOutput> {
+    receiver: Receiver<Result<T, anyhow::Error>>,
+}
+
+impl<T> Completion<T> {
+    fn failed(error: anyhow::Error) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let _ = sender.send(Err(error));
+        Completion { receiver }
+    }
+
+    fn dropped() -> anyhow::Error {
+        throw_error::<()>("The command was dropped without running").unwrap_err()
+    }
+
+    /// Blocks until the work has run.
+    pub fn wait(self) -> Result<T, anyhow::Error> {
+        self.receiver.recv().map_err(|_| Self::dropped())?
+    }
+
+    /// Blocks until the work has run or `timeout` passed; can be called
+    /// again after a timeout.
+    pub fn wait_timeout(&self, timeout: Duration) -> Result<T, anyhow::Error> {
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                Err(throw_error::<()>("Timed out waiting for the command").unwrap_err())
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(Self::dropped()),
+        }
+    }
+
+    /// The result if the work has run already.
+    pub fn try_wait(&self) -> Option<Result<T, anyhow::Error>> {
+        match self.receiver.try_recv() {
+            Ok(result) => Some(result),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(Self::dropped())),
+        }
+    }
+}
+
+/// A scrolling capture in progress.
+struct ScrollCapture {
+    /// Captured region in virtual-screen coordinates.
+    region: Rect,
+    stitcher: Stitcher,
+}
+
+type HistoryJob = Box<dyn FnOnce(&mut HistoryStore) -> Result<(), anyhow::Error> + Send>;
+
+/// Adds `capture` to `history` and drops old entries beyond `quota`.
+fn add_to_history(
+    history: &mut HistoryStore,
+    capture: &NewCapture,
+    quota: &Quota,
+) -> Result<(), anyhow::Error> {
+    history
+        .add(capture)
+        .map_err(|e| anyhow!("Adding capture to history failed: {}", e))?;
+    if *quota != Quota::default() {
+        history.prune(quota, SystemTime::now())?;
+    }
+    Ok(())
+}
+
+/// What the history needs to know about the capture on screen besides its
+/// document.
+struct OpenCapture {
+    created: SystemTime,
+    ocr_text: Option<String>,
+}
+
+/// Thread writing the history, so encoding images never holds up the
+/// windows. Jobs run in the order they were queued, each on the history in
+/// the directory that was configured when it was queued.
+struct HistoryWriter {
+    jobs: Sender<(PathBuf, HistoryJob)>,
+    thread: JoinHandle<()>,
+}
+
+impl HistoryWriter {
+    fn spawn() -> Self {
+        let (jobs, receiver) = mpsc::channel::<(PathBuf, HistoryJob)>();
+        let thread = thread::spawn(move || {
+            let mut history: Option<HistoryStore> = None;
+            for (directory, job) in receiver {
+                if history
+                    .as_ref()
+                    .is_none_or(|store| store.root() != directory)
+                {
+                    history = match HistoryStore::open(&directory) {
+                        Ok(store) => Some(store),
+                        Err(e) => {
+                            eprintln!("Opening history {} failed: {}", directory.display(), e);
+                            None
+                        }
+                    };
+                }
+                if let Some(history) = history.as_mut() {
+                    if let Err(e) = job(history) {
+                        eprintln!("{}", e);
+                    }
+                }
+            }
+        });
+        HistoryWriter { jobs, thread }
+    }
 }
 
 pub struct WindowController {
     transparent_window: Mutex<Option<Window>>,
     opaque_window: Mutex<Option<Window>>,
     main_window: Mutex<Option<Window>>,
+    /// Last captured screen with its selection, annotations and history.
+    editor: Mutex<Editor>,
+    scroll: Mutex<Option<ScrollCapture>>,
+    /// Started with the first capture.
+    history: Mutex<Option<HistoryWriter>>,
+    /// The capture on screen, until it is written to the history.
+    open_capture: Mutex<Option<OpenCapture>>,
+    config: Mutex<Config>,
+    /// File the last capture was saved to.
+    last_saved: Mutex<Option<PathBuf>>,
+    /// Text found by the last OCR run.
+    last_text: Mutex<Option<String>>,
+    /// Work from other threads waiting for the thread of the windows.
+    pending: Mutex<Vec<Job>>,
 }
 
 impl WindowController {
@@ -32,8 +269,32 @@ impl WindowController {
             transparent_window: Mutex::new(None),
             opaque_window: Mutex::new(None),
             main_window: Mutex::new(None),
+            editor: Mutex::new(Editor::new(DEFAULT_HISTORY_DEPTH)),
+            scroll: Mutex::new(None),
+            history: Mutex::new(None),
+            open_capture: Mutex::new(None),
+            config: Mutex::new(Config::default()),
+            last_saved: Mutex::new(None),
+            last_text: Mutex::new(None),
+            pending: Mutex::new(Vec::new()),
         }
     }
+
+    /// Applies user settings; the capture on screen already goes to the
+    /// history directory configured here.
+    pub fn configure(&self, config: Config) -> Result<(), anyhow::Error> {
+        self.editor()?
+            .history_mut()
+            .set_depth(config.output.undo_depth);
+        *self.config()? = config;
+        Ok(())
+    }
+
+    pub fn config(&self) -> Result<MutexGuard<'_, Config>, anyhow::Error> {
+        self.config
+            .lock()
+            .map_err(|_| throw_error::<()>("Failed to lock configuration").unwrap_err())
+    }
     fn window_ref(&self, window_type: WindowType) -> Option<&Mutex<Option<Window>>> {
         match window_type {
             WindowType::Transparent => Some(&self.transparent_window),
@@ -62,17 +323,460 @@ impl WindowController {
         Ok(())
     }
 
-    pub fn dispatch(&self, window_type: WindowType, command: Command) -> Result<(), anyhow::Error> {
-        if let Some(window) = &*self.locked_window(window_type)? {
-            match command {
-                Command::Show => window.show(),
-                Command::AutoScreenshot => window.auto_screenshot(),
-                Command::TriggerScreenshot => window.trigger_screenshot(),
-                Command::Reload => window.reload(),
-                Command::Hide => window.hide(),
-                Command::DrawRectangle { start, end } => window.draw_rectangle(start, end),
+    /// Frame shown by the last `AutoScreenshot`, if any.
+    pub fn last_capture(&self) -> Option<Frame> {
+        self.editor()
+            .ok()
+            .and_then(|editor| editor.document().frame.clone())
+    }
+
+    pub fn last_saved(&self) -> Option<PathBuf> {
+        self.last_saved.lock().ok().and_then(|path| path.clone())
+    }
+
+    pub fn last_text(&self) -> Option<String> {
+        self.last_text.lock().ok().and_then(|text| text.clone())
+    }
+
+    fn set_last_saved(&self, path: &Path) {
+        if let Ok(mut last) = self.last_saved.lock() {
+            *last = Some(path.to_path_buf());
+        }
+    }
+
+    fn set_last_capture(&self, frame: Frame) {
+        self.save_history();
+        if let Ok(mut editor) = self.editor() {
+            editor.load(frame);
+        }
+        if let Ok(mut open) = self.open_capture.lock() {
+            *open = Some(OpenCapture {
+                created: SystemTime::now(),
+                ocr_text: None,
+            });
+        }
+    }
+
+    /// Runs `job` on the history thread after all jobs queued before it;
+    /// its errors are reported there.
+    fn queue_history(
+        &self,
+        job: impl FnOnce(&mut HistoryStore) -> Result<(), anyhow::Error> + Send + 'static,
+    ) {
+        let directory = match self.config() {
+            Ok(config) => config.output.history.clone(),
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        };
+        let Ok(mut writer) = self.history.lock() else {
+            return;
+        };
+        let writer = writer.get_or_insert_with(HistoryWriter::spawn);
+        if writer.jobs.send((directory, Box::new(job))).is_err() {
+            eprintln!("History thread stopped");
+        }
+    }
+
+    /// Adds a capture to the history and drops old entries beyond the
+    /// configured quota. Failures are reported but never stop the capture
+    /// itself.
+    fn record_history(&self, frame: Frame, monitor: Option<String>) {
+        let quota = self.quota();
+        self.queue_history(move |history| {
+            add_to_history(
+                history,
+                &NewCapture {
+                    monitor,
+                    ..NewCapture::new(&frame)
+                },
+                &quota,
+            )
+        });
+    }
+
+    fn quota(&self) -> Quota {
+        self.config()
+            .map(|config| config.quota())
+            .unwrap_or_default()
+    }
+
+    /// Writes the capture on screen to the history with its selection,
+    /// annotations and OCR text, e.g. before it is replaced.
+    ///
+    /// Each capture is written once, when it is done, so its redactions
+    /// are burned into the stored image a single time and the unredacted
+    /// pixels never reach the disk.
+    fn save_history(&self) {
+        let Some(open) = self
+            .open_capture
+            .lock()
+            .ok()
+            .and_then(|mut open| open.take())
+        else {
+            return;
+        };
+        let Ok(editor) = self.editor() else {
+            return;
+        };
+        let document = editor.document();
+        let Some(frame) = document.frame.clone() else {
+            return;
+        };
+        let selection = document.selection;
+        let annotations = document.annotations.clone();
+        drop(editor);
+
+        let quota = self.quota();
+        self.queue_history(move |history| {
+            add_to_history(
+                history,
+                &NewCapture {
+                    selection,
+                    ocr_text: open.ocr_text,
+                    annotations: Some(&annotations),
+                    created: open.created,
+                    ..NewCapture::new(&frame)
+                },
+                &quota,
+            )
+        });
+    }
+
+    /// Writes the capture on screen to the history and waits until
+    /// everything queued is written, e.g. on exit.
+    pub fn close_history(&self) {
+        self.save_history();
+        let writer = self
+            .history
+            .lock()
+            .ok()
+            .and_then(|mut writer| writer.take());
+        if let Some(HistoryWriter { jobs, thread }) = writer {
+            drop(jobs);
+            let _ = thread.join();
+        }
+    }
+
+    /// Document of the current capture and its undo history.
+    pub fn editor(&self) -> Result<MutexGuard<Editor>, anyhow::Error> {
+        self.editor
+            .lock()
+            .map_err(|_| throw_error::<()>("Failed to lock editor").unwrap_err())
+    }
+
+    /// Applies `edit` to the current capture and records it for undo.
+    pub fn edit(&self, edit: Edit) -> Result<(), anyhow::Error> {
+        self.editor()?.apply(edit)
+    }
+
+    /// Returns whether there was something to undo.
+    pub fn undo(&self) -> Result<bool, anyhow::Error> {
+        self.editor()?.undo()
+    }
+
+    /// Returns whether there was something to redo.
+    pub fn redo(&self) -> Result<bool, anyhow::Error> {
+        self.editor()?.redo()
+    }
+
+    /// Runs OCR on `rect` of the last captured screen, in coordinates of
+    /// `viewport`, or on all of it.
+    fn recognize_text(
+        &self,
+        viewport: Viewport,
+        rect: Option<Rect>,
+    ) -> Result<String, anyhow::Error> {
+        let frame = self
+            .last_capture()
+            .ok_or_else(|| throw_error::<()>("No screen captured yet").unwrap_err())?;
+        let rect = rect.map(|rect| viewport.rect_to(&Viewport::for_frame(&frame), &rect));
+        let engine = PreprocessedEngine::new(Pipeline::for_screen_text(), default_engine());
+        let text = recognize_region(&engine, &frame, rect)?.text();
+        if let Ok(mut last) = self.last_text.lock() {
+            *last = Some(text.clone());
+        }
+
+        if let Ok(mut open) = self.open_capture.lock() {
+            if let Some(open) = open.as_mut() {
+                open.ocr_text = Some(text.clone());
+            }
+        }
+        Ok(text)
+    }
+
+    /// Adds blackout redactions over all located findings as one undo step
+    /// and returns what was found.
+    fn redact_sensitive(&self) -> Result<Report, anyhow::Error> {
+        let frame = self
+            .last_capture()
+            .ok_or_else(|| throw_error::<()>("No screen captured yet").unwrap_err())?;
+        let engine = PreprocessedEngine::new(Pipeline::for_screen_text(), default_engine());
+        let report = self.config()?.detector()?.detect(&engine, &frame)?;
+
+        let mut editor = self.editor()?;
+        let edit = report.redaction_edit(editor.document(), RedactionStyle::blackout());
+        editor.apply(edit)?;
+        Ok(report)
+    }
+
+    /// Saves `region` of the live screen without showing it.
+    fn capture_to_file(&self, path: &Path, region: CaptureRegion) -> Result<(), anyhow::Error> {
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        let layout = monitor_layout()?;
+        let frame = layout.extract(&GdiBackend.capture()?, region)?;
+        Exporter::default().save(&frame, path)?;
+        self.set_last_saved(path);
+
+        let monitor = layout.region_monitor(region).map(|m| m.name.clone());
+        self.record_history(frame, monitor);
+        Ok(())
+    }
+
+    pub fn is_scroll_capturing(&self) -> bool {
+        self.scroll.lock().is_ok_and(|scroll| scroll.is_some())
+    }
+
+    fn scroll_capture(&self) -> Result<MutexGuard<'_, Option<ScrollCapture>>, anyhow::Error> {
+        self.scroll
+            .lock()
+            .map_err(|_| throw_error::<()>("Failed to lock scrolling capture").unwrap_err())
+    }
+
+    fn start_scroll_capture(&self, region: Rect) -> Result<StitchStep, anyhow::Error> {
+        *self.scroll_capture()? = Some(ScrollCapture {
+            region,
+            stitcher: Stitcher::new(),
+        });
+        self.scroll_capture_frame()
+    }
+
+    fn scroll_capture_frame(&self) -> Result<StitchStep, anyhow::Error> {
+        let mut scroll = self.scroll_capture()?;
+        let capture = scroll
+            .as_mut()
+            .ok_or_else(|| throw_error::<()>("No scrolling capture running").unwrap_err())?;
+        let screen = GdiBackend.capture()?;
+        let frame =
+            screen.crop_rect(&Viewport::for_frame(&screen).rect_to_client(&capture.region))?;
+        capture.stitcher.push(&frame)
+    }
+
+    /// Saves the stitched image and returns its height.
+    fn finish_scroll_capture(&self, path: &Path) -> Result<u32, anyhow::Error> {
+        let capture = self
+            .scroll_capture()?
+            .take()
+            .ok_or_else(|| throw_error::<()>("No scrolling capture running").unwrap_err())?;
+        let frame = capture.stitcher.finish()?;
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        Exporter::default().save(&frame, path)?;
+        self.set_last_saved(path);
+        Ok(frame.height)
+    }
+
+    /// The last captured screen with its annotations, cut to the bounding
+    /// box of `shape` if given.
+    fn crop_capture(
+        &self,
+        viewport: Viewport,
+        shape: Option<&SelectionShape>,
+        fill: Option<Color>,
+    ) -> Result<Frame, anyhow::Error> {
+        let editor = self.editor()?;
+        let document = editor.document();
+        let frame = document
+            .frame
+            .as_ref()
+            .ok_or_else(|| throw_error::<()>("No screen captured yet").unwrap_err())?;
+        let flattened = flatten(frame, &document.annotations);
+        match shape {
+            Some(shape) => {
+                let target = Viewport::for_frame(frame);
+                let shape = shape.map(|p| target.to_client(viewport.to_screen(p)));
+                crop_shape(&flattened, &shape, fill)
             }
+            None => Ok(flattened),
         }
+    }
+
+    fn capture_shape(
+        &self,
+        viewport: Viewport,
+        shape: &SelectionShape,
+        fill: Option<Color>,
+        path: &Path,
+    ) -> Result<(), anyhow::Error> {
+        let out = self.crop_capture(viewport, Some(shape), fill)?;
+        if let Some(directory) = path.parent() {
+            std::fs::create_dir_all(directory)?;
+        }
+        Exporter::default().save(&out, path)?;
+        self.set_last_saved(path);
         Ok(())
     }
+
+    /// Runs `command` on `window_type`; must be called on the thread of
+    /// the windows, see [`WindowController::submit`] for other threads.
+    pub fn dispatch(
+        &self,
+        window_type: WindowType,
+        command: Command,
+    ) -> Result<CommandOutput, anyhow::Error> {
+        let guard = self.locked_window(window_type)?;
+        let window = guard
+            .as_ref()
+            .ok_or_else(|| anyhow!("No {:?} window", window_type))?;
+        match command {
+            Command::Show => window.show(),
+            Command::AutoScreenshot => {
+                let frame = window
+                    .auto_screenshot()
+                    .ok_or_else(|| throw_error::<()>("Screenshot failed").unwrap_err())?;
+                self.set_last_capture(frame.clone());
+                return Ok(CommandOutput::Captured { frame });
+            }
+            Command::TriggerScreenshot => window.trigger_screenshot(),
+            Command::Reload => window.reload(),
+            Command::Hide => window.hide(),
+            Command::DrawRectangle { start, end } => window.draw_rectangle(
+                D2D_POINT_2F {
+                    x: start.x,
+                    y: start.y,
+                },
+                D2D_POINT_2F { x: end.x, y: end.y },
+                &self.editor()?.document().annotations,
+                &self.config()?.overlay_style(),
+            ),
+            Command::DrawShape { points, closed } => window.draw_shape(
+                &points,
+                closed,
+                &self.editor()?.document().annotations,
+                &self.config()?.overlay_style(),
+            ),
+            Command::CaptureShape { shape, fill, path } => {
+                self.capture_shape(window.viewport(), &shape, fill, &path)
+                    .map_err(|e| anyhow!("Saving selection to {} failed: {}", path.display(), e))?;
+                return Ok(CommandOutput::Saved { path });
+            }
+            Command::RecognizeText { rect } => {
+                let text = self
+                    .recognize_text(window.viewport(), rect)
+                    .map_err(|e| anyhow!("OCR failed: {}", e))?;
+                return Ok(CommandOutput::Text { text });
+            }
+            Command::CopyImage { shape } => {
+                let frame = self.crop_capture(window.viewport(), shape.as_ref(), None)?;
+                let contents = clipboard::image_contents(&frame)?;
+                return copy(window.get_hwnd(), &contents);
+            }
+            Command::CopyText { rect } => {
+                let text = self
+                    .recognize_text(window.viewport(), rect)
+                    .map_err(|e| anyhow!("OCR failed: {}", e))?;
+                return copy(window.get_hwnd(), &clipboard::text_contents(&text));
+            }
+            Command::RedactSensitive => {
+                let report = self
+                    .redact_sensitive()
+                    .map_err(|e| anyhow!("Sensitive data detection failed: {}", e))?;
+                return Ok(CommandOutput::Redacted {
+                    count: report.findings.len(),
+                    report,
+                });
+            }
+            Command::Countdown { seconds } => {
+                window
+                    .set_passthrough(seconds.is_some())
+                    .map_err(|e| anyhow!("Changing window attributes failed: {}", e))?;
+                if let Some(seconds) = seconds {
+                    window.draw_countdown(seconds);
+                }
+            }
+            Command::StartScrollCapture { rect } => {
+                window
+                    .set_passthrough(true)
+                    .map_err(|e| anyhow!("Changing window attributes failed: {}", e))?;
+                window.draw_region_frame(&rect);
+                let region = window.viewport().rect_to_screen(&rect);
+                let step = self
+                    .start_scroll_capture(region)
+                    .map_err(|e| anyhow!("Scrolling capture failed: {}", e))?;
+                return Ok(CommandOutput::Scrolled { step });
+            }
+            Command::ScrollCaptureFrame => {
+                let step = self
+                    .scroll_capture_frame()
+                    .map_err(|e| anyhow!("Skipped frame: {}", e))?;
+                return Ok(CommandOutput::Scrolled { step });
+            }
+            Command::FinishScrollCapture { path } => {
+                let passthrough = window.set_passthrough(false);
+                let height = self.finish_scroll_capture(&path).map_err(|e| {
+                    anyhow!("Scrolling capture to {} failed: {}", path.display(), e)
+                })?;
+                passthrough.map_err(|e| anyhow!("Changing window attributes failed: {}", e))?;
+                return Ok(CommandOutput::Stitched { path, height });
+            }
+            Command::CaptureToFile { path, region } => {
+                self.capture_to_file(&path, region)
+                    .map_err(|e| anyhow!("Capture to {} failed: {}", path.display(), e))?;
+                return Ok(CommandOutput::Saved { path });
+            }
+        }
+        Ok(CommandOutput::Done)
+    }
+
+    /// Runs `job` on the thread of the windows, which picks it up with the
+    /// next message; for callers on other threads.
+    pub fn run_on_ui<T, F>(&self, job: F) -> Completion<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&WindowController) -> Result<T, anyhow::Error> + Send + 'static,
+    {
+        let hwnd = match self.locked_window(WindowType::Transparent) {
+            Ok(window) => match window.as_ref() {
+                Some(window) => window.get_hwnd(),
+                None => return Completion::failed(anyhow!("No window to run commands on")),
+            },
+            Err(e) => return Completion::failed(e),
+        };
+        let (sender, receiver) = mpsc::channel();
+        match self.pending.lock() {
+            Ok(mut pending) => pending.push(Box::new(move |controller| {
+                let _ = sender.send(job(controller));
+            })),
+            Err(_) => {
+                return Completion::failed(
+                    throw_error::<()>("Failed to lock command queue").unwrap_err(),
+                )
+            }
+        }
+        if let Err(e) = unsafe { PostMessageW(hwnd, WM_RUN_PENDING, WPARAM(0), LPARAM(0)) } {
+            return Completion::failed(e.into());
+        }
+        Completion { receiver }
+    }
+
+    /// [`WindowController::dispatch`] from any thread.
+    pub fn submit(&self, window_type: WindowType, command: Command) -> Completion {
+        self.run_on_ui(move |controller| controller.dispatch(window_type, command))
+    }
+
+    /// Runs the work queued by other threads; called by the window
+    /// procedure on [`WM_RUN_PENDING`].
+    pub fn run_pending(&self) {
+        let jobs = match self.pending.lock() {
+            Ok(mut pending) => std::mem::take(&mut *pending),
+            Err(_) => return,
+        };
+        for job in jobs {
+            job(self);
+        }
+    }
 }
@@ -1,27 +1,66 @@
-use crate::win_fact::WindowType;
-use crate::window_controller::{Command, CONTROLLER};
+use crate::config::{Action, Config, Keymap};
+use crate::errorhandler::{show_error, show_status};
+use crate::export::Exporter;
+use crate::geometry::{Point, Rect};
+use crate::ipc::{Handler, IpcCommand};
+use crate::lasso::{SelectionShape, ShapeKind, ShapeTool};
+use crate::monitor::CaptureRegion;
+use crate::picker::WindowPicker;
+use crate::recording::{Recorder, SessionEvent};
+use crate::selection::{
+    Buttons, CursorShape, HitZone, InputEvent, Key, Modifiers, MouseButton, Redraw, SelectionModel,
+    SelectionUpdate,
+};
+use crate::stitch::StitchStep;
+use crate::timer::{CaptureTimer, SystemClock, TimerEvent, TimerMode, TimerSettings};
+use crate::undo::Edit;
+use crate::win_fact::{client_viewport, enumerate_windows, WindowType};
+use crate::window_controller::{Command, CommandOutput, CONTROLLER, WM_RUN_PENDING};
 use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::path::PathBuf;
 use std::sync::Mutex;
-use windows::Win32::Graphics::Gdi::{DeleteObject, RedrawWindow, UpdateWindow};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use windows::Win32::Graphics::Gdi::RedrawWindow;
 
 use windows::Win32::{
-    Foundation::{COLORREF, FALSE, HWND, LPARAM, LRESULT, RECT, TRUE, WPARAM},
-    Graphics::Direct2D::Common::{D2D_POINT_2F, D2D_RECT_F},
-    Graphics::Gdi::{
-        BeginPaint, CreateSolidBrush, EndPaint, FillRect, InvalidateRect, SetBkMode, HBRUSH,
-        PAINTSTRUCT, RDW_INTERNALPAINT, RDW_INVALIDATE, TRANSPARENT,
-    },
-    System::SystemServices::MK_LBUTTON,
+    Foundation::{HWND, LPARAM, LRESULT, POINT, RECT, WPARAM},
+    Graphics::Gdi::{BeginPaint, EndPaint, ScreenToClient, PAINTSTRUCT, RDW_INTERNALPAINT},
+    System::SystemServices::{MK_LBUTTON, MK_MBUTTON, MK_RBUTTON},
     UI::{
-        Input::KeyboardAndMouse::{VK_ESCAPE, VK_R, VK_S},
+        Input::KeyboardAndMouse::{
+            GetKeyState, VK_CONTROL, VK_DOWN, VK_ESCAPE, VK_LEFT, VK_MENU, VK_RETURN, VK_RIGHT,
+            VK_SHIFT, VK_UP,
+        },
         WindowsAndMessaging::{
-            DefWindowProcW, PostQuitMessage, WM_DESTROY, WM_ERASEBKGND, WM_KEYDOWN, WM_LBUTTONDOWN,
-            WM_LBUTTONUP, WM_MOUSEMOVE, WM_PAINT,
+            DefWindowProcW, GetCursorPos, IsWindowVisible, KillTimer, LoadCursorW, PostQuitMessage,
+            SetCursor, SetTimer, HTCLIENT, IDC_CROSS, IDC_SIZEALL, IDC_SIZENESW, IDC_SIZENS,
+            IDC_SIZENWSE, IDC_SIZEWE, WM_DESTROY, WM_ERASEBKGND, WM_KEYDOWN, WM_LBUTTONDOWN,
+            WM_LBUTTONUP, WM_MOUSEMOVE, WM_PAINT, WM_SETCURSOR, WM_TIMER,
         },
     },
 };
-static INVALIDATED_RECT: Mutex<Option<D2D_RECT_F>> = Mutex::new(None);
-static START_POINT: Mutex<Option<D2D_POINT_2F>> = Mutex::new(None);
+static SELECTION: Mutex<SelectionModel> = Mutex::new(SelectionModel::new());
+/// Set while the next click picks a window instead of starting a drag.
+static PICKER: Mutex<Option<WindowPicker>> = Mutex::new(None);
+/// Set while selecting with a lasso or polygon instead of a rectangle.
+static SHAPE_TOOL: Mutex<Option<ShapeTool>> = Mutex::new(None);
+static TIMER: Lazy<Mutex<CaptureTimer>> =
+    Lazy::new(|| Mutex::new(CaptureTimer::new(Box::new(SystemClock::default()))));
+static TIMER_SETTINGS: Lazy<Mutex<TimerSettings>> = Lazy::new(Default::default);
+static KEYMAP: Lazy<Mutex<Keymap>> = Lazy::new(Default::default);
+/// Set while the session is being recorded.
+static RECORDER: Mutex<Option<Recorder>> = Mutex::new(None);
+
+/// Id of the `WM_TIMER` driving timed captures.
+const CAPTURE_TIMER_ID: usize = 1;
+/// Id of the `WM_TIMER` taking the frames of a scrolling capture.
+const SCROLL_TIMER_ID: usize = 2;
+/// Time between the frames of a scrolling capture, in milliseconds.
+const SCROLL_FRAME_INTERVAL: u32 = 200;
+/// How long a command from another program waits for its result, e.g.
+/// while OCR runs.
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(60);
 
 macro_rules! get_x_lparam {
     ($lparam:expr) => {
@@ -35,6 +74,755 @@ macro_rules! get_y_lparam {
     };
 }
 
+fn pointer_pos(lparam: LPARAM) -> Point {
+    Point::new(
+        get_x_lparam!(lparam.0) as f32,
+        get_y_lparam!(lparam.0) as f32,
+    )
+}
+
+fn held_buttons(wparam: WPARAM) -> Buttons {
+    Buttons {
+        left: (wparam.0 & MK_LBUTTON.0 as usize) != 0,
+        right: (wparam.0 & MK_RBUTTON.0 as usize) != 0,
+        middle: (wparam.0 & MK_MBUTTON.0 as usize) != 0,
+    }
+}
+
+fn translate_key(vk: usize) -> Key {
+    match vk as u16 {
+        k if k == VK_ESCAPE.0 => Key::Escape,
+        k if k == VK_RETURN.0 => Key::Enter,
+        k if k == VK_LEFT.0 => Key::Left,
+        k if k == VK_RIGHT.0 => Key::Right,
+        k if k == VK_UP.0 => Key::Up,
+        k if k == VK_DOWN.0 => Key::Down,
+        // Letter and digit virtual-key codes match their ASCII upper case
+        k @ (0x30..=0x39 | 0x41..=0x5A) => Key::Char(k as u8 as char),
+        k => Key::Other(k as u32),
+    }
+}
+
+fn key_modifiers() -> Modifiers {
+    unsafe {
+        Modifiers {
+            shift: GetKeyState(VK_SHIFT.0 as i32) < 0,
+            ctrl: GetKeyState(VK_CONTROL.0 as i32) < 0,
+            alt: GetKeyState(VK_MENU.0 as i32) < 0,
+        }
+    }
+}
+
+/// Maps the `WM_*` messages the selection cares about onto [`InputEvent`]s.
+fn translate_input(message: u32, wparam: WPARAM, lparam: LPARAM) -> Option<InputEvent> {
+    match message {
+        WM_LBUTTONDOWN => Some(InputEvent::PointerDown {
+            pos: pointer_pos(lparam),
+            button: MouseButton::Left,
+        }),
+        WM_MOUSEMOVE => Some(InputEvent::PointerMove {
+            pos: pointer_pos(lparam),
+            buttons: held_buttons(wparam),
+        }),
+        WM_LBUTTONUP => Some(InputEvent::PointerUp {
+            pos: pointer_pos(lparam),
+            button: MouseButton::Left,
+        }),
+        WM_KEYDOWN => Some(InputEvent::Key {
+            key: translate_key(wparam.0),
+            modifiers: key_modifiers(),
+        }),
+        _ => None,
+    }
+}
+
+fn to_win_rect(rect: Rect) -> RECT {
+    RECT {
+        left: rect.left as _,
+        top: rect.top as _,
+        right: rect.right as _,
+        bottom: rect.bottom as _,
+    }
+}
+
+fn request_redraw(window: HWND, redraw: Redraw) {
+    unsafe {
+        match redraw {
+            Redraw::None => {}
+            Redraw::Area(rect) => {
+                let _ = RedrawWindow(window, Some(&to_win_rect(rect)), None, RDW_INTERNALPAINT);
+            }
+            Redraw::Full => {
+                let _ = RedrawWindow(window, None, None, RDW_INTERNALPAINT);
+            }
+        }
+    }
+}
+
+/// Shows the resize/move cursor matching what lies under the pointer.
+fn update_cursor(window: HWND) -> bool {
+    let mut pos = Default::default();
+    unsafe {
+        if GetCursorPos(&mut pos).is_err() || !ScreenToClient(window, &mut pos).as_bool() {
+            return false;
+        }
+    }
+
+    let zone = if PICKER.lock().unwrap().is_some() || SHAPE_TOOL.lock().unwrap().is_some() {
+        HitZone::Outside
+    } else {
+        SELECTION
+            .lock()
+            .unwrap()
+            .hit_test(Point::new(pos.x as f32, pos.y as f32))
+    };
+    let id = match zone.cursor() {
+        CursorShape::Crosshair => IDC_CROSS,
+        CursorShape::Move => IDC_SIZEALL,
+        CursorShape::ResizeNorthSouth => IDC_SIZENS,
+        CursorShape::ResizeEastWest => IDC_SIZEWE,
+        CursorShape::ResizeNorthWestSouthEast => IDC_SIZENWSE,
+        CursorShape::ResizeNorthEastSouthWest => IDC_SIZENESW,
+    };
+
+    unsafe {
+        match LoadCursorW(None, id) {
+            Ok(cursor) => {
+                SetCursor(cursor);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn feed_selection(window: HWND, event: InputEvent) {
+    let update = SELECTION.lock().unwrap().handle(event);
+    request_redraw(window, update.redraw);
+    record_selection(event, update);
+}
+
+/// Mirrors selection changes into the undo history; everything between
+/// pressing and releasing the left button becomes one undo step.
+fn record_selection(event: InputEvent, update: SelectionUpdate) {
+    let Ok(mut editor) = CONTROLLER.editor() else {
+        return;
+    };
+    if let InputEvent::PointerDown {
+        button: MouseButton::Left,
+        ..
+    } = event
+    {
+        editor.begin_coalescing();
+    }
+
+    let before = editor.document().selection;
+    if update.selection != before {
+        let _ = editor.apply(Edit::SetSelection {
+            before,
+            after: update.selection,
+        });
+    }
+
+    if let InputEvent::PointerUp {
+        button: MouseButton::Left,
+        ..
+    } = event
+    {
+        editor.end_coalescing();
+    }
+}
+
+/// Runs `command` and tells the user what came of it, e.g. where a
+/// capture was saved, in a message box owned by `window`.
+///
+/// The message box runs its own message loop, so no lock may be held here.
+fn report(window: HWND, window_type: WindowType, command: Command) {
+    match CONTROLLER.dispatch(window_type, command) {
+        Ok(
+            CommandOutput::Done
+            | CommandOutput::Captured { .. }
+            | CommandOutput::Scrolled {
+                step: StitchStep::Started | StitchStep::Unchanged,
+            },
+        ) => {}
+        Ok(output) => show_status(window, output),
+        Err(e) => show_error(window, e),
+    }
+}
+
+/// File in the capture directory named after `name` and the current time.
+fn capture_path(name: &str) -> PathBuf {
+    capture_file(name, "png")
+}
+
+fn capture_file(name: &str, extension: &str) -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    TIMER_SETTINGS
+        .lock()
+        .unwrap()
+        .sequence
+        .directory()
+        .join(format!("{}-{}.{}", name, seconds, extension))
+}
+
+/// Adds `event` to the running recording, if any; a failing recording is
+/// stopped.
+fn record(window: HWND, event: SessionEvent) {
+    let mut recorder = RECORDER.lock().unwrap();
+    let Some(Err(e)) = recorder.as_mut().map(|active| active.record(event)) else {
+        return;
+    };
+    *recorder = None;
+    drop(recorder);
+    show_error(window, format!("Recording failed, stopped: {}", e));
+}
+
+/// Starts recording the session, or stops the running recording. The
+/// capture on screen is saved next to the recording to replay it against.
+fn toggle_recording(window: HWND) {
+    let running = RECORDER.lock().unwrap().take();
+    if let Some(recorder) = running {
+        show_status(
+            window,
+            format!("Stopped recording ({} events)", recorder.recorded()),
+        );
+        return;
+    }
+
+    let path = capture_file("session", "jsonl");
+    let screen = path.with_extension("png");
+    let Some(frame) = CONTROLLER.last_capture() else {
+        show_status(window, "Nothing captured to record on yet");
+        return;
+    };
+    if let Err(e) = Exporter::default().save(&frame, &screen) {
+        show_error(window, format!("Saving {} failed: {}", screen.display(), e));
+        return;
+    }
+    match Recorder::create(&path) {
+        Ok(recorder) => *RECORDER.lock().unwrap() = Some(recorder),
+        Err(e) => {
+            show_error(window, e);
+            return;
+        }
+    }
+    record(
+        window,
+        SessionEvent::Capture {
+            origin: client_viewport(window).origin,
+        },
+    );
+    let selection = SELECTION.lock().unwrap().selection();
+    if let Some(rect) = selection {
+        record(window, SessionEvent::Select { rect: Some(rect) });
+    }
+    show_status(
+        window,
+        format!(
+            "Recording to {}, replay with: ocr --fixture {} replay {}",
+            path.display(),
+            screen.display(),
+            path.display()
+        ),
+    );
+}
+
+/// Takes over the key bindings and timer settings of `config`.
+pub fn configure(config: &Config) -> anyhow::Result<()> {
+    *KEYMAP.lock().unwrap() = config.keymap()?;
+    *TIMER_SETTINGS.lock().unwrap() = config.timer_settings();
+    Ok(())
+}
+
+/// The action bound to the key of a `WM_KEYDOWN`, if any.
+fn key_action(message: u32, wparam: WPARAM, lparam: LPARAM) -> Option<Action> {
+    match translate_input(message, wparam, lparam)? {
+        InputEvent::Key { key, modifiers } => KEYMAP.lock().unwrap().action(key, modifiers),
+        _ => None,
+    }
+}
+
+/// Starts picking windows, or stops and shows the committed selection again.
+fn toggle_picker(window: HWND) {
+    let mut picker = PICKER.lock().unwrap();
+    if picker.take().is_none() {
+        let listed = enumerate_windows().map(|windows| *picker = Some(WindowPicker::new(windows)));
+        drop(picker);
+        if let Err(e) = listed {
+            show_error(window, format!("Listing windows failed: {}", e));
+        }
+        return;
+    }
+    drop(picker);
+
+    let selection = CONTROLLER
+        .editor()
+        .ok()
+        .and_then(|editor| editor.document().selection);
+    record(window, SessionEvent::Select { rect: selection });
+    let update = SELECTION.lock().unwrap().select(selection);
+    request_redraw(window, update.redraw);
+}
+
+/// Highlights the window under the pointer and selects it on click; Ctrl
+/// picks child controls. Returns `false` if not picking.
+fn feed_picker(window: HWND, event: InputEvent) -> bool {
+    let mut guard = PICKER.lock().unwrap();
+    let Some(picker) = guard.as_mut() else {
+        return false;
+    };
+    let viewport = client_viewport(window);
+    let to_screen = |p: Point| viewport.to_screen(p);
+    let to_client = |rect: Rect| viewport.rect_to_client(&rect);
+
+    match event {
+        InputEvent::PointerMove { pos, .. } => {
+            picker.set_children(key_modifiers().ctrl);
+            if picker.hover(to_screen(pos)) {
+                let update = SELECTION
+                    .lock()
+                    .unwrap()
+                    .select(picker.highlight().map(to_client));
+                request_redraw(window, update.redraw);
+            }
+        }
+        InputEvent::PointerDown {
+            pos,
+            button: MouseButton::Left,
+        } => {
+            picker.set_children(key_modifiers().ctrl);
+            let picked = picker.pick(to_screen(pos)).map(to_client);
+            *guard = None;
+            drop(guard);
+            record(window, SessionEvent::Select { rect: picked });
+
+            let update = SELECTION.lock().unwrap().select(picked);
+            request_redraw(window, update.redraw);
+            if let Ok(mut editor) = CONTROLLER.editor() {
+                let before = editor.document().selection;
+                let _ = editor.apply(Edit::SetSelection {
+                    before,
+                    after: update.selection,
+                });
+            }
+        }
+        _ => {}
+    }
+    true
+}
+
+/// Switches to selecting with a `kind` outline, or back to rectangles if
+/// that is already active.
+fn toggle_shape(window: HWND, kind: ShapeKind) {
+    record(window, SessionEvent::ToggleShape { kind });
+    let mut tool = SHAPE_TOOL.lock().unwrap();
+    *tool = match tool.take() {
+        Some(active) if active.kind() == kind => None,
+        _ => {
+            let mut tool = ShapeTool::new(kind);
+            if let Ok(config) = CONTROLLER.config() {
+                tool.set_fill(config.overlay.outside_fill);
+            }
+            Some(tool)
+        }
+    };
+    let shape = tool.as_ref().map_or("rectangle".to_string(), |t| {
+        format!("{:?}", t.kind()).to_lowercase()
+    });
+    drop(tool);
+    request_redraw(window, Redraw::Full);
+    show_status(window, format!("Selection shape: {}", shape));
+}
+
+/// Draws the lasso or polygon and saves the selected part once it is
+/// closed. Returns `false` if selecting rectangles.
+fn feed_shape(window: HWND, event: InputEvent) -> bool {
+    let mut guard = SHAPE_TOOL.lock().unwrap();
+    let Some(tool) = guard.as_mut() else {
+        return false;
+    };
+    let update = tool.handle(event);
+    let fill = tool.fill();
+    drop(guard);
+
+    request_redraw(window, update.redraw);
+    if let (true, Some(shape)) = (update.committed, update.shape) {
+        let path = capture_path("selection");
+        record(
+            window,
+            SessionEvent::SaveShape {
+                shape: shape.clone(),
+                fill,
+                path: path.clone(),
+            },
+        );
+        report(
+            window,
+            WindowType::Opaque,
+            Command::CaptureShape { shape, fill, path },
+        );
+    }
+    true
+}
+
+/// Undo (`redo == false`) or redo one step and show the restored selection.
+fn step_history(window: HWND, redo: bool) {
+    record(
+        window,
+        if redo {
+            SessionEvent::Redo
+        } else {
+            SessionEvent::Undo
+        },
+    );
+    let stepped = if redo {
+        CONTROLLER.redo()
+    } else {
+        CONTROLLER.undo()
+    };
+    if let Ok(true) = stepped {
+        let selection = CONTROLLER
+            .editor()
+            .ok()
+            .and_then(|editor| editor.document().selection);
+        SELECTION.lock().unwrap().select(selection);
+        request_redraw(window, Redraw::Full);
+    }
+}
+
+/// Starts `mode`, or cancels the timed capture that is already running.
+fn toggle_timer(window: HWND, mode: TimerMode) {
+    let mut timer = TIMER.lock().unwrap();
+    if timer.cancel() {
+        drop(timer);
+        finish_timer(window);
+        return;
+    }
+    let started = timer.start(mode);
+    drop(timer);
+    if let Err(e) = started {
+        show_error(window, format!("Timed capture failed: {}", e));
+        return;
+    }
+
+    // Uncover the screen so menus and tooltips can be opened meanwhile
+    let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Hide);
+    pump_timer(window);
+}
+
+/// Acts on the due timer events and arms `WM_TIMER` for the next one.
+fn pump_timer(window: HWND) {
+    let (mode, events, wakeup) = {
+        let mut timer = TIMER.lock().unwrap();
+        let mode = timer.mode();
+        let events = timer.poll();
+        (mode, events, timer.next_wakeup())
+    };
+
+    for event in events {
+        let result = match event {
+            TimerEvent::Countdown { seconds } => CONTROLLER
+                .dispatch(
+                    WindowType::Transparent,
+                    Command::Countdown {
+                        seconds: Some(seconds),
+                    },
+                )
+                .map(drop),
+            // A delayed capture is taken by reopening the overlay
+            TimerEvent::Capture { index } => match mode {
+                Some(TimerMode::Interval { .. }) => {
+                    let path = TIMER_SETTINGS.lock().unwrap().sequence.path(index);
+                    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+                    let saved = CONTROLLER.dispatch(
+                        WindowType::Opaque,
+                        Command::CaptureToFile {
+                            path,
+                            region: CaptureRegion::AllMonitors,
+                        },
+                    );
+                    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Show);
+                    saved.map(drop)
+                }
+                _ => Ok(()),
+            },
+            TimerEvent::Finished => {
+                finish_timer(window);
+                Ok(())
+            }
+        };
+        // Interval captures are not announced one by one, and a failure
+        // stops the timer instead of showing a message box every tick
+        if let Err(e) = result {
+            TIMER.lock().unwrap().cancel();
+            finish_timer(window);
+            show_error(window, e);
+            return;
+        }
+    }
+
+    unsafe {
+        match wakeup {
+            Some(wait) => {
+                SetTimer(
+                    window,
+                    CAPTURE_TIMER_ID,
+                    wait.as_millis().max(1) as u32,
+                    None,
+                );
+            }
+            None => {
+                let _ = KillTimer(window, CAPTURE_TIMER_ID);
+            }
+        }
+    }
+}
+
+/// Leaves the countdown and shows the overlay over a fresh capture.
+fn finish_timer(window: HWND) {
+    unsafe {
+        let _ = KillTimer(window, CAPTURE_TIMER_ID);
+    }
+    report(
+        window,
+        WindowType::Transparent,
+        Command::Countdown { seconds: None },
+    );
+    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+    let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Show);
+}
+
+/// Starts a scrolling capture of the selection, or ends the running one
+/// and saves the stitched image.
+fn toggle_scroll_capture(window: HWND) {
+    if CONTROLLER.is_scroll_capturing() {
+        unsafe {
+            let _ = KillTimer(window, SCROLL_TIMER_ID);
+        }
+        report(
+            window,
+            WindowType::Transparent,
+            Command::FinishScrollCapture {
+                path: capture_path("scroll"),
+            },
+        );
+        let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+        let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Show);
+        return;
+    }
+
+    let Some(rect) = SELECTION.lock().unwrap().selection() else {
+        show_status(window, "Select the region to scroll through first");
+        return;
+    };
+    // Uncover the live screen so it can be scrolled below the overlay
+    let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Hide);
+    report(
+        window,
+        WindowType::Transparent,
+        Command::StartScrollCapture { rect },
+    );
+    unsafe {
+        SetTimer(window, SCROLL_TIMER_ID, SCROLL_FRAME_INTERVAL, None);
+    }
+}
+
+/// Switches the bounds the window picker highlights, e.g. with or without
+/// the drop shadow.
+fn cycle_bounds_mode(window: HWND) {
+    let mut guard = PICKER.lock().unwrap();
+    let Some(picker) = guard.as_mut() else {
+        return;
+    };
+    let mode = picker.bounds_mode().next();
+    picker.set_bounds_mode(mode);
+
+    let viewport = client_viewport(window);
+    let highlight = picker
+        .highlight()
+        .map(|rect| viewport.rect_to_client(&rect));
+    drop(guard);
+    let update = SELECTION.lock().unwrap().select(highlight);
+    request_redraw(window, update.redraw);
+    show_status(window, format!("Window bounds: {:?}", mode));
+}
+
+/// Saves `region` of the live screen without the overlay.
+fn capture_region(window: HWND, name: &str, region: CaptureRegion) {
+    let path = capture_path(name);
+    record(
+        window,
+        SessionEvent::CaptureToFile {
+            region,
+            path: path.clone(),
+        },
+    );
+    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+    report(
+        window,
+        WindowType::Opaque,
+        Command::CaptureToFile { path, region },
+    );
+    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Show);
+}
+
+fn run_action(window: HWND, action: Action) {
+    match action {
+        Action::Quit => unsafe { PostQuitMessage(0) },
+        Action::Undo => step_history(window, false),
+        Action::Redo => step_history(window, true),
+        Action::Screenshot => {
+            let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::TriggerScreenshot);
+        }
+        Action::Ocr => {
+            let selection = SELECTION.lock().unwrap().selection();
+            if let Some(rect) = selection {
+                record(window, SessionEvent::RecognizeText { rect });
+                report(
+                    window,
+                    WindowType::Opaque,
+                    Command::RecognizeText { rect: Some(rect) },
+                );
+            }
+        }
+        Action::ScrollCapture => toggle_scroll_capture(window),
+        Action::PickWindow => toggle_picker(window),
+        Action::Lasso => toggle_shape(window, ShapeKind::Lasso),
+        Action::Polygon => toggle_shape(window, ShapeKind::Polygon),
+        Action::BoundsMode => cycle_bounds_mode(window),
+        Action::CaptureMonitor => {
+            let mut cursor = POINT::default();
+            unsafe {
+                let _ = GetCursorPos(&mut cursor);
+            }
+            let region = CaptureRegion::MonitorAt {
+                x: cursor.x as f32,
+                y: cursor.y as f32,
+            };
+            capture_region(window, "monitor", region);
+        }
+        Action::CaptureAllMonitors => capture_region(window, "screens", CaptureRegion::AllMonitors),
+        Action::DelayedCapture => {
+            let mode = TIMER_SETTINGS.lock().unwrap().delayed();
+            toggle_timer(window, mode);
+        }
+        Action::CycleDelay => {
+            let delay = TIMER_SETTINGS.lock().unwrap().cycle_delay();
+            show_status(window, format!("Capture delay: {} s", delay.as_secs()));
+        }
+        Action::IntervalCapture => {
+            let mode = TIMER_SETTINGS.lock().unwrap().interval();
+            toggle_timer(window, mode);
+        }
+        Action::Redact => {
+            record(window, SessionEvent::RedactSensitive);
+            report(window, WindowType::Opaque, Command::RedactSensitive);
+            request_redraw(window, Redraw::Full);
+        }
+        Action::Reload => {
+            record(window, SessionEvent::ResetSelection);
+            let update = SELECTION.lock().unwrap().reset();
+            request_redraw(window, update.redraw);
+            let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+            let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Reload);
+        }
+        Action::Record => toggle_recording(window),
+        Action::CopyImage => {
+            let shape = SELECTION
+                .lock()
+                .unwrap()
+                .selection()
+                .map(|rect| SelectionShape::Rect { rect });
+            report(window, WindowType::Opaque, Command::CopyImage { shape });
+        }
+        Action::CopyText => {
+            let rect = SELECTION.lock().unwrap().selection();
+            report(window, WindowType::Opaque, Command::CopyText { rect });
+        }
+    }
+}
+
+/// Hands commands from other programs, see [`crate::ipc`], to the thread of
+/// the overlay `window` and waits for their results.
+pub struct ControlHandler {
+    window: isize,
+}
+
+impl ControlHandler {
+    pub fn new(window: HWND) -> Self {
+        ControlHandler { window: window.0 }
+    }
+}
+
+impl Handler for ControlHandler {
+    fn handle(&self, command: IpcCommand) -> anyhow::Result<Value> {
+        let window = self.window;
+        CONTROLLER
+            .run_on_ui(move |_| run_control(HWND(window), command))
+            .wait_timeout(CONTROL_TIMEOUT)
+    }
+}
+
+/// Saves `region` of the live screen to `path`, hiding the overlay
+/// meanwhile if it is shown.
+fn capture_control(window: HWND, path: PathBuf, region: CaptureRegion) -> anyhow::Result<Value> {
+    let visible = unsafe { IsWindowVisible(window).as_bool() };
+    if visible {
+        let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
+    }
+    let output = CONTROLLER.dispatch(WindowType::Opaque, Command::CaptureToFile { path, region });
+    if visible {
+        let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Show);
+    }
+    Ok(serde_json::to_value(output?)?)
+}
+
+fn run_control(window: HWND, command: IpcCommand) -> anyhow::Result<Value> {
+    match command {
+        IpcCommand::Ping => return Ok(json!("pong")),
+        IpcCommand::Show => {
+            CONTROLLER.dispatch(WindowType::Transparent, Command::Hide)?;
+            CONTROLLER.dispatch(WindowType::Opaque, Command::Show)?;
+        }
+        IpcCommand::Hide => {
+            CONTROLLER.dispatch(WindowType::Transparent, Command::Hide)?;
+            CONTROLLER.dispatch(WindowType::Opaque, Command::Hide)?;
+        }
+        IpcCommand::Reload => run_action(window, Action::Reload),
+        IpcCommand::TriggerScreenshot => {
+            CONTROLLER.dispatch(WindowType::Opaque, Command::TriggerScreenshot)?;
+        }
+        IpcCommand::CaptureRegion { rect, path } => {
+            let path = path.unwrap_or_else(|| capture_path("region"));
+            return capture_control(window, path, CaptureRegion::Rect { rect });
+        }
+        IpcCommand::CaptureToFile { region, path } => {
+            let path = path.unwrap_or_else(|| capture_path("screens"));
+            return capture_control(window, path, region);
+        }
+        IpcCommand::RecognizeText { rect } => {
+            let viewport = client_viewport(window);
+            let rect = rect.map(|rect| viewport.rect_to_client(&rect));
+            let output =
+                CONTROLLER.dispatch(WindowType::Opaque, Command::RecognizeText { rect })?;
+            return Ok(serde_json::to_value(output)?);
+        }
+        IpcCommand::RedactSensitive => {
+            let output = CONTROLLER.dispatch(WindowType::Opaque, Command::RedactSensitive)?;
+            request_redraw(window, Redraw::Full);
+            return Ok(serde_json::to_value(output)?);
+        }
+        IpcCommand::LastCapturePath => return Ok(json!({ "path": CONTROLLER.last_saved() })),
+        IpcCommand::LastOcrText => return Ok(json!({ "text": CONTROLLER.last_text() })),
+        IpcCommand::Quit => unsafe { PostQuitMessage(0) },
+    }
+    Ok(Value::Null)
+}
+
 pub extern "system" fn transparent_handler(
     window: HWND,
     message: u32,
@@ -44,77 +832,51 @@ pub extern "system" fn transparent_handler(
     unsafe {
         match message {
             WM_MOUSEMOVE | WM_LBUTTONDOWN | WM_LBUTTONUP => {
-                let x = get_x_lparam!(lparam.0);
-                let y = get_y_lparam!(lparam.0);
-
-                if message == WM_LBUTTONDOWN {
-                    let mut start_point = START_POINT.lock().unwrap();
-                    *start_point = Some(D2D_POINT_2F {
-                        x: x as f32,
-                        y: y as f32,
-                    });
-                } else if message == WM_MOUSEMOVE && (wparam.0 & MK_LBUTTON.0 as usize) != 0 {
-                    let start_point = START_POINT.lock().unwrap();
-                    if let Some(start) = *start_point {
-                        let end = D2D_POINT_2F {
-                            x: x as f32,
-                            y: y as f32,
-                        };
-
-                        let mut invalidated_rect = INVALIDATED_RECT.lock().unwrap();
-                        *invalidated_rect = Some(D2D_RECT_F {
-                            left: start.x.min(end.x),
-                            top: start.y.min(end.y),
-                            right: start.x.max(end.x),
-                            bottom: start.y.max(end.y),
-                        });
-
-                        if let Some(rect) = invalidated_rect.as_ref() {
-                            let rect = RECT {
-                                left: rect.left as _,
-                                top: rect.top as _,
-                                right: rect.right as _,
-                                bottom: rect.bottom as _,
-                            };
-                            //InvalidateRect(window, Some(&rect), FALSE);
-                            RedrawWindow(window, Some(&rect), None, RDW_INTERNALPAINT);
+                if let Some(event) = translate_input(message, wparam, lparam) {
+                    if !feed_picker(window, event) {
+                        record(window, SessionEvent::Input { event });
+                        if !feed_shape(window, event) {
+                            feed_selection(window, event);
                         }
                     }
                 }
 
                 LRESULT(0)
             }
+            WM_SETCURSOR if (lparam.0 & 0xFFFF) as u32 == HTCLIENT => {
+                if update_cursor(window) {
+                    LRESULT(1)
+                } else {
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+            }
             WM_PAINT => {
-                let invalidated_rect = INVALIDATED_RECT.lock().unwrap().clone();
-                let rect = match invalidated_rect {
-                    Some(rect) => RECT {
-                        left: rect.left as _,
-                        top: rect.top as _,
-                        right: rect.right as _,
-                        bottom: rect.bottom as _,
-                    },
-                    None => RECT::default(),
-                };
+                let selection = SELECTION.lock().unwrap().selection();
+                let rect = selection.map(to_win_rect).unwrap_or_default();
 
                 let mut ps = PAINTSTRUCT {
                     rcPaint: rect,
                     ..PAINTSTRUCT::default()
                 };
 
-                let hdc = BeginPaint(window, &mut ps);
+                let _hdc = BeginPaint(window, &mut ps);
 
-                if let Some(rect) = invalidated_rect {
+                let shape = SHAPE_TOOL
+                    .lock()
+                    .unwrap()
+                    .as_ref()
+                    .map(|tool| (tool.outline(), tool.is_closed()));
+                if let Some((points, closed)) = shape {
+                    let _ = CONTROLLER.dispatch(
+                        WindowType::Transparent,
+                        Command::DrawShape { points, closed },
+                    );
+                } else if let Some(rect) = selection {
                     let _ = CONTROLLER.dispatch(
                         WindowType::Transparent,
                         Command::DrawRectangle {
-                            start: D2D_POINT_2F {
-                                x: rect.left,
-                                y: rect.top,
-                            },
-                            end: D2D_POINT_2F {
-                                x: rect.right,
-                                y: rect.bottom,
-                            },
+                            start: Point::new(rect.left, rect.top),
+                            end: Point::new(rect.right, rect.bottom),
                         },
                     );
                 }
@@ -122,17 +884,32 @@ pub extern "system" fn transparent_handler(
                 LRESULT(0)
             }
             WM_KEYDOWN => {
-                if wparam.0 == VK_ESCAPE.0 as usize {
-                    PostQuitMessage(0);
+                if let Some(event) = translate_input(message, wparam, lparam) {
+                    record(window, SessionEvent::Input { event });
+                    if !feed_shape(window, event) {
+                        feed_selection(window, event);
+                    }
                 }
-                if wparam.0 == VK_S.0 as usize {
-                    println!("Pressed S");
-                    let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::TriggerScreenshot);
+                if let Some(action) = key_action(message, wparam, lparam) {
+                    run_action(window, action);
                 }
-                if wparam.0 == VK_R.0 as usize {
-                    println!("Reloaded");
-                    let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Hide);
-                    let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::Reload);
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == CAPTURE_TIMER_ID => {
+                pump_timer(window);
+                LRESULT(0)
+            }
+            WM_RUN_PENDING => {
+                CONTROLLER.run_pending();
+                LRESULT(0)
+            }
+            WM_TIMER if wparam.0 == SCROLL_TIMER_ID => {
+                // Stop scrolling instead of showing a message box every frame
+                if let Err(e) =
+                    CONTROLLER.dispatch(WindowType::Transparent, Command::ScrollCaptureFrame)
+                {
+                    let _ = KillTimer(window, SCROLL_TIMER_ID);
+                    show_error(window, e);
                 }
                 LRESULT(0)
             }
@@ -156,7 +933,7 @@ pub extern "system" fn opaque_handler(
     unsafe {
         match message {
             WM_KEYDOWN => {
-                if wparam.0 == VK_ESCAPE.0 as usize {
+                if key_action(message, wparam, lparam) == Some(Action::Quit) {
                     PostQuitMessage(0);
                 }
                 LRESULT(0)
@@ -169,7 +946,16 @@ pub extern "system" fn opaque_handler(
 
             WM_ERASEBKGND => {
                 //if FIRST_PAINT.swap(false, Ordering::SeqCst) {
-                let _ = CONTROLLER.dispatch(WindowType::Opaque, Command::AutoScreenshot);
+                if let Ok(CommandOutput::Captured { .. }) =
+                    CONTROLLER.dispatch(WindowType::Opaque, Command::AutoScreenshot)
+                {
+                    record(
+                        window,
+                        SessionEvent::Capture {
+                            origin: client_viewport(window).origin,
+                        },
+                    );
+                }
                 let _ = CONTROLLER.dispatch(WindowType::Transparent, Command::Show);
                 //}
                 LRESULT(0)
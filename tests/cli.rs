@@ -0,0 +1,435 @@
+use image::{Rgba, RgbaImage};
+use ocr::capture::Frame;
+use ocr::cli::{run, run_with, Cli, CliError, ExitCode, Platform, Subcommand, USAGE};
+use ocr::geometry::Rect;
+use ocr::ipc::IpcCommand;
+use ocr::ocr::{OcrBlock, OcrDocument, OcrEngine, OcrLine, OcrPage, OcrWord};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Empty directory for one test, removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("cli_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// The pixel every fixture has at `(x, y)`.
+fn pattern(x: u32, y: u32) -> Rgba<u8> {
+    Rgba([x as u8 * 6, y as u8 * 12, 200, 255])
+}
+
+/// A 40x20 screen and an empty configuration in `dir`, returned as the
+/// global options that select them.
+fn fixture(dir: &TempDir) -> Vec<String> {
+    let screen = dir.path("screen.png");
+    RgbaImage::from_fn(40, 20, pattern).save(&screen).unwrap();
+    let config = dir.path("config.toml");
+    fs::write(&config, "").unwrap();
+    args(&["--fixture", &screen, "--config", &config])
+}
+
+/// An OCR fixture in `dir` answering with one line of `words`.
+fn ocr_fixture(dir: &TempDir, words: &[&str]) -> String {
+    let words = words
+        .iter()
+        .enumerate()
+        .map(|(i, text)| OcrWord {
+            text: text.to_string(),
+            rect: Rect::new(i as f32 * 10.0, 0.0, i as f32 * 10.0 + 8.0, 10.0),
+            confidence: Some(0.9),
+        })
+        .collect::<Vec<_>>();
+    let lines = if words.is_empty() {
+        Vec::new()
+    } else {
+        vec![OcrLine::new(words)]
+    };
+    let document = OcrDocument {
+        pages: vec![OcrPage::new(
+            Rect::new(0.0, 0.0, 40.0, 20.0),
+            vec![OcrBlock::new(lines)],
+        )],
+    };
+    let path = dir.path("ocr.json");
+    fs::write(&path, document.to_json().unwrap()).unwrap();
+    path
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Parses and runs `args` like `main` does; returns what was written and
+/// the exit status.
+fn run_args(args: Vec<String>) -> (Vec<u8>, Result<(), CliError>) {
+    let mut out = Vec::new();
+    let result = Cli::parse(args).and_then(|cli| run(&cli, &mut out));
+    (out, result)
+}
+
+fn exit_code(result: &Result<(), CliError>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.exit.code(),
+    }
+}
+
+fn output(out: &[u8]) -> &str {
+    std::str::from_utf8(out).unwrap()
+}
+
+#[test]
+fn exit_codes_match_the_usage_text() {
+    let codes = [
+        (ExitCode::Success, 0, "0 success"),
+        (ExitCode::Failure, 1, "1 failure"),
+        (ExitCode::Usage, 2, "2 usage"),
+        (ExitCode::Config, 3, "3 configuration"),
+        (ExitCode::Capture, 4, "4 capture"),
+        (ExitCode::Ocr, 5, "5 OCR"),
+        (ExitCode::NoText, 6, "6 no text"),
+        (ExitCode::Output, 7, "7 writing"),
+    ];
+    let usage = USAGE.split_whitespace().collect::<Vec<_>>().join(" ");
+    for (exit, code, documented) in codes {
+        assert_eq!(exit.code(), code);
+        assert!(usage.contains(documented), "{}", documented);
+    }
+}
+
+#[test]
+fn global_options_may_follow_the_command() {
+    let cli = Cli::parse(args(&[
+        "--set",
+        "overlay.opacity=0.5",
+        "capture",
+        "--region",
+        "10,20,30,40",
+        "--fixture",
+        "screen.png",
+        "--monitor",
+        "1",
+        "--logical",
+        "-o",
+        "out.jpg",
+        "--quality",
+        "80",
+        "--delay",
+        "0.5",
+    ]))
+    .unwrap();
+
+    assert_eq!(cli.settings, ["overlay.opacity=0.5"]);
+    assert_eq!(cli.fixture, Some(PathBuf::from("screen.png")));
+    let Some(Subcommand::Capture(capture)) = cli.command else {
+        panic!("not a capture: {:?}", cli.command);
+    };
+    assert_eq!(capture.region, Some(Rect::new(10.0, 20.0, 40.0, 60.0)));
+    assert_eq!(capture.monitor, Some(1));
+    assert!(capture.logical);
+    assert_eq!(capture.output, Some(PathBuf::from("out.jpg")));
+    assert_eq!(capture.quality, Some(80));
+    assert_eq!(capture.delay.unwrap().as_millis(), 500);
+}
+
+#[test]
+fn parses_every_command() {
+    let command = |line: &[&str]| Cli::parse(args(line)).unwrap().command;
+    assert_eq!(command(&[]), None);
+    assert_eq!(command(&["--help"]), Some(Subcommand::Help));
+    assert_eq!(command(&["help"]), Some(Subcommand::Help));
+    assert_eq!(
+        command(&["monitors", "--json"]),
+        Some(Subcommand::Monitors { json: true })
+    );
+    assert_eq!(
+        command(&["config", "--path"]),
+        Some(Subcommand::Config { path: true })
+    );
+    assert_eq!(
+        command(&["send", "ping"]),
+        Some(Subcommand::Send {
+            command: IpcCommand::Ping
+        })
+    );
+    assert!(matches!(
+        command(&[
+            "send",
+            "recognize_text",
+            r#"{"rect":{"left":0,"top":0,"right":5,"bottom":5}}"#
+        ]),
+        Some(Subcommand::Send {
+            command: IpcCommand::RecognizeText { rect: Some(_) }
+        })
+    ));
+    let Some(Subcommand::Ocr(ocr)) = command(&["ocr", "in.png", "--format", "hocr", "--raw"])
+    else {
+        panic!("not ocr");
+    };
+    assert_eq!(ocr.image, Some(PathBuf::from("in.png")));
+    assert!(ocr.raw);
+    let Some(Subcommand::Export(export)) = command(&[
+        "export",
+        "in.png",
+        "--annotations",
+        "a.json",
+        "--format",
+        "webp",
+    ]) else {
+        panic!("not export");
+    };
+    assert_eq!(export.annotations, Some(PathBuf::from("a.json")));
+    let Some(Subcommand::Replay(replay)) = command(&["replay", "s.json", "--realtime", "--json"])
+    else {
+        panic!("not replay");
+    };
+    assert!(replay.realtime && replay.json);
+}
+
+#[test]
+fn bad_arguments_are_usage_errors() {
+    let cases: &[(&[&str], &str)] = &[
+        (&["--verbose"], "Unknown option `--verbose`"),
+        (&["shoot"], "Unknown command `shoot`, see `help`"),
+        (&["capture", "--region"], "--region needs a value"),
+        (
+            &["capture", "--region", "1,2,3"],
+            "--region 1,2,3: expected X,Y,W,H",
+        ),
+        (
+            &["capture", "--region", "1,2,0,3"],
+            "--region 1,2,0,3: width and height must be positive",
+        ),
+        (
+            &["capture", "--region", "a,2,3,4"],
+            "--region: `a` is not a valid number",
+        ),
+        (
+            &["capture", "--quality", "0"],
+            "--quality 0: expected 1 to 100",
+        ),
+        (
+            &["capture", "--format", "gif"],
+            "Unknown image format `gif`",
+        ),
+        (
+            &["capture", "extra"],
+            "capture: unexpected argument `extra`",
+        ),
+        (&["ocr", "--format", "pdf"], "Unknown text format `pdf`"),
+        (&["export"], "export needs an image"),
+        (&["replay"], "replay needs a recorded session"),
+        (
+            &["monitors", "--all"],
+            "monitors: unexpected argument `--all`",
+        ),
+        (&["send"], "send needs a command"),
+    ];
+    for (line, message) in cases {
+        let error = Cli::parse(args(line)).unwrap_err();
+        assert_eq!(error.exit, ExitCode::Usage, "{:?}", line);
+        assert_eq!(error.to_string(), *message);
+    }
+}
+
+#[test]
+fn captures_a_region_of_the_fixture() {
+    let dir = TempDir::new("capture");
+    let target = dir.path("out/capture.png");
+    let mut line = fixture(&dir);
+    line.extend(args(&["capture", "--region", "5,4,10,6", "-o", &target]));
+
+    let (out, result) = run_args(line);
+    assert_eq!(exit_code(&result), 0);
+    assert_eq!(output(&out).trim(), target);
+    let image = image::open(&target).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (10, 6));
+    assert_eq!(*image.get_pixel(0, 0), pattern(5, 4));
+    assert_eq!(*image.get_pixel(9, 5), pattern(14, 9));
+}
+
+#[test]
+fn captures_to_standard_output() {
+    let dir = TempDir::new("stdout");
+    let mut line = fixture(&dir);
+    line.extend(args(&["capture", "-o", "-"]));
+
+    let (out, result) = run_args(line);
+    assert_eq!(exit_code(&result), 0);
+    let image = image::load_from_memory(&out).unwrap().to_rgba8();
+    assert_eq!(image.dimensions(), (40, 20));
+}
+
+#[test]
+fn lists_the_monitors_of_the_fixture() {
+    let dir = TempDir::new("monitors");
+    let mut line = fixture(&dir);
+    line.push("monitors".to_string());
+
+    let (out, result) = run_args(line);
+    assert_eq!(exit_code(&result), 0);
+    assert!(output(&out).starts_with("0\t"), "{}", output(&out));
+    assert!(output(&out).contains("\t40x20+0+0\t1\tprimary"));
+}
+
+#[test]
+fn recognizes_text_with_the_ocr_fixture() {
+    let dir = TempDir::new("ocr");
+    let mut line = fixture(&dir);
+    let words = ocr_fixture(&dir, &["Hello", "world"]);
+    line.extend(args(&["--ocr-fixture", &words, "ocr"]));
+
+    let (out, result) = run_args(line.clone());
+    assert_eq!(exit_code(&result), 0);
+    assert_eq!(output(&out), "Hello world\n");
+
+    line.extend(args(&["--format", "json"]));
+    let (out, result) = run_args(line);
+    assert_eq!(exit_code(&result), 0);
+    let document = OcrDocument::from_json(output(&out)).unwrap();
+    assert_eq!(document.text(), "Hello world");
+}
+
+#[test]
+fn missing_command_is_a_usage_error() {
+    let cli = Cli::parse(Vec::new()).unwrap();
+    let platform = Platform::for_cli(&cli).unwrap();
+    let error = run_with(&cli, &platform, &mut Vec::new()).unwrap_err();
+    assert_eq!(error.exit, ExitCode::Usage);
+}
+
+#[test]
+fn quality_for_lossless_formats_is_a_usage_error() {
+    let dir = TempDir::new("quality");
+    let mut line = fixture(&dir);
+    line.extend(args(&[
+        "capture",
+        "-o",
+        &dir.path("a.png"),
+        "--quality",
+        "50",
+    ]));
+
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 2);
+}
+
+#[test]
+fn broken_configuration_exits_with_3() {
+    let dir = TempDir::new("config");
+    let config = dir.path("config.toml");
+    fs::write(&config, "[overlay]\nopacity = \"high\"").unwrap();
+
+    let (_, result) = run_args(args(&["--config", &config, "config"]));
+    assert_eq!(exit_code(&result), 3);
+    let (_, result) = run_args(args(&["--config", &dir.path("missing.toml"), "config"]));
+    assert_eq!(exit_code(&result), 3);
+}
+
+#[test]
+fn unreadable_images_and_regions_outside_exit_with_4() {
+    let dir = TempDir::new("input");
+    let mut line = fixture(&dir);
+    line.extend(args(&[
+        "capture",
+        "--region",
+        "50,30,10,10",
+        "-o",
+        &dir.path("a.png"),
+    ]));
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 4);
+
+    let (_, result) = run_args(args(&["ocr", &dir.path("missing.png")]));
+    assert_eq!(exit_code(&result), 4);
+
+    let mut line = fixture(&dir);
+    line.extend(args(&["monitors", "--fixture", &dir.path("missing.png")]));
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 4);
+}
+
+struct BrokenEngine;
+
+impl OcrEngine for BrokenEngine {
+    fn name(&self) -> &'static str {
+        "broken"
+    }
+
+    fn recognize(&self, _frame: &Frame) -> anyhow::Result<OcrDocument> {
+        Err(anyhow::anyhow!("No engine"))
+    }
+}
+
+#[test]
+fn failing_ocr_exits_with_5() {
+    let dir = TempDir::new("ocr_fails");
+    let mut line = fixture(&dir);
+    line.push("ocr".to_string());
+    let cli = Cli::parse(line).unwrap();
+    let mut platform = Platform::for_cli(&cli).unwrap();
+    platform.engine = Box::new(|| Box::new(BrokenEngine));
+
+    let error = run_with(&cli, &platform, &mut Vec::new()).unwrap_err();
+    assert_eq!(error.exit.code(), 5);
+    assert_eq!(error.to_string(), "No engine");
+}
+
+#[test]
+fn no_text_exits_with_6_after_writing_the_result() {
+    let dir = TempDir::new("no_text");
+    let mut line = fixture(&dir);
+    let empty = ocr_fixture(&dir, &[]);
+    let target = dir.path("text.txt");
+    line.extend(args(&["--ocr-fixture", &empty, "ocr", "-o", &target]));
+
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 6);
+    assert_eq!(fs::read_to_string(&target).unwrap(), "\n");
+}
+
+#[test]
+fn unwritable_output_exits_with_7() {
+    let dir = TempDir::new("output");
+    let file = dir.path("file");
+    fs::write(&file, "").unwrap();
+    let mut line = fixture(&dir);
+    let target = Path::new(&file).join("capture.png");
+    line.extend(args(&["capture", "-o", &target.to_string_lossy()]));
+
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 7);
+}
+
+#[test]
+fn unreachable_overlay_exits_with_1() {
+    let dir = TempDir::new("send");
+    let address = format!("ipc.address={}", dir.path("missing.sock"));
+    let mut line = fixture(&dir);
+    line.extend(args(&["--set", &address, "send", "ping"]));
+    let (_, result) = run_args(line);
+    assert_eq!(exit_code(&result), 1);
+}
+
+#[test]
+fn help_prints_the_usage() {
+    let (out, result) = run_args(args(&["help"]));
+    assert_eq!(exit_code(&result), 0);
+    assert_eq!(output(&out), USAGE);
+}
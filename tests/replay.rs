@@ -0,0 +1,277 @@
+use image::{Rgba, RgbaImage};
+use ocr::annotation::Shape;
+use ocr::capture::{FixtureBackend, Frame};
+use ocr::cli::Platform;
+use ocr::config::Config;
+use ocr::geometry::{Point, Rect};
+use ocr::lasso::SelectionShape;
+use ocr::monitor::{CaptureRegion, MonitorLayout};
+use ocr::ocr::{FixtureEngine, OcrBlock, OcrDocument, OcrLine, OcrPage, OcrWord};
+use ocr::recording::{load_session, Recorder, ReplayOutput, Replayer, SessionEvent};
+use ocr::selection::{Buttons, InputEvent, MouseButton};
+use ocr::timer::ManualClock;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// Empty directory for one test, removed again when dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("replay_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A white 60x40 screen with a black bar along the bottom.
+fn screen(path: &Path) {
+    RgbaImage::from_fn(60, 40, |x, y| {
+        if (5..55).contains(&x) && (30..34).contains(&y) {
+            BLACK
+        } else {
+            WHITE
+        }
+    })
+    .save(path)
+    .unwrap();
+}
+
+/// One line of text with an e-mail address, with the boxes an engine
+/// reports for the twice upscaled screen.
+fn document() -> OcrDocument {
+    let word = |text: &str, rect| OcrWord {
+        text: text.to_string(),
+        rect,
+        confidence: Some(0.9),
+    };
+    let line = OcrLine::new(vec![
+        word("Contact", Rect::new(4.0, 4.0, 60.0, 24.0)),
+        word("max@example.com", Rect::new(70.0, 4.0, 110.0, 24.0)),
+    ]);
+    OcrDocument {
+        pages: vec![OcrPage::new(
+            Rect::new(0.0, 0.0, 120.0, 80.0),
+            vec![OcrBlock::new(vec![line])],
+        )],
+    }
+}
+
+fn platform(screen: &Path) -> Platform {
+    let engine = FixtureEngine::new(document());
+    Platform {
+        backend: Box::new(FixtureBackend::new(screen)),
+        layout: Box::new(|frame: &Frame| {
+            Ok(MonitorLayout::single(
+                frame.width,
+                frame.height,
+                frame.scale,
+            ))
+        }),
+        engine: Box::new(move || Box::new(engine.clone())),
+    }
+}
+
+fn drag(from: Point, to: Point) -> Vec<SessionEvent> {
+    let left = Buttons {
+        left: true,
+        ..Buttons::default()
+    };
+    [
+        InputEvent::PointerDown {
+            pos: from,
+            button: MouseButton::Left,
+        },
+        InputEvent::PointerMove {
+            pos: to,
+            buttons: left,
+        },
+        InputEvent::PointerUp {
+            pos: to,
+            button: MouseButton::Left,
+        },
+    ]
+    .into_iter()
+    .map(|event| SessionEvent::Input { event })
+    .collect()
+}
+
+fn load(path: &Path) -> RgbaImage {
+    image::open(path).unwrap().to_rgba8()
+}
+
+#[test]
+fn recorded_session_replays_against_fixtures() {
+    let dir = TempDir::new("session");
+    let screen_path = dir.0.join("screen.png");
+    screen(&screen_path);
+
+    let whole = Rect::new(0.0, 0.0, 60.0, 40.0);
+    let mut session = vec![SessionEvent::Capture {
+        origin: Point::new(0.0, 0.0),
+    }];
+    session.extend(drag(Point::new(5.0, 4.0), Point::new(15.0, 10.0)));
+    session.extend([
+        SessionEvent::SaveShape {
+            shape: SelectionShape::Rect {
+                rect: Rect::new(5.0, 4.0, 15.0, 10.0),
+            },
+            fill: None,
+            path: PathBuf::from("recorded/selection.png"),
+        },
+        SessionEvent::RecognizeText { rect: whole },
+        SessionEvent::RedactSensitive,
+        SessionEvent::Undo,
+        SessionEvent::Redo,
+        SessionEvent::SaveShape {
+            shape: SelectionShape::Rect { rect: whole },
+            fill: None,
+            path: PathBuf::from("recorded/redacted.png"),
+        },
+        SessionEvent::CaptureToFile {
+            region: CaptureRegion::AllMonitors,
+            path: PathBuf::from("recorded/screen.png"),
+        },
+    ]);
+
+    // Record with a clock that moves 100 ms per event
+    let recording = dir.0.join("session.jsonl");
+    let clock = ManualClock::new();
+    let mut recorder = Recorder::new(
+        Box::new(File::create(&recording).unwrap()),
+        Box::new(clock.clone()),
+    );
+    for event in &session {
+        recorder.record(event.clone()).unwrap();
+        clock.advance(Duration::from_millis(100));
+    }
+    assert_eq!(recorder.recorded(), session.len());
+    drop(recorder);
+
+    let events = load_session(&recording).unwrap();
+    let times: Vec<u64> = events.iter().map(|e| e.at).collect();
+    assert_eq!(
+        times,
+        (0..session.len() as u64)
+            .map(|i| i * 100)
+            .collect::<Vec<_>>()
+    );
+    let replayed: Vec<SessionEvent> = events.iter().map(|e| e.event.clone()).collect();
+    assert_eq!(replayed, session);
+
+    let platform = platform(&screen_path);
+    let config = Config::default();
+    let output_dir = dir.0.join("out");
+    let mut replayer = Replayer::new(&platform, &config);
+    replayer.set_output_dir(&output_dir);
+    let outputs = replayer.replay(&events).unwrap();
+
+    assert_eq!(
+        outputs,
+        [
+            ReplayOutput::Saved {
+                path: output_dir.join("selection.png")
+            },
+            ReplayOutput::Text {
+                text: "Contact max@example.com".to_string()
+            },
+            ReplayOutput::Redacted { count: 1 },
+            ReplayOutput::Saved {
+                path: output_dir.join("redacted.png")
+            },
+            ReplayOutput::Saved {
+                path: output_dir.join("screen.png")
+            },
+        ]
+    );
+    assert_eq!(replayer.selection(), Some(Rect::new(5.0, 4.0, 15.0, 10.0)));
+
+    // The redaction came back with redo and covers the address, which the
+    // engine saw at twice the size
+    let annotations = &replayer.document().annotations;
+    assert_eq!(annotations.len(), 1);
+    let Shape::Redaction(redaction) = annotations.get(0).unwrap().to_shape() else {
+        panic!("not a redaction");
+    };
+    assert_eq!(redaction.rect, Rect::new(33.0, 0.0, 57.0, 14.0));
+
+    let selection = load(&output_dir.join("selection.png"));
+    assert_eq!(selection.dimensions(), (10, 6));
+    assert!(selection.pixels().all(|p| *p == WHITE));
+
+    let redacted = load(&output_dir.join("redacted.png"));
+    assert_eq!(redacted.dimensions(), (60, 40));
+    assert_eq!(*redacted.get_pixel(45, 7), BLACK);
+    assert_eq!(*redacted.get_pixel(20, 7), WHITE);
+    assert_eq!(*redacted.get_pixel(10, 31), BLACK);
+
+    assert_eq!(load(&output_dir.join("screen.png")), load(&screen_path));
+    // Nothing went to the recorded paths
+    assert!(!Path::new("recorded").exists());
+}
+
+#[test]
+fn replay_stops_at_the_first_failing_event() {
+    let dir = TempDir::new("failing");
+    let screen_path = dir.0.join("screen.png");
+    screen(&screen_path);
+    let recording = dir.0.join("session.jsonl");
+    let mut recorder = Recorder::new(
+        Box::new(File::create(&recording).unwrap()),
+        Box::new(ManualClock::new()),
+    );
+    recorder.record(SessionEvent::RedactSensitive).unwrap();
+    drop(recorder);
+
+    let platform = platform(&screen_path);
+    let config = Config::default();
+    let error = Replayer::new(&platform, &config)
+        .replay(&load_session(&recording).unwrap())
+        .unwrap_err();
+    assert_eq!(error.to_string(), "Event 1 at 0 ms: No screen captured yet");
+}
+
+#[test]
+fn recorder_skips_pointer_moves_without_a_held_button() {
+    let dir = TempDir::new("hover");
+    let recording = dir.0.join("session.jsonl");
+    let mut recorder = Recorder::new(
+        Box::new(File::create(&recording).unwrap()),
+        Box::new(ManualClock::new()),
+    );
+    let hover = |x| SessionEvent::Input {
+        event: InputEvent::PointerMove {
+            pos: Point::new(x, 3.0),
+            buttons: Buttons::default(),
+        },
+    };
+    let session = drag(Point::new(5.0, 4.0), Point::new(15.0, 10.0));
+    for x in [1.0, 2.0, 3.0] {
+        recorder.record(hover(x)).unwrap();
+    }
+    for event in &session {
+        recorder.record(event.clone()).unwrap();
+        recorder.record(hover(20.0)).unwrap();
+    }
+    assert_eq!(recorder.recorded(), session.len());
+    drop(recorder);
+
+    let events: Vec<SessionEvent> = load_session(&recording)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.event)
+        .collect();
+    // The move of the drag itself is kept
+    assert_eq!(events, session);
+}